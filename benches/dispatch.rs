@@ -0,0 +1,30 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use vfs_nodes::{SymLinkScheme, Vfs};
+
+fn vfs(dispatch_cache: bool) -> Vfs {
+	let mut vfs = Vfs::default();
+	for i in 0..16 {
+		vfs.add_scheme(format!("scheme{}", i), SymLinkScheme::default())
+			.unwrap();
+	}
+	vfs.set_dispatch_cache(dispatch_cache);
+	vfs
+}
+
+fn dispatch(c: &mut Criterion) {
+	let url = url::Url::parse("data:,x").unwrap();
+	let mut group = c.benchmark_group("dispatch");
+	for (name, dispatch_cache) in [("uncached", false), ("cached", true)] {
+		let vfs = vfs(dispatch_cache);
+		group.bench_function(format!("get_scheme/{}", name), |b| {
+			b.iter(|| vfs.get_scheme(black_box("data")).is_ok())
+		});
+		group.bench_function(format!("metadata/{}", name), |b| {
+			b.iter(|| futures_lite::future::block_on(vfs.metadata(black_box(&url))).is_ok())
+		});
+	}
+	group.finish();
+}
+
+criterion_group!(benches, dispatch);
+criterion_main!(benches);
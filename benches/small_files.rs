@@ -0,0 +1,65 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use futures_lite::AsyncReadExt;
+use std::path::PathBuf;
+use vfs_nodes::scheme::NodeGetOptions;
+use vfs_nodes::{TokioFileSystemScheme, Vfs};
+
+const FILE_COUNT: usize = 256;
+const FILE_LEN: usize = 512;
+
+fn setup() -> (PathBuf, Vec<String>) {
+	let root = std::env::current_dir()
+		.unwrap()
+		.join("target")
+		.join("bench_small_files");
+	std::fs::create_dir_all(&root).unwrap();
+	let uris = (0..FILE_COUNT)
+		.map(|i| {
+			let name = format!("file_{}.txt", i);
+			std::fs::write(root.join(&name), vec![b'a' + (i % 26) as u8; FILE_LEN]).unwrap();
+			format!("fs:/{}", name)
+		})
+		.collect();
+	(root, uris)
+}
+
+fn small_files(c: &mut Criterion) {
+	let (root, uris) = setup();
+	let vfs = Vfs::default();
+	vfs.add_scheme("fs", TokioFileSystemScheme::new(root))
+		.unwrap();
+	let rt = tokio::runtime::Builder::new_current_thread()
+		.build()
+		.unwrap();
+
+	let mut group = c.benchmark_group("small_files");
+	group.bench_function("read_to_vec_at", |b| {
+		b.iter(|| {
+			rt.block_on(async {
+				for uri in &uris {
+					let data = vfs.read_to_vec_at(uri).await.unwrap();
+					assert_eq!(data.len(), FILE_LEN);
+				}
+			})
+		})
+	});
+	group.bench_function("get_node_at", |b| {
+		b.iter(|| {
+			rt.block_on(async {
+				for uri in &uris {
+					let mut node = vfs
+						.get_node_at(uri, &NodeGetOptions::new().read(true))
+						.await
+						.unwrap();
+					let mut data = Vec::new();
+					node.read_to_end(&mut data).await.unwrap();
+					assert_eq!(data.len(), FILE_LEN);
+				}
+			})
+		})
+	});
+	group.finish();
+}
+
+criterion_group!(benches, small_files);
+criterion_main!(benches);
@@ -0,0 +1,96 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use std::collections::HashMap;
+use url::Url;
+use vfs_nodes::scheme::{BorrowedReadDirStream, NodeGetOptions, NodeMetadata};
+use vfs_nodes::{PinnedNode, Scheme, SchemeError, StaticRouteScheme, Vfs};
+
+const ROUTE_COUNT: usize = 4096;
+
+/// The same table looked up through a `HashMap`, only `metadata` is needed to compare lookups.
+struct HashMapScheme(HashMap<String, &'static [u8]>);
+
+#[async_trait::async_trait]
+impl Scheme for HashMapScheme {
+	async fn get_node<'a>(
+		&self,
+		_vfs: &Vfs,
+		_url: &'a Url,
+		_options: &NodeGetOptions,
+	) -> Result<PinnedNode, SchemeError<'a>> {
+		Err(SchemeError::Unsupported("get_node"))
+	}
+
+	async fn remove_node<'a>(
+		&self,
+		_vfs: &Vfs,
+		_url: &'a Url,
+		_force: bool,
+	) -> Result<(), SchemeError<'a>> {
+		Err(SchemeError::Unsupported("remove_node"))
+	}
+
+	async fn metadata<'a>(
+		&self,
+		_vfs: &Vfs,
+		url: &'a Url,
+	) -> Result<NodeMetadata, SchemeError<'a>> {
+		let data = self
+			.0
+			.get(url.path())
+			.ok_or(SchemeError::NodeDoesNotExist(url.path().into()))?;
+		Ok(NodeMetadata {
+			is_node: true,
+			len: Some((data.len(), Some(data.len()))),
+			modified: None,
+			..Default::default()
+		})
+	}
+
+	async fn read_dir<'s, 'a>(
+		&'s self,
+		_vfs: &'s Vfs,
+		_url: &'a Url,
+	) -> Result<BorrowedReadDirStream<'s>, SchemeError<'a>> {
+		Err(SchemeError::Unsupported("read_dir"))
+	}
+}
+
+fn routes() -> &'static [(&'static str, &'static [u8])] {
+	let mut routes: Vec<(&'static str, &'static [u8])> = (0..ROUTE_COUNT)
+		.map(|i| {
+			let path: &'static str = Box::leak(format!("/assets/{:05}.bin", i).into_boxed_str());
+			(path, &b"static asset"[..])
+		})
+		.collect();
+	routes.sort();
+	Box::leak(routes.into_boxed_slice())
+}
+
+fn static_routes(c: &mut Criterion) {
+	let routes = routes();
+	let static_routes = StaticRouteScheme::new(routes).unwrap();
+	let hashmap: HashMap<String, &'static [u8]> =
+		routes.iter().map(|(p, d)| (p.to_string(), *d)).collect();
+	let mut group = c.benchmark_group("static_routes");
+	let path = format!("/assets/{:05}.bin", ROUTE_COUNT / 3);
+	group.bench_function("get/static", |b| {
+		b.iter(|| static_routes.get(black_box(&path)))
+	});
+	group.bench_function("get/hashmap", |b| {
+		b.iter(|| hashmap.get(black_box(path.as_str())).copied())
+	});
+
+	let vfs = Vfs::empty();
+	vfs.add_scheme("static", static_routes).unwrap();
+	vfs.add_scheme("hashmap", HashMapScheme(hashmap)).unwrap();
+	for scheme in ["static", "hashmap"] {
+		let url = Url::parse(&format!("{}:/assets/{:05}.bin", scheme, ROUTE_COUNT / 3)).unwrap();
+		group.bench_function(format!("metadata/{}", scheme), |b| {
+			b.iter(|| futures_lite::future::block_on(vfs.metadata(black_box(&url))).is_ok())
+		});
+	}
+	group.finish();
+}
+
+criterion_group!(benches, static_routes);
+criterion_main!(benches);
@@ -0,0 +1,46 @@
+use futures_lite::AsyncReadExt;
+use vfs_nodes::scheme::{NodeGetOptions, NodeMetadata};
+use vfs_nodes::{FnScheme, Vfs};
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+	// Nothing here needs a runtime, so a simple executor is enough
+	futures_lite::future::block_on(async {
+		let vfs = Vfs::default();
+
+		// An `echo` scheme that returns the path it was given as the node content, it just
+		// forwards to the `data` scheme that `Vfs::default` already registered.
+		vfs.add_scheme(
+			"echo",
+			FnScheme::new()
+				.on_get_node(|vfs, url, options| {
+					Box::pin(async move {
+						Ok(vfs
+							.get_node_at(&format!("data:{}", url.path()), options)
+							.await?)
+					})
+				})
+				.on_metadata(|_vfs, url| {
+					let len = url.path().len();
+					Box::pin(async move {
+						Ok(NodeMetadata {
+							is_node: true,
+							len: Some((len, Some(len))),
+							modified: None,
+							..Default::default()
+						})
+					})
+				}),
+		)?;
+
+		let mut buffer = String::new();
+		vfs.get_node_at("echo:Hello%20closures", &NodeGetOptions::new().read(true))
+			.await?
+			.read_to_string(&mut buffer)
+			.await?;
+		println!("echo returned: {}", buffer);
+		println!("{:?}", vfs.metadata_at("echo:Hello").await?);
+		// Anything without a closure is unsupported
+		assert!(vfs.remove_node_at("echo:Hello", false).await.is_err());
+		Ok(())
+	})
+}
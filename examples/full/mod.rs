@@ -20,7 +20,7 @@ library, it's just to keep the example files distinct.\n"
 `Vfs::empty()` instead and you will have to `add_scheme` everything.  You can also call
 `add_default_schemes` afterwards to add the default schemes anyway.\n"
 	);
-	let mut vfs = Vfs::empty();
+	let vfs = Vfs::empty();
 
 	println!(
 		"Next we'll add some schemes, they can be added/removed dynamically at any time as long as you
@@ -60,8 +60,8 @@ it to fall back to, but you can add more with a variety of read/write access.\n"
 			.read(
 				SymLinkScheme::builder()
 					// So accessing `"overlay:/mem/blah"` will direct to `"mem:/linked/blah"`
-					.link("/mem", Url::parse("mem:/linked")?)
-					.link("/data", Url::parse("data:")?)
+					.link("/mem", Url::parse("mem:/linked")?)?
+					.link("/data", Url::parse("data:")?)?
 					.build(),
 			)
 			.build(),
@@ -0,0 +1,44 @@
+use std::collections::BTreeMap;
+use url::Url;
+use vfs_nodes::{Resolution, ResolverScheme, UrlResolver, Vfs};
+
+/// Serves a few fixed notes, all of them at the root.
+struct Notes(BTreeMap<&'static str, &'static str>);
+
+#[async_trait::async_trait]
+impl UrlResolver for Notes {
+	async fn resolve(&self, url: &Url) -> Resolution {
+		match url.path() {
+			"/" => Resolution::Directory(self.0.keys().map(|name| name.to_string()).collect()),
+			path => match self.0.get(path.trim_start_matches('/')) {
+				Some(note) => Resolution::Bytes(note.as_bytes().to_vec()),
+				None => Resolution::NotFound,
+			},
+		}
+	}
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+	// Nothing here needs a runtime, so a simple executor is enough
+	futures_lite::future::block_on(async {
+		let vfs = Vfs::empty();
+		let notes = Notes(
+			[
+				("todo", "write an example"),
+				("done", "implement UrlResolver"),
+			]
+			.iter()
+			.copied()
+			.collect(),
+		);
+		vfs.add_scheme("notes", ResolverScheme::new(notes))?;
+
+		for entry in vfs.read_dir_sorted_at("notes:/").await? {
+			let note = vfs.read_to_vec_at(entry.url.as_str()).await?;
+			println!("{}: {}", entry.url, String::from_utf8_lossy(&note));
+		}
+		// The resolver only reads, so writes are refused
+		assert!(vfs.remove_node_at("notes:/todo", false).await.is_err());
+		Ok(())
+	})
+}
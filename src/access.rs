@@ -0,0 +1,103 @@
+use crate::scheme::NodeGetOptions;
+use crate::VfsError;
+use url::Url;
+
+/// The kinds of operation a `VfsAccessControl` is asked about.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum VfsOp {
+	/// Opening a node for reading, or reading it whole.
+	Read,
+	/// Opening a node for writing or creating it, or changing its metadata.
+	Write,
+	Remove,
+	/// Listing a directory.
+	List,
+	/// Reading the metadata of a node or resolving its canonical url.
+	Stat,
+}
+
+impl std::fmt::Display for VfsOp {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.write_str(match self {
+			VfsOp::Read => "read",
+			VfsOp::Write => "write",
+			VfsOp::Remove => "remove",
+			VfsOp::List => "list",
+			VfsOp::Stat => "stat",
+		})
+	}
+}
+
+/// Authorizes every operation made through a `Vfs` before it reaches a scheme, see
+/// `Vfs::set_access_control`.  Schemes that go back through the `Vfs`, like symlinks, are checked
+/// again for the url they resolve to.
+pub trait VfsAccessControl: Send + Sync + 'static {
+	/// Return `VfsError::AccessDenied` to refuse `op` on `url`, any other error is passed on too.
+	fn check(&self, op: VfsOp, url: &Url) -> Result<(), VfsError<'static>>;
+}
+
+/// The operations opening a node with `options` needs, an open with neither read nor write still
+/// counts as a read.
+pub(crate) fn node_ops(options: &NodeGetOptions) -> impl Iterator<Item = VfsOp> {
+	let read = options.get_read() || !options.get_write();
+	let write = options.get_write();
+	read.then_some(VfsOp::Read)
+		.into_iter()
+		.chain(write.then_some(VfsOp::Write))
+}
+
+#[cfg(test)]
+#[cfg(all(feature = "backend_tokio", feature = "in_memory"))]
+mod async_tokio_tests {
+	use super::{VfsAccessControl, VfsOp};
+	use crate::scheme::NodeGetOptions;
+	use crate::{MemoryScheme, Vfs, VfsError};
+	use std::sync::Arc;
+	use url::Url;
+
+	/// Lets a tenant read everything but only write outside of `/shared/`.
+	struct ReadOnlyShared;
+
+	impl VfsAccessControl for ReadOnlyShared {
+		fn check(&self, op: VfsOp, url: &Url) -> Result<(), VfsError<'static>> {
+			match op {
+				VfsOp::Write | VfsOp::Remove if url.path().starts_with("/shared/") => {
+					Err(VfsError::AccessDenied(op, url.clone()))
+				}
+				_ => Ok(()),
+			}
+		}
+	}
+
+	#[tokio::test]
+	async fn deny_writes_to_prefix() {
+		let mut vfs = Vfs::empty();
+		vfs.add_scheme("mem", MemoryScheme::default()).unwrap();
+		let create = NodeGetOptions::new().create(true);
+		vfs.get_node_at("mem:/shared/readme", &create)
+			.await
+			.unwrap();
+		vfs.set_access_control(Arc::new(ReadOnlyShared));
+
+		assert!(matches!(
+			vfs.get_node_at("mem:/shared/readme", &create).await,
+			Err(VfsError::AccessDenied(VfsOp::Write, url)) if url.path() == "/shared/readme"
+		));
+		assert!(matches!(
+			vfs.remove_node_at("mem:/shared/readme", false).await,
+			Err(VfsError::AccessDenied(VfsOp::Remove, _))
+		));
+		assert!(vfs
+			.get_node_at("mem:/shared/readme", &NodeGetOptions::new().read(true))
+			.await
+			.is_ok());
+		assert!(vfs.metadata_at("mem:/shared/readme").await.is_ok());
+		assert!(vfs.get_node_at("mem:/private/notes", &create).await.is_ok());
+
+		vfs.clear_access_control();
+		assert!(vfs
+			.remove_node_at("mem:/shared/readme", false)
+			.await
+			.is_ok());
+	}
+}
@@ -0,0 +1,55 @@
+//! The time source of the crate.  `wasm32-unknown-unknown` has no clock in `std`, calling
+//! `SystemTime::now` or `Instant::now` there panics, so there the time is read from JavaScript's
+//! `Date.now()` instead.  Everywhere else these are the ones of `std`.
+use std::time::SystemTime;
+
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+pub(crate) use std::time::Instant;
+
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+#[cfg_attr(not(feature = "in_memory"), allow(dead_code))]
+pub(crate) fn now() -> SystemTime {
+	SystemTime::now()
+}
+
+#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+#[cfg_attr(not(feature = "in_memory"), allow(dead_code))]
+pub(crate) fn now() -> SystemTime {
+	std::time::UNIX_EPOCH + since_epoch()
+}
+
+#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+fn since_epoch() -> std::time::Duration {
+	std::time::Duration::from_secs_f64(js_sys::Date::now().max(0.0) / 1000.0)
+}
+
+/// Stands in for `std::time::Instant` with the methods the crate uses.  It is read from the same
+/// clock as `now`, so it can go back when the system clock is set back, the durations between
+/// instants then saturate to zero instead.
+#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub(crate) struct Instant(std::time::Duration);
+
+#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+impl Instant {
+	pub(crate) fn now() -> Self {
+		Self(since_epoch())
+	}
+
+	pub(crate) fn saturating_duration_since(&self, earlier: Instant) -> std::time::Duration {
+		self.0.saturating_sub(earlier.0)
+	}
+
+	pub(crate) fn elapsed(&self) -> std::time::Duration {
+		Self::now().saturating_duration_since(*self)
+	}
+}
+
+#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+impl std::ops::Add<std::time::Duration> for Instant {
+	type Output = Instant;
+
+	fn add(self, duration: std::time::Duration) -> Instant {
+		Instant(self.0 + duration)
+	}
+}
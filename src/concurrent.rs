@@ -0,0 +1,160 @@
+use futures_lite::Stream;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+enum Slot<Fut: Future> {
+	Running(Pin<Box<Fut>>),
+	Done(Fut::Output),
+}
+
+/// Maps each item of a stream to a future and runs up to `limit` of those futures at once,
+/// yielding their outputs in the same order as the items came in, or as they complete if made with
+/// `unordered`.
+pub(crate) struct Buffered<S, F, Fut: Future> {
+	stream: Option<S>,
+	map: F,
+	slots: VecDeque<Slot<Fut>>,
+	limit: usize,
+	ordered: bool,
+}
+
+impl<S, F, Fut> Buffered<S, F, Fut>
+where
+	S: Stream + Unpin,
+	F: FnMut(S::Item) -> Fut,
+	Fut: Future,
+{
+	pub(crate) fn new(stream: S, limit: usize, map: F) -> Self {
+		Self {
+			stream: Some(stream),
+			map,
+			slots: VecDeque::with_capacity(limit.max(1)),
+			limit: limit.max(1),
+			ordered: true,
+		}
+	}
+
+	pub(crate) fn unordered(stream: S, limit: usize, map: F) -> Self {
+		Self {
+			ordered: false,
+			..Self::new(stream, limit, map)
+		}
+	}
+}
+
+// The futures are boxed and the stream is required to be `Unpin`, nothing is structurally pinned
+impl<S: Unpin, F, Fut: Future> Unpin for Buffered<S, F, Fut> {}
+
+impl<S, F, Fut> Stream for Buffered<S, F, Fut>
+where
+	S: Stream + Unpin,
+	F: FnMut(S::Item) -> Fut,
+	Fut: Future,
+{
+	type Item = Fut::Output;
+
+	fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+		let this = self.get_mut();
+		while this.slots.len() < this.limit {
+			let stream = match this.stream.as_mut() {
+				Some(stream) => stream,
+				None => break,
+			};
+			match Pin::new(stream).poll_next(cx) {
+				Poll::Ready(Some(item)) => {
+					let fut = (this.map)(item);
+					this.slots.push_back(Slot::Running(Box::pin(fut)));
+				}
+				Poll::Ready(None) => this.stream = None,
+				Poll::Pending => break,
+			}
+		}
+		for slot in this.slots.iter_mut() {
+			if let Slot::Running(fut) = slot {
+				if let Poll::Ready(output) = fut.as_mut().poll(cx) {
+					*slot = Slot::Done(output);
+				}
+			}
+		}
+		let done = if this.ordered {
+			this.slots.front().map(|_| 0)
+		} else {
+			this.slots
+				.iter()
+				.position(|slot| matches!(slot, Slot::Done(_)))
+		};
+		match done.map(|index| &this.slots[index]) {
+			Some(Slot::Done(_)) => match done.and_then(|index| this.slots.remove(index)) {
+				Some(Slot::Done(output)) => {
+					// Wake again so the freed slot gets refilled from the stream
+					cx.waker().wake_by_ref();
+					Poll::Ready(Some(output))
+				}
+				_ => unreachable!("slot was just checked to be done"),
+			},
+			None if this.slots.is_empty() && this.stream.is_none() => Poll::Ready(None),
+			_ => Poll::Pending,
+		}
+	}
+
+	fn size_hint(&self) -> (usize, Option<usize>) {
+		let (lower, upper) = self
+			.stream
+			.as_ref()
+			.map_or((0, Some(0)), |stream| stream.size_hint());
+		let len = self.slots.len();
+		(
+			lower.saturating_add(len),
+			upper.and_then(|upper| upper.checked_add(len)),
+		)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::Buffered;
+	use futures_lite::{future, stream, StreamExt};
+	use std::sync::atomic::{AtomicUsize, Ordering};
+
+	#[test]
+	fn ordered_and_bounded() {
+		let running = AtomicUsize::new(0);
+		let max_running = AtomicUsize::new(0);
+		let results: Vec<_> = future::block_on(
+			Buffered::new(stream::iter(0..20usize), 4, |i| {
+				let running = &running;
+				let max_running = &max_running;
+				async move {
+					let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+					max_running.fetch_max(now, Ordering::SeqCst);
+					// Finish out of order to make sure the output order is still kept
+					for _ in 0..(20 - i) % 3 {
+						future::yield_now().await;
+					}
+					running.fetch_sub(1, Ordering::SeqCst);
+					i * 2
+				}
+			})
+			.collect(),
+		);
+		assert_eq!(results, (0..20).map(|i| i * 2).collect::<Vec<_>>());
+		assert!(max_running.load(Ordering::SeqCst) <= 4);
+	}
+
+	#[test]
+	fn unordered_completion() {
+		let results: Vec<_> = future::block_on(
+			Buffered::unordered(stream::iter(0..4usize), 4, |i| async move {
+				// The later items finish first
+				for _ in 0..(4 - i) {
+					future::yield_now().await;
+				}
+				i
+			})
+			.collect(),
+		);
+		assert_eq!(results, [3, 2, 1, 0]);
+	}
+}
@@ -0,0 +1,50 @@
+use crate::Scheme;
+use std::sync::{Arc, Mutex};
+
+/// Remembers the last scheme `Vfs::get_scheme` found so repeated lookups of the same scheme name
+/// can skip hashing the name.  Each entry is tagged with the `SchemeMap` generation it was found
+/// in and only used while that is still the current generation, so a scheme that was removed or
+/// replaced since, such as through another clone of the `Vfs`, is never handed out.
+pub(crate) struct DispatchCache(Mutex<Option<CachedScheme>>);
+
+/// The scheme name, the scheme, and the generation it was found in.
+type CachedScheme = (String, Arc<dyn Scheme>, usize);
+
+impl DispatchCache {
+	pub(crate) fn new() -> Self {
+		Self(Mutex::new(None))
+	}
+
+	/// Returns the cached scheme if it was stored under `scheme_name` in `generation`, skips the
+	/// cache entirely instead of waiting if another thread is using it.
+	pub(crate) fn get(&self, scheme_name: &str, generation: usize) -> Option<Arc<dyn Scheme>> {
+		let cached = self.0.try_lock().ok()?;
+		match &*cached {
+			Some((name, scheme, cached_generation))
+				if *cached_generation == generation && name == scheme_name =>
+			{
+				Some(scheme.clone())
+			}
+			_ => None,
+		}
+	}
+
+	pub(crate) fn set(&self, scheme_name: &str, scheme: &Arc<dyn Scheme>, generation: usize) {
+		if let Ok(mut cached) = self.0.try_lock() {
+			match &mut *cached {
+				Some((name, cached_scheme, cached_generation)) => {
+					// Reuse the name allocation
+					name.clear();
+					name.push_str(scheme_name);
+					*cached_scheme = scheme.clone();
+					*cached_generation = generation;
+				}
+				None => *cached = Some((scheme_name.to_owned(), scheme.clone(), generation)),
+			}
+		}
+	}
+
+	pub(crate) fn clear(&self) {
+		*self.0.lock().expect("poisoned lock") = None;
+	}
+}
@@ -12,7 +12,17 @@ pub enum SchemeError<'name> {
 	UrlAccessError(Cow<'name, Url>),
 	NodeDoesNotExist(Cow<'name, str>),
 	NodeAlreadyExists(Cow<'name, str>),
+	IsADirectory(Cow<'name, str>),
+	/// A directory operation, such as `read_dir`, on a path that is a node.
+	NotADirectory(Cow<'name, str>),
 	IOError(std::io::Error),
+	/// An IO error along with the path of the node it happened on.
+	IOErrorAt(Cow<'name, str>, std::io::Error),
+	Unsupported(&'static str),
+	/// A `data:` url that is not of the form `data:[<mediatype>][;base64],<data>`, with why.
+	InvalidDataUrl(&'static str),
+	/// A write or create at the path that would take a `QuotaScheme` past one of its limits.
+	QuotaExceeded(Cow<'name, str>),
 }
 
 impl<'name> SchemeError<'name> {
@@ -27,9 +37,38 @@ impl<'name> SchemeError<'name> {
 			SchemeError::UrlAccessError(url) => {
 				SchemeError::UrlAccessError(Cow::Owned(url.into_owned()))
 			}
+			SchemeError::IsADirectory(name) => {
+				SchemeError::IsADirectory(Cow::Owned(name.into_owned()))
+			}
+			SchemeError::NotADirectory(name) => {
+				SchemeError::NotADirectory(Cow::Owned(name.into_owned()))
+			}
 			SchemeError::GenericError(msg, source) => SchemeError::GenericError(msg, source),
 			SchemeError::UrlParseError(path) => SchemeError::UrlParseError(path),
 			SchemeError::IOError(source) => SchemeError::IOError(source),
+			SchemeError::IOErrorAt(name, source) => {
+				SchemeError::IOErrorAt(Cow::Owned(name.into_owned()), source)
+			}
+			SchemeError::Unsupported(operation) => SchemeError::Unsupported(operation),
+			SchemeError::InvalidDataUrl(reason) => SchemeError::InvalidDataUrl(reason),
+			SchemeError::QuotaExceeded(name) => {
+				SchemeError::QuotaExceeded(Cow::Owned(name.into_owned()))
+			}
+		}
+	}
+}
+
+impl<'name> SchemeError<'name> {
+	/// For `map_err`, wraps an IO error with the path of the node it happened on.
+	pub fn io_at(name: &'name str) -> impl FnOnce(std::io::Error) -> Self {
+		move |source| SchemeError::IOErrorAt(Cow::Borrowed(name), source)
+	}
+
+	/// The IO error underneath this error, if it is one.
+	pub fn io_error(&self) -> Option<&std::io::Error> {
+		match self {
+			SchemeError::IOError(source) | SchemeError::IOErrorAt(_, source) => Some(source),
+			_ => None,
 		}
 	}
 }
@@ -42,13 +81,31 @@ impl<'name> std::fmt::Display for SchemeError<'name> {
 				f.write_fmt(format_args!("node not found: {}", name))
 			}
 			SchemeError::IOError(_source) => f.write_str("generic IO error"),
+			SchemeError::IOErrorAt(name, source) => {
+				f.write_fmt(format_args!("IO error at {}: {}", name, source))
+			}
 			SchemeError::NodeAlreadyExists(name) => {
 				f.write_fmt(format_args!("node already exists: {}", name))
 			}
 			SchemeError::UrlAccessError(url) => {
 				f.write_fmt(format_args!("access error with path: {}", url))
 			}
+			SchemeError::IsADirectory(name) => {
+				f.write_fmt(format_args!("node is a directory: {}", name))
+			}
+			SchemeError::NotADirectory(name) => {
+				f.write_fmt(format_args!("node is not a directory: {}", name))
+			}
 			SchemeError::UrlParseError(_source) => f.write_str("failed parsing url string"),
+			SchemeError::Unsupported(operation) => {
+				f.write_fmt(format_args!("unsupported operation: {}", operation))
+			}
+			SchemeError::InvalidDataUrl(reason) => {
+				f.write_fmt(format_args!("invalid data url: {}", reason))
+			}
+			SchemeError::QuotaExceeded(name) => {
+				f.write_fmt(format_args!("quota exceeded at: {}", name))
+			}
 		}
 	}
 }
@@ -62,9 +119,15 @@ impl<'name> std::error::Error for SchemeError<'name> {
 			}),
 			SchemeError::NodeDoesNotExist(_name) => None,
 			SchemeError::IOError(source) => Some(source),
+			SchemeError::IOErrorAt(_name, source) => Some(source),
 			SchemeError::NodeAlreadyExists(_name) => None,
 			SchemeError::UrlAccessError(_url) => None,
+			SchemeError::IsADirectory(_name) => None,
+			SchemeError::NotADirectory(_name) => None,
 			SchemeError::UrlParseError(source) => Some(source),
+			SchemeError::Unsupported(_operation) => None,
+			SchemeError::InvalidDataUrl(_reason) => None,
+			SchemeError::QuotaExceeded(_name) => None,
 		}
 	}
 }
@@ -161,6 +224,13 @@ impl From<url::ParseError> for SchemeError<'static> {
 
 impl<'name> From<VfsError<'name>> for SchemeError<'static> {
 	fn from(source: VfsError<'name>) -> Self {
-		SchemeError::GenericError(Some("vfs error"), Some(Box::new(source.into_owned())))
+		match source {
+			// Pass scheme errors through as-is so schemes forwarding to the vfs keep their meaning
+			VfsError::SchemeError(source) => source,
+			VfsError::NodeIo(source) => SchemeError::IOError(source),
+			source => {
+				SchemeError::GenericError(Some("vfs error"), Some(Box::new(source.into_owned())))
+			}
+		}
 	}
 }
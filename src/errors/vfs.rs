@@ -1,14 +1,30 @@
+use crate::access::VfsOp;
 use crate::SchemeError;
 use std::borrow::Cow;
-use url::ParseError;
+use url::{ParseError, Url};
 
 #[derive(Debug)]
 pub enum VfsError<'name> {
 	SchemeAlreadyExists(String),
 	SchemeNotFound(Cow<'name, str>),
 	SchemeWrongType(Cow<'name, str>, &'static str),
+	/// The scheme cannot be borrowed mutably as it is also held elsewhere, such as by a clone of
+	/// the `Vfs`, see `Vfs::get_scheme_mut`.
+	SchemeShared(Cow<'name, str>),
 	UrlParseFailed(url::ParseError),
 	SchemeError(SchemeError<'static>),
+	/// A recursive operation reached a directory it had already visited.
+	DirectoryLoop(Url),
+	/// The `VfsAccessControl` of the `Vfs` refused the operation.
+	AccessDenied(VfsOp, Url),
+	/// Nothing appeared at the url before the deadline of `Vfs::wait_for`.
+	TimedOut(Url),
+	/// The path of the url has more segments or bytes than the `Vfs` allows, see
+	/// `Vfs::set_max_path_segments` and `Vfs::set_max_path_bytes`.
+	PathTooLong(Url),
+	/// Reading or writing a node that was already opened failed, such as reading a node opened
+	/// write-only, as opposed to a scheme failing to open or look it up.
+	NodeIo(std::io::Error),
 }
 
 impl<'scheme_name> VfsError<'scheme_name> {
@@ -23,8 +39,25 @@ impl<'scheme_name> VfsError<'scheme_name> {
 			VfsError::SchemeWrongType(scheme_name, type_name) => {
 				VfsError::SchemeWrongType(Cow::Owned(scheme_name.into_owned()), type_name)
 			}
+			VfsError::SchemeShared(scheme_name) => {
+				VfsError::SchemeShared(Cow::Owned(scheme_name.into_owned()))
+			}
 			VfsError::UrlParseFailed(source) => VfsError::UrlParseFailed(source),
 			VfsError::SchemeError(source) => VfsError::SchemeError(source.into_owned()),
+			VfsError::DirectoryLoop(url) => VfsError::DirectoryLoop(url),
+			VfsError::AccessDenied(op, url) => VfsError::AccessDenied(op, url),
+			VfsError::TimedOut(url) => VfsError::TimedOut(url),
+			VfsError::PathTooLong(url) => VfsError::PathTooLong(url),
+			VfsError::NodeIo(source) => VfsError::NodeIo(source),
+		}
+	}
+
+	/// The IO error underneath this error, if it is one, whether from a node or a scheme.
+	pub fn io_error(&self) -> Option<&std::io::Error> {
+		match self {
+			VfsError::NodeIo(source) => Some(source),
+			VfsError::SchemeError(source) => source.io_error(),
+			_ => None,
 		}
 	}
 }
@@ -42,8 +75,20 @@ impl<'scheme_name> std::fmt::Display for VfsError<'scheme_name> {
 				"scheme `{}` cannot be cast to type: {}",
 				scheme_name, type_name
 			)),
+			VfsError::SchemeShared(scheme_name) => {
+				f.write_fmt(format_args!("scheme is shared: {}", scheme_name))
+			}
 			VfsError::UrlParseFailed(_source) => f.write_str("url failed to parse"),
 			VfsError::SchemeError(_source) => f.write_str("scheme error"),
+			VfsError::DirectoryLoop(url) => {
+				f.write_fmt(format_args!("directory loop detected at: {}", url))
+			}
+			VfsError::AccessDenied(op, url) => {
+				f.write_fmt(format_args!("access denied to {}: {}", op, url))
+			}
+			VfsError::TimedOut(url) => f.write_fmt(format_args!("timed out waiting for: {}", url)),
+			VfsError::PathTooLong(url) => f.write_fmt(format_args!("path too long: {}", url)),
+			VfsError::NodeIo(source) => f.write_fmt(format_args!("node IO error: {}", source)),
 		}
 	}
 }
@@ -54,8 +99,14 @@ impl<'scheme_name> std::error::Error for VfsError<'scheme_name> {
 			VfsError::SchemeAlreadyExists(_scheme_name) => None,
 			VfsError::SchemeNotFound(_scheme_name) => None,
 			VfsError::SchemeWrongType(_scheme_name, _type_name) => None,
+			VfsError::SchemeShared(_scheme_name) => None,
 			VfsError::UrlParseFailed(source) => Some(source),
 			VfsError::SchemeError(source) => Some(source),
+			VfsError::DirectoryLoop(_url) => None,
+			VfsError::AccessDenied(_op, _url) => None,
+			VfsError::TimedOut(_url) => None,
+			VfsError::PathTooLong(_url) => None,
+			VfsError::NodeIo(source) => Some(source),
 		}
 	}
 }
@@ -66,6 +117,12 @@ impl From<url::ParseError> for VfsError<'static> {
 	}
 }
 
+impl From<std::io::Error> for VfsError<'static> {
+	fn from(source: std::io::Error) -> Self {
+		VfsError::NodeIo(source)
+	}
+}
+
 impl<'name> From<SchemeError<'name>> for VfsError<'static> {
 	fn from(source: SchemeError<'name>) -> Self {
 		VfsError::SchemeError(source.into_owned())
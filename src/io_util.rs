@@ -0,0 +1,196 @@
+use futures_lite::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use std::io::ErrorKind;
+#[cfg(any(feature = "backend_tokio", feature = "backend_async_std"))]
+use std::time::Duration;
+
+const COPY_BUFFER_LEN: usize = 8 * 1024;
+
+/// Like `AsyncReadExt::read_to_end` but retries reads that fail with `ErrorKind::Interrupted`, as
+/// filesystem nodes return when a syscall is interrupted by a signal, instead of giving up.
+pub(crate) async fn read_to_end(
+	reader: &mut (impl AsyncRead + Unpin + ?Sized),
+	data: &mut Vec<u8>,
+) -> std::io::Result<usize> {
+	let start_len = data.len();
+	let mut buffer = [0; COPY_BUFFER_LEN];
+	loop {
+		match reader.read(&mut buffer).await {
+			Ok(0) => return Ok(data.len() - start_len),
+			Ok(amt) => data.extend_from_slice(&buffer[..amt]),
+			Err(error) if error.kind() == ErrorKind::Interrupted => continue,
+			Err(error) => return Err(error),
+		}
+	}
+}
+
+/// Like `AsyncWriteExt::write_all` but retries writes that fail with `ErrorKind::Interrupted`,
+/// see `read_to_end`.
+pub(crate) async fn write_all(
+	writer: &mut (impl AsyncWrite + Unpin + ?Sized),
+	mut data: &[u8],
+) -> std::io::Result<()> {
+	while !data.is_empty() {
+		match writer.write(data).await {
+			Ok(0) => return Err(ErrorKind::WriteZero.into()),
+			Ok(amt) => data = &data[amt..],
+			Err(error) if error.kind() == ErrorKind::Interrupted => continue,
+			Err(error) => return Err(error),
+		}
+	}
+	Ok(())
+}
+
+/// Like `futures_lite::io::copy` but retries reads and writes that fail with
+/// `ErrorKind::Interrupted`, see `read_to_end`.
+pub(crate) async fn copy(
+	reader: &mut (impl AsyncRead + Unpin + ?Sized),
+	writer: &mut (impl AsyncWrite + Unpin + ?Sized),
+) -> std::io::Result<u64> {
+	let mut copied = 0;
+	let mut buffer = [0; COPY_BUFFER_LEN];
+	loop {
+		let len = match reader.read(&mut buffer).await {
+			Ok(0) => return Ok(copied),
+			Ok(len) => len,
+			Err(error) if error.kind() == ErrorKind::Interrupted => continue,
+			Err(error) => return Err(error),
+		};
+		write_all(writer, &buffer[..len]).await?;
+		copied += len as u64;
+	}
+}
+
+/// Sleeps on the runtime of the enabled backend, tokio's when both are enabled.
+#[cfg(any(feature = "backend_tokio", feature = "backend_async_std"))]
+pub(crate) async fn sleep(duration: Duration) {
+	#[cfg(feature = "backend_tokio")]
+	tokio::time::sleep(duration).await;
+	#[cfg(not(feature = "backend_tokio"))]
+	async_std::task::sleep(duration).await;
+}
+
+#[cfg(test)]
+mod tests {
+	use futures_lite::future::block_on;
+	use futures_lite::{AsyncRead, AsyncWrite};
+	use std::io::ErrorKind;
+	use std::pin::Pin;
+	use std::task::{Context, Poll};
+
+	/// Fails every other call, `interruptions` times with `Interrupted` and then with `kind`.
+	struct FlakyNode {
+		data: Vec<u8>,
+		cursor: usize,
+		interruptions: usize,
+		kind: ErrorKind,
+		fail_next: bool,
+	}
+
+	impl FlakyNode {
+		fn new(data: &[u8], interruptions: usize, kind: ErrorKind) -> Self {
+			Self {
+				data: data.to_vec(),
+				cursor: 0,
+				interruptions,
+				kind,
+				fail_next: true,
+			}
+		}
+
+		fn fail(&mut self) -> Option<std::io::Error> {
+			self.fail_next = !self.fail_next;
+			if self.fail_next {
+				return None;
+			}
+			if self.interruptions == 0 {
+				return (self.kind != ErrorKind::Interrupted).then(|| self.kind.into());
+			}
+			self.interruptions -= 1;
+			Some(ErrorKind::Interrupted.into())
+		}
+	}
+
+	impl AsyncRead for FlakyNode {
+		fn poll_read(
+			mut self: Pin<&mut Self>,
+			_cx: &mut Context<'_>,
+			buf: &mut [u8],
+		) -> Poll<std::io::Result<usize>> {
+			if let Some(error) = self.fail() {
+				return Poll::Ready(Err(error));
+			}
+			// A few bytes at a time so the failures land in the middle
+			let amt = (self.data.len() - self.cursor).min(buf.len()).min(3);
+			buf[..amt].copy_from_slice(&self.data[self.cursor..self.cursor + amt]);
+			self.cursor += amt;
+			Poll::Ready(Ok(amt))
+		}
+	}
+
+	impl AsyncWrite for FlakyNode {
+		fn poll_write(
+			mut self: Pin<&mut Self>,
+			_cx: &mut Context<'_>,
+			buf: &[u8],
+		) -> Poll<std::io::Result<usize>> {
+			if let Some(error) = self.fail() {
+				return Poll::Ready(Err(error));
+			}
+			let amt = buf.len().min(3);
+			self.data.extend_from_slice(&buf[..amt]);
+			Poll::Ready(Ok(amt))
+		}
+
+		fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+			Poll::Ready(Ok(()))
+		}
+
+		fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+			Poll::Ready(Ok(()))
+		}
+	}
+
+	#[test]
+	fn read_to_end_retries_interrupted() {
+		let mut node = FlakyNode::new(b"interrupted content", 4, ErrorKind::Interrupted);
+		let mut data = Vec::new();
+		assert_eq!(
+			block_on(super::read_to_end(&mut node, &mut data)).unwrap(),
+			19
+		);
+		assert_eq!(data, b"interrupted content");
+		assert_eq!(node.interruptions, 0);
+	}
+
+	#[test]
+	fn read_to_end_propagates_other_errors() {
+		let mut node = FlakyNode::new(b"interrupted content", 2, ErrorKind::BrokenPipe);
+		let mut data = Vec::new();
+		let error = block_on(super::read_to_end(&mut node, &mut data)).unwrap_err();
+		assert_eq!(error.kind(), ErrorKind::BrokenPipe);
+	}
+
+	#[test]
+	fn write_all_retries_interrupted() {
+		let mut node = FlakyNode::new(b"", 4, ErrorKind::Interrupted);
+		block_on(super::write_all(&mut node, b"interrupted content")).unwrap();
+		assert_eq!(node.data, b"interrupted content");
+		assert_eq!(node.interruptions, 0);
+	}
+
+	#[test]
+	fn write_all_propagates_other_errors() {
+		let mut node = FlakyNode::new(b"", 2, ErrorKind::BrokenPipe);
+		let error = block_on(super::write_all(&mut node, b"interrupted content")).unwrap_err();
+		assert_eq!(error.kind(), ErrorKind::BrokenPipe);
+	}
+
+	#[test]
+	fn copy_retries_interrupted() {
+		let mut reader = FlakyNode::new(b"interrupted content", 4, ErrorKind::Interrupted);
+		let mut writer = FlakyNode::new(b"", 4, ErrorKind::Interrupted);
+		assert_eq!(block_on(super::copy(&mut reader, &mut writer)).unwrap(), 19);
+		assert_eq!(writer.data, b"interrupted content");
+		assert_eq!(writer.interruptions, 0);
+	}
+}
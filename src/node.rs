@@ -1,5 +1,10 @@
 use crate::as_any_cast;
+use crate::scheme::PinnedNode;
+use crate::SchemeError;
+use futures_lite::future::poll_fn;
 use futures_lite::{AsyncRead, AsyncSeek, AsyncWrite};
+use std::io::SeekFrom;
+use std::pin::Pin;
 use std::task::Poll;
 
 // TODO:  Should we go through the pain to make alloc-less async traits?
@@ -26,6 +31,41 @@ pub trait Node:
 	fn is_reader(&self) -> bool;
 	fn is_writer(&self) -> bool;
 	fn is_seeker(&self) -> bool;
+
+	/// The total length of the content when the node knows it without holding all of it, such as
+	/// a streaming node that was told the length when it was opened, so it can resolve
+	/// `SeekFrom::End` with `seek_position`.  `None` by default.
+	fn known_len(&self) -> Option<u64> {
+		None
+	}
+
+	/// Whether the cursor is at, or past, the end of the content, so the next read would return
+	/// EOF, without having to try one.  `None` by default, for nodes that cannot tell, such as
+	/// streams that only find their end by reading it.
+	fn is_at_end(&self) -> Option<bool> {
+		None
+	}
+
+	/// Flushes, then makes sure all data and metadata reached durable storage, like
+	/// `std::fs::File::sync_all`.  Nodes without durable storage only flush.
+	async fn sync_all(self: Pin<&mut Self>) -> std::io::Result<()> {
+		let mut this = self;
+		poll_fn(|cx| this.as_mut().poll_flush(cx)).await
+	}
+
+	/// Like `sync_all` but may skip metadata that is not needed to read the data back, like
+	/// `std::fs::File::sync_data`.  Nodes without durable storage only flush.
+	async fn sync_data(self: Pin<&mut Self>) -> std::io::Result<()> {
+		let mut this = self;
+		poll_fn(|cx| this.as_mut().poll_flush(cx)).await
+	}
+
+	/// Opens another handle on the same content with the same access, without going through the
+	/// scheme again.  The new handle has its own cursor, starting at the beginning.  Nodes that
+	/// cannot do so return `Unsupported`.
+	async fn try_clone(self: Pin<&mut Self>) -> Result<PinnedNode, SchemeError<'static>> {
+		Err(SchemeError::Unsupported("try_clone"))
+	}
 }
 
 impl dyn Node {
@@ -42,6 +82,21 @@ pub fn poll_io_err<T>() -> Poll<std::io::Result<T>> {
 	Poll::Ready(Err(std::io::Error::from_raw_os_error(13)))
 }
 
+/// Resolves `pos` to an absolute offset for a node whose cursor is at `cursor` and whose length,
+/// if known, is `len`, for streaming nodes that fetch from an offset instead of seeking within data
+/// they hold.  Offsets are clamped to the content like the in-memory nodes do, and `SeekFrom::End`
+/// fails with `ErrorKind::Unsupported` when the length is unknown.
+pub fn seek_position(pos: SeekFrom, cursor: u64, len: Option<u64>) -> std::io::Result<u64> {
+	let position = match pos {
+		SeekFrom::Start(offset) => offset,
+		SeekFrom::Current(offset) => cursor.saturating_add_signed(offset),
+		SeekFrom::End(offset) => len
+			.ok_or(std::io::ErrorKind::Unsupported)?
+			.saturating_add_signed(offset),
+	};
+	Ok(len.map_or(position, |len| position.min(len)))
+}
+
 pub trait IsAllowed: Sized {
 	fn allowed(self) -> bool;
 
@@ -86,3 +141,139 @@ impl IsAllowed for bool {
 		self
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::{poll_io_err, seek_position, Node};
+	use futures_lite::future::block_on;
+	use futures_lite::{AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt, AsyncWrite};
+	use std::io::{ErrorKind, SeekFrom};
+	use std::pin::Pin;
+	use std::task::{Context, Poll};
+
+	/// Stands in for a remote node that is fetched with ranged requests from its cursor on.
+	struct StreamingNode {
+		remote: &'static [u8],
+		len: Option<u64>,
+		cursor: u64,
+		requested: Vec<u64>,
+	}
+
+	impl Node for StreamingNode {
+		fn is_reader(&self) -> bool {
+			true
+		}
+
+		fn is_writer(&self) -> bool {
+			false
+		}
+
+		fn is_seeker(&self) -> bool {
+			true
+		}
+
+		fn known_len(&self) -> Option<u64> {
+			self.len
+		}
+	}
+
+	impl AsyncRead for StreamingNode {
+		fn poll_read(
+			mut self: Pin<&mut Self>,
+			_cx: &mut Context<'_>,
+			buf: &mut [u8],
+		) -> Poll<std::io::Result<usize>> {
+			let start = (self.cursor as usize).min(self.remote.len());
+			self.requested.push(start as u64);
+			let amt = (self.remote.len() - start).min(buf.len());
+			buf[..amt].copy_from_slice(&self.remote[start..start + amt]);
+			self.cursor += amt as u64;
+			Poll::Ready(Ok(amt))
+		}
+	}
+
+	impl AsyncWrite for StreamingNode {
+		fn poll_write(
+			self: Pin<&mut Self>,
+			_cx: &mut Context<'_>,
+			_buf: &[u8],
+		) -> Poll<std::io::Result<usize>> {
+			poll_io_err()
+		}
+
+		fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+			poll_io_err()
+		}
+
+		fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+			poll_io_err()
+		}
+	}
+
+	impl AsyncSeek for StreamingNode {
+		fn poll_seek(
+			mut self: Pin<&mut Self>,
+			_cx: &mut Context<'_>,
+			pos: SeekFrom,
+		) -> Poll<std::io::Result<u64>> {
+			let known_len = self.known_len();
+			let position = seek_position(pos, self.cursor, known_len)?;
+			self.cursor = position;
+			Poll::Ready(Ok(position))
+		}
+	}
+
+	fn streaming_node(len: Option<u64>) -> StreamingNode {
+		StreamingNode {
+			remote: b"a streamed node of 32 bytes long",
+			len,
+			cursor: 0,
+			requested: Vec::new(),
+		}
+	}
+
+	#[test]
+	fn seek_from_end_with_known_len() {
+		let mut node = streaming_node(Some(32));
+		assert_eq!(block_on(node.seek(SeekFrom::End(-10))).unwrap(), 22);
+		let mut tail = String::new();
+		block_on(node.read_to_string(&mut tail)).unwrap();
+		assert_eq!(tail, "bytes long");
+		assert_eq!(node.requested[0], 22, "only the tail was requested");
+		assert_eq!(block_on(node.seek(SeekFrom::End(-100))).unwrap(), 0);
+		assert_eq!(block_on(node.seek(SeekFrom::Current(100))).unwrap(), 32);
+	}
+
+	#[test]
+	fn seek_from_end_with_unknown_len() {
+		let mut node = streaming_node(None);
+		let error = block_on(node.seek(SeekFrom::End(-10))).unwrap_err();
+		assert_eq!(error.kind(), ErrorKind::Unsupported);
+		assert_eq!(block_on(node.seek(SeekFrom::Start(22))).unwrap(), 22);
+	}
+
+	#[test]
+	fn range_hint_opens_ranged() {
+		use crate::scheme::NodeGetOptions;
+		use crate::{FnScheme, Vfs};
+		// Stands in for a network scheme that applies the range to its first request
+		let scheme = FnScheme::new().on_get_node(|_vfs, _url, options| {
+			let mut node = streaming_node(Some(32));
+			node.cursor = options.get_range().map_or(0, |(start, _end)| start);
+			Box::pin(async move { Ok(Box::pin(node) as super::PinnedNode) })
+		});
+		let vfs = Vfs::empty();
+		vfs.add_scheme("remote", scheme).unwrap();
+		let options = NodeGetOptions::new().read(true).range(Some((22, None)));
+		assert_eq!(options.get_range(), Some((22, None)));
+		block_on(async {
+			let mut node = vfs.get_node_at("remote:/node", &options).await.unwrap();
+			assert_eq!(node.seek(SeekFrom::Current(0)).await.unwrap(), 22);
+			let mut tail = String::new();
+			node.read_to_string(&mut tail).await.unwrap();
+			assert_eq!(tail, "bytes long");
+			let node = node.downcast_ref::<StreamingNode>().unwrap();
+			assert_eq!(node.requested[0], 22, "no request before the range");
+		});
+	}
+}
@@ -1,20 +1,86 @@
-use crate::{as_any_cast, Node, SchemeError, Vfs};
-use futures_lite::Stream;
+use crate::{as_any_cast, io_util, Node, SchemeError, Vfs};
+use futures_lite::{AsyncWriteExt, Stream};
+use std::borrow::Cow;
 use std::pin::Pin;
+use std::time::SystemTime;
 use url::Url;
 
-#[derive(Debug, Clone)]
+/// What kind of entry a url names, see `NodeMetadata::kind`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NodeKind {
+	File,
+	Directory,
+	/// A link to another entry that was not followed.
+	Symlink,
+	/// Anything else, such as a device or socket on a filesystem.
+	Other,
+}
+
+/// What a scheme knows about a url.  Everything beyond `is_node` is optional and left `None` by
+/// schemes that cannot tell, so build it with `..Default::default()` to only fill in what is known.
+#[derive(Debug, Clone, Default)]
 pub struct NodeMetadata {
 	/// If this is true then `get_node` should usually return a Node for this URL, else not, like if
 	/// it is a directory for example.
 	pub is_node: bool,
 	/// The length of the data if it is knowable, shortest possible to longest possible if knowable.
 	pub len: Option<(usize, Option<usize>)>,
+	/// When the node was last modified, if the scheme tracks it.
+	pub modified: Option<SystemTime>,
+	/// Whether the url names a file, a directory or something else, `None` if the scheme cannot
+	/// tell, such as one that only knows a node exists because opening it worked.
+	pub kind: Option<NodeKind>,
+	/// When the node was created, `None` if the scheme does not record it or the platform does
+	/// not report it, as some filesystems do not.
+	pub created: Option<SystemTime>,
+	/// When the node was last read, `None` if the scheme does not track reads.  Filesystems
+	/// mounted with `noatime` report it but do not keep it up to date.
+	pub accessed: Option<SystemTime>,
+	/// Whether the node cannot be opened for writing regardless of who asks, such as a read-only
+	/// file or a node of a read-only scheme.
+	pub read_only: Option<bool>,
 }
 
 #[derive(Debug, Clone)]
 pub struct NodeEntry {
 	pub url: Url,
+	/// The metadata of the entry if the scheme learned it while listing, so it is not looked up
+	/// again, `None` doesn't mean anything more than that `Vfs::metadata` has to be asked.
+	pub metadata: Option<NodeMetadata>,
+}
+
+impl NodeEntry {
+	pub fn new(url: Url) -> Self {
+		Self {
+			url,
+			metadata: None,
+		}
+	}
+
+	pub fn with_metadata(self, metadata: NodeMetadata) -> Self {
+		Self {
+			metadata: Some(metadata),
+			..self
+		}
+	}
+
+	/// The entry at `segments` below the directory `dir`, with or without its trailing `/`.  Each
+	/// segment is a raw name that is percent-encoded as needed, so a `/` or `?` in a name stays part
+	/// of it, and schemes should build their entries with this so they all encode names the same.
+	/// `None` if `dir` cannot have a path, such as a `data:` url.
+	pub fn child<S: AsRef<str>>(dir: &Url, segments: impl IntoIterator<Item = S>) -> Option<Self> {
+		let mut url = dir.clone();
+		{
+			let mut path = url.path_segments_mut().ok()?;
+			path.pop_if_empty();
+			for segment in segments {
+				path.push(segment.as_ref());
+			}
+		}
+		url.set_query(None);
+		url.set_fragment(None);
+		Some(NodeEntry::new(url))
+	}
 }
 
 // copied from futures-core because futures-lite doesn't re-export it and there's no point not to
@@ -22,8 +88,34 @@ pub struct NodeEntry {
 // return a read_dir
 pub type ReadDirStream = Pin<Box<dyn Stream<Item = NodeEntry> + Send + 'static>>;
 
-/// This is modeled after `std::fs::OpenOptions`, same definitions for the options.
-#[derive(Clone, Debug, Default)]
+/// A `ReadDirStream` that still borrows from something, such as the `Vfs` it queries metadata from.
+pub type BorrowedReadDirStream<'s> = Pin<Box<dyn Stream<Item = NodeEntry> + Send + 's>>;
+
+/// What happened to a watched url, see `Scheme::watch`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WatchEventKind {
+	Created,
+	/// The content or metadata changed, a node being written to can report this several times.
+	Modified,
+	Removed,
+}
+
+#[derive(Debug, Clone)]
+pub struct WatchEvent {
+	/// The url that changed, the watched url itself or anything below it.
+	pub url: Url,
+	pub kind: WatchEventKind,
+}
+
+/// The changes under a watched url, the watch ends when this is dropped.
+pub type WatchStream = Pin<Box<dyn Stream<Item = WatchEvent> + Send + 'static>>;
+
+/// This is modeled after `std::fs::OpenOptions`, same definitions for the options, plus
+/// `create_parents` for schemes that have directories.
+///
+/// Equality and hashing cover every option as each one changes what opening a node does, so it
+/// can be used with the `Url` as a cache key.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct NodeGetOptions {
 	read: bool,
 	write: bool,
@@ -31,6 +123,27 @@ pub struct NodeGetOptions {
 	truncate: bool,
 	create: bool,
 	create_new: bool,
+	create_parents: bool,
+	snapshot: bool,
+	accept_encoding: Option<String>,
+	range: Option<(u64, Option<u64>)>,
+}
+
+impl Default for NodeGetOptions {
+	fn default() -> Self {
+		Self {
+			read: false,
+			write: false,
+			append: false,
+			truncate: false,
+			create: false,
+			create_new: false,
+			create_parents: true,
+			snapshot: false,
+			accept_encoding: None,
+			range: None,
+		}
+	}
 }
 
 impl NodeGetOptions {
@@ -62,6 +175,22 @@ impl NodeGetOptions {
 		self.create_new
 	}
 
+	pub fn get_create_parents(&self) -> bool {
+		self.create_parents
+	}
+
+	pub fn get_snapshot(&self) -> bool {
+		self.snapshot
+	}
+
+	pub fn get_accept_encoding(&self) -> Option<&str> {
+		self.accept_encoding.as_deref()
+	}
+
+	pub fn get_range(&self) -> Option<(u64, Option<u64>)> {
+		self.range
+	}
+
 	pub fn read(self, read: bool) -> Self {
 		Self { read, ..self }
 	}
@@ -105,6 +234,62 @@ impl NodeGetOptions {
 			..self
 		}
 	}
+
+	/// Whether creating a node also creates any missing parent directories, on by default.  When
+	/// off the parent must already exist or creating fails with `NodeDoesNotExist` for the parent.
+	pub fn create_parents(self, create_parents: bool) -> Self {
+		Self {
+			create_parents,
+			..self
+		}
+	}
+
+	/// Whether a node opened without `write` reads from a copy of the content taken when it is
+	/// opened, so it never sees a write made through another node in between two of its reads.
+	/// Off by default.  Schemes whose nodes cannot change underneath a reader ignore it, the memory
+	/// scheme copies the whole node on open so it costs the node's length in memory per reader.
+	pub fn snapshot(self, snapshot: bool) -> Self {
+		Self { snapshot, ..self }
+	}
+
+	/// Advisory, the encodings a network scheme may ask the remote to transfer the node in, in the
+	/// form of an HTTP `Accept-Encoding` value such as `"gzip, br"`.  Content is still read decoded
+	/// from the node, it only allows a compressed transfer.  Local schemes ignore it.
+	pub fn accept_encoding(self, accept_encoding: Option<String>) -> Self {
+		Self {
+			accept_encoding,
+			..self
+		}
+	}
+
+	/// Advisory, the bytes from `start` up to the exclusive `end`, or to the end of the node, that
+	/// the caller is going to read, so a network scheme can fetch only those rather than opening at
+	/// the start and seeking.  A scheme that uses it opens the node with its cursor at `start`,
+	/// content offsets are unchanged, so `seek(SeekFrom::Current(0))` tells if it was applied.
+	/// Local schemes ignore it and open at the start as usual.
+	pub fn range(self, range: Option<(u64, Option<u64>)>) -> Self {
+		Self { range, ..self }
+	}
+
+	/// Fails for options that contradict each other, like `std::fs::OpenOptions::open` does and
+	/// with the same `ErrorKind::InvalidInput` IO error: `truncate` along with `append`, or
+	/// `truncate`, `create` or `create_new` with nothing to write, which the builders only lead to
+	/// when `write(false)` comes after them.  `Vfs` checks this before any scheme sees the options.
+	pub fn validate(&self) -> Result<(), SchemeError<'static>> {
+		let message = if self.truncate && self.append {
+			"truncate and append contradict each other"
+		} else if self.truncate && !self.write {
+			"truncate without write"
+		} else if (self.create || self.create_new) && !self.write && !self.append {
+			"create without write or append"
+		} else {
+			return Ok(());
+		};
+		Err(SchemeError::IOError(std::io::Error::new(
+			std::io::ErrorKind::InvalidInput,
+			message,
+		)))
+	}
 }
 
 impl From<NodeGetOptions> for std::fs::OpenOptions {
@@ -170,11 +355,175 @@ pub trait Scheme: as_any_cast::AsAnyCast + Sync + 'static {
 		force: bool,
 	) -> Result<(), SchemeError<'a>>;
 	async fn metadata<'a>(&self, vfs: &Vfs, url: &'a Url) -> Result<NodeMetadata, SchemeError<'a>>;
+	/// The metadata of each of `urls`, in the same order, for schemes that can look up many at once
+	/// cheaper than one at a time, such as with a single batched request.  A url that fails only
+	/// fails its own result.  The default calls `metadata` for each url in turn.
+	async fn metadata_many(
+		&self,
+		vfs: &Vfs,
+		urls: &[Url],
+	) -> Vec<Result<NodeMetadata, SchemeError<'static>>> {
+		let mut results = Vec::with_capacity(urls.len());
+		for url in urls {
+			results.push(
+				self.metadata(vfs, url)
+					.await
+					.map_err(SchemeError::into_owned),
+			);
+		}
+		results
+	}
 	/// List a set of nodes related to a given `url`.  Note, depending on the backend this can and
 	/// will include duplicates, recursive paths, directories that aren't actually nodes,, etc...
 	/// It's your job to figure out what you want.
-	async fn read_dir<'a>(&self, vfs: &Vfs, url: &'a Url)
-		-> Result<ReadDirStream, SchemeError<'a>>;
+	/// A `url` without a trailing `/` names the directory itself rather than its parent, and one
+	/// that is a node fails with `SchemeError::NotADirectory`.
+	/// The stream may keep borrowing the scheme and the `vfs`, such as to open further listings
+	/// lazily.  It must be fused, returning `None` again if polled after it ended, so combinators
+	/// can poll it without tracking that themselves.
+	async fn read_dir<'s, 'a>(
+		&'s self,
+		vfs: &'s Vfs,
+		url: &'a Url,
+	) -> Result<BorrowedReadDirStream<'s>, SchemeError<'a>>;
+	/// Read a whole node in a single operation if the scheme has a cheaper way to do so than
+	/// opening and streaming a node, as long as it is no longer than `max_len`.  Returns `None` if
+	/// the scheme has no such fast path or the node is too long, the caller should then fall back
+	/// to `get_node`.
+	async fn read_small_file<'a>(
+		&self,
+		_vfs: &Vfs,
+		_url: &'a Url,
+		_max_len: usize,
+	) -> Result<Option<Vec<u8>>, SchemeError<'a>> {
+		Ok(None)
+	}
+	/// Read a whole node as `Bytes` that share the scheme's own buffer of it, such as a node that
+	/// is compiled into the binary.  Returns `None` if the scheme keeps no such buffer, the caller
+	/// should then fall back to reading the node.
+	#[cfg(feature = "bytes")]
+	async fn read_bytes<'a>(
+		&self,
+		_vfs: &Vfs,
+		_url: &'a Url,
+	) -> Result<Option<bytes::Bytes>, SchemeError<'a>> {
+		Ok(None)
+	}
+	/// Get a node along with its metadata.  The default asks for the metadata and then opens the
+	/// node, schemes that have to decode or load a node to know its metadata should override this
+	/// to do that work once.
+	async fn stat_and_open<'a>(
+		&self,
+		vfs: &Vfs,
+		url: &'a Url,
+		options: &NodeGetOptions,
+	) -> Result<(NodeMetadata, PinnedNode), SchemeError<'a>> {
+		let metadata = self.metadata(vfs, url).await?;
+		let node = self.get_node(vfs, url, options).await?;
+		Ok((metadata, node))
+	}
+	/// Get a node when the caller already has its `metadata`, such as from an earlier `metadata`
+	/// call, so schemes that would look it up to open the node can skip that.  The metadata may
+	/// be stale, a scheme relying on it must still fail cleanly if the node changed since.  The
+	/// default ignores it and calls `get_node`.
+	async fn get_node_with_metadata<'a>(
+		&self,
+		vfs: &Vfs,
+		url: &'a Url,
+		options: &NodeGetOptions,
+		_metadata: &NodeMetadata,
+	) -> Result<PinnedNode, SchemeError<'a>> {
+		self.get_node(vfs, url, options).await
+	}
+	/// Resolve `url` to the url that actually backs it, such as the destination of a symlink, so
+	/// recursive operations can tell when they revisit a directory.  Most schemes back their own
+	/// urls so the default returns `url` as-is.
+	async fn canonicalize<'a>(&self, _vfs: &Vfs, url: &'a Url) -> Result<Url, SchemeError<'a>> {
+		Ok(url.clone())
+	}
+	/// Set when the node at `url` was last modified, such as to carry it over when moving a node
+	/// between schemes.  Schemes that do not track or cannot change it return `Unsupported`.
+	async fn set_modified<'a>(
+		&self,
+		_vfs: &Vfs,
+		_url: &'a Url,
+		_modified: SystemTime,
+	) -> Result<(), SchemeError<'a>> {
+		Err(SchemeError::Unsupported("set_modified"))
+	}
+	/// Create an empty directory at `url`, failing if its parent does not exist or something is
+	/// already there.  With `parents` any missing directories above it are created as well and an
+	/// already existing directory is not an error, like `create_dir_all`.
+	async fn create_dir<'a>(
+		&self,
+		_vfs: &Vfs,
+		_url: &'a Url,
+		_parents: bool,
+	) -> Result<(), SchemeError<'a>> {
+		Err(SchemeError::Unsupported("create_dir"))
+	}
+	/// Copy the node at `from` to `to`, both urls of this scheme, replacing anything already at
+	/// `to`.  `Vfs::copy_node` tries this first when both urls are of the same scheme, such as to
+	/// let a filesystem copy without passing the content through the vfs, and streams the node over
+	/// itself if this returns `Unsupported`.
+	async fn copy_node<'a>(
+		&self,
+		_vfs: &Vfs,
+		_from: &'a Url,
+		_to: &'a Url,
+	) -> Result<(), SchemeError<'a>> {
+		Err(SchemeError::Unsupported("copy_node"))
+	}
+	/// Move the node at `from` to `to`, both urls of this scheme, replacing anything already at
+	/// `to` and keeping its modified time where `set_modified` allows, see `Vfs::rename_node`.  The
+	/// default copies the content over and then removes `from`, schemes that can rename in place,
+	/// atomically or at least without copying, like filesystems, should override it.
+	async fn rename_node<'a>(
+		&self,
+		vfs: &Vfs,
+		from: &'a Url,
+		to: &'a Url,
+	) -> Result<(), SchemeError<'a>> {
+		let metadata = self.metadata(vfs, from).await?;
+		if !metadata.is_node {
+			return Err(SchemeError::IsADirectory(Cow::Borrowed(from.path())));
+		}
+		let mut reader = self
+			.get_node(vfs, from, &NodeGetOptions::new().read(true))
+			.await?;
+		let mut writer = self
+			.get_node(vfs, to, &NodeGetOptions::new().create(true).truncate(true))
+			.await?;
+		io_util::copy(&mut reader, &mut writer).await?;
+		writer.close().await?;
+		drop(writer);
+		drop(reader);
+		if let Some(modified) = metadata.modified {
+			match self.set_modified(vfs, to, modified).await {
+				Ok(()) | Err(SchemeError::Unsupported(_)) => (),
+				Err(error) => return Err(error),
+			}
+		}
+		self.remove_node(vfs, from, false).await
+	}
+	/// Open a node as two halves with independent cursors over the same content, a read-only half
+	/// starting at the beginning and a write-only half opened with `options`, so one can tail what
+	/// the other writes.  Schemes that cannot share content between two cursors return
+	/// `Unsupported`.
+	async fn get_node_split<'a>(
+		&self,
+		_vfs: &Vfs,
+		_url: &'a Url,
+		_options: &NodeGetOptions,
+	) -> Result<(PinnedNode, PinnedNode), SchemeError<'a>> {
+		Err(SchemeError::Unsupported("get_node_split"))
+	}
+	/// Watch `url`, along with everything below it if it is a directory, for changes, such as to
+	/// hot-reload assets.  Events are only of changes made after this returns, and schemes may
+	/// coalesce or drop some under heavy churn, so treat them as hints to look again.
+	async fn watch<'a>(&self, _vfs: &Vfs, _url: &'a Url) -> Result<WatchStream, SchemeError<'a>> {
+		Err(SchemeError::Unsupported("watch"))
+	}
 }
 
 impl dyn Scheme {
@@ -193,7 +542,29 @@ pub(crate) mod tests {
 
 	#[test]
 	fn node_access() {
-		let mut vfs = Vfs::empty_with_capacity(10);
+		let vfs = Vfs::empty_with_capacity(10);
 		vfs.add_default_schemes().unwrap();
 	}
+
+	#[test]
+	fn node_get_options_as_key() {
+		use crate::scheme::NodeGetOptions;
+		use std::collections::HashMap;
+		let mut cache = HashMap::new();
+		let read = NodeGetOptions::new().read(true);
+		cache.insert((Url::parse("data:,a").unwrap(), read.clone()), 1);
+		cache.insert(
+			(Url::parse("data:,a").unwrap(), read.clone().write(true)),
+			2,
+		);
+		assert_eq!(cache.get(&(Url::parse("data:,a").unwrap(), read)), Some(&1));
+		assert_eq!(
+			cache.get(&(
+				Url::parse("data:,a").unwrap(),
+				NodeGetOptions::new().write(true).read(true)
+			)),
+			Some(&2)
+		);
+		assert!(!cache.contains_key(&(Url::parse("data:,a").unwrap(), NodeGetOptions::new())));
+	}
 }
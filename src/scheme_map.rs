@@ -0,0 +1,98 @@
+use crate::Scheme;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+
+/// The schemes of a `Vfs` by name, shared by all of its clones, so schemes can be added and
+/// removed through a `&Vfs`.  Lookups hand out their own `Arc` of a scheme so it stays alive for
+/// as long as it is used even if it is removed meanwhile.  The lock is never held across an
+/// `await`, only for the lookup or change itself.
+pub(crate) struct SchemeMap {
+	schemes: RwLock<HashMap<String, Arc<dyn Scheme>>>,
+	/// Bumped on every change while the write lock is held, so a `DispatchCache` can tell if the
+	/// scheme it remembers may be stale.
+	generation: AtomicUsize,
+}
+
+impl SchemeMap {
+	pub(crate) fn with_capacity(capacity: usize) -> Self {
+		Self::from_map(HashMap::with_capacity(capacity))
+	}
+
+	fn from_map(schemes: HashMap<String, Arc<dyn Scheme>>) -> Self {
+		Self {
+			schemes: RwLock::new(schemes),
+			generation: AtomicUsize::new(0),
+		}
+	}
+
+	/// A separate map holding the same schemes, changes to either are not seen by the other.
+	pub(crate) fn snapshot(&self) -> Self {
+		Self::from_map(self.schemes.read().expect("poisoned lock").clone())
+	}
+
+	pub(crate) fn generation(&self) -> usize {
+		self.generation.load(Ordering::Acquire)
+	}
+
+	/// The scheme named `scheme_name` along with the generation it was found in.
+	pub(crate) fn get(&self, scheme_name: &str) -> Option<(Arc<dyn Scheme>, usize)> {
+		let schemes = self.schemes.read().expect("poisoned lock");
+		let scheme = schemes.get(scheme_name)?.clone();
+		Some((scheme, self.generation()))
+	}
+
+	/// The scheme named `scheme_name` if it is not shared, such as by a clone of its `Vfs` or by
+	/// an operation still using it.
+	pub(crate) fn get_mut(&mut self, scheme_name: &str) -> Option<&mut dyn Scheme> {
+		let schemes = self.schemes.get_mut().expect("poisoned lock");
+		Arc::get_mut(schemes.get_mut(scheme_name)?)
+	}
+
+	pub(crate) fn contains(&self, scheme_name: &str) -> bool {
+		self.schemes
+			.read()
+			.expect("poisoned lock")
+			.contains_key(scheme_name)
+	}
+
+	pub(crate) fn entries(&self) -> Vec<(String, Arc<dyn Scheme>)> {
+		self.schemes
+			.read()
+			.expect("poisoned lock")
+			.iter()
+			.map(|(name, scheme)| (name.clone(), scheme.clone()))
+			.collect()
+	}
+
+	/// Adds `scheme` unless there already is one named `scheme_name`, failing with that name.
+	pub(crate) fn add(&self, scheme_name: String, scheme: Arc<dyn Scheme>) -> Result<(), String> {
+		self.change(|schemes| match schemes.entry(scheme_name) {
+			Entry::Occupied(entry) => Err(entry.key().clone()),
+			Entry::Vacant(entry) => {
+				entry.insert(scheme);
+				Ok(())
+			}
+		})
+	}
+
+	pub(crate) fn replace(
+		&self,
+		scheme_name: String,
+		scheme: Arc<dyn Scheme>,
+	) -> Option<Arc<dyn Scheme>> {
+		self.change(|schemes| schemes.insert(scheme_name, scheme))
+	}
+
+	pub(crate) fn remove(&self, scheme_name: &str) -> Option<Arc<dyn Scheme>> {
+		self.change(|schemes| schemes.remove(scheme_name))
+	}
+
+	fn change<R>(&self, f: impl FnOnce(&mut HashMap<String, Arc<dyn Scheme>>) -> R) -> R {
+		let mut schemes = self.schemes.write().expect("poisoned lock");
+		let result = f(&mut schemes);
+		self.generation.fetch_add(1, Ordering::Release);
+		result
+	}
+}
@@ -0,0 +1,340 @@
+use crate::node::poll_io_err;
+use crate::scheme::{BorrowedReadDirStream, NodeEntry, NodeGetOptions, NodeMetadata};
+use crate::{Node, PinnedNode, Scheme, SchemeError, Vfs};
+use futures_lite::{AsyncRead, AsyncSeek, AsyncWrite};
+use std::borrow::Cow;
+use std::collections::BTreeSet;
+use std::io::SeekFrom;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use url::Url;
+
+/// A container format, such as an archive or a game's pak file, that `AssetContainerScheme` serves
+/// as read-only nodes.  Entry names are paths relative to the root of the container separated by
+/// `/`, without a leading `/`.
+pub trait ContainerBackend: Send + Sync + 'static {
+	/// The names of every entry in the container.  Names ending in `/` are directories, which only
+	/// need to be listed if they are empty since the directories of the other entries are implied.
+	fn list(&self) -> Vec<String>;
+	/// The length of the file entry `name` once opened, `None` if there is no such file.
+	fn len(&self, name: &str) -> Option<usize>;
+	/// Read out the whole content of the file entry `name`, decompressing it if need be.
+	fn open_entry(&self, name: &str) -> Result<Vec<u8>, SchemeError<'static>>;
+}
+
+/// The parent directory of a container path, `""` being the root.
+fn parent(path: &str) -> &str {
+	path.rfind('/').map_or("", |pos| &path[..pos])
+}
+
+/// Adapts a `ContainerBackend` to a read-only scheme, so a new container format only needs to
+/// implement the backend.  The entries are indexed once on construction, an entry's content is
+/// only read out of the backend when it is opened.
+pub struct AssetContainerScheme<B: ContainerBackend + ?Sized = dyn ContainerBackend> {
+	backend: Box<B>,
+	files: BTreeSet<String>,
+	dirs: BTreeSet<String>,
+}
+
+impl<B: ContainerBackend> AssetContainerScheme<B> {
+	pub fn new(backend: B) -> Self {
+		Self::from_boxed(Box::new(backend))
+	}
+}
+
+impl<B: ContainerBackend + ?Sized> AssetContainerScheme<B> {
+	pub fn from_boxed(backend: Box<B>) -> Self {
+		let mut files = BTreeSet::new();
+		let mut dirs = BTreeSet::new();
+		dirs.insert(String::new());
+		for name in backend.list() {
+			let name = name.trim_start_matches('/');
+			let mut dir = if let Some(dir) = name.strip_suffix('/') {
+				dir
+			} else {
+				files.insert(name.to_owned());
+				parent(name)
+			};
+			// Containers do not need to have entries for every directory, so add any that are implied
+			while !dir.is_empty() && dirs.insert(dir.to_owned()) {
+				dir = parent(dir);
+			}
+		}
+		Self {
+			backend,
+			files,
+			dirs,
+		}
+	}
+
+	pub fn backend(&self) -> &B {
+		&self.backend
+	}
+
+	fn container_path(url: &Url) -> &str {
+		url.path().trim_start_matches('/')
+	}
+}
+
+#[async_trait::async_trait]
+impl<B: ContainerBackend + ?Sized> Scheme for AssetContainerScheme<B> {
+	async fn get_node<'a>(
+		&self,
+		_vfs: &Vfs,
+		url: &'a Url,
+		options: &NodeGetOptions,
+	) -> Result<PinnedNode, SchemeError<'a>> {
+		if !options.get_read() || options.get_write() {
+			return Err(SchemeError::UrlAccessError(Cow::Borrowed(url)));
+		}
+		let path = Self::container_path(url);
+		if self.files.contains(path) {
+			let data = self.backend.open_entry(path)?;
+			Ok(Box::pin(AssetContainerNode { data, cursor: 0 }))
+		} else if self.dirs.contains(path.trim_end_matches('/')) {
+			Err(SchemeError::IsADirectory(Cow::Borrowed(url.path())))
+		} else {
+			Err(SchemeError::NodeDoesNotExist(Cow::Borrowed(url.path())))
+		}
+	}
+
+	async fn remove_node<'a>(
+		&self,
+		_vfs: &Vfs,
+		url: &'a Url,
+		_force: bool,
+	) -> Result<(), SchemeError<'a>> {
+		Err(SchemeError::UrlAccessError(Cow::Borrowed(url)))
+	}
+
+	async fn metadata<'a>(
+		&self,
+		_vfs: &Vfs,
+		url: &'a Url,
+	) -> Result<NodeMetadata, SchemeError<'a>> {
+		let path = Self::container_path(url);
+		if self.files.contains(path) {
+			Ok(NodeMetadata {
+				is_node: true,
+				len: self.backend.len(path).map(|len| (len, Some(len))),
+				modified: None,
+				..Default::default()
+			})
+		} else if self.dirs.contains(path.trim_end_matches('/')) {
+			Ok(NodeMetadata {
+				is_node: false,
+				len: None,
+				modified: None,
+				..Default::default()
+			})
+		} else {
+			Err(SchemeError::NodeDoesNotExist(Cow::Borrowed(url.path())))
+		}
+	}
+
+	async fn read_dir<'s, 'a>(
+		&'s self,
+		_vfs: &'s Vfs,
+		url: &'a Url,
+	) -> Result<BorrowedReadDirStream<'s>, SchemeError<'a>> {
+		if self.files.contains(Self::container_path(url)) {
+			return Err(SchemeError::NotADirectory(Cow::Borrowed(url.path())));
+		}
+		let dir = Self::container_path(url).trim_end_matches('/');
+		if !self.dirs.contains(dir) {
+			return Err(SchemeError::NodeDoesNotExist(Cow::Borrowed(url.path())));
+		}
+		let mut children: Vec<&str> = self
+			.files
+			.iter()
+			.chain(self.dirs.iter())
+			.map(String::as_str)
+			.filter(|path| !path.is_empty() && parent(path) == dir)
+			.collect();
+		children.sort_unstable();
+		let entries: Vec<NodeEntry> = children
+			.into_iter()
+			.map(|path| {
+				let mut url = url.clone();
+				url.set_path(&format!("/{}", path));
+				NodeEntry::new(url)
+			})
+			.collect();
+		Ok(Box::pin(futures_lite::stream::iter(entries)))
+	}
+}
+
+pub struct AssetContainerNode {
+	data: Vec<u8>,
+	cursor: usize,
+}
+
+#[async_trait::async_trait]
+impl Node for AssetContainerNode {
+	fn is_reader(&self) -> bool {
+		true
+	}
+
+	fn is_writer(&self) -> bool {
+		false
+	}
+
+	fn is_seeker(&self) -> bool {
+		true
+	}
+
+	fn is_at_end(&self) -> Option<bool> {
+		Some(self.cursor >= self.data.len())
+	}
+}
+
+impl AsyncRead for AssetContainerNode {
+	fn poll_read(
+		mut self: Pin<&mut Self>,
+		_cx: &mut Context<'_>,
+		buf: &mut [u8],
+	) -> Poll<std::io::Result<usize>> {
+		if self.cursor >= self.data.len() {
+			return Poll::Ready(Ok(0));
+		}
+
+		let amt = std::cmp::min(self.data.len() - self.cursor, buf.len());
+		buf[..amt].copy_from_slice(&self.data[self.cursor..(self.cursor + amt)]);
+		self.cursor += amt;
+
+		Poll::Ready(Ok(amt))
+	}
+}
+
+impl AsyncWrite for AssetContainerNode {
+	fn poll_write(
+		self: Pin<&mut Self>,
+		_cx: &mut Context<'_>,
+		_buf: &[u8],
+	) -> Poll<std::io::Result<usize>> {
+		poll_io_err()
+	}
+
+	fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+		poll_io_err()
+	}
+
+	fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+		poll_io_err()
+	}
+}
+
+impl AsyncSeek for AssetContainerNode {
+	fn poll_seek(
+		mut self: Pin<&mut Self>,
+		_cx: &mut Context<'_>,
+		pos: SeekFrom,
+	) -> Poll<std::io::Result<u64>> {
+		match pos {
+			SeekFrom::Start(pos) => {
+				if pos > self.data.len() as u64 {
+					self.cursor = self.data.len();
+				} else {
+					self.cursor = pos as usize;
+				}
+			}
+			SeekFrom::End(end_pos) => {
+				if end_pos > 0 {
+					self.cursor = self.data.len();
+				} else if (-end_pos) as usize > self.data.len() {
+					self.cursor = 0;
+				} else {
+					self.cursor = self.data.len() - ((-end_pos) as usize);
+				}
+			}
+			SeekFrom::Current(offset) => {
+				let new_cur = self.cursor as i64 + offset;
+				if new_cur < 0 {
+					self.cursor = 0;
+				} else if new_cur as usize > self.data.len() {
+					self.cursor = self.data.len();
+				} else {
+					self.cursor = new_cur as usize;
+				}
+			}
+		};
+		Poll::Ready(Ok(self.cursor as u64))
+	}
+}
+
+#[cfg(test)]
+#[cfg(feature = "backend_tokio")]
+mod async_tokio_tests {
+	use super::ContainerBackend;
+	use crate::scheme::NodeGetOptions;
+	use crate::{AssetContainerScheme, SchemeError, Vfs, VfsError};
+	use futures_lite::{AsyncReadExt, StreamExt};
+	use std::collections::HashMap;
+
+	/// A made up pak format that is just a map of names to contents.
+	struct PakBackend(HashMap<String, Vec<u8>>);
+
+	impl ContainerBackend for PakBackend {
+		fn list(&self) -> Vec<String> {
+			self.0.keys().cloned().collect()
+		}
+
+		fn len(&self, name: &str) -> Option<usize> {
+			self.0.get(name).map(Vec::len)
+		}
+
+		fn open_entry(&self, name: &str) -> Result<Vec<u8>, SchemeError<'static>> {
+			self.0
+				.get(name)
+				.cloned()
+				.ok_or_else(|| "no such pak entry".into())
+		}
+	}
+
+	fn pak_vfs() -> Vfs {
+		let mut entries = HashMap::new();
+		entries.insert("maps/e1m1.bsp".to_owned(), b"level".to_vec());
+		entries.insert("sounds/".to_owned(), Vec::new());
+		entries.insert("progs.dat".to_owned(), b"bytecode".to_vec());
+		let backend: Box<dyn ContainerBackend> = Box::new(PakBackend(entries));
+		let vfs = Vfs::empty();
+		vfs.add_scheme("pak", AssetContainerScheme::from_boxed(backend))
+			.unwrap();
+		vfs
+	}
+
+	#[tokio::test]
+	async fn container_backend() {
+		let vfs = pak_vfs();
+		let mut buffer = String::new();
+		vfs.get_node_at("pak:/maps/e1m1.bsp", &NodeGetOptions::new().read(true))
+			.await
+			.unwrap()
+			.read_to_string(&mut buffer)
+			.await
+			.unwrap();
+		assert_eq!(&buffer, "level");
+		assert_eq!(
+			vfs.metadata_at("pak:/progs.dat").await.unwrap().len,
+			Some((8, Some(8)))
+		);
+		assert!(!vfs.metadata_at("pak:/maps").await.unwrap().is_node);
+		assert!(matches!(
+			vfs.get_node_at("pak:/maps", &NodeGetOptions::new().read(true))
+				.await,
+			Err(VfsError::SchemeError(SchemeError::IsADirectory(_)))
+		));
+		assert!(vfs
+			.get_node_at("pak:/progs.dat", &NodeGetOptions::new().write(true))
+			.await
+			.is_err());
+		let listed: Vec<_> = vfs
+			.read_dir_at("pak:/")
+			.await
+			.unwrap()
+			.map(|entry| entry.url.path().to_owned())
+			.collect()
+			.await;
+		assert_eq!(listed, ["/maps", "/progs.dat", "/sounds"]);
+	}
+}
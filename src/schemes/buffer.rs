@@ -0,0 +1,307 @@
+use crate::node::seek_position;
+use crate::scheme::{BorrowedReadDirStream, NodeEntry, NodeGetOptions, NodeMetadata};
+use crate::{Node, PinnedNode, Scheme, SchemeError, Vfs};
+use futures_lite::{AsyncRead, AsyncSeek, AsyncWrite};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::io::SeekFrom;
+use std::pin::Pin;
+use std::sync::{Arc, RwLock};
+use std::task::{Context, Poll};
+use url::Url;
+
+/// A buffer shared between the application and a `BufferScheme`.
+pub type SharedBuffer = Arc<RwLock<Vec<u8>>>;
+
+/// Serves buffers the application owns and registers at runtime, so a write through the vfs is
+/// seen by every other holder of the buffer and the other way around.  Unlike `MemoryScheme` the
+/// vfs cannot create nodes, only open the registered ones, and removing a node only unregisters
+/// its buffer.
+#[derive(Default)]
+pub struct BufferScheme {
+	buffers: RwLock<HashMap<String, SharedBuffer>>,
+}
+
+impl BufferScheme {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Serves `buffer` at the url path `path`, such as `/config.json`, returning the buffer that
+	/// was registered there before if any.
+	pub fn register(&self, path: &str, buffer: SharedBuffer) -> Option<SharedBuffer> {
+		self.buffers
+			.write()
+			.expect("poisoned lock")
+			.insert(path.to_owned(), buffer)
+	}
+
+	pub fn unregister(&self, path: &str) -> Option<SharedBuffer> {
+		self.buffers.write().expect("poisoned lock").remove(path)
+	}
+
+	fn buffer(&self, path: &str) -> Option<SharedBuffer> {
+		self.buffers
+			.read()
+			.expect("poisoned lock")
+			.get(path)
+			.cloned()
+	}
+}
+
+#[async_trait::async_trait]
+impl Scheme for BufferScheme {
+	async fn get_node<'a>(
+		&self,
+		_vfs: &Vfs,
+		url: &'a Url,
+		options: &NodeGetOptions,
+	) -> Result<PinnedNode, SchemeError<'a>> {
+		let buffer = match self.buffer(url.path()) {
+			Some(_buffer) if options.get_create_new() => {
+				return Err(SchemeError::NodeAlreadyExists(Cow::Borrowed(url.path())))
+			}
+			Some(buffer) => buffer,
+			None => return Err(SchemeError::NodeDoesNotExist(Cow::Borrowed(url.path()))),
+		};
+		let cursor = {
+			let mut data = buffer.write().expect("poisoned lock");
+			if options.get_truncate() {
+				data.clear();
+			}
+			if options.get_append() {
+				data.len()
+			} else {
+				0
+			}
+		};
+		Ok(Box::pin(BufferNode {
+			buffer,
+			cursor,
+			read: options.get_read(),
+			write: options.get_write(),
+		}))
+	}
+
+	async fn remove_node<'a>(
+		&self,
+		_vfs: &Vfs,
+		url: &'a Url,
+		_force: bool,
+	) -> Result<(), SchemeError<'a>> {
+		match self.unregister(url.path()) {
+			Some(_buffer) => Ok(()),
+			None => Err(SchemeError::NodeDoesNotExist(Cow::Borrowed(url.path()))),
+		}
+	}
+
+	async fn metadata<'a>(
+		&self,
+		_vfs: &Vfs,
+		url: &'a Url,
+	) -> Result<NodeMetadata, SchemeError<'a>> {
+		match self.buffer(url.path()) {
+			Some(buffer) => {
+				let len = buffer.read().expect("poisoned lock").len();
+				Ok(NodeMetadata {
+					is_node: true,
+					len: Some((len, Some(len))),
+					modified: None,
+					..Default::default()
+				})
+			}
+			None => Err(SchemeError::NodeDoesNotExist(Cow::Borrowed(url.path()))),
+		}
+	}
+
+	async fn read_dir<'s, 'a>(
+		&'s self,
+		_vfs: &'s Vfs,
+		url: &'a Url,
+	) -> Result<BorrowedReadDirStream<'s>, SchemeError<'a>> {
+		let path = url.path();
+		let path = if path.ends_with('/') {
+			Cow::Borrowed(path)
+		} else if self.buffer(path).is_some() {
+			return Err(SchemeError::NotADirectory(Cow::Borrowed(path)));
+		} else {
+			Cow::Owned(format!("{}/", path))
+		};
+		let mut paths: Vec<String> = self
+			.buffers
+			.read()
+			.expect("poisoned lock")
+			.keys()
+			.filter(|registered| registered.starts_with(&*path))
+			.cloned()
+			.collect();
+		paths.sort();
+		let url = url.clone();
+		let entries = paths.into_iter().map(move |path| {
+			let mut url = url.clone();
+			url.set_path(&path);
+			NodeEntry::new(url)
+		});
+		Ok(Box::pin(futures_lite::stream::iter(entries)))
+	}
+
+	async fn read_small_file<'a>(
+		&self,
+		_vfs: &Vfs,
+		url: &'a Url,
+		max_len: usize,
+	) -> Result<Option<Vec<u8>>, SchemeError<'a>> {
+		Ok(self.buffer(url.path()).and_then(|buffer| {
+			let data = buffer.read().expect("poisoned lock");
+			(data.len() <= max_len).then(|| data.clone())
+		}))
+	}
+}
+
+pub struct BufferNode {
+	buffer: SharedBuffer,
+	cursor: usize,
+	read: bool,
+	write: bool,
+}
+
+#[async_trait::async_trait]
+impl Node for BufferNode {
+	fn is_reader(&self) -> bool {
+		self.read
+	}
+
+	fn is_writer(&self) -> bool {
+		self.write
+	}
+
+	fn is_seeker(&self) -> bool {
+		self.read || self.write
+	}
+
+	fn known_len(&self) -> Option<u64> {
+		Some(self.buffer.read().expect("poisoned lock").len() as u64)
+	}
+
+	fn is_at_end(&self) -> Option<bool> {
+		Some(self.cursor >= self.buffer.read().expect("poisoned lock").len())
+	}
+
+	async fn try_clone(self: Pin<&mut Self>) -> Result<PinnedNode, SchemeError<'static>> {
+		Ok(Box::pin(BufferNode {
+			buffer: self.buffer.clone(),
+			cursor: 0,
+			read: self.read,
+			write: self.write,
+		}))
+	}
+}
+
+impl AsyncRead for BufferNode {
+	fn poll_read(
+		mut self: Pin<&mut Self>,
+		_cx: &mut Context<'_>,
+		buf: &mut [u8],
+	) -> Poll<std::io::Result<usize>> {
+		if !self.read {
+			return Poll::Ready(Err(std::io::Error::from_raw_os_error(13)));
+		}
+		let data = self.buffer.read().expect("poisoned lock");
+		let remaining = data.get(self.cursor..).unwrap_or_default();
+		let amt = remaining.len().min(buf.len());
+		buf[..amt].copy_from_slice(&remaining[..amt]);
+		drop(data); // Minimize the life of the lock
+		self.cursor += amt;
+		Poll::Ready(Ok(amt))
+	}
+}
+
+impl AsyncWrite for BufferNode {
+	/// Always writes all of `buf`, overwriting from the cursor and growing the buffer as needed.
+	fn poll_write(
+		mut self: Pin<&mut Self>,
+		_cx: &mut Context<'_>,
+		buf: &[u8],
+	) -> Poll<std::io::Result<usize>> {
+		if !self.write {
+			return Poll::Ready(Err(std::io::Error::from_raw_os_error(13)));
+		}
+		let mut data = self.buffer.write().expect("poisoned lock");
+		// Another holder of the buffer may have shrunk it since the last write
+		let cursor = self.cursor.min(data.len());
+		let overlap = (data.len() - cursor).min(buf.len());
+		data[cursor..cursor + overlap].copy_from_slice(&buf[..overlap]);
+		data.extend_from_slice(&buf[overlap..]);
+		drop(data); // Minimize the life of the lock
+		self.cursor = cursor + buf.len();
+		Poll::Ready(Ok(buf.len()))
+	}
+
+	fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+		if !self.write {
+			return Poll::Ready(Err(std::io::Error::from_raw_os_error(13)));
+		}
+		Poll::Ready(Ok(()))
+	}
+
+	fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+		if !self.write {
+			return Poll::Ready(Err(std::io::Error::from_raw_os_error(13)));
+		}
+		Poll::Ready(Ok(()))
+	}
+}
+
+impl AsyncSeek for BufferNode {
+	fn poll_seek(
+		mut self: Pin<&mut Self>,
+		_cx: &mut Context<'_>,
+		pos: SeekFrom,
+	) -> Poll<std::io::Result<u64>> {
+		if !self.read && !self.write {
+			return Poll::Ready(Err(std::io::Error::from_raw_os_error(13)));
+		}
+		let position = seek_position(pos, self.cursor as u64, self.known_len())?;
+		self.cursor = position as usize;
+		Poll::Ready(Ok(position))
+	}
+}
+
+#[cfg(test)]
+#[cfg(feature = "backend_tokio")]
+mod async_tokio_tests {
+	use crate::scheme::NodeGetOptions;
+	use crate::{BufferScheme, Vfs};
+	use futures_lite::AsyncWriteExt;
+	use std::sync::{Arc, RwLock};
+
+	#[tokio::test]
+	async fn buffer_shared() {
+		let buffer = Arc::new(RwLock::new(b"hello world".to_vec()));
+		let scheme = BufferScheme::new();
+		scheme.register("/greeting", buffer.clone());
+		let vfs = Vfs::empty();
+		vfs.add_scheme("buf", scheme).unwrap();
+
+		let mut node = vfs
+			.get_node_at("buf:/greeting", &NodeGetOptions::new().write(true))
+			.await
+			.unwrap();
+		node.write_all(b"HELLO").await.unwrap();
+		assert_eq!(*buffer.read().unwrap(), b"HELLO world");
+
+		buffer.write().unwrap().extend_from_slice(b"!");
+		assert_eq!(
+			vfs.read_to_vec_at("buf:/greeting").await.unwrap(),
+			b"HELLO world!"
+		);
+
+		assert!(vfs
+			.get_node_at("buf:/other", &NodeGetOptions::new().create(true))
+			.await
+			.is_err());
+		vfs.remove_node_at("buf:/greeting", false).await.unwrap();
+		assert!(vfs.metadata_at("buf:/greeting").await.is_err());
+		assert_eq!(*buffer.read().unwrap(), b"HELLO world!");
+	}
+}
@@ -0,0 +1,530 @@
+use crate::clock::Instant;
+use crate::scheme::{BorrowedReadDirStream, NodeGetOptions, NodeMetadata, WatchStream};
+use crate::{PinnedNode, Scheme, SchemeError, Vfs};
+use futures_lite::{AsyncReadExt, AsyncWriteExt};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+use url::Url;
+
+/// How long a `CachingScheme` trusts a cached body or metadata unless told otherwise.
+pub const CACHE_DEFAULT_TTL: Duration = Duration::from_secs(60);
+/// How many bytes of bodies a `CachingScheme` keeps in its cache unless told otherwise.
+pub const CACHE_DEFAULT_MAX_SIZE: u64 = 64 * 1024 * 1024;
+
+type InvalidateHook = Box<dyn Fn(&Url) + Send + Sync>;
+
+struct CachedBody {
+	fetched: Instant,
+	used: Instant,
+	cached: Url,
+	len: u64,
+}
+
+#[derive(Default)]
+struct CacheState {
+	metadata: HashMap<Url, (Instant, NodeMetadata)>,
+	bodies: HashMap<Url, CachedBody>,
+	size: u64,
+	next_id: u64,
+	/// Cached bodies that were forgotten but not yet removed from the cache directory.
+	stale: Vec<Url>,
+}
+
+impl CacheState {
+	fn forget_body(&mut self, url: &Url) -> bool {
+		match self.bodies.remove(url) {
+			Some(body) => {
+				self.size -= body.len;
+				self.stale.push(body.cached);
+				true
+			}
+			None => false,
+		}
+	}
+}
+
+/// Wraps a slow scheme, such as http or sftp, and keeps copies of the bodies of the nodes read
+/// through it as nodes in a cache directory, which can be in any fast scheme of the `Vfs` such as
+/// `mem:/cache/`, along with their metadata.  A cached result is trusted for a ttl and bodies are
+/// evicted, least recently used first, once they total more than a max size.  Nodes larger than
+/// the max size are not cached, nor are nodes opened for writing or with a range, which instead
+/// forget the url like removing or renaming it does.  Changes made behind the back of the vfs are
+/// only seen once the cached result expires or `invalidate` is called.  Listing passes through
+/// uncached.  The index of the cache is kept in memory, so a cache directory is not reused across
+/// runs and should not be shared between caching schemes.
+pub struct CachingScheme {
+	scheme: Box<dyn Scheme>,
+	cache: Url,
+	ttl: Duration,
+	max_size: u64,
+	on_invalidate: Option<InvalidateHook>,
+	state: Mutex<CacheState>,
+}
+
+impl CachingScheme {
+	pub fn new(scheme: impl Scheme, cache: Url) -> Self {
+		Self::new_boxed(Box::new(scheme), cache)
+	}
+
+	pub fn new_boxed(scheme: Box<dyn Scheme>, cache: Url) -> Self {
+		Self {
+			scheme,
+			cache: crate::walk::dir_url(&cache),
+			ttl: CACHE_DEFAULT_TTL,
+			max_size: CACHE_DEFAULT_MAX_SIZE,
+			on_invalidate: None,
+			state: Mutex::default(),
+		}
+	}
+
+	/// How long a cached result is trusted, `CACHE_DEFAULT_TTL` by default.
+	pub fn with_ttl(self, ttl: Duration) -> Self {
+		Self { ttl, ..self }
+	}
+
+	/// How many bytes of bodies are kept, `CACHE_DEFAULT_MAX_SIZE` by default.
+	pub fn with_max_size(self, max_size: u64) -> Self {
+		Self { max_size, ..self }
+	}
+
+	/// Called with each url whose cached body or metadata is forgotten, whether invalidated,
+	/// expired or evicted, such as to drop derived state held elsewhere.
+	pub fn on_invalidate<F>(self, hook: F) -> Self
+	where
+		F: Fn(&Url) + Send + Sync + 'static,
+	{
+		Self {
+			on_invalidate: Some(Box::new(hook)),
+			..self
+		}
+	}
+
+	pub fn inner(&self) -> &dyn Scheme {
+		&*self.scheme
+	}
+
+	/// The directory url cached bodies are stored under.
+	pub fn cache_url(&self) -> &Url {
+		&self.cache
+	}
+
+	pub fn ttl(&self) -> Duration {
+		self.ttl
+	}
+
+	pub fn max_size(&self) -> u64 {
+		self.max_size
+	}
+
+	/// The total length of the bodies currently cached.
+	pub fn cached_size(&self) -> u64 {
+		self.state.lock().expect("poisoned lock").size
+	}
+
+	/// Forgets the cached body and metadata of `url` and, as it may be a directory, of everything
+	/// below it.  Forgotten bodies are removed from the cache directory by the next operation.
+	pub fn invalidate(&self, url: &Url) {
+		let below = crate::walk::dir_url(url);
+		let is_below = |cached: &Url| cached == url || cached.as_str().starts_with(below.as_str());
+		let mut forgotten = Vec::new();
+		{
+			let mut state = self.state.lock().expect("poisoned lock");
+			state.metadata.retain(|cached, _| {
+				if is_below(cached) {
+					forgotten.push(cached.clone());
+				}
+				!is_below(cached)
+			});
+			let bodies: Vec<Url> = state
+				.bodies
+				.keys()
+				.filter(|u| is_below(u))
+				.cloned()
+				.collect();
+			for body in bodies {
+				state.forget_body(&body);
+				forgotten.push(body);
+			}
+		}
+		self.notify(forgotten);
+	}
+
+	/// Forgets every cached body and metadata.
+	pub fn clear(&self) {
+		let forgotten = {
+			let mut state = self.state.lock().expect("poisoned lock");
+			let mut forgotten: Vec<Url> = state.metadata.drain().map(|(url, _)| url).collect();
+			let bodies: Vec<Url> = state.bodies.keys().cloned().collect();
+			for body in bodies {
+				state.forget_body(&body);
+				forgotten.push(body);
+			}
+			forgotten
+		};
+		self.notify(forgotten);
+	}
+
+	fn notify(&self, mut forgotten: Vec<Url>) {
+		if let Some(hook) = &self.on_invalidate {
+			forgotten.sort_unstable();
+			forgotten.dedup();
+			forgotten.iter().for_each(hook);
+		}
+	}
+
+	/// Removes forgotten bodies from the cache directory, a body that cannot be removed is left
+	/// behind rather than failing the operation that happened to come next.
+	async fn purge(&self, vfs: &Vfs) {
+		let stale = std::mem::take(&mut self.state.lock().expect("poisoned lock").stale);
+		for cached in stale {
+			let _ = vfs.remove_node(&cached, false).await;
+		}
+	}
+
+	fn cached_metadata(&self, url: &Url) -> Option<NodeMetadata> {
+		let mut state = self.state.lock().expect("poisoned lock");
+		match state.metadata.get(url) {
+			Some((at, metadata)) if at.elapsed() < self.ttl => return Some(metadata.clone()),
+			Some(_expired) => state.metadata.remove(url),
+			None => return None,
+		};
+		drop(state);
+		self.notify(vec![url.clone()]);
+		None
+	}
+
+	fn remember_metadata(&self, url: &Url, metadata: &NodeMetadata) {
+		self.state
+			.lock()
+			.expect("poisoned lock")
+			.metadata
+			.insert(url.clone(), (Instant::now(), metadata.clone()));
+	}
+
+	fn cached_body(&self, url: &Url) -> Option<Url> {
+		let mut state = self.state.lock().expect("poisoned lock");
+		match state.bodies.get_mut(url) {
+			Some(body) if body.fetched.elapsed() < self.ttl => {
+				body.used = Instant::now();
+				return Some(body.cached.clone());
+			}
+			Some(_expired) => state.forget_body(url),
+			None => return None,
+		};
+		drop(state);
+		self.notify(vec![url.clone()]);
+		None
+	}
+
+	fn next_cache_url(&self) -> Option<Url> {
+		let mut state = self.state.lock().expect("poisoned lock");
+		state.next_id += 1;
+		self.cache.join(&state.next_id.to_string()).ok()
+	}
+
+	/// Records `cached` as the body of `url`, evicting the least recently used bodies until the
+	/// cache fits in the max size again.
+	fn remember_body(&self, url: &Url, cached: Url, len: u64) {
+		let mut evicted = Vec::new();
+		{
+			let mut state = self.state.lock().expect("poisoned lock");
+			state.forget_body(url);
+			let now = Instant::now();
+			state.bodies.insert(
+				url.clone(),
+				CachedBody {
+					fetched: now,
+					used: now,
+					cached,
+					len,
+				},
+			);
+			state.size += len;
+			while state.size > self.max_size {
+				let oldest = state
+					.bodies
+					.iter()
+					.filter(|(cached, _)| *cached != url)
+					.min_by_key(|(_, body)| body.used)
+					.map(|(cached, _)| cached.clone());
+				match oldest {
+					Some(oldest) => {
+						state.forget_body(&oldest);
+						evicted.push(oldest);
+					}
+					None => break,
+				}
+			}
+		}
+		self.notify(evicted);
+	}
+
+	/// Reads the whole of `url` from the wrapped scheme into a new node of the cache directory,
+	/// `None` if it is too large to cache or could not be stored.
+	async fn fetch<'a>(
+		&self,
+		vfs: &Vfs,
+		url: &'a Url,
+		options: &NodeGetOptions,
+	) -> Result<Option<Url>, SchemeError<'a>> {
+		let mut node = self.scheme.get_node(vfs, url, options).await?;
+		if node.known_len().is_some_and(|len| len > self.max_size) {
+			return Ok(None);
+		}
+		let mut body = Vec::new();
+		(&mut node)
+			.take(self.max_size + 1)
+			.read_to_end(&mut body)
+			.await?;
+		if body.len() as u64 > self.max_size {
+			return Ok(None);
+		}
+		let cached = match self.next_cache_url() {
+			Some(cached) => cached,
+			None => return Ok(None),
+		};
+		let stored = async {
+			let mut node = vfs
+				.get_node(&cached, &NodeGetOptions::new().create(true).truncate(true))
+				.await?;
+			node.write_all(&body).await?;
+			node.close().await?;
+			Ok::<_, SchemeError<'static>>(())
+		};
+		if stored.await.is_err() {
+			self.state.lock().expect("poisoned lock").stale.push(cached);
+			return Ok(None);
+		}
+		self.remember_body(url, cached.clone(), body.len() as u64);
+		self.purge(vfs).await;
+		Ok(Some(cached))
+	}
+}
+
+#[async_trait::async_trait]
+impl Scheme for CachingScheme {
+	async fn get_node<'a>(
+		&self,
+		vfs: &Vfs,
+		url: &'a Url,
+		options: &NodeGetOptions,
+	) -> Result<PinnedNode, SchemeError<'a>> {
+		let writing = options.get_write() || options.get_append();
+		if writing {
+			self.invalidate(url);
+		}
+		if writing || options.get_range().is_some() {
+			self.purge(vfs).await;
+			return self.scheme.get_node(vfs, url, options).await;
+		}
+		let read = NodeGetOptions::new().read(true);
+		if let Some(cached) = self.cached_body(url) {
+			match vfs.get_node(&cached, &read).await {
+				Ok(node) => return Ok(node),
+				// Removed behind the back of the cache, so fetched again
+				Err(_error) => self.invalidate(url),
+			}
+		}
+		self.purge(vfs).await;
+		if let Some(cached) = self.fetch(vfs, url, options).await? {
+			if let Ok(node) = vfs.get_node(&cached, &read).await {
+				return Ok(node);
+			}
+		}
+		self.scheme.get_node(vfs, url, options).await
+	}
+
+	async fn remove_node<'a>(
+		&self,
+		vfs: &Vfs,
+		url: &'a Url,
+		force: bool,
+	) -> Result<(), SchemeError<'a>> {
+		// Forgotten even on failure, as a forced removal may have removed part of a directory
+		self.invalidate(url);
+		self.purge(vfs).await;
+		self.scheme.remove_node(vfs, url, force).await
+	}
+
+	async fn metadata<'a>(&self, vfs: &Vfs, url: &'a Url) -> Result<NodeMetadata, SchemeError<'a>> {
+		if let Some(metadata) = self.cached_metadata(url) {
+			return Ok(metadata);
+		}
+		let metadata = self.scheme.metadata(vfs, url).await?;
+		self.remember_metadata(url, &metadata);
+		Ok(metadata)
+	}
+
+	async fn read_dir<'s, 'a>(
+		&'s self,
+		vfs: &'s Vfs,
+		url: &'a Url,
+	) -> Result<BorrowedReadDirStream<'s>, SchemeError<'a>> {
+		self.scheme.read_dir(vfs, url).await
+	}
+
+	async fn canonicalize<'a>(&self, vfs: &Vfs, url: &'a Url) -> Result<Url, SchemeError<'a>> {
+		self.scheme.canonicalize(vfs, url).await
+	}
+
+	async fn set_modified<'a>(
+		&self,
+		vfs: &Vfs,
+		url: &'a Url,
+		modified: SystemTime,
+	) -> Result<(), SchemeError<'a>> {
+		self.invalidate(url);
+		self.scheme.set_modified(vfs, url, modified).await
+	}
+
+	async fn create_dir<'a>(
+		&self,
+		vfs: &Vfs,
+		url: &'a Url,
+		parents: bool,
+	) -> Result<(), SchemeError<'a>> {
+		self.invalidate(url);
+		self.scheme.create_dir(vfs, url, parents).await
+	}
+
+	async fn copy_node<'a>(
+		&self,
+		vfs: &Vfs,
+		from: &'a Url,
+		to: &'a Url,
+	) -> Result<(), SchemeError<'a>> {
+		self.invalidate(to);
+		self.purge(vfs).await;
+		self.scheme.copy_node(vfs, from, to).await
+	}
+
+	async fn rename_node<'a>(
+		&self,
+		vfs: &Vfs,
+		from: &'a Url,
+		to: &'a Url,
+	) -> Result<(), SchemeError<'a>> {
+		self.invalidate(from);
+		self.invalidate(to);
+		self.purge(vfs).await;
+		self.scheme.rename_node(vfs, from, to).await
+	}
+
+	async fn watch<'a>(&self, vfs: &Vfs, url: &'a Url) -> Result<WatchStream, SchemeError<'a>> {
+		self.scheme.watch(vfs, url).await
+	}
+}
+
+#[cfg(test)]
+#[cfg(feature = "backend_tokio")]
+#[cfg(feature = "in_memory")]
+mod async_tokio_tests {
+	use crate::scheme::NodeGetOptions;
+	use crate::{CachingScheme, MemoryScheme, Vfs};
+	use futures_lite::{AsyncWriteExt, StreamExt};
+	use std::sync::{Arc, Mutex};
+	use std::time::Duration;
+	use url::Url;
+
+	/// Writes to the wrapped scheme directly, behind the back of the cache.
+	async fn write_behind(vfs: &Vfs, path: &str, data: &[u8]) {
+		let caching = vfs.get_scheme_as::<CachingScheme>("slow").unwrap();
+		let url = Url::parse(&format!("slow:{}", path)).unwrap();
+		let mut node = caching
+			.inner()
+			.get_node(
+				vfs,
+				&url,
+				&NodeGetOptions::new().create(true).truncate(true),
+			)
+			.await
+			.unwrap();
+		node.write_all(data).await.unwrap();
+		node.close().await.unwrap();
+	}
+
+	async fn cached_count(vfs: &Vfs) -> usize {
+		match vfs.read_dir_at("cache:/bodies").await {
+			Ok(entries) => entries.count().await,
+			Err(_error) => 0,
+		}
+	}
+
+	fn caching_vfs(caching: impl FnOnce(CachingScheme) -> CachingScheme) -> Vfs {
+		let vfs = Vfs::empty();
+		vfs.add_scheme("cache", MemoryScheme::default()).unwrap();
+		let cache = Url::parse("cache:/bodies").unwrap();
+		vfs.add_scheme(
+			"slow",
+			caching(CachingScheme::new(MemoryScheme::default(), cache)),
+		)
+		.unwrap();
+		vfs
+	}
+
+	#[tokio::test]
+	async fn caching_bodies() {
+		let invalidated = Arc::new(Mutex::new(Vec::new()));
+		let hook = invalidated.clone();
+		let vfs = caching_vfs(|caching| {
+			caching.on_invalidate(move |url| hook.lock().unwrap().push(url.to_string()))
+		});
+		write_behind(&vfs, "/node", b"one").await;
+		assert_eq!(vfs.read_to_vec_at("slow:/node").await.unwrap(), b"one");
+		assert_eq!(cached_count(&vfs).await, 1);
+		assert_eq!(
+			vfs.metadata_at("slow:/node").await.unwrap().len,
+			Some((3, Some(3)))
+		);
+
+		write_behind(&vfs, "/node", b"two!").await;
+		assert_eq!(vfs.read_to_vec_at("slow:/node").await.unwrap(), b"one");
+		assert_eq!(
+			vfs.metadata_at("slow:/node").await.unwrap().len,
+			Some((3, Some(3)))
+		);
+
+		let caching = vfs.get_scheme_as::<CachingScheme>("slow").unwrap();
+		caching.invalidate(&Url::parse("slow:/").unwrap());
+		assert_eq!(*invalidated.lock().unwrap(), ["slow:/node"]);
+		assert_eq!(caching.cached_size(), 0);
+		assert_eq!(vfs.read_to_vec_at("slow:/node").await.unwrap(), b"two!");
+		assert_eq!(cached_count(&vfs).await, 1, "the stale body was removed");
+
+		// Writing through the cache forgets the url
+		let mut node = vfs
+			.get_node_at("slow:/node", &NodeGetOptions::new().truncate(true))
+			.await
+			.unwrap();
+		node.write_all(b"three").await.unwrap();
+		node.close().await.unwrap();
+		assert_eq!(vfs.read_to_vec_at("slow:/node").await.unwrap(), b"three");
+	}
+
+	#[tokio::test]
+	async fn caching_limits() {
+		let vfs = caching_vfs(|caching| caching.with_max_size(4));
+		write_behind(&vfs, "/large", b"large").await;
+		write_behind(&vfs, "/a", b"aaa").await;
+		write_behind(&vfs, "/b", b"bbb").await;
+		assert_eq!(vfs.read_to_vec_at("slow:/large").await.unwrap(), b"large");
+		assert_eq!(cached_count(&vfs).await, 0, "larger than the max size");
+
+		assert_eq!(vfs.read_to_vec_at("slow:/a").await.unwrap(), b"aaa");
+		assert_eq!(vfs.read_to_vec_at("slow:/b").await.unwrap(), b"bbb");
+		let caching = vfs.get_scheme_as::<CachingScheme>("slow").unwrap();
+		assert_eq!(caching.cached_size(), 3, "a was evicted");
+		write_behind(&vfs, "/a", b"AAA").await;
+		write_behind(&vfs, "/b", b"BBB").await;
+		assert_eq!(vfs.read_to_vec_at("slow:/b").await.unwrap(), b"bbb");
+		assert_eq!(vfs.read_to_vec_at("slow:/a").await.unwrap(), b"AAA");
+
+		let vfs = caching_vfs(|caching| caching.with_ttl(Duration::ZERO));
+		write_behind(&vfs, "/node", b"one").await;
+		assert_eq!(vfs.read_to_vec_at("slow:/node").await.unwrap(), b"one");
+		write_behind(&vfs, "/node", b"two").await;
+		assert_eq!(vfs.read_to_vec_at("slow:/node").await.unwrap(), b"two");
+		assert_eq!(cached_count(&vfs).await, 1);
+	}
+}
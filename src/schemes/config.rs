@@ -0,0 +1,454 @@
+use crate::node::{poll_io_err, seek_position};
+use crate::scheme::{BorrowedReadDirStream, NodeEntry, NodeGetOptions, NodeMetadata};
+use crate::{Node, PinnedNode, Scheme, SchemeError, Vfs, VfsError};
+use futures_lite::{AsyncRead, AsyncSeek, AsyncWrite, StreamExt};
+use std::borrow::Cow;
+use std::collections::BTreeSet;
+use std::io::SeekFrom;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use url::Url;
+
+/// Serves configuration files out of a stack of directories in the `Vfs`, such as
+/// `defaults/ < env/ < local/`, where each node comes from the highest priority layer that has it.
+/// With the `config_merge` feature and `merge` enabled, `.toml` and `.json` nodes are instead read
+/// from every layer that has them and deep-merged, tables of a higher layer merge into those below
+/// and any other value replaces the one below.  Writes go to the highest priority layer.
+pub struct ConfigScheme {
+	/// From the lowest to the highest priority, each ending in `/`.
+	layers: Vec<Url>,
+	#[cfg(feature = "config_merge")]
+	merge: bool,
+}
+
+impl ConfigScheme {
+	/// `layers` are the urls of directories from the lowest to the highest priority.
+	pub fn new(layers: impl IntoIterator<Item = Url>) -> Self {
+		let layers = layers
+			.into_iter()
+			.map(|mut layer| {
+				if !layer.path().ends_with('/') {
+					layer.set_path(&format!("{}/", layer.path()));
+				}
+				layer
+			})
+			.collect();
+		Self {
+			layers,
+			#[cfg(feature = "config_merge")]
+			merge: false,
+		}
+	}
+
+	/// Whether `.toml` and `.json` nodes are deep-merged across the layers, `false` by default.
+	#[cfg(feature = "config_merge")]
+	pub fn merge(self, merge: bool) -> Self {
+		Self { merge, ..self }
+	}
+
+	pub fn layers(&self) -> &[Url] {
+		&self.layers
+	}
+
+	fn layer_url<'a>(layer: &Url, url: &'a Url) -> Result<Url, SchemeError<'a>> {
+		Ok(layer.join(url.path().trim_start_matches('/'))?)
+	}
+
+	#[cfg(feature = "config_merge")]
+	fn merge_format(&self, url: &Url) -> Option<merge::Format> {
+		if self.merge {
+			merge::Format::of(url.path())
+		} else {
+			None
+		}
+	}
+
+	#[cfg(feature = "config_merge")]
+	async fn get_merged<'a>(
+		&self,
+		vfs: &Vfs,
+		url: &'a Url,
+		format: merge::Format,
+	) -> Result<PinnedNode, SchemeError<'a>> {
+		let mut documents = Vec::new();
+		for layer in &self.layers {
+			match vfs.read_to_vec(&Self::layer_url(layer, url)?).await {
+				Ok(data) => documents.push(data),
+				Err(error) if is_missing(&error) => (),
+				Err(error) => return Err(error.into()),
+			}
+		}
+		let data = match documents.len() {
+			0 => return Err(SchemeError::NodeDoesNotExist(Cow::Borrowed(url.path()))),
+			// Nothing to merge, so keep the document as written
+			1 => documents.pop().unwrap_or_default(),
+			_ => format.merge(documents)?,
+		};
+		Ok(Box::pin(ConfigNode { data, cursor: 0 }))
+	}
+}
+
+fn is_missing(error: &VfsError) -> bool {
+	matches!(
+		error,
+		VfsError::SchemeError(SchemeError::NodeDoesNotExist(_))
+	)
+}
+
+#[async_trait::async_trait]
+impl Scheme for ConfigScheme {
+	async fn get_node<'a>(
+		&self,
+		vfs: &Vfs,
+		url: &'a Url,
+		options: &NodeGetOptions,
+	) -> Result<PinnedNode, SchemeError<'a>> {
+		let top = match self.layers.last() {
+			Some(top) => top,
+			None => return Err(SchemeError::NodeDoesNotExist(Cow::Borrowed(url.path()))),
+		};
+		if options.get_write() {
+			let layer_url = Self::layer_url(top, url)?;
+			return Ok(vfs.get_node(&layer_url, options).await?);
+		}
+		#[cfg(feature = "config_merge")]
+		if let Some(format) = self.merge_format(url) {
+			return self.get_merged(vfs, url, format).await;
+		}
+		for layer in self.layers.iter().rev() {
+			match vfs.get_node(&Self::layer_url(layer, url)?, options).await {
+				Ok(node) => return Ok(node),
+				Err(error) if is_missing(&error) => (),
+				Err(error) => return Err(error.into()),
+			}
+		}
+		Err(SchemeError::NodeDoesNotExist(Cow::Borrowed(url.path())))
+	}
+
+	async fn remove_node<'a>(
+		&self,
+		_vfs: &Vfs,
+		_url: &'a Url,
+		_force: bool,
+	) -> Result<(), SchemeError<'a>> {
+		Err(SchemeError::Unsupported("remove_node"))
+	}
+
+	async fn metadata<'a>(&self, vfs: &Vfs, url: &'a Url) -> Result<NodeMetadata, SchemeError<'a>> {
+		for layer in self.layers.iter().rev() {
+			match vfs.metadata(&Self::layer_url(layer, url)?).await {
+				Ok(metadata) => {
+					#[cfg(feature = "config_merge")]
+					let metadata = if metadata.is_node && self.merge_format(url).is_some() {
+						// Only known once the layers are merged
+						NodeMetadata {
+							len: None,
+							..metadata
+						}
+					} else {
+						metadata
+					};
+					return Ok(metadata);
+				}
+				Err(error) if is_missing(&error) => (),
+				Err(error) => return Err(error.into()),
+			}
+		}
+		Err(SchemeError::NodeDoesNotExist(Cow::Borrowed(url.path())))
+	}
+
+	async fn read_dir<'s, 'a>(
+		&'s self,
+		vfs: &'s Vfs,
+		url: &'a Url,
+	) -> Result<BorrowedReadDirStream<'s>, SchemeError<'a>> {
+		let mut names = BTreeSet::new();
+		let mut found = false;
+		for layer in &self.layers {
+			let layer_url = Self::layer_url(layer, url)?;
+			let mut entries = match vfs.read_dir(&layer_url).await {
+				Ok(entries) => entries,
+				Err(error) if is_missing(&error) => continue,
+				Err(error) => return Err(error.into()),
+			};
+			found = true;
+			while let Some(entry) = entries.next().await {
+				if let Some(name) = entry.url.path().strip_prefix(layer.path()) {
+					names.insert(name.to_owned());
+				}
+			}
+		}
+		if !found {
+			return Err(SchemeError::NodeDoesNotExist(Cow::Borrowed(url.path())));
+		}
+		let entries: Vec<NodeEntry> = names
+			.into_iter()
+			.map(|name| {
+				let mut url = url.clone();
+				url.set_path(&format!("/{}", name));
+				NodeEntry::new(url)
+			})
+			.collect();
+		Ok(Box::pin(futures_lite::stream::iter(entries)))
+	}
+}
+
+#[cfg(feature = "config_merge")]
+mod merge {
+	use crate::SchemeError;
+
+	#[derive(Clone, Copy)]
+	pub(super) enum Format {
+		Toml,
+		Json,
+	}
+
+	impl Format {
+		pub(super) fn of(path: &str) -> Option<Self> {
+			if path.ends_with(".toml") {
+				Some(Format::Toml)
+			} else if path.ends_with(".json") {
+				Some(Format::Json)
+			} else {
+				None
+			}
+		}
+
+		/// Merges `documents`, from the lowest to the highest priority, into one.
+		pub(super) fn merge(
+			self,
+			documents: Vec<Vec<u8>>,
+		) -> Result<Vec<u8>, SchemeError<'static>> {
+			match self {
+				Format::Toml => {
+					let mut merged = toml::Table::new();
+					for document in documents {
+						let document = std::str::from_utf8(&document).map_err(|error| {
+							("config layer is not utf-8", Box::new(error) as Box<_>)
+						})?;
+						let table = document.parse::<toml::Table>().map_err(|error| {
+							("config layer is not valid toml", Box::new(error) as Box<_>)
+						})?;
+						merge_toml(&mut merged, table);
+					}
+					let merged = toml::to_string(&merged).map_err(|error| {
+						(
+							"merged config failed to serialize",
+							Box::new(error) as Box<_>,
+						)
+					})?;
+					Ok(merged.into_bytes())
+				}
+				Format::Json => {
+					let mut merged = serde_json::Value::Object(serde_json::Map::new());
+					for document in documents {
+						let value = serde_json::from_slice(&document).map_err(|error| {
+							("config layer is not valid json", Box::new(error) as Box<_>)
+						})?;
+						merge_json(&mut merged, value);
+					}
+					serde_json::to_vec_pretty(&merged).map_err(|error| {
+						(
+							"merged config failed to serialize",
+							Box::new(error) as Box<_>,
+						)
+							.into()
+					})
+				}
+			}
+		}
+	}
+
+	fn merge_toml(base: &mut toml::Table, over: toml::Table) {
+		for (key, value) in over {
+			match (base.get_mut(&key), value) {
+				(Some(toml::Value::Table(base)), toml::Value::Table(over)) => {
+					merge_toml(base, over)
+				}
+				(_, value) => {
+					base.insert(key, value);
+				}
+			}
+		}
+	}
+
+	fn merge_json(base: &mut serde_json::Value, over: serde_json::Value) {
+		match (base, over) {
+			(serde_json::Value::Object(base), serde_json::Value::Object(over)) => {
+				for (key, value) in over {
+					match base.get_mut(&key) {
+						Some(base) => merge_json(base, value),
+						None => {
+							base.insert(key, value);
+						}
+					}
+				}
+			}
+			(base, over) => *base = over,
+		}
+	}
+}
+
+pub struct ConfigNode {
+	data: Vec<u8>,
+	cursor: usize,
+}
+
+#[async_trait::async_trait]
+impl Node for ConfigNode {
+	fn is_reader(&self) -> bool {
+		true
+	}
+
+	fn is_writer(&self) -> bool {
+		false
+	}
+
+	fn is_seeker(&self) -> bool {
+		true
+	}
+
+	fn is_at_end(&self) -> Option<bool> {
+		Some(self.cursor >= self.data.len())
+	}
+
+	fn known_len(&self) -> Option<u64> {
+		Some(self.data.len() as u64)
+	}
+}
+
+impl AsyncRead for ConfigNode {
+	fn poll_read(
+		mut self: Pin<&mut Self>,
+		_cx: &mut Context<'_>,
+		buf: &mut [u8],
+	) -> Poll<std::io::Result<usize>> {
+		let remaining = &self.data[self.cursor.min(self.data.len())..];
+		let amt = remaining.len().min(buf.len());
+		buf[..amt].copy_from_slice(&remaining[..amt]);
+		self.cursor += amt;
+		Poll::Ready(Ok(amt))
+	}
+}
+
+impl AsyncWrite for ConfigNode {
+	fn poll_write(
+		self: Pin<&mut Self>,
+		_cx: &mut Context<'_>,
+		_buf: &[u8],
+	) -> Poll<std::io::Result<usize>> {
+		poll_io_err()
+	}
+
+	fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+		poll_io_err()
+	}
+
+	fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+		poll_io_err()
+	}
+}
+
+impl AsyncSeek for ConfigNode {
+	fn poll_seek(
+		mut self: Pin<&mut Self>,
+		_cx: &mut Context<'_>,
+		pos: SeekFrom,
+	) -> Poll<std::io::Result<u64>> {
+		let position = seek_position(pos, self.cursor as u64, self.known_len())?;
+		self.cursor = position as usize;
+		Poll::Ready(Ok(position))
+	}
+}
+
+#[cfg(test)]
+#[cfg(all(feature = "backend_tokio", feature = "in_memory"))]
+mod async_tokio_tests {
+	use crate::scheme::NodeGetOptions;
+	use crate::{ConfigScheme, MemoryScheme, SchemeError, Vfs, VfsError};
+	use futures_lite::{AsyncReadExt, AsyncWriteExt, StreamExt};
+	use url::Url;
+
+	async fn write(vfs: &Vfs, uri: &str, content: &str) {
+		let mut node = vfs
+			.get_node_at(uri, &NodeGetOptions::new().create(true).truncate(true))
+			.await
+			.unwrap();
+		node.write_all(content.as_bytes()).await.unwrap();
+	}
+
+	async fn read(vfs: &Vfs, uri: &str) -> Result<String, VfsError<'static>> {
+		let mut node = vfs
+			.get_node_at(uri, &NodeGetOptions::new().read(true))
+			.await?;
+		let mut buffer = String::new();
+		node.read_to_string(&mut buffer).await.unwrap();
+		Ok(buffer)
+	}
+
+	async fn config_vfs(config: impl FnOnce(ConfigScheme) -> ConfigScheme) -> Vfs {
+		let vfs = Vfs::empty();
+		vfs.add_scheme("mem", MemoryScheme::default()).unwrap();
+		let layers = ["mem:/defaults", "mem:/local/"].map(|layer| Url::parse(layer).unwrap());
+		vfs.add_scheme("config", config(ConfigScheme::new(layers)))
+			.unwrap();
+		write(
+			&vfs,
+			"mem:/defaults/app.toml",
+			"[window]\nwidth = 800\nheight = 600\n",
+		)
+		.await;
+		write(&vfs, "mem:/defaults/keys.txt", "default keys").await;
+		write(&vfs, "mem:/local/app.toml", "[window]\nwidth = 1920\n").await;
+		vfs
+	}
+
+	#[tokio::test]
+	async fn config_first_match() {
+		let vfs = config_vfs(|config| config).await;
+		assert_eq!(
+			read(&vfs, "config:/app.toml").await.unwrap(),
+			"[window]\nwidth = 1920\n"
+		);
+		assert_eq!(
+			read(&vfs, "config:/keys.txt").await.unwrap(),
+			"default keys"
+		);
+		assert!(matches!(
+			read(&vfs, "config:/missing.toml").await,
+			Err(VfsError::SchemeError(SchemeError::NodeDoesNotExist(_)))
+		));
+		let names: Vec<_> = vfs
+			.read_dir_at("config:/")
+			.await
+			.unwrap()
+			.map(|entry| entry.url.path().to_owned())
+			.collect()
+			.await;
+		assert_eq!(names, ["/app.toml", "/keys.txt"]);
+
+		write(&vfs, "config:/keys.txt", "local keys").await;
+		assert_eq!(
+			read(&vfs, "mem:/local/keys.txt").await.unwrap(),
+			"local keys"
+		);
+		assert_eq!(read(&vfs, "config:/keys.txt").await.unwrap(), "local keys");
+	}
+
+	#[cfg(feature = "config_merge")]
+	#[tokio::test]
+	async fn config_merged_toml() {
+		let vfs = config_vfs(|config| config.merge(true)).await;
+		let merged: toml::Table = read(&vfs, "config:/app.toml")
+			.await
+			.unwrap()
+			.parse()
+			.unwrap();
+		assert_eq!(merged["window"]["width"].as_integer(), Some(1920));
+		assert_eq!(merged["window"]["height"].as_integer(), Some(600));
+		assert_eq!(
+			read(&vfs, "config:/keys.txt").await.unwrap(),
+			"default keys"
+		);
+	}
+}
@@ -1,5 +1,5 @@
 use crate::node::poll_io_err;
-use crate::scheme::{NodeGetOptions, NodeMetadata, ReadDirStream};
+use crate::scheme::{BorrowedReadDirStream, NodeGetOptions, NodeKind, NodeMetadata};
 use crate::{Node, PinnedNode, Scheme, SchemeError, Vfs};
 use futures_lite::{AsyncRead, AsyncSeek, AsyncWrite};
 use std::borrow::Cow;
@@ -8,22 +8,86 @@ use std::pin::Pin;
 use std::task::{Context, Poll};
 use url::Url;
 
-pub struct DataLoaderScheme {}
+#[derive(Debug)]
+pub enum DataLoaderError {
+	Base64Failure(base64::DecodeError),
+}
 
-impl Default for DataLoaderScheme {
-	fn default() -> Self {
-		DataLoaderScheme {}
+impl std::fmt::Display for DataLoaderError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			DataLoaderError::Base64Failure(base64::DecodeError::InvalidByte(offset, byte)) => f
+				.write_fmt(format_args!(
+					"invalid base64 byte 0x{:02x} at offset {}",
+					byte, offset
+				)),
+			DataLoaderError::Base64Failure(base64::DecodeError::InvalidLastSymbol(
+				offset,
+				byte,
+			)) => f.write_fmt(format_args!(
+				"invalid trailing base64 byte 0x{:02x} at offset {}",
+				byte, offset
+			)),
+			DataLoaderError::Base64Failure(base64::DecodeError::InvalidLength) => {
+				f.write_str("invalid base64 length")
+			}
+		}
 	}
 }
 
+impl std::error::Error for DataLoaderError {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			DataLoaderError::Base64Failure(source) => Some(source),
+		}
+	}
+}
+
+#[cfg(test)]
+thread_local! {
+	/// How many urls this thread has decoded, so tests can check work is not repeated.
+	static PARSE_COUNT: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+}
+
+#[derive(Default)]
+pub struct DataLoaderScheme {}
+
 impl DataLoaderScheme {
 	pub fn new() -> Self {
 		Self::default()
 	}
 
-	pub fn parse_url_into_data(url: &Url) -> Result<(&str, Box<[u8]>), SchemeError> {
+	/// Decodes a base64 payload, trying the standard alphabet first and then the URL-safe one,
+	/// either of them with or without padding.  If every attempt fails then the error of the
+	/// attempt that got furthest into the payload is returned.
+	pub fn decode_base64(data: &[u8]) -> Result<Vec<u8>, DataLoaderError> {
+		let standard_err = match base64::decode_config(data, base64::STANDARD) {
+			Ok(decoded) => return Ok(decoded),
+			Err(error) => error,
+		};
+		let url_safe_err = match base64::decode_config(data, base64::URL_SAFE) {
+			Ok(decoded) => return Ok(decoded),
+			Err(error) => error,
+		};
+		let reached = |error: &base64::DecodeError| match error {
+			base64::DecodeError::InvalidByte(offset, _byte) => *offset,
+			base64::DecodeError::InvalidLastSymbol(offset, _byte) => *offset,
+			base64::DecodeError::InvalidLength => data.len(),
+		};
+		if reached(&url_safe_err) > reached(&standard_err) {
+			Err(DataLoaderError::Base64Failure(url_safe_err))
+		} else {
+			Err(DataLoaderError::Base64Failure(standard_err))
+		}
+	}
+
+	pub fn parse_url_into_data(url: &Url) -> Result<(&str, Box<[u8]>), SchemeError<'_>> {
+		#[cfg(test)]
+		PARSE_COUNT.with(|count| count.set(count.get() + 1));
 		if url.path_segments().is_some() {
-			return Err(SchemeError::NodeDoesNotExist(Cow::Borrowed(url.path())));
+			return Err(SchemeError::InvalidDataUrl(
+				"data urls have no path, expected `data:[<mediatype>][;base64],<data>`",
+			));
 		}
 		let (data_type, data) = url
 			.path()
@@ -31,22 +95,35 @@ impl DataLoaderScheme {
 			.unwrap_or(("text/plain;charset=US-ASCII", url.path()));
 		let (mimetype, data) = if data_type == "base64" || data_type.ends_with(";base64") {
 			let mimetype = data_type.trim_end_matches("base64").trim_end_matches(';');
-			let data = base64::decode(data).map_err(|source| {
+			// Padding is commonly percent-encoded in the wild, so decode that first
+			let data: Vec<u8> = percent_encoding::percent_decode_str(data).collect();
+			let data = Self::decode_base64(&data).map_err(|source| {
 				(
-					"data_loader error",
+					"data_loader invalid base64 payload",
 					Box::new(source) as Box<dyn std::error::Error + Send + Sync>,
 				)
 			})?;
 			(mimetype, data)
 		} else {
 			let mimetype = data_type;
-			let data = percent_encoding::percent_decode_str(&data).collect();
+			let data = percent_encoding::percent_decode_str(data).collect();
 			(mimetype, data)
 		};
 		Ok((mimetype, data.into_boxed_slice()))
 	}
 }
 
+/// The content of a `data:` url is the whole node, it can only be read.
+fn data_metadata(data: &[u8]) -> NodeMetadata {
+	NodeMetadata {
+		is_node: true,
+		len: Some((data.len(), Some(data.len()))),
+		kind: Some(NodeKind::File),
+		read_only: Some(true),
+		..Default::default()
+	}
+}
+
 #[async_trait::async_trait]
 impl Scheme for DataLoaderScheme {
 	async fn get_node<'a>(
@@ -79,18 +156,26 @@ impl Scheme for DataLoaderScheme {
 		url: &'a Url,
 	) -> Result<NodeMetadata, SchemeError<'a>> {
 		let (_mimetype, data) = Self::parse_url_into_data(url)?;
-		Ok(NodeMetadata {
-			is_node: true,
-			len: Some((data.len(), Some(data.len()))),
-		})
+		Ok(data_metadata(&data))
 	}
 
-	async fn read_dir<'a>(
+	async fn read_dir<'s, 'a>(
+		&'s self,
+		_vfs: &'s Vfs,
+		url: &'a Url,
+	) -> Result<BorrowedReadDirStream<'s>, SchemeError<'a>> {
+		Err(SchemeError::NodeDoesNotExist(Cow::Borrowed(url.as_str())))
+	}
+
+	async fn stat_and_open<'a>(
 		&self,
 		_vfs: &Vfs,
 		url: &'a Url,
-	) -> Result<ReadDirStream, SchemeError<'a>> {
-		Err(SchemeError::NodeDoesNotExist(Cow::Borrowed(url.as_str())))
+		_options: &NodeGetOptions,
+	) -> Result<(NodeMetadata, PinnedNode), SchemeError<'a>> {
+		let (_mimetype, data) = Self::parse_url_into_data(url)?;
+		let metadata = data_metadata(&data);
+		Ok((metadata, Box::pin(DataLoaderNode { data, cursor: 0 })))
 	}
 }
 
@@ -113,6 +198,10 @@ impl Node for DataLoaderNode {
 	fn is_seeker(&self) -> bool {
 		true
 	}
+
+	fn is_at_end(&self) -> Option<bool> {
+		Some(self.cursor >= self.data.len())
+	}
 	// async fn read<'s>(&'s mut self) -> Option<&'s mut (dyn AsyncRead + Unpin)> {
 	// 	Some(self)
 	// }
@@ -203,8 +292,9 @@ impl AsyncSeek for DataLoaderNode {
 #[cfg(test)]
 #[cfg(feature = "backend_tokio")]
 mod async_tokio_tests {
+	use super::PARSE_COUNT;
 	use crate::scheme::NodeGetOptions;
-	use crate::Vfs;
+	use crate::{DataLoaderError, SchemeError, Vfs, VfsError};
 	use futures_lite::io::SeekFrom;
 	use futures_lite::{AsyncReadExt, AsyncSeekExt};
 	use url::Url;
@@ -239,6 +329,22 @@ mod async_tokio_tests {
 		);
 	}
 
+	#[tokio::test]
+	async fn path_is_invalid() {
+		let vfs = Vfs::default();
+		let url = u("data:/with/path");
+		assert!(matches!(
+			vfs.get_node(&url, &NodeGetOptions::new().read(true)).await,
+			Err(VfsError::SchemeError(SchemeError::InvalidDataUrl(_)))
+		));
+		match vfs.metadata(&url).await {
+			Err(VfsError::SchemeError(error @ SchemeError::InvalidDataUrl(_))) => {
+				assert!(error.to_string().starts_with("invalid data url: "))
+			}
+			other => panic!("expected an invalid data url error: {:?}", other),
+		}
+	}
+
 	#[tokio::test]
 	async fn node_reading() {
 		let vfs = Vfs::default();
@@ -267,6 +373,67 @@ mod async_tokio_tests {
 		assert_eq!(&buffer, "st");
 	}
 
+	async fn read_data(vfs: &Vfs, uri: &str) -> Vec<u8> {
+		let mut node = vfs
+			.get_node_at(uri, &NodeGetOptions::new().read(true))
+			.await
+			.unwrap();
+		let mut buffer = Vec::new();
+		node.read_to_end(&mut buffer).await.unwrap();
+		buffer
+	}
+
+	#[tokio::test]
+	async fn base64_variants() {
+		let vfs = Vfs::default();
+		assert_eq!(
+			read_data(&vfs, "data:base64,U29tZSB0ZXN0IHRleHQ=").await,
+			b"Some test text",
+			"standard"
+		);
+		assert_eq!(
+			read_data(&vfs, "data:base64,U29tZSB0ZXN0IHRleHQ").await,
+			b"Some test text",
+			"unpadded"
+		);
+		assert_eq!(
+			read_data(&vfs, "data:base64,U29tZSB0ZXN0IHRleHQ%3D").await,
+			b"Some test text",
+			"percent-encoded padding"
+		);
+		assert_eq!(
+			read_data(&vfs, "data:application/octet-stream;base64,-_-_").await,
+			[0xfb, 0xff, 0xbf],
+			"url-safe"
+		);
+		assert_eq!(
+			read_data(&vfs, "data:base64,-_8").await,
+			[0xfb, 0xff],
+			"url-safe unpadded"
+		);
+	}
+
+	#[tokio::test]
+	async fn base64_invalid() {
+		let vfs = Vfs::default();
+		let error = vfs
+			.get_node_at(
+				"data:base64,U29tZ$B0ZXN0",
+				&NodeGetOptions::new().read(true),
+			)
+			.await
+			.err()
+			.unwrap();
+		let mut source: &dyn std::error::Error = &error;
+		while let Some(next) = source.source() {
+			source = next;
+			if source.is::<DataLoaderError>() {
+				break;
+			}
+		}
+		assert_eq!(source.to_string(), "invalid base64 byte 0x24 at offset 5");
+	}
+
 	#[tokio::test]
 	async fn node_writing() {
 		let vfs = Vfs::default();
@@ -276,4 +443,20 @@ mod async_tokio_tests {
 			.unwrap();
 		assert!(!node.is_writer());
 	}
+
+	#[tokio::test]
+	async fn stat_and_open_decodes_once() {
+		let vfs = Vfs::default();
+		let parse_count = || PARSE_COUNT.with(|count| count.get());
+		let before = parse_count();
+		let (metadata, mut node) = vfs
+			.stat_and_open_at("data:;base64,aGVsbG8=", &NodeGetOptions::new().read(true))
+			.await
+			.unwrap();
+		assert_eq!(parse_count() - before, 1);
+		assert_eq!(metadata.len, Some((5, Some(5))));
+		let mut buffer = String::new();
+		node.read_to_string(&mut buffer).await.unwrap();
+		assert_eq!(&buffer, "hello");
+	}
 }
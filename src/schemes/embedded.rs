@@ -1,5 +1,5 @@
 use crate::node::poll_io_err;
-use crate::scheme::{NodeEntry, NodeGetOptions, NodeMetadata, ReadDirStream};
+use crate::scheme::{BorrowedReadDirStream, NodeEntry, NodeGetOptions, NodeKind, NodeMetadata};
 use crate::{Node, PinnedNode, Scheme, SchemeError, Vfs};
 use futures_lite::{AsyncRead, AsyncSeek, AsyncWrite, Stream};
 use rust_embed::RustEmbed;
@@ -12,13 +12,16 @@ use std::task::{Context, Poll};
 use url::Url;
 
 pub struct EmbeddedScheme<Embed: RustEmbed + Send + Sync + 'static> {
+	/// Prepended to url paths to get the embedded path, either empty or ending in `/`.
+	prefix: String,
 	_phantom: PhantomData<Embed>,
 }
 
 impl<Embed: RustEmbed + Send + Sync + 'static> Default for EmbeddedScheme<Embed> {
 	fn default() -> Self {
 		EmbeddedScheme {
-			_phantom: PhantomData::default(),
+			prefix: String::new(),
+			_phantom: PhantomData,
 		}
 	}
 }
@@ -27,6 +30,49 @@ impl<Embed: RustEmbed + Send + Sync + 'static> EmbeddedScheme<Embed> {
 	pub fn new() -> Self {
 		Self::default()
 	}
+
+	/// Only serves the embedded files under the `prefix` directory, at the root of the scheme, so
+	/// with a prefix of `assets` the url `embed:/logo.png` is the embedded `assets/logo.png`.
+	pub fn with_prefix(prefix: &str) -> Self {
+		let prefix = prefix.trim_matches('/');
+		Self {
+			prefix: if prefix.is_empty() {
+				String::new()
+			} else {
+				format!("{}/", prefix)
+			},
+			_phantom: PhantomData,
+		}
+	}
+
+	/// The path of the embedded file at the url `path`.
+	fn embedded_path(&self, path: &str) -> String {
+		format!("{}{}", self.prefix, path.get(1..).unwrap_or_default())
+	}
+
+	/// Embedded files have no real directories, so a directory is the root, a path with a trailing
+	/// `/`, or any path that embedded files are stored under.
+	fn is_dir(&self, path: &str) -> bool {
+		let path = path.strip_prefix('/').unwrap_or(path);
+		let embedded_path = self.embedded_path(&format!("/{}", path));
+		path.is_empty()
+			|| path.ends_with('/')
+			|| Embed::iter().any(|file| {
+				file.strip_prefix(embedded_path.as_str())
+					.is_some_and(|rest| rest.starts_with('/'))
+			})
+	}
+}
+
+/// Embedded files carry nothing but their content, and are compiled in so cannot be written.
+fn embedded_metadata(data: &[u8]) -> NodeMetadata {
+	NodeMetadata {
+		is_node: true,
+		len: Some((data.len(), Some(data.len()))),
+		kind: Some(NodeKind::File),
+		read_only: Some(true),
+		..Default::default()
+	}
 }
 
 #[async_trait::async_trait]
@@ -37,12 +83,11 @@ impl<Embed: RustEmbed + Send + Sync + 'static> Scheme for EmbeddedScheme<Embed>
 		url: &'a Url,
 		options: &NodeGetOptions,
 	) -> Result<PinnedNode, SchemeError<'a>> {
-		if url.path().is_empty() {
-			return Err(SchemeError::NodeDoesNotExist(Cow::Borrowed(url.path())));
-		}
 		if options.get_read() {
-			if let Some(data) = Embed::get(&url.path()[1..]) {
+			if let Some(data) = Embed::get(&self.embedded_path(url.path())) {
 				Ok(Box::pin(EmbeddedNode { data, cursor: 0 }))
+			} else if self.is_dir(url.path()) {
+				Err(SchemeError::IsADirectory(Cow::Borrowed(url.path())))
 			} else {
 				Err(SchemeError::NodeDoesNotExist(Cow::Borrowed(url.path())))
 			}
@@ -65,70 +110,118 @@ impl<Embed: RustEmbed + Send + Sync + 'static> Scheme for EmbeddedScheme<Embed>
 		_vfs: &Vfs,
 		url: &'a Url,
 	) -> Result<NodeMetadata, SchemeError<'a>> {
-		if url.path().is_empty() {
-			return Err(SchemeError::NodeDoesNotExist(Cow::Borrowed(url.path())));
-		}
-		if let Some(data) = Embed::get(&url.path()[1..]) {
+		if let Some(data) = Embed::get(&self.embedded_path(url.path())) {
+			Ok(embedded_metadata(&data))
+		} else if self.is_dir(url.path()) {
 			Ok(NodeMetadata {
-				is_node: true,
-				len: Some((data.len(), Some(data.len()))),
+				is_node: false,
+				kind: Some(NodeKind::Directory),
+				read_only: Some(true),
+				..Default::default()
 			})
 		} else {
 			Err(SchemeError::NodeDoesNotExist(Cow::Borrowed(url.path())))
 		}
 	}
 
-	async fn read_dir<'a>(
+	#[cfg(feature = "bytes")]
+	async fn read_bytes<'a>(
 		&self,
 		_vfs: &Vfs,
 		url: &'a Url,
-	) -> Result<ReadDirStream, SchemeError<'a>> {
-		let mut path = url.path();
+	) -> Result<Option<bytes::Bytes>, SchemeError<'a>> {
+		Ok(
+			Embed::get(&self.embedded_path(url.path())).map(|data| match data {
+				Cow::Borrowed(data) => bytes::Bytes::from_static(data),
+				// Files are loaded from disk when not compiled in, such as in debug builds
+				Cow::Owned(data) => bytes::Bytes::from(data),
+			}),
+		)
+	}
+
+	async fn read_dir<'s, 'a>(
+		&'s self,
+		_vfs: &'s Vfs,
+		url: &'a Url,
+	) -> Result<BorrowedReadDirStream<'s>, SchemeError<'a>> {
+		let path = url.path();
 		if !path.starts_with('/') {
 			return Err(SchemeError::UrlAccessError(Cow::Borrowed(url)));
 		}
-		if !path.ends_with('/') {
-			if let Some(pos) = path.rfind('/') {
-				path = &path[..pos];
-			} else {
-				return Err(SchemeError::UrlAccessError(Cow::Borrowed(url)));
-			}
-		}
+		let path = if path.ends_with('/') {
+			Cow::Borrowed(path)
+		} else if Embed::get(&self.embedded_path(path)).is_some() {
+			return Err(SchemeError::NotADirectory(Cow::Borrowed(path)));
+		} else {
+			Cow::Owned(format!("{}/", path))
+		};
 		// RustEmbed doesn't have `Send` on it's internal debug iterator, so no compile, even though
 		// there's no reason it couldn't have it, plus why don't we just get a slice of names of the
 		// filenames anyway?  Meh, packing it all together here...
-		let data: Vec<_> = Embed::iter().collect();
+		// TODO:  Just return things in the current 'directory'
+		let base_path = self.embedded_path(&path);
+		let data: Vec<_> = Embed::iter()
+			.filter(|name| name.starts_with(base_path.as_str()))
+			.map(|name| {
+				let metadata = Embed::get(&name).map(|data| embedded_metadata(&data));
+				(name, metadata)
+			})
+			.collect();
+		// Entries are built from the root as the names hold the whole path below the prefix
 		let mut url = url.clone();
-		url.set_path(path);
-		Ok(Box::pin(EmbeddedReadDir(data.into_iter(), url)))
+		url.set_path("/");
+		url.set_query(None);
+		url.set_fragment(None);
+		Ok(Box::pin(EmbeddedReadDir(
+			data.into_iter(),
+			url,
+			self.prefix.len(),
+		)))
+	}
+
+	async fn stat_and_open<'a>(
+		&self,
+		vfs: &Vfs,
+		url: &'a Url,
+		options: &NodeGetOptions,
+	) -> Result<(NodeMetadata, PinnedNode), SchemeError<'a>> {
+		// Load the file once and take its length from the data the node will read
+		match Embed::get(&self.embedded_path(url.path())) {
+			Some(data) if options.get_read() => {
+				let metadata = embedded_metadata(&data);
+				Ok((metadata, Box::pin(EmbeddedNode { data, cursor: 0 })))
+			}
+			// Let `get_node` report why it cannot be opened
+			_ => Err(self
+				.get_node(vfs, url, options)
+				.await
+				.err()
+				.unwrap_or(SchemeError::NodeDoesNotExist(Cow::Borrowed(url.path())))),
+		}
 	}
 }
 
-struct EmbeddedReadDir(std::vec::IntoIter<Cow<'static, str>>, Url);
+/// The names under the directory with their metadata, the url listed, and the length of the prefix to strip from the
+/// names.
+struct EmbeddedReadDir(
+	std::vec::IntoIter<(Cow<'static, str>, Option<NodeMetadata>)>,
+	Url,
+	usize,
+);
 
 impl Stream for EmbeddedReadDir {
 	type Item = NodeEntry;
 
 	fn poll_next(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
 		let this = self.get_mut();
-		let base_path = &this.1.path()[1..]; // `read_dir` already checked for the prefix '/'
-		loop {
-			if let Some(path) = this.0.next() {
-				if path.starts_with(base_path) {
-					// TODO:  Just return things in the current 'directory'
-					if let Ok(url) = Url::parse(&format!("{}:/{}", this.1.scheme(), path)) {
-						let entry = NodeEntry { url };
-						return Poll::Ready(Some(entry));
-					} else {
-						return Poll::Ready(None);
-					}
-				} else {
-					continue;
-				}
-			} else {
-				return Poll::Ready(None);
+		// `read_dir` already filtered the names down to those under the requested path
+		for (path, metadata) in &mut this.0 {
+			if let Some(mut entry) = NodeEntry::child(&this.1, path[this.2..].split('/')) {
+				entry.metadata = metadata;
+				return Poll::Ready(Some(entry));
 			}
 		}
+		Poll::Ready(None)
 	}
 
 	fn size_hint(&self) -> (usize, Option<usize>) {
@@ -154,6 +247,10 @@ impl Node for EmbeddedNode {
 	fn is_seeker(&self) -> bool {
 		true
 	}
+
+	fn is_at_end(&self) -> Option<bool> {
+		Some(self.cursor >= self.data.len())
+	}
 	// async fn read<'s>(&'s mut self) -> Option<&'s mut (dyn AsyncRead + Unpin)> {
 	// 	Some(self)
 	// }
@@ -245,7 +342,7 @@ impl AsyncSeek for EmbeddedNode {
 #[cfg(feature = "backend_tokio")]
 mod async_tokio_tests {
 	use crate::scheme::NodeGetOptions;
-	use crate::{EmbeddedScheme, Vfs};
+	use crate::{EmbeddedScheme, SchemeError, Vfs, VfsError};
 	use futures_lite::io::SeekFrom;
 	use futures_lite::{AsyncReadExt, AsyncSeekExt, StreamExt};
 	use url::Url;
@@ -260,7 +357,7 @@ mod async_tokio_tests {
 
 	#[tokio::test]
 	async fn embed_read() {
-		let mut vfs = Vfs::empty();
+		let vfs = Vfs::empty();
 		vfs.add_scheme("embed", EmbeddedScheme::<EmbedTest>::new())
 			.unwrap();
 		let read = &NodeGetOptions::new().read(true);
@@ -276,9 +373,23 @@ mod async_tokio_tests {
 		buffer.clear();
 	}
 
+	#[cfg(feature = "bytes")]
+	#[tokio::test]
+	async fn embed_read_to_bytes() {
+		let vfs = Vfs::empty();
+		vfs.add_scheme("embed", EmbeddedScheme::<EmbedTest>::new())
+			.unwrap();
+		let data = vfs.read_to_bytes_at("embed:/full_tokio.rs").await.unwrap();
+		assert_eq!(
+			data,
+			std::fs::read("examples/full_tokio.rs").unwrap().as_slice()
+		);
+		assert!(vfs.read_to_bytes_at("embed:/nothing/here").await.is_err());
+	}
+
 	#[tokio::test]
 	async fn embed_seeking() {
-		let mut vfs = Vfs::empty();
+		let vfs = Vfs::empty();
 		vfs.add_scheme("embed", EmbeddedScheme::<EmbedTest>::new())
 			.unwrap();
 		let read = &NodeGetOptions::new().read(true);
@@ -297,13 +408,82 @@ mod async_tokio_tests {
 
 	#[tokio::test]
 	async fn embed_read_dir() {
-		let mut vfs = Vfs::empty();
+		let vfs = Vfs::empty();
 		vfs.add_scheme("embed", EmbeddedScheme::<EmbedTest>::new())
 			.unwrap();
 		assert!(vfs.read_dir_at("embed:/").await.unwrap().count().await > 0);
+		assert_eq!(
+			vfs.read_dir_at("embed:/full/").await.unwrap().size_hint(),
+			(1, Some(1))
+		);
 		assert_eq!(
 			vfs.read_dir_at("embed:/full/").await.unwrap().count().await,
 			1
 		);
+		assert_eq!(
+			vfs.read_dir_at("embed:/full").await.unwrap().count().await,
+			1
+		);
+		assert!(matches!(
+			vfs.read_dir_at("embed:/full_tokio.rs").await,
+			Err(VfsError::SchemeError(SchemeError::NotADirectory(_)))
+		));
+
+		let mut entries = vfs.read_dir_at("embed:/").await.unwrap();
+		while entries.next().await.is_some() {}
+		assert!(entries.next().await.is_none(), "fused");
+		assert!(entries.next().await.is_none(), "fused");
+	}
+
+	#[tokio::test]
+	async fn embed_directories() {
+		let vfs = Vfs::empty();
+		vfs.add_scheme("embed", EmbeddedScheme::<EmbedTest>::new())
+			.unwrap();
+		let read = &NodeGetOptions::new().read(true);
+		for uri in ["embed:/", "embed:/full", "embed:/full/"] {
+			assert!(
+				matches!(
+					vfs.get_node_at(uri, read).await,
+					Err(VfsError::SchemeError(SchemeError::IsADirectory(_)))
+				),
+				"{}",
+				uri
+			);
+			assert!(!vfs.metadata_at(uri).await.unwrap().is_node, "{}", uri);
+		}
+		assert!(vfs.metadata_at("embed:/fu").await.is_err());
+		assert!(vfs.metadata_at("embed:/full/mod.rs").await.unwrap().is_node);
+	}
+
+	#[tokio::test]
+	async fn embed_prefix() {
+		let vfs = Vfs::empty();
+		vfs.add_scheme("embed", EmbeddedScheme::<EmbedTest>::with_prefix("full"))
+			.unwrap();
+		let read = &NodeGetOptions::new().read(true);
+		let mut buffer = String::new();
+		vfs.get_node_at("embed:/mod.rs", read)
+			.await
+			.unwrap()
+			.read_to_string(&mut buffer)
+			.await
+			.unwrap();
+		assert_eq!(
+			buffer,
+			std::fs::read_to_string("examples/full/mod.rs").unwrap()
+		);
+		assert!(vfs.metadata_at("embed:/mod.rs").await.unwrap().is_node);
+		assert!(!vfs.metadata_at("embed:/").await.unwrap().is_node);
+		assert!(vfs.get_node_at("embed:/full_tokio.rs", read).await.is_err());
+		assert!(vfs.get_node_at("embed:/full/mod.rs", read).await.is_err());
+		let entries: Vec<_> = vfs
+			.read_dir_at("embed:/")
+			.await
+			.unwrap()
+			.map(|entry| entry.url.to_string())
+			.collect()
+			.await;
+		assert_eq!(entries, ["embed:/mod.rs"]);
 	}
 }
@@ -0,0 +1,735 @@
+use crate::node::{seek_position, IsAllowed};
+use crate::scheme::{BorrowedReadDirStream, NodeEntry, NodeGetOptions, NodeMetadata, WatchStream};
+use crate::{Node, PinnedNode, Scheme, SchemeError, Vfs};
+use futures_lite::{AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt, AsyncWrite, AsyncWriteExt};
+use futures_lite::{Future, StreamExt};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, NONCE_LEN};
+use ring::rand::{SecureRandom, SystemRandom};
+use std::io::SeekFrom;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::SystemTime;
+use url::Url;
+
+/// The length of plaintext sealed into each chunk of an encrypted node, the last chunk may be
+/// shorter.  Reads and writes only ever decrypt and encrypt the chunks they touch.
+pub const ENCRYPTED_CHUNK_SIZE: usize = 64 * 1024;
+
+const MAGIC: &[u8; 8] = b"vfsenc\x01\x00";
+const ID_LEN: usize = 16;
+const TAG_LEN: usize = 16;
+const SEALED_OVERHEAD: usize = NONCE_LEN + TAG_LEN;
+const HEADER_LEN: usize = MAGIC.len() + ID_LEN + SEALED_OVERHEAD + 8;
+const SEALED_CHUNK_LEN: usize = ENCRYPTED_CHUNK_SIZE + SEALED_OVERHEAD;
+
+/// The AEAD cipher an `EncryptedScheme` seals its chunks with, both take a 256-bit key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Cipher {
+	Aes256Gcm,
+	ChaCha20Poly1305,
+}
+
+/// Wraps a scheme and transparently encrypts the content of its nodes on write and decrypts it on
+/// read, everything else, such as listing, removing and renaming, is forwarded as-is.
+///
+/// The stored form of a node is a header holding a random id and the sealed plaintext length,
+/// followed by the content in `ENCRYPTED_CHUNK_SIZE` chunks each sealed with its own random nonce
+/// and authenticated against the id and its index, so chunks cannot be reordered or moved between
+/// nodes, and seeking only has to decrypt the chunk it lands in.  Lengths reported by `metadata`
+/// and `read_dir` are those of the plaintext.  Seeks are clamped to the end of the content, like
+/// the key-value schemes, so writes never leave gaps.  `read_small_file` and `read_bytes` are not
+/// forwarded as they would hand back the sealed content, and the inner scheme has to support
+/// seeking for any node opened through this one.
+pub struct EncryptedScheme {
+	scheme: Box<dyn Scheme>,
+	sealer: Arc<Sealer>,
+}
+
+impl EncryptedScheme {
+	pub fn new(scheme: impl Scheme, cipher: Cipher, key: &[u8; 32]) -> Self {
+		Self::new_boxed(Box::new(scheme), cipher, key)
+	}
+
+	pub fn new_boxed(scheme: Box<dyn Scheme>, cipher: Cipher, key: &[u8; 32]) -> Self {
+		let algorithm = match cipher {
+			Cipher::Aes256Gcm => &ring::aead::AES_256_GCM,
+			Cipher::ChaCha20Poly1305 => &ring::aead::CHACHA20_POLY1305,
+		};
+		let key = UnboundKey::new(algorithm, key).expect("both ciphers take a 32 byte key");
+		Self {
+			scheme,
+			sealer: Arc::new(Sealer {
+				key: LessSafeKey::new(key),
+				rng: SystemRandom::new(),
+			}),
+		}
+	}
+
+	pub fn inner(&self) -> &dyn Scheme {
+		&*self.scheme
+	}
+
+	pub fn into_inner(self) -> Box<dyn Scheme> {
+		self.scheme
+	}
+}
+
+/// The plaintext length of a node stored as `stored` bytes, `None` if no well-formed node is that
+/// long.
+fn plaintext_len(stored: usize) -> Option<usize> {
+	if stored == 0 {
+		return Some(0);
+	}
+	let body = stored.checked_sub(HEADER_LEN)?;
+	let (chunks, rest) = (body / SEALED_CHUNK_LEN, body % SEALED_CHUNK_LEN);
+	match rest {
+		0 => Some(chunks * ENCRYPTED_CHUNK_SIZE),
+		rest if rest > SEALED_OVERHEAD => {
+			Some(chunks * ENCRYPTED_CHUNK_SIZE + rest - SEALED_OVERHEAD)
+		}
+		_ => None,
+	}
+}
+
+fn plaintext_metadata(mut metadata: NodeMetadata) -> NodeMetadata {
+	if metadata.is_node {
+		metadata.len = metadata
+			.len
+			.and_then(|(len, _)| plaintext_len(len))
+			.map(|len| (len, Some(len)));
+	}
+	metadata
+}
+
+fn chunk_aad(id: &[u8; ID_LEN], index: u64) -> [u8; ID_LEN + 8] {
+	let mut aad = [0; ID_LEN + 8];
+	aad[..ID_LEN].copy_from_slice(id);
+	aad[ID_LEN..].copy_from_slice(&index.to_le_bytes());
+	aad
+}
+
+fn header_aad(id: &[u8; ID_LEN]) -> [u8; MAGIC.len() + ID_LEN] {
+	let mut aad = [0; MAGIC.len() + ID_LEN];
+	aad[..MAGIC.len()].copy_from_slice(MAGIC);
+	aad[MAGIC.len()..].copy_from_slice(id);
+	aad
+}
+
+fn invalid_data(message: &'static str) -> std::io::Error {
+	std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
+
+struct Sealer {
+	key: LessSafeKey,
+	rng: SystemRandom,
+}
+
+impl Sealer {
+	fn random_id(&self) -> std::io::Result<[u8; ID_LEN]> {
+		let mut id = [0; ID_LEN];
+		self.rng
+			.fill(&mut id)
+			.map_err(|_| std::io::Error::other("no randomness available"))?;
+		Ok(id)
+	}
+
+	/// The nonce, the ciphertext and the tag, in that order.
+	fn seal(&self, aad: &[u8], plaintext: &[u8]) -> std::io::Result<Vec<u8>> {
+		let mut nonce = [0; NONCE_LEN];
+		self.rng
+			.fill(&mut nonce)
+			.map_err(|_| std::io::Error::other("no randomness available"))?;
+		let mut sealed = Vec::with_capacity(plaintext.len() + SEALED_OVERHEAD);
+		sealed.extend_from_slice(&nonce);
+		sealed.extend_from_slice(plaintext);
+		let tag = self
+			.key
+			.seal_in_place_separate_tag(
+				Nonce::assume_unique_for_key(nonce),
+				Aad::from(aad),
+				&mut sealed[NONCE_LEN..],
+			)
+			.map_err(|_| std::io::Error::other("encryption failed"))?;
+		sealed.extend_from_slice(tag.as_ref());
+		Ok(sealed)
+	}
+
+	fn open(&self, aad: &[u8], mut sealed: Vec<u8>) -> std::io::Result<Vec<u8>> {
+		if sealed.len() < SEALED_OVERHEAD {
+			return Err(invalid_data("encrypted chunk is truncated"));
+		}
+		let nonce = Nonce::try_assume_unique_for_key(&sealed[..NONCE_LEN])
+			.map_err(|_| invalid_data("encrypted chunk is truncated"))?;
+		let len = self
+			.key
+			.open_in_place(nonce, Aad::from(aad), &mut sealed[NONCE_LEN..])
+			.map_err(|_| invalid_data("encrypted content failed to authenticate"))?
+			.len();
+		sealed.drain(..NONCE_LEN);
+		sealed.truncate(len);
+		Ok(sealed)
+	}
+
+	fn seal_header(&self, id: &[u8; ID_LEN], len: u64) -> std::io::Result<Vec<u8>> {
+		let mut header = Vec::with_capacity(HEADER_LEN);
+		header.extend_from_slice(MAGIC);
+		header.extend_from_slice(id);
+		header.extend_from_slice(&self.seal(&header_aad(id), &len.to_le_bytes())?);
+		Ok(header)
+	}
+
+	fn open_header(&self, header: &[u8]) -> std::io::Result<([u8; ID_LEN], u64)> {
+		if header.len() != HEADER_LEN || !header.starts_with(MAGIC) {
+			return Err(invalid_data("not an encrypted node"));
+		}
+		let mut id = [0; ID_LEN];
+		id.copy_from_slice(&header[MAGIC.len()..MAGIC.len() + ID_LEN]);
+		let len = self.open(&header_aad(&id), header[MAGIC.len() + ID_LEN..].to_vec())?;
+		let mut bytes = [0; 8];
+		bytes.copy_from_slice(&len);
+		Ok((id, u64::from_le_bytes(bytes)))
+	}
+}
+
+#[async_trait::async_trait]
+impl Scheme for EncryptedScheme {
+	async fn get_node<'a>(
+		&self,
+		vfs: &Vfs,
+		url: &'a Url,
+		options: &NodeGetOptions,
+	) -> Result<PinnedNode, SchemeError<'a>> {
+		// The inner node is always read, even for a write-only node, to load the chunks a write
+		// only partially covers, and appends are resolved here against the plaintext length, so
+		// an append still opens the inner node for writing, as `Vfs` accepts `create` with it.
+		let inner_options = options
+			.clone()
+			.read(true)
+			.append(false)
+			.write(options.get_write() || options.get_append());
+		let mut inner = self.scheme.get_node(vfs, url, &inner_options).await?;
+		let mut header = Vec::with_capacity(HEADER_LEN);
+		(&mut inner)
+			.take(HEADER_LEN as u64)
+			.read_to_end(&mut header)
+			.await?;
+		let header_stored = !header.is_empty();
+		let (id, len) = if header_stored {
+			self.sealer.open_header(&header)?
+		} else {
+			(self.sealer.random_id()?, 0)
+		};
+		Ok(Box::pin(EncryptedNode {
+			sealer: self.sealer.clone(),
+			state: State::Idle(inner),
+			id,
+			len,
+			cursor: 0,
+			chunk: None,
+			header_dirty: !header_stored && options.get_write(),
+			header_stored,
+			read: options.get_read(),
+			write: options.get_write(),
+			append: options.get_append(),
+		}))
+	}
+
+	async fn remove_node<'a>(
+		&self,
+		vfs: &Vfs,
+		url: &'a Url,
+		force: bool,
+	) -> Result<(), SchemeError<'a>> {
+		self.scheme.remove_node(vfs, url, force).await
+	}
+
+	async fn metadata<'a>(&self, vfs: &Vfs, url: &'a Url) -> Result<NodeMetadata, SchemeError<'a>> {
+		self.scheme.metadata(vfs, url).await.map(plaintext_metadata)
+	}
+
+	async fn read_dir<'s, 'a>(
+		&'s self,
+		vfs: &'s Vfs,
+		url: &'a Url,
+	) -> Result<BorrowedReadDirStream<'s>, SchemeError<'a>> {
+		let entries = self.scheme.read_dir(vfs, url).await?;
+		Ok(Box::pin(entries.map(|entry| NodeEntry {
+			url: entry.url,
+			metadata: entry.metadata.map(plaintext_metadata),
+		})))
+	}
+
+	async fn canonicalize<'a>(&self, vfs: &Vfs, url: &'a Url) -> Result<Url, SchemeError<'a>> {
+		self.scheme.canonicalize(vfs, url).await
+	}
+
+	async fn set_modified<'a>(
+		&self,
+		vfs: &Vfs,
+		url: &'a Url,
+		modified: SystemTime,
+	) -> Result<(), SchemeError<'a>> {
+		self.scheme.set_modified(vfs, url, modified).await
+	}
+
+	async fn create_dir<'a>(
+		&self,
+		vfs: &Vfs,
+		url: &'a Url,
+		parents: bool,
+	) -> Result<(), SchemeError<'a>> {
+		self.scheme.create_dir(vfs, url, parents).await
+	}
+
+	async fn copy_node<'a>(
+		&self,
+		vfs: &Vfs,
+		from: &'a Url,
+		to: &'a Url,
+	) -> Result<(), SchemeError<'a>> {
+		self.scheme.copy_node(vfs, from, to).await
+	}
+
+	async fn rename_node<'a>(
+		&self,
+		vfs: &Vfs,
+		from: &'a Url,
+		to: &'a Url,
+	) -> Result<(), SchemeError<'a>> {
+		self.scheme.rename_node(vfs, from, to).await
+	}
+
+	async fn watch<'a>(&self, vfs: &Vfs, url: &'a Url) -> Result<WatchStream, SchemeError<'a>> {
+		self.scheme.watch(vfs, url).await
+	}
+}
+
+struct Chunk {
+	index: u64,
+	data: Vec<u8>,
+	dirty: bool,
+}
+
+type Operation =
+	Pin<Box<dyn Future<Output = (PinnedNode, std::io::Result<Option<Chunk>>)> + Send + Sync>>;
+
+enum State {
+	Idle(PinnedNode),
+	Busy(Operation),
+	Taken,
+}
+
+/// A node of an `EncryptedScheme`, holding the one chunk the cursor is in decrypted, which is
+/// sealed and written back when the cursor leaves it or the node is flushed.
+pub struct EncryptedNode {
+	sealer: Arc<Sealer>,
+	state: State,
+	id: [u8; ID_LEN],
+	len: u64,
+	cursor: u64,
+	chunk: Option<Chunk>,
+	header_dirty: bool,
+	header_stored: bool,
+	read: bool,
+	write: bool,
+	append: bool,
+}
+
+impl EncryptedNode {
+	fn poll_pending(&mut self, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+		if let State::Busy(operation) = &mut self.state {
+			let (inner, result) = futures_lite::ready!(operation.as_mut().poll(cx));
+			self.state = State::Idle(inner);
+			if let Some(chunk) = result? {
+				self.chunk = Some(chunk);
+			}
+		}
+		Poll::Ready(Ok(()))
+	}
+
+	fn start(&mut self, operation: impl FnOnce(PinnedNode) -> Operation) {
+		match std::mem::replace(&mut self.state, State::Taken) {
+			State::Idle(inner) => self.state = State::Busy(operation(inner)),
+			_ => unreachable!("operations only start once the pending one is done"),
+		}
+	}
+
+	fn start_load(&mut self, index: u64) {
+		let start = index * ENCRYPTED_CHUNK_SIZE as u64;
+		if start >= self.len {
+			self.chunk = Some(Chunk {
+				index,
+				data: Vec::new(),
+				dirty: false,
+			});
+			return;
+		}
+		let plain_len = (self.len - start).min(ENCRYPTED_CHUNK_SIZE as u64) as usize;
+		let offset = (HEADER_LEN + index as usize * SEALED_CHUNK_LEN) as u64;
+		let sealer = self.sealer.clone();
+		let aad = chunk_aad(&self.id, index);
+		self.start(move |mut inner| {
+			Box::pin(async move {
+				let result = async {
+					inner.seek(SeekFrom::Start(offset)).await?;
+					let mut sealed = vec![0; plain_len + SEALED_OVERHEAD];
+					inner.read_exact(&mut sealed).await?;
+					let data = sealer.open(&aad, sealed)?;
+					Ok(Some(Chunk {
+						index,
+						data,
+						dirty: false,
+					}))
+				}
+				.await;
+				(inner, result)
+			})
+		});
+	}
+
+	fn start_store(&mut self, offset: u64, sealed: std::io::Result<Vec<u8>>) {
+		self.start(move |mut inner| {
+			Box::pin(async move {
+				let result = async {
+					let sealed = sealed?;
+					inner.seek(SeekFrom::Start(offset)).await?;
+					inner.write_all(&sealed).await?;
+					Ok(None)
+				}
+				.await;
+				(inner, result)
+			})
+		});
+	}
+
+	fn start_store_chunk(&mut self) {
+		// Inner nodes may clamp seeks to their end, so the header goes first on a new node.
+		if !self.header_stored {
+			return self.start_store_header();
+		}
+		let chunk = self.chunk.as_mut().expect("only stored when dirty");
+		chunk.dirty = false;
+		let offset = (HEADER_LEN + chunk.index as usize * SEALED_CHUNK_LEN) as u64;
+		let sealed = self
+			.sealer
+			.seal(&chunk_aad(&self.id, chunk.index), &chunk.data);
+		self.start_store(offset, sealed);
+	}
+
+	fn start_store_header(&mut self) {
+		self.header_dirty = false;
+		self.header_stored = true;
+		let sealed = self.sealer.seal_header(&self.id, self.len);
+		self.start_store(0, sealed);
+	}
+
+	/// Makes the chunk `index` the current one, writing back the current one first if dirty.
+	fn poll_chunk(&mut self, cx: &mut Context<'_>, index: u64) -> Poll<std::io::Result<()>> {
+		loop {
+			futures_lite::ready!(self.poll_pending(cx))?;
+			match &self.chunk {
+				Some(chunk) if chunk.index == index => return Poll::Ready(Ok(())),
+				Some(chunk) if chunk.dirty => self.start_store_chunk(),
+				_ => self.start_load(index),
+			}
+		}
+	}
+
+	/// Writes back the current chunk and then the header, whichever are dirty.
+	fn poll_store(&mut self, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+		loop {
+			futures_lite::ready!(self.poll_pending(cx))?;
+			if self.chunk.as_ref().is_some_and(|chunk| chunk.dirty) {
+				self.start_store_chunk();
+			} else if self.header_dirty {
+				self.start_store_header();
+			} else {
+				return Poll::Ready(Ok(()));
+			}
+		}
+	}
+
+	fn inner(&mut self) -> &mut PinnedNode {
+		match &mut self.state {
+			State::Idle(inner) => inner,
+			_ => unreachable!("only used once the pending operation is done"),
+		}
+	}
+}
+
+impl Node for EncryptedNode {
+	fn is_reader(&self) -> bool {
+		self.read
+	}
+
+	fn is_writer(&self) -> bool {
+		self.write
+	}
+
+	fn is_seeker(&self) -> bool {
+		true
+	}
+
+	fn known_len(&self) -> Option<u64> {
+		Some(self.len)
+	}
+
+	fn is_at_end(&self) -> Option<bool> {
+		Some(self.cursor >= self.len)
+	}
+}
+
+impl AsyncRead for EncryptedNode {
+	fn poll_read(
+		self: Pin<&mut Self>,
+		cx: &mut Context<'_>,
+		buf: &mut [u8],
+	) -> Poll<std::io::Result<usize>> {
+		let this = self.get_mut();
+		this.read.into_poll_io_then(|| {
+			if this.cursor >= this.len || buf.is_empty() {
+				return Poll::Ready(Ok(0));
+			}
+			let index = this.cursor / ENCRYPTED_CHUNK_SIZE as u64;
+			futures_lite::ready!(this.poll_chunk(cx, index))?;
+			let chunk = this.chunk.as_ref().expect("just loaded");
+			let offset = (this.cursor % ENCRYPTED_CHUNK_SIZE as u64) as usize;
+			let remaining = chunk.data.get(offset..).unwrap_or(&[]);
+			let amt = remaining.len().min(buf.len());
+			buf[..amt].copy_from_slice(&remaining[..amt]);
+			this.cursor += amt as u64;
+			Poll::Ready(Ok(amt))
+		})
+	}
+}
+
+impl AsyncWrite for EncryptedNode {
+	fn poll_write(
+		self: Pin<&mut Self>,
+		cx: &mut Context<'_>,
+		buf: &[u8],
+	) -> Poll<std::io::Result<usize>> {
+		let this = self.get_mut();
+		this.write.into_poll_io_then(|| {
+			if this.append {
+				this.cursor = this.len;
+			}
+			let index = this.cursor / ENCRYPTED_CHUNK_SIZE as u64;
+			futures_lite::ready!(this.poll_chunk(cx, index))?;
+			let chunk = this.chunk.as_mut().expect("just loaded");
+			let offset = (this.cursor % ENCRYPTED_CHUNK_SIZE as u64) as usize;
+			let amt = (ENCRYPTED_CHUNK_SIZE - offset).min(buf.len());
+			let overwritten = (chunk.data.len() - offset).min(amt);
+			chunk.data[offset..offset + overwritten].copy_from_slice(&buf[..overwritten]);
+			chunk.data.extend_from_slice(&buf[overwritten..amt]);
+			chunk.dirty = true;
+			this.cursor += amt as u64;
+			if this.cursor > this.len {
+				this.len = this.cursor;
+				this.header_dirty = true;
+			}
+			Poll::Ready(Ok(amt))
+		})
+	}
+
+	fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+		let this = self.get_mut();
+		this.write.into_poll_io_then(|| {
+			futures_lite::ready!(this.poll_store(cx))?;
+			this.inner().as_mut().poll_flush(cx)
+		})
+	}
+
+	fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+		let this = self.get_mut();
+		if !this.write {
+			return Poll::Ready(Ok(()));
+		}
+		futures_lite::ready!(this.poll_store(cx))?;
+		this.inner().as_mut().poll_close(cx)
+	}
+}
+
+impl AsyncSeek for EncryptedNode {
+	fn poll_seek(
+		self: Pin<&mut Self>,
+		_cx: &mut Context<'_>,
+		pos: SeekFrom,
+	) -> Poll<std::io::Result<u64>> {
+		let this = self.get_mut();
+		let position = seek_position(pos, this.cursor, Some(this.len))?.min(this.len);
+		this.cursor = position;
+		Poll::Ready(Ok(position))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{plaintext_len, ENCRYPTED_CHUNK_SIZE, HEADER_LEN, SEALED_CHUNK_LEN};
+
+	#[test]
+	fn plaintext_lengths() {
+		assert_eq!(plaintext_len(0), Some(0));
+		assert_eq!(plaintext_len(HEADER_LEN - 1), None);
+		assert_eq!(plaintext_len(HEADER_LEN), Some(0));
+		assert_eq!(plaintext_len(HEADER_LEN + 28 + 5), Some(5));
+		assert_eq!(plaintext_len(HEADER_LEN + 28), None);
+		assert_eq!(
+			plaintext_len(HEADER_LEN + SEALED_CHUNK_LEN),
+			Some(ENCRYPTED_CHUNK_SIZE)
+		);
+		assert_eq!(
+			plaintext_len(HEADER_LEN + 2 * SEALED_CHUNK_LEN + 28 + 1),
+			Some(2 * ENCRYPTED_CHUNK_SIZE + 1)
+		);
+	}
+}
+
+#[cfg(test)]
+#[cfg(feature = "backend_tokio")]
+#[cfg(feature = "in_memory")]
+mod async_tokio_tests {
+	use super::{HEADER_LEN, SEALED_CHUNK_LEN};
+	use crate::scheme::NodeGetOptions;
+	use crate::{Cipher, EncryptedScheme, MemoryScheme, Vfs, VfsError};
+	use futures_lite::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt, StreamExt};
+	use std::io::SeekFrom;
+	use url::Url;
+
+	async fn stored(vfs: &Vfs, path: &str) -> Vec<u8> {
+		let scheme = vfs.get_scheme_as::<EncryptedScheme>("enc").unwrap();
+		let url = Url::parse(&format!("enc:{}", path)).unwrap();
+		let mut node = scheme
+			.inner()
+			.get_node(vfs, &url, &NodeGetOptions::new().read(true))
+			.await
+			.unwrap();
+		let mut data = Vec::new();
+		node.read_to_end(&mut data).await.unwrap();
+		data
+	}
+
+	#[tokio::test]
+	async fn encrypted_memory() {
+		for cipher in [Cipher::Aes256Gcm, Cipher::ChaCha20Poly1305] {
+			let vfs = Vfs::empty();
+			vfs.add_scheme(
+				"enc",
+				EncryptedScheme::new(MemoryScheme::default(), cipher, &[7; 32]),
+			)
+			.unwrap();
+			let content: Vec<u8> = (0..150_000u32).map(|i| (i % 251) as u8).collect();
+			let mut node = vfs
+				.get_node_at("enc:/secret", &NodeGetOptions::new().create_new(true))
+				.await
+				.unwrap();
+			node.write_all(&content).await.unwrap();
+			node.close().await.unwrap();
+
+			let sealed = stored(&vfs, "/secret").await;
+			assert_eq!(
+				sealed.len(),
+				HEADER_LEN + 2 * SEALED_CHUNK_LEN + 28 + 18_928
+			);
+			assert!(!sealed.windows(64).any(|window| window == &content[..64]));
+			assert_eq!(vfs.read_to_vec_at("enc:/secret").await.unwrap(), content);
+			assert_eq!(
+				vfs.metadata_at("enc:/secret").await.unwrap().len,
+				Some((150_000, Some(150_000)))
+			);
+			let listed = vfs
+				.read_dir_at("enc:/")
+				.await
+				.unwrap()
+				.collect::<Vec<_>>()
+				.await;
+			assert_eq!(listed.len(), 1);
+
+			// Overwrite across a chunk boundary, then append past the end.
+			let mut node = vfs
+				.get_node_at("enc:/secret", &NodeGetOptions::new().read(true).write(true))
+				.await
+				.unwrap();
+			node.seek(SeekFrom::Start(65_530)).await.unwrap();
+			node.write_all(b"boundary").await.unwrap();
+			node.seek(SeekFrom::End(0)).await.unwrap();
+			node.write_all(b"tail").await.unwrap();
+			node.seek(SeekFrom::Start(65_528)).await.unwrap();
+			let mut read = [0; 12];
+			node.read_exact(&mut read).await.unwrap();
+			assert_eq!(&read, b"\x11\x12boundary\x1b\x1c");
+			node.close().await.unwrap();
+			let mut expected = content.clone();
+			expected[65_530..65_538].copy_from_slice(b"boundary");
+			expected.extend_from_slice(b"tail");
+			assert_eq!(vfs.read_to_vec_at("enc:/secret").await.unwrap(), expected);
+
+			let mut node = vfs
+				.get_node_at(
+					"enc:/secret",
+					&NodeGetOptions::new().write(true).append(true),
+				)
+				.await
+				.unwrap();
+			node.write_all(b"!").await.unwrap();
+			node.close().await.unwrap();
+			expected.push(b'!');
+			assert_eq!(vfs.read_to_vec_at("enc:/secret").await.unwrap(), expected);
+
+			// A different key cannot open what was sealed.
+			let sealed = stored(&vfs, "/secret").await;
+			let other = Vfs::empty();
+			other
+				.add_scheme(
+					"enc",
+					EncryptedScheme::new(MemoryScheme::default(), cipher, &[8; 32]),
+				)
+				.unwrap();
+			let scheme = other.get_scheme_as::<EncryptedScheme>("enc").unwrap();
+			let mut node = scheme
+				.inner()
+				.get_node(
+					&other,
+					&Url::parse("enc:/secret").unwrap(),
+					&NodeGetOptions::new().create_new(true),
+				)
+				.await
+				.unwrap();
+			node.write_all(&sealed).await.unwrap();
+			node.close().await.unwrap();
+			assert!(matches!(
+				other.read_to_vec_at("enc:/secret").await,
+				Err(VfsError::SchemeError(_))
+			));
+		}
+	}
+
+	#[tokio::test]
+	async fn encrypted_create_append() {
+		let vfs = Vfs::empty();
+		vfs.add_scheme(
+			"enc",
+			EncryptedScheme::new(
+				crate::TokioFileSystemScheme::new(std::env::current_dir().unwrap()),
+				Cipher::Aes256Gcm,
+				&[7; 32],
+			),
+		)
+		.unwrap();
+		let _ = std::fs::remove_file("target/encrypted_create_append");
+		vfs.get_node_at(
+			"enc:/target/encrypted_create_append",
+			&NodeGetOptions::new().create(true).write(false).append(true),
+		)
+		.await
+		.unwrap();
+		assert_eq!(
+			vfs.read_to_vec_at("enc:/target/encrypted_create_append")
+				.await
+				.unwrap(),
+			b""
+		);
+		std::fs::remove_file("target/encrypted_create_append").unwrap();
+	}
+}
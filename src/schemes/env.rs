@@ -0,0 +1,298 @@
+use crate::node::{poll_io_err, seek_position};
+use crate::scheme::{BorrowedReadDirStream, NodeEntry, NodeGetOptions, NodeKind, NodeMetadata};
+use crate::{Node, PinnedNode, Scheme, SchemeError, Vfs};
+use futures_lite::{AsyncRead, AsyncSeek, AsyncWrite};
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use std::borrow::Cow;
+use std::io::SeekFrom;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use url::Url;
+
+/// What is percent-encoded of a variable name in the urls `read_dir` hands back.
+const NAME_ENCODE_SET: &AsciiSet = &NON_ALPHANUMERIC.remove(b'_').remove(b'-').remove(b'.');
+
+/// Serves the environment variables of the process as small read-only nodes holding their value,
+/// `env:HOME` or `env:/HOME`, so config loaders reading through the vfs can read them too.  The
+/// root, `env:` or `env:/`, is a directory listing every variable.  Variables are read when asked
+/// for, so later changes to the environment are seen, and values that are not valid UTF-8 are
+/// read lossily.  `with_prefix` limits what is served to the variables starting with a prefix.
+#[derive(Default)]
+pub struct EnvScheme {
+	prefix: String,
+}
+
+impl EnvScheme {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Only serves, and lists, the variables whose name starts with `prefix`, such as `APP_`.
+	pub fn with_prefix(prefix: impl Into<String>) -> Self {
+		Self {
+			prefix: prefix.into(),
+		}
+	}
+
+	pub fn prefix(&self) -> &str {
+		&self.prefix
+	}
+
+	/// The variable name `url` names, `None` for the root.
+	fn name<'a>(url: &'a Url) -> Option<Cow<'a, str>> {
+		let path = url.path();
+		let path = path.strip_prefix('/').unwrap_or(path);
+		if path.is_empty() {
+			None
+		} else {
+			Some(percent_decode_str(path).decode_utf8_lossy())
+		}
+	}
+
+	/// The value of the variable `name`, `None` if it is not set or not served.
+	pub fn get(&self, name: &str) -> Option<String> {
+		// `var_os` may panic on names the platform cannot hold
+		if !name.starts_with(&self.prefix) || name.is_empty() || name.contains(['=', '\0']) {
+			return None;
+		}
+		std::env::var_os(name).map(|value| value.to_string_lossy().into_owned())
+	}
+
+	fn value<'a>(&self, url: &'a Url) -> Result<String, SchemeError<'a>> {
+		match Self::name(url) {
+			None => Err(SchemeError::IsADirectory(Cow::Borrowed(url.path()))),
+			Some(name) => self
+				.get(&name)
+				.ok_or(SchemeError::NodeDoesNotExist(Cow::Borrowed(url.path()))),
+		}
+	}
+}
+
+fn env_metadata(value: &str) -> NodeMetadata {
+	NodeMetadata {
+		is_node: true,
+		len: Some((value.len(), Some(value.len()))),
+		kind: Some(NodeKind::File),
+		read_only: Some(true),
+		..Default::default()
+	}
+}
+
+#[async_trait::async_trait]
+impl Scheme for EnvScheme {
+	async fn get_node<'a>(
+		&self,
+		_vfs: &Vfs,
+		url: &'a Url,
+		options: &NodeGetOptions,
+	) -> Result<PinnedNode, SchemeError<'a>> {
+		if !options.get_read() || options.get_write() || options.get_append() {
+			return Err(SchemeError::UrlAccessError(Cow::Borrowed(url)));
+		}
+		let data = self.value(url)?.into_bytes();
+		Ok(Box::pin(EnvNode { data, cursor: 0 }))
+	}
+
+	async fn remove_node<'a>(
+		&self,
+		_vfs: &Vfs,
+		url: &'a Url,
+		_force: bool,
+	) -> Result<(), SchemeError<'a>> {
+		Err(SchemeError::UrlAccessError(Cow::Borrowed(url)))
+	}
+
+	async fn metadata<'a>(
+		&self,
+		_vfs: &Vfs,
+		url: &'a Url,
+	) -> Result<NodeMetadata, SchemeError<'a>> {
+		match Self::name(url) {
+			None => Ok(NodeMetadata {
+				is_node: false,
+				kind: Some(NodeKind::Directory),
+				read_only: Some(true),
+				..Default::default()
+			}),
+			Some(_name) => Ok(env_metadata(&self.value(url)?)),
+		}
+	}
+
+	async fn read_dir<'s, 'a>(
+		&'s self,
+		_vfs: &'s Vfs,
+		url: &'a Url,
+	) -> Result<BorrowedReadDirStream<'s>, SchemeError<'a>> {
+		if Self::name(url).is_some() {
+			self.value(url)?;
+			return Err(SchemeError::NotADirectory(Cow::Borrowed(url.path())));
+		}
+		let mut variables: Vec<(String, String)> = std::env::vars_os()
+			.filter_map(|(name, value)| {
+				let name = name.into_string().ok()?;
+				name.starts_with(&self.prefix)
+					.then(|| (name, value.to_string_lossy().into_owned()))
+			})
+			.collect();
+		variables.sort_unstable();
+		let scheme = url.scheme().to_owned();
+		let entries: Vec<NodeEntry> = variables
+			.into_iter()
+			.filter_map(|(name, value)| {
+				let name = utf8_percent_encode(&name, NAME_ENCODE_SET);
+				let url = Url::parse(&format!("{}:{}", scheme, name)).ok()?;
+				Some(NodeEntry::new(url).with_metadata(env_metadata(&value)))
+			})
+			.collect();
+		Ok(Box::pin(futures_lite::stream::iter(entries)))
+	}
+
+	async fn read_small_file<'a>(
+		&self,
+		_vfs: &Vfs,
+		url: &'a Url,
+		max_len: usize,
+	) -> Result<Option<Vec<u8>>, SchemeError<'a>> {
+		let value = self.value(url)?;
+		Ok((value.len() <= max_len).then(|| value.into_bytes()))
+	}
+}
+
+pub struct EnvNode {
+	data: Vec<u8>,
+	cursor: usize,
+}
+
+impl Node for EnvNode {
+	fn is_reader(&self) -> bool {
+		true
+	}
+
+	fn is_writer(&self) -> bool {
+		false
+	}
+
+	fn is_seeker(&self) -> bool {
+		true
+	}
+
+	fn known_len(&self) -> Option<u64> {
+		Some(self.data.len() as u64)
+	}
+
+	fn is_at_end(&self) -> Option<bool> {
+		Some(self.cursor >= self.data.len())
+	}
+}
+
+impl AsyncRead for EnvNode {
+	fn poll_read(
+		mut self: Pin<&mut Self>,
+		_cx: &mut Context<'_>,
+		buf: &mut [u8],
+	) -> Poll<std::io::Result<usize>> {
+		let remaining = self.data.get(self.cursor..).unwrap_or(&[]);
+		let amt = remaining.len().min(buf.len());
+		buf[..amt].copy_from_slice(&remaining[..amt]);
+		self.cursor += amt;
+		Poll::Ready(Ok(amt))
+	}
+}
+
+impl AsyncWrite for EnvNode {
+	fn poll_write(
+		self: Pin<&mut Self>,
+		_cx: &mut Context<'_>,
+		_buf: &[u8],
+	) -> Poll<std::io::Result<usize>> {
+		poll_io_err()
+	}
+
+	fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+		poll_io_err()
+	}
+
+	fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+		Poll::Ready(Ok(()))
+	}
+}
+
+impl AsyncSeek for EnvNode {
+	fn poll_seek(
+		mut self: Pin<&mut Self>,
+		_cx: &mut Context<'_>,
+		pos: SeekFrom,
+	) -> Poll<std::io::Result<u64>> {
+		let position = seek_position(pos, self.cursor as u64, Some(self.data.len() as u64))?;
+		self.cursor = position as usize;
+		Poll::Ready(Ok(position))
+	}
+}
+
+#[cfg(test)]
+#[cfg(feature = "backend_tokio")]
+mod async_tokio_tests {
+	use crate::scheme::NodeGetOptions;
+	use crate::{EnvScheme, SchemeError, Vfs, VfsError};
+	use futures_lite::StreamExt;
+
+	#[tokio::test]
+	async fn env_variables() {
+		std::env::set_var("VFS_NODES_ENV_TEST_VALUE", "some value");
+		std::env::set_var("VFS_NODES_ENV_TEST_OTHER", "other");
+		let vfs = Vfs::empty();
+		vfs.add_scheme("env", EnvScheme::with_prefix("VFS_NODES_ENV_TEST_"))
+			.unwrap();
+
+		assert_eq!(
+			vfs.read_to_vec_at("env:VFS_NODES_ENV_TEST_VALUE")
+				.await
+				.unwrap(),
+			b"some value"
+		);
+		assert_eq!(
+			vfs.read_to_vec_at("env:/VFS_NODES_ENV_TEST_OTHER")
+				.await
+				.unwrap(),
+			b"other"
+		);
+		assert_eq!(
+			vfs.metadata_at("env:VFS_NODES_ENV_TEST_VALUE")
+				.await
+				.unwrap()
+				.len,
+			Some((10, Some(10)))
+		);
+		let listed: Vec<String> = vfs
+			.read_dir_at("env:")
+			.await
+			.unwrap()
+			.map(|entry| entry.url.to_string())
+			.collect()
+			.await;
+		assert_eq!(
+			listed,
+			[
+				"env:VFS_NODES_ENV_TEST_OTHER",
+				"env:VFS_NODES_ENV_TEST_VALUE"
+			]
+		);
+
+		assert!(matches!(
+			vfs.read_to_vec_at("env:VFS_NODES_ENV_TEST_MISSING").await,
+			Err(VfsError::SchemeError(SchemeError::NodeDoesNotExist(_)))
+		));
+		assert!(matches!(
+			vfs.read_to_vec_at("env:PATH").await,
+			Err(VfsError::SchemeError(SchemeError::NodeDoesNotExist(_)))
+		));
+		assert!(matches!(
+			vfs.get_node_at(
+				"env:VFS_NODES_ENV_TEST_VALUE",
+				&NodeGetOptions::new().write(true)
+			)
+			.await,
+			Err(VfsError::SchemeError(SchemeError::UrlAccessError(_)))
+		));
+	}
+}
@@ -0,0 +1,360 @@
+use crate::node::{poll_io_err, seek_position};
+use crate::scheme::{BorrowedReadDirStream, NodeGetOptions, NodeKind, NodeMetadata};
+use crate::schemes::js::{js_message, JsSend};
+use crate::{Node, PinnedNode, Scheme, SchemeError, Vfs};
+use futures_lite::{AsyncRead, AsyncSeek, AsyncWrite};
+use js_sys::{Promise, Uint8Array};
+use std::borrow::Cow;
+use std::io::SeekFrom;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::SystemTime;
+use url::Url;
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{Request, RequestInit, Response, Window, WorkerGlobalScope};
+
+/// Serves read-only nodes fetched with the `fetch` of the browser, or of the worker, the wasm
+/// module runs in, so a game built for the web reads its assets through the same `get_node_at`
+/// calls as a native build reading them from a `FilesystemScheme`.  The path of the url is what
+/// is fetched, `web:assets/level.json` relative to the page and `web:/assets/level.json` from the
+/// root of its origin, or both relative to the base given to `with_base`.  Registered under
+/// `http` or `https` the whole url is fetched as is.  The whole body is read before the node is
+/// handed back and the node can then be seeked freely.  `metadata` is a HEAD request, listing
+/// directories is not supported, and opening for writing or removing fail with `UrlAccessError`.
+#[derive(Clone, Default)]
+pub struct FetchScheme {
+	base: Option<Url>,
+}
+
+impl FetchScheme {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Resolves the paths of urls against `base` instead of the page, such as a CDN serving the
+	/// assets, `https://cdn.example.com/game/`; mind the trailing `/` for relative paths.
+	pub fn with_base(base: Url) -> Self {
+		Self { base: Some(base) }
+	}
+
+	pub fn base(&self) -> Option<&Url> {
+		self.base.as_ref()
+	}
+
+	/// What is handed to `fetch` for `url`.
+	fn target<'a>(&self, url: &'a Url) -> Result<String, SchemeError<'a>> {
+		if matches!(url.scheme(), "http" | "https") {
+			return Ok(url.as_str().to_owned());
+		}
+		let path = match url.query() {
+			Some(query) => format!("{}?{}", url.path(), query),
+			None => url.path().to_owned(),
+		};
+		match &self.base {
+			Some(base) => base
+				.join(&path)
+				.map(String::from)
+				.map_err(|_| SchemeError::UrlAccessError(Cow::Borrowed(url))),
+			None => Ok(path),
+		}
+	}
+
+	/// Fetches `url`, reading the body unless it is a HEAD request.  The JavaScript values all
+	/// stay within the future so only what was read out of them has to be `Send`.
+	async fn send<'a>(
+		&self,
+		url: &'a Url,
+		method: &str,
+		range: Option<(u64, Option<u64>)>,
+	) -> Result<Fetched, SchemeError<'a>> {
+		let target = self.target(url)?;
+		JsSend(Box::pin(async move {
+			let response = fetch_response(url, &target, method, range).await?;
+			let body = if method == "HEAD" {
+				Vec::new()
+			} else {
+				let buffer = JsFuture::from(response.array_buffer().map_err(request_failed)?)
+					.await
+					.map_err(request_failed)?;
+				Uint8Array::new(&buffer).to_vec()
+			};
+			// The whole body when the server ignored the range, the node is seeked to it instead
+			let offset = if response.status() == 206 {
+				content_range_start(&response).ok_or("invalid Content-Range of an HTTP response")?
+			} else {
+				0
+			};
+			Ok::<_, SchemeError<'a>>(Fetched {
+				offset,
+				len: header(&response, "Content-Length").and_then(|len| len.parse().ok()),
+				modified: header(&response, "Last-Modified")
+					.and_then(|modified| httpdate::parse_http_date(&modified).ok()),
+				body,
+			})
+		}))
+		.await
+	}
+}
+
+/// What was read out of a response.
+struct Fetched {
+	/// Where in the resource the body starts.
+	offset: u64,
+	len: Option<usize>,
+	modified: Option<SystemTime>,
+	body: Vec<u8>,
+}
+
+async fn fetch_response<'a>(
+	url: &'a Url,
+	target: &str,
+	method: &str,
+	range: Option<(u64, Option<u64>)>,
+) -> Result<Response, SchemeError<'a>> {
+	let init = RequestInit::new();
+	init.set_method(method);
+	let request = Request::new_with_str_and_init(target, &init).map_err(request_failed)?;
+	match range {
+		Some((start, None)) => request
+			.headers()
+			.set("Range", &format!("bytes={}-", start))
+			.map_err(request_failed)?,
+		Some((start, Some(end))) if end > start => request
+			.headers()
+			.set("Range", &format!("bytes={}-{}", start, end - 1))
+			.map_err(request_failed)?,
+		_ => (),
+	}
+	let response: Response = JsFuture::from(fetch(&request)?)
+		.await
+		.map_err(request_failed)?
+		.unchecked_into();
+	match response.status() {
+		404 | 410 => Err(SchemeError::NodeDoesNotExist(Cow::Borrowed(url.path()))),
+		_ if response.ok() => Ok(response),
+		_ => Err(SchemeError::GenericError(
+			Some("unexpected HTTP status"),
+			Some(format!("{} {}", response.status(), response.status_text()).into()),
+		)),
+	}
+}
+
+/// Calls the `fetch` of the window, or of the worker when not run in a window.
+fn fetch(request: &Request) -> Result<Promise, SchemeError<'static>> {
+	let global = js_sys::global();
+	if let Some(window) = global.dyn_ref::<Window>() {
+		Ok(window.fetch_with_request(request))
+	} else if let Some(worker) = global.dyn_ref::<WorkerGlobalScope>() {
+		Ok(worker.fetch_with_request(request))
+	} else {
+		Err(SchemeError::Unsupported(
+			"fetch outside of a window or worker",
+		))
+	}
+}
+
+fn request_failed(error: JsValue) -> SchemeError<'static> {
+	SchemeError::GenericError(Some("fetch failed"), Some(js_message(&error).into()))
+}
+
+fn header(response: &Response, name: &str) -> Option<String> {
+	response.headers().get(name).ok()?
+}
+
+/// The start of a `Content-Range: bytes <start>-<end>/<total>`.
+fn content_range_start(response: &Response) -> Option<u64> {
+	let value = header(response, "Content-Range")?;
+	let (range, _total) = value.strip_prefix("bytes ")?.split_once('/')?;
+	range.split_once('-')?.0.parse().ok()
+}
+
+#[async_trait::async_trait]
+impl Scheme for FetchScheme {
+	async fn get_node<'a>(
+		&self,
+		_vfs: &Vfs,
+		url: &'a Url,
+		options: &NodeGetOptions,
+	) -> Result<PinnedNode, SchemeError<'a>> {
+		if options.get_write() || options.get_append() {
+			return Err(SchemeError::UrlAccessError(Cow::Borrowed(url)));
+		}
+		let fetched = self.send(url, "GET", options.get_range()).await?;
+		let cursor = options.get_range().map_or(0, |(start, _end)| start);
+		Ok(Box::pin(FetchNode {
+			data: fetched.body,
+			offset: fetched.offset,
+			cursor: cursor.max(fetched.offset),
+		}))
+	}
+
+	async fn remove_node<'a>(
+		&self,
+		_vfs: &Vfs,
+		url: &'a Url,
+		_force: bool,
+	) -> Result<(), SchemeError<'a>> {
+		Err(SchemeError::UrlAccessError(Cow::Borrowed(url)))
+	}
+
+	async fn metadata<'a>(
+		&self,
+		_vfs: &Vfs,
+		url: &'a Url,
+	) -> Result<NodeMetadata, SchemeError<'a>> {
+		let fetched = self.send(url, "HEAD", None).await?;
+		Ok(NodeMetadata {
+			is_node: true,
+			len: fetched.len.map(|len| (len, Some(len))),
+			modified: fetched.modified,
+			kind: Some(NodeKind::File),
+			read_only: Some(true),
+			..Default::default()
+		})
+	}
+
+	async fn read_dir<'s, 'a>(
+		&'s self,
+		_vfs: &'s Vfs,
+		_url: &'a Url,
+	) -> Result<BorrowedReadDirStream<'s>, SchemeError<'a>> {
+		Err(SchemeError::Unsupported("read_dir"))
+	}
+}
+
+pub struct FetchNode {
+	data: Vec<u8>,
+	/// Where in the resource `data` starts, when only a range of it was fetched.
+	offset: u64,
+	cursor: u64,
+}
+
+impl FetchNode {
+	fn len(&self) -> u64 {
+		self.offset + self.data.len() as u64
+	}
+}
+
+impl Node for FetchNode {
+	fn is_reader(&self) -> bool {
+		true
+	}
+
+	fn is_writer(&self) -> bool {
+		false
+	}
+
+	fn is_seeker(&self) -> bool {
+		true
+	}
+
+	fn known_len(&self) -> Option<u64> {
+		Some(self.len())
+	}
+
+	fn is_at_end(&self) -> Option<bool> {
+		Some(self.cursor >= self.len())
+	}
+}
+
+impl AsyncRead for FetchNode {
+	fn poll_read(
+		mut self: Pin<&mut Self>,
+		_cx: &mut Context<'_>,
+		buf: &mut [u8],
+	) -> Poll<std::io::Result<usize>> {
+		// Before `offset` was not fetched, reading there would silently skip to it
+		let start = match self.cursor.checked_sub(self.offset) {
+			Some(start) => start as usize,
+			None => return poll_io_err(),
+		};
+		let remaining = self.data.get(start..).unwrap_or(&[]);
+		let amt = remaining.len().min(buf.len());
+		buf[..amt].copy_from_slice(&remaining[..amt]);
+		self.cursor += amt as u64;
+		Poll::Ready(Ok(amt))
+	}
+}
+
+impl AsyncWrite for FetchNode {
+	fn poll_write(
+		self: Pin<&mut Self>,
+		_cx: &mut Context<'_>,
+		_buf: &[u8],
+	) -> Poll<std::io::Result<usize>> {
+		poll_io_err()
+	}
+
+	fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+		poll_io_err()
+	}
+
+	fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+		Poll::Ready(Ok(()))
+	}
+}
+
+impl AsyncSeek for FetchNode {
+	fn poll_seek(
+		mut self: Pin<&mut Self>,
+		_cx: &mut Context<'_>,
+		pos: SeekFrom,
+	) -> Poll<std::io::Result<u64>> {
+		let position = seek_position(pos, self.cursor, Some(self.len()))?;
+		self.cursor = position;
+		Poll::Ready(Ok(position))
+	}
+}
+
+#[cfg(test)]
+mod wasm_tests {
+	use crate::scheme::NodeGetOptions;
+	use crate::{FetchScheme, SchemeError, Vfs, VfsError};
+	use futures_lite::{AsyncReadExt, AsyncSeekExt};
+	use std::io::SeekFrom;
+	use wasm_bindgen_test::wasm_bindgen_test;
+
+	// The test runner serves its page at the root of its origin, so that is fetched
+	fn vfs() -> Vfs {
+		let vfs = Vfs::empty();
+		vfs.add_scheme("web", FetchScheme::new()).unwrap();
+		vfs
+	}
+
+	#[wasm_bindgen_test]
+	async fn fetch() {
+		let vfs = vfs();
+		assert!(!vfs.read_to_vec_at("web:/").await.unwrap().is_empty());
+		assert!(vfs.metadata_at("web:/").await.unwrap().is_node);
+		assert!(vfs
+			.get_node_at("web:/", &NodeGetOptions::new().write(true))
+			.await
+			.is_err());
+	}
+
+	#[wasm_bindgen_test]
+	async fn missing() {
+		assert!(matches!(
+			vfs().read_to_vec_at("web:/vfs_nodes/missing").await,
+			Err(VfsError::SchemeError(SchemeError::NodeDoesNotExist(_)))
+		));
+	}
+
+	#[wasm_bindgen_test]
+	async fn ranged() {
+		let vfs = vfs();
+		let full = vfs.read_to_vec_at("web:/").await.unwrap();
+		let mut node = vfs
+			.get_node_at(
+				"web:/",
+				&NodeGetOptions::new().read(true).range(Some((1, Some(4)))),
+			)
+			.await
+			.unwrap();
+		assert_eq!(node.seek(SeekFrom::Current(0)).await.unwrap(), 1);
+		let mut buffer = [0; 3];
+		node.read_exact(&mut buffer).await.unwrap();
+		assert_eq!(buffer, full[1..4]);
+	}
+}
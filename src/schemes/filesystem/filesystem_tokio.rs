@@ -1,12 +1,15 @@
+use super::node_metadata;
 use crate::node::IsAllowed;
-use crate::scheme::{NodeEntry, NodeGetOptions, NodeMetadata, ReadDirStream};
+use crate::scheme::{BorrowedReadDirStream, NodeEntry, NodeGetOptions, NodeMetadata};
 use crate::{Node, PinnedNode, Scheme, SchemeError, Vfs};
-use futures_lite::{ready, AsyncRead, AsyncSeek, AsyncWrite, Stream};
+use futures_lite::{ready, AsyncRead, AsyncSeek, AsyncWrite, StreamExt};
+use percent_encoding::percent_decode_str;
 use std::borrow::Cow;
 use std::io::{IoSlice, SeekFrom};
 use std::path::PathBuf;
 use std::pin::Pin;
 use std::task::{Context, Poll};
+use std::time::SystemTime;
 use tokio::fs::OpenOptions;
 use url::Url;
 
@@ -46,41 +49,94 @@ impl TokioFileSystemScheme {
 		}
 	}
 
+	/// Segments are percent-decoded into file names, one that decodes to something that is not a
+	/// single file name, such as `..` or one holding a `/`, could escape the root so is refused.
 	pub fn fs_path_from_url<'a>(&self, url: &'a Url) -> Result<PathBuf, SchemeError<'a>> {
-		Ok(url
+		let segments = url
 			.path_segments()
-			.ok_or(SchemeError::NodeDoesNotExist(Cow::Borrowed(url.path())))?
-			.fold(self.root_path.clone(), |mut path, part| {
-				path.push(part);
-				path
-			}))
+			.ok_or(SchemeError::NodeDoesNotExist(Cow::Borrowed(url.path())))?;
+		let mut path = self.root_path.clone();
+		for segment in segments {
+			let name = percent_decode_str(segment).decode_utf8_lossy();
+			if name == "." || name == ".." || name.contains(['/', '\\']) {
+				return Err(SchemeError::UrlAccessError(Cow::Borrowed(url)));
+			}
+			path.push(&*name);
+		}
+		Ok(path)
 	}
-}
 
-#[async_trait::async_trait]
-impl Scheme for TokioFileSystemScheme {
-	async fn get_node<'a>(
+	/// Opens the file at `path` once it is known not to be a directory.
+	async fn open_path<'a>(
 		&self,
-		_vfs: &Vfs,
 		url: &'a Url,
+		path: PathBuf,
 		options: &NodeGetOptions,
 	) -> Result<PinnedNode, SchemeError<'a>> {
-		let path = self.fs_path_from_url(url)?;
 		if options.get_create() {
 			let parent_path = path
 				.parent()
-				.ok_or(SchemeError::UrlAccessError(Cow::Borrowed(&url)))?;
-			tokio::fs::create_dir_all(parent_path).await?;
+				.ok_or(SchemeError::UrlAccessError(Cow::Borrowed(url)))?;
+			if options.get_create_parents() {
+				tokio::fs::create_dir_all(parent_path)
+					.await
+					.map_err(SchemeError::io_at(url.path()))?;
+			} else if !tokio::fs::metadata(parent_path)
+				.await
+				.is_ok_and(|metadata| metadata.is_dir())
+			{
+				let url_path = url.path();
+				let parent = url_path.rfind('/').map_or("/", |pos| &url_path[..pos]);
+				return Err(SchemeError::NodeDoesNotExist(Cow::Borrowed(parent)));
+			}
 		}
-		let file = OpenOptions::from(options).open(path).await?;
+		let file = OpenOptions::from(options)
+			.open(&path)
+			.await
+			.map_err(SchemeError::io_at(url.path()))?;
 		let node = TokioFileSystemNode {
 			file,
+			path,
+			append: options.get_append(),
 			seek: None,
 			read: options.get_read(),
 			write: options.get_write(),
 		};
 		Ok(Box::pin(node))
 	}
+}
+
+#[async_trait::async_trait]
+impl Scheme for TokioFileSystemScheme {
+	async fn get_node<'a>(
+		&self,
+		_vfs: &Vfs,
+		url: &'a Url,
+		options: &NodeGetOptions,
+	) -> Result<PinnedNode, SchemeError<'a>> {
+		let path = self.fs_path_from_url(url)?;
+		if let Ok(metadata) = tokio::fs::metadata(&path).await {
+			if metadata.is_dir() {
+				return Err(SchemeError::IsADirectory(Cow::Borrowed(url.path())));
+			}
+		}
+		self.open_path(url, path, options).await
+	}
+
+	/// Skips looking up whether `url` is a directory, trusting `metadata` instead.
+	async fn get_node_with_metadata<'a>(
+		&self,
+		_vfs: &Vfs,
+		url: &'a Url,
+		options: &NodeGetOptions,
+		metadata: &NodeMetadata,
+	) -> Result<PinnedNode, SchemeError<'a>> {
+		let path = self.fs_path_from_url(url)?;
+		if !metadata.is_node {
+			return Err(SchemeError::IsADirectory(Cow::Borrowed(url.path())));
+		}
+		self.open_path(url, path, options).await
+	}
 
 	async fn remove_node<'a>(
 		&self,
@@ -91,12 +147,18 @@ impl Scheme for TokioFileSystemScheme {
 		let path = self.fs_path_from_url(url)?;
 		if path.exists() {
 			if path.is_file() {
-				tokio::fs::remove_file(&path).await?;
+				tokio::fs::remove_file(&path)
+					.await
+					.map_err(SchemeError::io_at(url.path()))?;
 			} else if path.is_dir() {
 				if force {
-					tokio::fs::remove_dir_all(&path).await?;
+					tokio::fs::remove_dir_all(&path)
+						.await
+						.map_err(SchemeError::io_at(url.path()))?;
 				} else {
-					tokio::fs::remove_dir(&path).await?;
+					tokio::fs::remove_dir(&path)
+						.await
+						.map_err(SchemeError::io_at(url.path()))?;
 				}
 			}
 		}
@@ -109,63 +171,181 @@ impl Scheme for TokioFileSystemScheme {
 		url: &'a Url,
 	) -> Result<NodeMetadata, SchemeError<'a>> {
 		let path = self.fs_path_from_url(url)?;
-		if let Ok(metadata) = tokio::fs::metadata(path).await {
-			let size = metadata.len() as usize;
-			Ok(NodeMetadata {
-				is_node: metadata.is_file(),
-				len: Some((size, Some(size))),
-			})
+		// Only a symlink needs a second lookup to follow it
+		let metadata = match tokio::fs::symlink_metadata(&path).await {
+			Ok(metadata) if metadata.file_type().is_symlink() => tokio::fs::metadata(&path)
+				.await
+				.map(|metadata| node_metadata(&metadata, true)),
+			result => result.map(|metadata| node_metadata(&metadata, false)),
+		};
+		match metadata {
+			Ok(metadata) => Ok(metadata),
+			Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+				Err(SchemeError::NodeDoesNotExist(Cow::Borrowed(url.path())))
+			}
+			Err(error) => Err(SchemeError::IOErrorAt(Cow::Borrowed(url.path()), error)),
+		}
+	}
+
+	async fn read_dir<'s, 'a>(
+		&'s self,
+		_vfs: &'s Vfs,
+		url: &'a Url,
+	) -> Result<BorrowedReadDirStream<'s>, SchemeError<'a>> {
+		let path = self.fs_path_from_url(url)?;
+		if path.is_file() {
+			Err(SchemeError::NotADirectory(Cow::Borrowed(url.path())))
+		} else if path.exists() {
+			let read_dir = tokio::fs::read_dir(&path)
+				.await
+				.map_err(SchemeError::io_at(url.path()))?;
+			Ok(Box::pin(
+				futures_lite::stream::unfold((read_dir, url.clone()), next_dir_entry).fuse(),
+			))
 		} else {
 			Err(SchemeError::NodeDoesNotExist(Cow::Borrowed(url.path())))
 		}
 	}
 
-	async fn read_dir<'a>(
+	async fn read_small_file<'a>(
 		&self,
 		_vfs: &Vfs,
 		url: &'a Url,
-	) -> Result<ReadDirStream, SchemeError<'a>> {
+		max_len: usize,
+	) -> Result<Option<Vec<u8>>, SchemeError<'a>> {
 		let path = self.fs_path_from_url(url)?;
-		if path.exists() {
-			Ok(Box::pin(TokioReadDirWrapper(
-				tokio::fs::read_dir(&path).await?,
-				url.clone(),
-			)))
+		match tokio::fs::metadata(&path).await {
+			Ok(metadata) if metadata.is_file() && metadata.len() <= max_len as u64 => {
+				let data = tokio::fs::read(&path)
+					.await
+					.map_err(SchemeError::io_at(url.path()))?;
+				Ok(Some(data))
+			}
+			_ => Ok(None),
+		}
+	}
+
+	async fn set_modified<'a>(
+		&self,
+		_vfs: &Vfs,
+		url: &'a Url,
+		modified: SystemTime,
+	) -> Result<(), SchemeError<'a>> {
+		let path = self.fs_path_from_url(url)?;
+		tokio::task::spawn_blocking(move || std::fs::File::open(path)?.set_modified(modified))
+			.await
+			.map_err(std::io::Error::from)??;
+		Ok(())
+	}
+
+	#[cfg(feature = "watch")]
+	async fn watch<'a>(
+		&self,
+		_vfs: &Vfs,
+		url: &'a Url,
+	) -> Result<crate::scheme::WatchStream, SchemeError<'a>> {
+		let path = self.fs_path_from_url(url)?;
+		super::watch_path(&self.root_path, &path, url)
+	}
+
+	async fn create_dir<'a>(
+		&self,
+		_vfs: &Vfs,
+		url: &'a Url,
+		parents: bool,
+	) -> Result<(), SchemeError<'a>> {
+		let path = self.fs_path_from_url(url)?;
+		if parents {
+			tokio::fs::create_dir_all(path).await
 		} else {
-			Err(SchemeError::NodeDoesNotExist(Cow::Borrowed(url.path())))
+			tokio::fs::create_dir(path).await
 		}
+		.map_err(SchemeError::io_at(url.path()))?;
+		Ok(())
+	}
+
+	async fn copy_node<'a>(
+		&self,
+		_vfs: &Vfs,
+		from: &'a Url,
+		to: &'a Url,
+	) -> Result<(), SchemeError<'a>> {
+		let from_path = self.fs_path_from_url(from)?;
+		let to_path = self.fs_path_from_url(to)?;
+		tokio::fs::copy(from_path, to_path)
+			.await
+			.map_err(SchemeError::io_at(from.path()))?;
+		Ok(())
+	}
+
+	async fn rename_node<'a>(
+		&self,
+		_vfs: &Vfs,
+		from: &'a Url,
+		to: &'a Url,
+	) -> Result<(), SchemeError<'a>> {
+		let from_path = self.fs_path_from_url(from)?;
+		let to_path = self.fs_path_from_url(to)?;
+		tokio::fs::rename(from_path, to_path)
+			.await
+			.map_err(SchemeError::io_at(from.path()))?;
+		Ok(())
+	}
+
+	async fn get_node_split<'a>(
+		&self,
+		vfs: &Vfs,
+		url: &'a Url,
+		options: &NodeGetOptions,
+	) -> Result<(PinnedNode, PinnedNode), SchemeError<'a>> {
+		// Two handles on the same file, the writer first so it can create the file
+		let writer = self
+			.get_node(vfs, url, &options.clone().read(false).write(true))
+			.await?;
+		let reader = self
+			.get_node(vfs, url, &NodeGetOptions::new().read(true))
+			.await?;
+		Ok((reader, writer))
 	}
 }
 
 // Yeah, tokio's ReadDir really doesn't implement `Stream`, instead you have to call it manually...
-struct TokioReadDirWrapper(tokio::fs::ReadDir, Url);
-
-impl Stream for TokioReadDirWrapper {
-	type Item = NodeEntry;
-
-	fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-		loop {
-			match ready!(self.0.poll_next_entry(cx)) {
-				Err(_io_error) => continue,          // skip nodes with IO errors
-				Ok(None) => break Poll::Ready(None), // done
-				Ok(Some(entry)) => {
-					if let Some(entry_sub_path) = entry.file_name().to_str() {
-						if let Ok(entry_url) = self.1.join(&entry_sub_path) {
-							break Poll::Ready(Some(NodeEntry { url: entry_url }));
-						} else {
-							continue; // failed parsing new URL entry, invalid name format
-						}
-					} else {
-						continue; // failed to convert the OsStr to a normal string, so it will be an invalid URL as well
-					}
-				}
+/// The next entry of a directory being listed along with its metadata, the directory is listed at
+/// `dir`.
+async fn next_dir_entry(
+	(mut read_dir, dir): (tokio::fs::ReadDir, Url),
+) -> Option<(NodeEntry, (tokio::fs::ReadDir, Url))> {
+	loop {
+		let entry = match read_dir.next_entry().await {
+			Err(_io_error) => continue, // skip nodes with IO errors
+			Ok(None) => return None,    // done
+			Ok(Some(entry)) => entry,
+		};
+		// A name that is not valid UTF-8 cannot be put in a url
+		let name = entry.file_name();
+		let node_entry = match name
+			.to_str()
+			.and_then(|name| NodeEntry::child(&dir, [name]))
+		{
+			Some(node_entry) => node_entry,
+			None => continue,
+		};
+		// The entry has the metadata of a symlink itself, that is left to `metadata` to follow
+		let node_entry = match entry.metadata().await {
+			Ok(metadata) if !metadata.file_type().is_symlink() => {
+				node_entry.with_metadata(node_metadata(&metadata, false))
 			}
-		}
+			_ => node_entry,
+		};
+		return Some((node_entry, (read_dir, dir)));
 	}
 }
 
 pub struct TokioFileSystemNode {
 	file: tokio::fs::File,
+	/// Where `file` was opened from, to open it again for `try_clone`.
+	path: PathBuf,
+	append: bool,
 	seek: Option<std::io::SeekFrom>,
 	read: bool,
 	write: bool,
@@ -184,6 +364,32 @@ impl Node for TokioFileSystemNode {
 	fn is_seeker(&self) -> bool {
 		self.read || self.write
 	}
+
+	async fn sync_all(self: Pin<&mut Self>) -> std::io::Result<()> {
+		self.get_mut().file.sync_all().await
+	}
+
+	async fn sync_data(self: Pin<&mut Self>) -> std::io::Result<()> {
+		self.get_mut().file.sync_data().await
+	}
+
+	async fn try_clone(self: Pin<&mut Self>) -> Result<PinnedNode, SchemeError<'static>> {
+		// A duplicated file descriptor shares its cursor, so open the file again instead
+		let file = tokio::fs::OpenOptions::new()
+			.read(self.read)
+			.write(self.write)
+			.append(self.append)
+			.open(&self.path)
+			.await?;
+		Ok(Box::pin(TokioFileSystemNode {
+			file,
+			path: self.path.clone(),
+			append: self.append,
+			seek: None,
+			read: self.read,
+			write: self.write,
+		}))
+	}
 	// async fn read<'s>(&'s mut self) -> Option<&'s mut (dyn AsyncRead + Unpin)> {
 	// 	if self.read {
 	// 		Some(self)
@@ -226,6 +432,7 @@ impl AsyncRead for TokioFileSystemNode {
 	}
 }
 
+// The counts are the file's own, which may be short of the whole buffer like any OS write
 impl AsyncWrite for TokioFileSystemNode {
 	fn poll_write(
 		mut self: Pin<&mut Self>,
@@ -281,7 +488,7 @@ impl AsyncSeek for TokioFileSystemNode {
 				ready!(tokio::io::AsyncSeek::poll_complete(file, cx))
 			};
 			self.as_mut().seek = None;
-			Poll::Ready(res.map(|p| p as u64))
+			Poll::Ready(res)
 		})
 	}
 }
@@ -294,10 +501,22 @@ mod tests_general {
 
 	const FILE_CONTENT_TEST_LOC: &str = "fs:/test_node_writing_tokio.txt";
 	const FILE_CONTENT_SEEK_TEST_LOC: &str = "fs:/test_node_seeking_tokio.txt";
+	const FILE_CONTENT_SYNC_TEST_LOC: &str = "fs:/test_node_sync_tokio.txt";
+	const FILE_CONTENT_PARENTS_TEST_LOC: &str = "fs:/test_create_parents_tokio/inner/node.txt";
+	const FILE_CONTENT_PARENTS_TEST_DIR: &str = "fs:/test_create_parents_tokio";
+	const FILE_CONTENT_ENCODED_TEST_DIR: &str = "test_encoded_names_tokio";
+	const FILE_CONTENT_MOVE_TEST_LOC: &str = "fs:/test_move_node_tokio.txt";
+	const FILE_CONTENT_CLONE_TEST_LOC: &str = "fs:/test_node_clone_tokio.txt";
+	const FILE_CONTENT_CREATE_DIR_TEST_DIR: &str = "fs:/test_create_dir_tokio";
+	const FILE_CONTENT_RENAME_TEST_LOC: &str = "fs:/test_node_rename_tokio.txt";
+	const FILE_CONTENT_RENAMED_TEST_LOC: &str = "fs:/test_node_renamed_tokio.txt";
+	#[cfg(feature = "watch")]
+	const FILE_CONTENT_WATCH_TEST_DIR: &str = "fs:/test_watch_tokio";
 
 	// Generic per test
-	use crate::scheme::NodeGetOptions;
-	use crate::Vfs;
+	use crate::scheme::{NodeGetOptions, NodeKind, NodeMetadata};
+	use crate::transfer::ConflictPolicy;
+	use crate::{SchemeError, Vfs, VfsError};
 	use futures_lite::io::SeekFrom;
 	use futures_lite::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt, StreamExt};
 	use url::Url;
@@ -310,7 +529,7 @@ mod tests_general {
 
 	#[async_test]
 	async fn scheme_access() {
-		let mut vfs = Vfs::default();
+		let vfs = Vfs::default();
 		vfs.add_scheme(
 			"fs",
 			FileSystemScheme::new(std::env::current_dir().unwrap()),
@@ -323,16 +542,26 @@ mod tests_general {
 			"file exists"
 		);
 		assert!(
-			vfs.get_node(&u("fs:/target"), &NodeGetOptions::new().read(true))
-				.await
-				.is_ok(),
-			"folder exists"
+			matches!(
+				vfs.get_node(&u("fs:/target"), &NodeGetOptions::new().read(true))
+					.await,
+				Err(VfsError::SchemeError(SchemeError::IsADirectory(_)))
+			),
+			"folder is not a node"
+		);
+		assert!(
+			matches!(
+				vfs.get_node(&u("fs:/"), &NodeGetOptions::new().read(true))
+					.await,
+				Err(VfsError::SchemeError(SchemeError::IsADirectory(_)))
+			),
+			"root is not a node"
 		);
 	}
 
 	#[async_test]
 	async fn node_reading_vfs() {
-		let mut vfs = Vfs::default();
+		let vfs = Vfs::default();
 		vfs.add_scheme(
 			"fs",
 			FileSystemScheme::new(std::env::current_dir().unwrap()),
@@ -349,7 +578,7 @@ mod tests_general {
 
 	#[async_test]
 	async fn node_writing() {
-		let mut vfs = Vfs::default();
+		let vfs = Vfs::default();
 		vfs.add_scheme(
 			"fs",
 			FileSystemScheme::new(std::env::current_dir().unwrap().join("target")),
@@ -381,9 +610,81 @@ mod tests_general {
 		assert_eq!(&buffer, FILE_TEST_CONTENT);
 	}
 
+	#[async_test]
+	async fn node_sync() {
+		let vfs = Vfs::default();
+		vfs.add_scheme(
+			"fs",
+			FileSystemScheme::new(std::env::current_dir().unwrap().join("target")),
+		)
+		.unwrap();
+		let mut node = vfs
+			.get_node(
+				&u(FILE_CONTENT_SYNC_TEST_LOC),
+				&NodeGetOptions::new()
+					.write(true)
+					.truncate(true)
+					.create(true),
+			)
+			.await
+			.unwrap();
+		node.write_all(FILE_TEST_CONTENT.as_bytes()).await.unwrap();
+		node.as_mut().sync_data().await.unwrap();
+		node.write_all(FILE_TEST_CONTENT.as_bytes()).await.unwrap();
+		node.as_mut().sync_all().await.unwrap();
+		let metadata = vfs.metadata(&u(FILE_CONTENT_SYNC_TEST_LOC)).await.unwrap();
+		vfs.remove_node(&u(FILE_CONTENT_SYNC_TEST_LOC), false)
+			.await
+			.unwrap();
+		let len = FILE_TEST_CONTENT.len() * 2;
+		assert_eq!(metadata.len, Some((len, Some(len))));
+	}
+
+	#[async_test]
+	async fn node_create_parents() {
+		let vfs = Vfs::default();
+		vfs.add_scheme(
+			"fs",
+			FileSystemScheme::new(std::env::current_dir().unwrap().join("target")),
+		)
+		.unwrap();
+		let _ = vfs
+			.remove_node(&u(FILE_CONTENT_PARENTS_TEST_DIR), true)
+			.await;
+		assert!(matches!(
+			vfs.get_node(
+				&u(FILE_CONTENT_PARENTS_TEST_LOC),
+				&NodeGetOptions::new().create_new(true).create_parents(false),
+			)
+			.await,
+			Err(VfsError::SchemeError(SchemeError::NodeDoesNotExist(path)))
+				if path.ends_with("/inner")
+		));
+		assert!(vfs
+			.metadata(&u(FILE_CONTENT_PARENTS_TEST_DIR))
+			.await
+			.is_err());
+
+		vfs.get_node(
+			&u(FILE_CONTENT_PARENTS_TEST_LOC),
+			&NodeGetOptions::new().create_new(true),
+		)
+		.await
+		.unwrap();
+		let is_node = vfs
+			.metadata(&u(FILE_CONTENT_PARENTS_TEST_LOC))
+			.await
+			.map(|metadata| metadata.is_node)
+			.map_err(VfsError::into_owned);
+		vfs.remove_node(&u(FILE_CONTENT_PARENTS_TEST_DIR), true)
+			.await
+			.unwrap();
+		assert!(is_node.unwrap());
+	}
+
 	#[async_test]
 	async fn node_seeking() {
-		let mut vfs = Vfs::default();
+		let vfs = Vfs::default();
 		vfs.add_scheme(
 			"fs",
 			FileSystemScheme::new(std::env::current_dir().unwrap().join("target")),
@@ -416,7 +717,7 @@ mod tests_general {
 
 	#[async_test]
 	async fn metadata() {
-		let mut vfs = Vfs::default();
+		let vfs = Vfs::default();
 		vfs.add_scheme(
 			"fs",
 			FileSystemScheme::new(std::env::current_dir().unwrap()),
@@ -425,20 +726,135 @@ mod tests_general {
 		let metadata = vfs.metadata_at("fs:/Cargo.toml").await.unwrap();
 		assert!(metadata.is_node);
 		assert!(metadata.len.unwrap().0 > 0);
+		assert_eq!(metadata.kind, Some(NodeKind::File));
+		assert_eq!(metadata.read_only, Some(false));
+		assert!(metadata.accessed.is_some());
 		let metadata = vfs.metadata_at("fs:/src").await.unwrap();
 		assert!(!metadata.is_node);
+		assert_eq!(metadata.kind, Some(NodeKind::Directory));
 		assert!(vfs.metadata_at("fs:/blah").await.is_err());
 		assert!(vfs.metadata_at("nothing:").await.is_err());
 	}
 
+	#[async_test]
+	async fn io_error_path() {
+		let vfs = Vfs::default();
+		vfs.add_scheme(
+			"fs",
+			FileSystemScheme::new(std::env::current_dir().unwrap()),
+		)
+		.unwrap();
+		let error = match vfs
+			.get_node_at("fs:/no/such/file.txt", &NodeGetOptions::new().read(true))
+			.await
+		{
+			Err(VfsError::SchemeError(error)) => error,
+			_ => panic!("opened a missing file"),
+		};
+		assert_eq!(
+			error.io_error().map(std::io::Error::kind),
+			Some(std::io::ErrorKind::NotFound)
+		);
+		let message = error.to_string();
+		assert!(message.contains("/no/such/file.txt"), "{}", message);
+	}
+
+	#[async_test]
+	async fn read_dir_fused() {
+		let vfs = Vfs::default();
+		vfs.add_scheme(
+			"fs",
+			FileSystemScheme::new(std::env::current_dir().unwrap()),
+		)
+		.unwrap();
+		let mut entries = vfs.read_dir_at("fs:/src/errors/").await.unwrap();
+		assert_eq!((&mut entries).count().await, 3);
+		assert!(entries.next().await.is_none(), "fused");
+		assert!(entries.next().await.is_none(), "fused");
+	}
+
+	#[async_test]
+	async fn read_dir_encoded_names() {
+		let root = std::env::current_dir().unwrap().join("target");
+		let dir = root.join(FILE_CONTENT_ENCODED_TEST_DIR);
+		let _ = std::fs::remove_dir_all(&dir);
+		std::fs::create_dir_all(&dir).unwrap();
+		std::fs::write(dir.join("with space ü.txt"), FILE_TEST_CONTENT).unwrap();
+		let vfs = Vfs::default();
+		vfs.add_scheme("fs", FileSystemScheme::new(root)).unwrap();
+		let entries: Vec<_> = vfs
+			.read_dir_at(&format!("fs:/{}", FILE_CONTENT_ENCODED_TEST_DIR))
+			.await
+			.unwrap()
+			.collect()
+			.await;
+		assert_eq!(entries.len(), 1);
+		let metadata = entries[0].metadata.as_ref().expect("listed with metadata");
+		let len = FILE_TEST_CONTENT.len();
+		assert_eq!(metadata.len, Some((len, Some(len))));
+		assert_eq!(metadata.kind, Some(NodeKind::File));
+		let url = u(entries[0].url.as_str());
+		assert_eq!(
+			url.path(),
+			format!(
+				"/{}/with%20space%20%C3%BC.txt",
+				FILE_CONTENT_ENCODED_TEST_DIR
+			)
+		);
+		let read = vfs.read_to_vec(&url).await.map_err(VfsError::into_owned);
+		std::fs::remove_dir_all(&dir).unwrap();
+		assert_eq!(read.unwrap(), FILE_TEST_CONTENT.as_bytes());
+		assert!(matches!(
+			vfs.metadata_at("fs:/test/..%2F..%2Fsecret").await,
+			Err(VfsError::SchemeError(SchemeError::UrlAccessError(_)))
+		));
+	}
+
+	#[async_test]
+	async fn open_with_metadata() {
+		let vfs = Vfs::default();
+		vfs.add_scheme(
+			"fs",
+			FileSystemScheme::new(std::env::current_dir().unwrap()),
+		)
+		.unwrap();
+		let read = &NodeGetOptions::new().read(true);
+		let metadata = vfs.metadata_at("fs:/Cargo.toml").await.unwrap();
+		let mut buffer = String::new();
+		vfs.open_with_metadata_at("fs:/Cargo.toml", read, &metadata)
+			.await
+			.unwrap()
+			.read_to_string(&mut buffer)
+			.await
+			.unwrap();
+		assert!(buffer.contains("[package]"));
+		// Trusted over the file system, so the file is taken for a directory without a lookup
+		let metadata = NodeMetadata {
+			is_node: false,
+			..metadata
+		};
+		assert!(matches!(
+			vfs.open_with_metadata_at("fs:/Cargo.toml", read, &metadata)
+				.await,
+			Err(VfsError::SchemeError(SchemeError::IsADirectory(_)))
+		));
+	}
+
 	#[async_test]
 	async fn list_nodes() {
-		let mut vfs = Vfs::default();
+		let vfs = Vfs::default();
 		vfs.add_scheme(
 			"fs",
 			FileSystemScheme::new(std::env::current_dir().unwrap()),
 		)
 		.unwrap();
+		assert_eq!(
+			vfs.read_dir_at("fs:/src/schemes/filesystem/")
+				.await
+				.unwrap()
+				.size_hint(),
+			(0, None)
+		);
 		assert_eq!(
 			vfs.read_dir_at("fs:/src/schemes/filesystem/")
 				.await
@@ -470,8 +886,230 @@ mod tests_general {
 				.unwrap()
 				.url
 				.path(),
-			"/src/schemes/mod.rs",
-			"like std::fs::read_dir trim any non-dir elements in the path"
+			"/src/schemes/filesystem/mod.rs",
+			"the directory itself rather than its parent"
 		);
+		assert!(matches!(
+			vfs.read_dir_at("fs:/src/schemes/filesystem/mod.rs").await,
+			Err(VfsError::SchemeError(SchemeError::NotADirectory(_)))
+		));
+	}
+
+	#[async_test]
+	async fn list_files_and_dirs() {
+		let vfs = Vfs::default();
+		vfs.add_scheme(
+			"fs",
+			FileSystemScheme::new(std::env::current_dir().unwrap()),
+		)
+		.unwrap();
+		let mut files: Vec<_> = vfs
+			.read_files_at("fs:/src/")
+			.await
+			.unwrap()
+			.map(|e| e.url.path().to_owned())
+			.collect()
+			.await;
+		files.sort();
+		assert!(files.contains(&"/src/lib.rs".to_owned()));
+		assert!(!files.contains(&"/src/errors".to_owned()));
+		assert!(!files.contains(&"/src/schemes".to_owned()));
+		let mut dirs: Vec<_> = vfs
+			.read_dirs_at("fs:/src/")
+			.await
+			.unwrap()
+			.map(|e| e.url.path().to_owned())
+			.collect()
+			.await;
+		dirs.sort();
+		assert_eq!(dirs, ["/src/errors", "/src/schemes"]);
+		assert!(vfs.read_files_at("fs:/nothing/").await.is_err());
+	}
+
+	#[async_test]
+	async fn node_try_clone() {
+		let vfs = Vfs::default();
+		vfs.add_scheme(
+			"fs",
+			FileSystemScheme::new(std::env::current_dir().unwrap().join("target")),
+		)
+		.unwrap();
+		let mut node = vfs
+			.get_node(
+				&u(FILE_CONTENT_CLONE_TEST_LOC),
+				&NodeGetOptions::new()
+					.read(true)
+					.write(true)
+					.truncate(true)
+					.create(true),
+			)
+			.await
+			.unwrap();
+		node.write_all(FILE_TEST_CONTENT.as_bytes()).await.unwrap();
+		node.flush().await.unwrap();
+		let mut clone = node.as_mut().try_clone().await.unwrap();
+		node.seek(SeekFrom::Start(5)).await.unwrap();
+		let mut from_clone = String::new();
+		clone.read_to_string(&mut from_clone).await.unwrap();
+		let mut from_node = String::new();
+		node.read_to_string(&mut from_node).await.unwrap();
+		drop(clone);
+		drop(node);
+		vfs.remove_node(&u(FILE_CONTENT_CLONE_TEST_LOC), false)
+			.await
+			.unwrap();
+		assert_eq!(from_clone, FILE_TEST_CONTENT);
+		assert_eq!(from_node, &FILE_TEST_CONTENT[5..]);
+	}
+
+	#[async_test]
+	async fn create_dir() {
+		let vfs = Vfs::default();
+		vfs.add_scheme(
+			"fs",
+			FileSystemScheme::new(std::env::current_dir().unwrap().join("target")),
+		)
+		.unwrap();
+		let _ = vfs
+			.remove_node_at(FILE_CONTENT_CREATE_DIR_TEST_DIR, true)
+			.await;
+		let nested = format!("{}/inner/deeper", FILE_CONTENT_CREATE_DIR_TEST_DIR);
+		let missing_parent = vfs.create_dir_at(&nested).await;
+		vfs.create_dir_at(FILE_CONTENT_CREATE_DIR_TEST_DIR)
+			.await
+			.unwrap();
+		let existing = vfs.create_dir_at(FILE_CONTENT_CREATE_DIR_TEST_DIR).await;
+		vfs.create_dir_all_at(&nested).await.unwrap();
+		vfs.create_dir_all_at(&nested).await.unwrap();
+		let metadata = vfs.metadata_at(&nested).await.unwrap();
+		vfs.remove_node_at(FILE_CONTENT_CREATE_DIR_TEST_DIR, true)
+			.await
+			.unwrap();
+		assert!(missing_parent.is_err());
+		assert!(existing.is_err());
+		assert!(!metadata.is_node);
+	}
+
+	#[cfg(feature = "watch")]
+	#[async_test]
+	async fn watch() {
+		let vfs = Vfs::default();
+		vfs.add_scheme(
+			"fs",
+			FileSystemScheme::new(std::env::current_dir().unwrap().join("target")),
+		)
+		.unwrap();
+		let _ = vfs.remove_node_at(FILE_CONTENT_WATCH_TEST_DIR, true).await;
+		vfs.create_dir_at(FILE_CONTENT_WATCH_TEST_DIR)
+			.await
+			.unwrap();
+		let mut events = vfs.watch_at(FILE_CONTENT_WATCH_TEST_DIR).await.unwrap();
+		let watched = format!("{}/node.txt", FILE_CONTENT_WATCH_TEST_DIR);
+		let mut node = vfs
+			.get_node_at(&watched, &NodeGetOptions::new().create_new(true))
+			.await
+			.unwrap();
+		node.write_all(FILE_TEST_CONTENT.as_bytes()).await.unwrap();
+		node.close().await.unwrap();
+		drop(node);
+		let event = events.next().await.unwrap();
+		vfs.remove_node_at(FILE_CONTENT_WATCH_TEST_DIR, true)
+			.await
+			.unwrap();
+		assert_eq!(event.url.as_str(), watched);
+		assert_eq!(event.kind, crate::scheme::WatchEventKind::Created);
+	}
+
+	#[async_test]
+	async fn copy_and_move_within_scheme() {
+		let vfs = Vfs::default();
+		vfs.add_scheme(
+			"fs",
+			FileSystemScheme::new(std::env::current_dir().unwrap().join("target")),
+		)
+		.unwrap();
+		let mut node = vfs
+			.get_node_at(
+				FILE_CONTENT_RENAME_TEST_LOC,
+				&NodeGetOptions::new().create(true).truncate(true),
+			)
+			.await
+			.unwrap();
+		node.write_all(FILE_TEST_CONTENT.as_bytes()).await.unwrap();
+		node.close().await.unwrap();
+		drop(node);
+
+		vfs.copy_node_at(
+			FILE_CONTENT_RENAME_TEST_LOC,
+			FILE_CONTENT_RENAMED_TEST_LOC,
+			ConflictPolicy::Overwrite,
+		)
+		.await
+		.unwrap();
+		let copied = vfs
+			.read_to_vec_at(FILE_CONTENT_RENAMED_TEST_LOC)
+			.await
+			.unwrap();
+		vfs.move_node_at(
+			FILE_CONTENT_RENAME_TEST_LOC,
+			FILE_CONTENT_RENAMED_TEST_LOC,
+			ConflictPolicy::Overwrite,
+		)
+		.await
+		.unwrap();
+		let source_gone = vfs.metadata_at(FILE_CONTENT_RENAME_TEST_LOC).await.is_err();
+		let moved = vfs
+			.read_to_vec_at(FILE_CONTENT_RENAMED_TEST_LOC)
+			.await
+			.unwrap();
+		vfs.remove_node_at(FILE_CONTENT_RENAMED_TEST_LOC, false)
+			.await
+			.unwrap();
+		assert_eq!(copied, FILE_TEST_CONTENT.as_bytes());
+		assert!(source_gone);
+		assert_eq!(moved, FILE_TEST_CONTENT.as_bytes());
+	}
+
+	#[cfg(feature = "in_memory")]
+	#[async_test]
+	async fn move_node_from_memory() {
+		use std::time::{Duration, SystemTime};
+		let vfs = Vfs::default();
+		vfs.add_scheme("mem", crate::MemoryScheme::default())
+			.unwrap();
+		vfs.add_scheme(
+			"fs",
+			FileSystemScheme::new(std::env::current_dir().unwrap().join("target")),
+		)
+		.unwrap();
+		let mut node = vfs
+			.get_node_at("mem:/node", &NodeGetOptions::new().create_new(true))
+			.await
+			.unwrap();
+		node.write_all(FILE_TEST_CONTENT.as_bytes()).await.unwrap();
+		drop(node);
+		let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+		vfs.set_modified_at("mem:/node", modified).await.unwrap();
+
+		let report = vfs
+			.move_node_at(
+				"mem:/node",
+				FILE_CONTENT_MOVE_TEST_LOC,
+				ConflictPolicy::Overwrite,
+			)
+			.await
+			.unwrap();
+		assert!(report.is_complete());
+		assert!(vfs.metadata_at("mem:/node").await.is_err());
+		let moved = vfs.metadata_at(FILE_CONTENT_MOVE_TEST_LOC).await.unwrap();
+		let content = vfs
+			.read_to_vec_at(FILE_CONTENT_MOVE_TEST_LOC)
+			.await
+			.unwrap();
+		vfs.remove_node_at(FILE_CONTENT_MOVE_TEST_LOC, false)
+			.await
+			.unwrap();
+		assert_eq!(moved.modified, Some(modified));
+		assert_eq!(content, FILE_TEST_CONTENT.as_bytes());
 	}
 }
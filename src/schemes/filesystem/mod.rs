@@ -3,7 +3,129 @@ pub mod filesystem_async_std;
 #[cfg(feature = "backend_tokio")]
 pub mod filesystem_tokio;
 
+/// The metadata of a filesystem entry, with `linked` if `metadata` is of the target of a symlink
+/// at the path rather than of the path itself.
+#[cfg(any(feature = "backend_tokio", feature = "backend_async_std"))]
+fn node_metadata(metadata: &std::fs::Metadata, linked: bool) -> crate::scheme::NodeMetadata {
+	use crate::scheme::NodeKind;
+	let file_type = metadata.file_type();
+	let kind = if linked {
+		NodeKind::Symlink
+	} else if file_type.is_file() {
+		NodeKind::File
+	} else if file_type.is_dir() {
+		NodeKind::Directory
+	} else {
+		NodeKind::Other
+	};
+	let size = metadata.len() as usize;
+	crate::scheme::NodeMetadata {
+		is_node: metadata.is_file(),
+		len: Some((size, Some(size))),
+		modified: metadata.modified().ok(),
+		kind: Some(kind),
+		created: metadata.created().ok(),
+		accessed: metadata.accessed().ok(),
+		read_only: Some(metadata.permissions().readonly()),
+	}
+}
+
+/// Watches `path` below `root`, recursively if it is a directory, reporting changes as urls of the
+/// scheme of `url` the way `fs_path_from_url` maps them.  Both backends share this as `notify`
+/// runs its own thread rather than any async runtime.
+#[cfg(feature = "watch")]
+#[cfg(any(feature = "backend_tokio", feature = "backend_async_std"))]
+fn watch_path<'a>(
+	root: &std::path::Path,
+	path: &std::path::Path,
+	url: &'a url::Url,
+) -> Result<crate::scheme::WatchStream, crate::SchemeError<'a>> {
+	use crate::scheme::{NodeEntry, WatchEvent, WatchEventKind};
+	use crate::SchemeError;
+	use notify::event::{ModifyKind, RenameMode};
+	use notify::{EventKind, RecursiveMode, Watcher};
+	use std::borrow::Cow;
+
+	// Backends may report paths through the canonical root rather than the one given
+	let roots = [
+		root.to_owned(),
+		root.canonicalize().unwrap_or_else(|_error| root.to_owned()),
+	];
+	let mut base = url.clone();
+	base.set_path("/");
+	base.set_query(None);
+	base.set_fragment(None);
+	let to_url = move |path: &std::path::Path| {
+		let relative = roots.iter().find_map(|root| path.strip_prefix(root).ok())?;
+		let names: Option<Vec<&str>> = relative
+			.components()
+			.map(|component| component.as_os_str().to_str())
+			.collect();
+		NodeEntry::child(&base, names?).map(|entry| entry.url)
+	};
+	let (sender, receiver) = async_channel::unbounded();
+	let handler = move |event: notify::Result<notify::Event>| {
+		let event = match event {
+			Ok(event) => event,
+			Err(_error) => return,
+		};
+		let kinds: &[WatchEventKind] = match event.kind {
+			EventKind::Create(_) => &[WatchEventKind::Created],
+			EventKind::Modify(ModifyKind::Name(RenameMode::From)) => &[WatchEventKind::Removed],
+			EventKind::Modify(ModifyKind::Name(RenameMode::To)) => &[WatchEventKind::Created],
+			// Renames seen from both ends list the old path and then the new one
+			EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => {
+				&[WatchEventKind::Removed, WatchEventKind::Created]
+			}
+			EventKind::Modify(_) => &[WatchEventKind::Modified],
+			EventKind::Remove(_) => &[WatchEventKind::Removed],
+			_ => return,
+		};
+		for (index, path) in event.paths.iter().enumerate() {
+			let kind = kinds[index.min(kinds.len() - 1)];
+			if let Some(url) = to_url(path) {
+				let _ = sender.try_send(WatchEvent { url, kind });
+			}
+		}
+	};
+	let watch_error = |error: notify::Error| match error.kind {
+		notify::ErrorKind::Io(error) => SchemeError::io_at(url.path())(error),
+		notify::ErrorKind::PathNotFound => SchemeError::NodeDoesNotExist(Cow::Borrowed(url.path())),
+		_ => SchemeError::from(("failed to watch", Box::new(error) as Box<_>)),
+	};
+	let mut watcher = notify::recommended_watcher(handler).map_err(watch_error)?;
+	watcher
+		.watch(path, RecursiveMode::Recursive)
+		.map_err(watch_error)?;
+	Ok(Box::pin(Watched {
+		_watcher: watcher,
+		receiver,
+	}))
+}
+
+/// The events of a watch, which ends when the watcher is dropped along with this.
+#[cfg(feature = "watch")]
+#[cfg(any(feature = "backend_tokio", feature = "backend_async_std"))]
+struct Watched {
+	_watcher: notify::RecommendedWatcher,
+	receiver: async_channel::Receiver<crate::scheme::WatchEvent>,
+}
+
+#[cfg(feature = "watch")]
+#[cfg(any(feature = "backend_tokio", feature = "backend_async_std"))]
+impl futures_lite::Stream for Watched {
+	type Item = crate::scheme::WatchEvent;
+
+	fn poll_next(
+		self: std::pin::Pin<&mut Self>,
+		cx: &mut std::task::Context<'_>,
+	) -> std::task::Poll<Option<Self::Item>> {
+		futures_lite::StreamExt::poll_next(&mut self.get_mut().receiver, cx)
+	}
+}
+
 pub mod prelude {
+	#[allow(unused_imports)] // Empty when no filesystem backend is enabled
 	use super::*;
 	#[cfg(feature = "backend_async_std")]
 	pub use filesystem_async_std::*;
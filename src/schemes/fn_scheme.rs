@@ -0,0 +1,201 @@
+use crate::scheme::{BorrowedReadDirStream, NodeGetOptions, NodeMetadata, ReadDirStream};
+use crate::{PinnedNode, Scheme, SchemeError, Vfs};
+use std::future::Future;
+use std::pin::Pin;
+use url::Url;
+
+/// The boxed future every `FnScheme` closure returns.
+pub type FnSchemeFuture<'a, T> =
+	Pin<Box<dyn Future<Output = Result<T, SchemeError<'static>>> + Send + 'a>>;
+
+type GetNodeFn = Box<
+	dyn for<'a> Fn(&'a Vfs, &'a Url, &'a NodeGetOptions) -> FnSchemeFuture<'a, PinnedNode>
+		+ Send
+		+ Sync,
+>;
+type RemoveNodeFn =
+	Box<dyn for<'a> Fn(&'a Vfs, &'a Url, bool) -> FnSchemeFuture<'a, ()> + Send + Sync>;
+type MetadataFn =
+	Box<dyn for<'a> Fn(&'a Vfs, &'a Url) -> FnSchemeFuture<'a, NodeMetadata> + Send + Sync>;
+type ReadDirFn =
+	Box<dyn for<'a> Fn(&'a Vfs, &'a Url) -> FnSchemeFuture<'a, ReadDirStream> + Send + Sync>;
+
+/// A scheme built out of closures, useful for prototyping or for injecting mock behaviour in
+/// tests.  Any operation without a closure returns `SchemeError::Unsupported`.
+#[derive(Default)]
+pub struct FnScheme {
+	get_node: Option<GetNodeFn>,
+	remove_node: Option<RemoveNodeFn>,
+	metadata: Option<MetadataFn>,
+	read_dir: Option<ReadDirFn>,
+}
+
+impl FnScheme {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	pub fn on_get_node<F>(self, get_node: F) -> Self
+	where
+		F: for<'a> Fn(&'a Vfs, &'a Url, &'a NodeGetOptions) -> FnSchemeFuture<'a, PinnedNode>
+			+ Send
+			+ Sync
+			+ 'static,
+	{
+		Self {
+			get_node: Some(Box::new(get_node)),
+			..self
+		}
+	}
+
+	pub fn on_remove_node<F>(self, remove_node: F) -> Self
+	where
+		F: for<'a> Fn(&'a Vfs, &'a Url, bool) -> FnSchemeFuture<'a, ()> + Send + Sync + 'static,
+	{
+		Self {
+			remove_node: Some(Box::new(remove_node)),
+			..self
+		}
+	}
+
+	pub fn on_metadata<F>(self, metadata: F) -> Self
+	where
+		F: for<'a> Fn(&'a Vfs, &'a Url) -> FnSchemeFuture<'a, NodeMetadata> + Send + Sync + 'static,
+	{
+		Self {
+			metadata: Some(Box::new(metadata)),
+			..self
+		}
+	}
+
+	pub fn on_read_dir<F>(self, read_dir: F) -> Self
+	where
+		F: for<'a> Fn(&'a Vfs, &'a Url) -> FnSchemeFuture<'a, ReadDirStream>
+			+ Send
+			+ Sync
+			+ 'static,
+	{
+		Self {
+			read_dir: Some(Box::new(read_dir)),
+			..self
+		}
+	}
+}
+
+#[async_trait::async_trait]
+impl Scheme for FnScheme {
+	async fn get_node<'a>(
+		&self,
+		vfs: &Vfs,
+		url: &'a Url,
+		options: &NodeGetOptions,
+	) -> Result<PinnedNode, SchemeError<'a>> {
+		match &self.get_node {
+			Some(get_node) => get_node(vfs, url, options).await,
+			None => Err(SchemeError::Unsupported("get_node")),
+		}
+	}
+
+	async fn remove_node<'a>(
+		&self,
+		vfs: &Vfs,
+		url: &'a Url,
+		force: bool,
+	) -> Result<(), SchemeError<'a>> {
+		match &self.remove_node {
+			Some(remove_node) => remove_node(vfs, url, force).await,
+			None => Err(SchemeError::Unsupported("remove_node")),
+		}
+	}
+
+	async fn metadata<'a>(&self, vfs: &Vfs, url: &'a Url) -> Result<NodeMetadata, SchemeError<'a>> {
+		match &self.metadata {
+			Some(metadata) => metadata(vfs, url).await,
+			None => Err(SchemeError::Unsupported("metadata")),
+		}
+	}
+
+	async fn read_dir<'s, 'a>(
+		&'s self,
+		vfs: &'s Vfs,
+		url: &'a Url,
+	) -> Result<BorrowedReadDirStream<'s>, SchemeError<'a>> {
+		match &self.read_dir {
+			Some(read_dir) => read_dir(vfs, url).await,
+			None => Err(SchemeError::Unsupported("read_dir")),
+		}
+	}
+}
+
+#[cfg(test)]
+#[cfg(feature = "backend_tokio")]
+mod async_tokio_tests {
+	use crate::scheme::{NodeGetOptions, NodeMetadata};
+	use crate::{FnScheme, SchemeError, Vfs, VfsError};
+	use futures_lite::AsyncReadExt;
+
+	#[tokio::test]
+	async fn closures() {
+		let vfs = Vfs::default();
+		vfs.add_scheme(
+			"echo",
+			FnScheme::new()
+				.on_get_node(|vfs, url, options| {
+					Box::pin(async move {
+						Ok(vfs
+							.get_node_at(&format!("data:{}", url.path()), options)
+							.await?)
+					})
+				})
+				.on_metadata(|_vfs, url| {
+					let len = url.path().len();
+					Box::pin(async move {
+						Ok(NodeMetadata {
+							is_node: true,
+							len: Some((len, Some(len))),
+							modified: None,
+							..Default::default()
+						})
+					})
+				}),
+		)
+		.unwrap();
+		let mut buffer = String::new();
+		vfs.get_node_at("echo:hello", &NodeGetOptions::new().read(true))
+			.await
+			.unwrap()
+			.read_to_string(&mut buffer)
+			.await
+			.unwrap();
+		assert_eq!(&buffer, "hello");
+		assert_eq!(
+			vfs.metadata_at("echo:hello").await.unwrap().len,
+			Some((5, Some(5)))
+		);
+	}
+
+	#[tokio::test]
+	async fn unsupported_defaults() {
+		let vfs = Vfs::empty();
+		vfs.add_scheme("nothing", FnScheme::new()).unwrap();
+		assert!(matches!(
+			vfs.get_node_at("nothing:/", &NodeGetOptions::new().read(true))
+				.await,
+			Err(VfsError::SchemeError(SchemeError::Unsupported("get_node")))
+		));
+		assert!(matches!(
+			vfs.remove_node_at("nothing:/", false).await,
+			Err(VfsError::SchemeError(SchemeError::Unsupported(
+				"remove_node"
+			)))
+		));
+		assert!(matches!(
+			vfs.metadata_at("nothing:/").await,
+			Err(VfsError::SchemeError(SchemeError::Unsupported("metadata")))
+		));
+		assert!(matches!(
+			vfs.read_dir_at("nothing:/").await,
+			Err(VfsError::SchemeError(SchemeError::Unsupported("read_dir")))
+		));
+	}
+}
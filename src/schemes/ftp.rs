@@ -0,0 +1,1323 @@
+use crate::node::{seek_position, IsAllowed};
+use crate::scheme::{BorrowedReadDirStream, NodeEntry, NodeGetOptions, NodeKind, NodeMetadata};
+use crate::{Node, PinnedNode, Scheme, SchemeError, Vfs};
+use futures_lite::{AsyncRead, AsyncSeek, AsyncWrite, Future};
+use percent_encoding::percent_decode_str;
+use std::borrow::Cow;
+use std::convert::TryFrom;
+use std::io::SeekFrom;
+use std::net::IpAddr;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, SystemTime};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufStream, ReadBuf};
+use tokio::net::TcpStream;
+use tokio_rustls::client::TlsStream;
+use tokio_rustls::rustls::pki_types::ServerName;
+use tokio_rustls::rustls::{ClientConfig, RootCertStore};
+use tokio_rustls::TlsConnector;
+use url::Url;
+
+/// How many idle control connections a `FtpScheme` keeps for reuse unless set otherwise, see
+/// `FtpSchemeBuilder::max_idle_connections`.
+pub const DEFAULT_FTP_MAX_IDLE_CONNECTIONS: usize = 4;
+
+/// Serves the files below `root_path` of a FTP server, or of a FTPS one with explicit TLS when
+/// `tls` is set.  Each request takes a logged in control connection, an idle one if there is one
+/// else a new one, and hands it back when done unless it was lost.  The server may have closed an
+/// idle one since, so a request that loses one is tried once more on a new connection.  FTP only moves whole files, so a
+/// node holds its whole content in memory, fetched when it is opened and stored when a node
+/// opened for writing is closed.  The requests need a tokio runtime.
+pub struct FtpScheme {
+	client: Arc<FtpClient>,
+}
+
+pub struct FtpSchemeBuilder {
+	address: String,
+	user: String,
+	password: String,
+	root_path: String,
+	tls: Option<Arc<ClientConfig>>,
+	max_idle_connections: usize,
+}
+
+/// A reply of the server that the request did not expect.
+#[derive(Debug)]
+pub struct FtpError {
+	code: u16,
+	message: String,
+}
+
+struct FtpClient {
+	address: String,
+	user: String,
+	password: String,
+	root_path: String,
+	tls: Option<(TlsConnector, ServerName<'static>)>,
+	max_idle_connections: usize,
+	idle: Mutex<Vec<Control>>,
+}
+
+/// A logged in control connection.
+struct Control {
+	stream: BufStream<FtpStream>,
+	peer: IpAddr,
+}
+
+enum FtpStream {
+	Plain(TcpStream),
+	Tls(Box<TlsStream<TcpStream>>),
+}
+
+/// Why a request failed, all but `Io` leave the control connection usable.
+enum Failure {
+	Io(std::io::Error),
+	Reply(FtpError),
+	DoesNotExist,
+	AlreadyExists,
+	IsADirectory,
+}
+
+struct ListEntry {
+	name: String,
+	metadata: Option<NodeMetadata>,
+}
+
+impl FtpScheme {
+	/// Connects to `address`, a `host` or `host:port`, logging in anonymously by default.
+	pub fn builder(address: impl Into<String>) -> FtpSchemeBuilder {
+		FtpSchemeBuilder {
+			address: address.into(),
+			user: "anonymous".to_owned(),
+			password: "anonymous".to_owned(),
+			root_path: String::new(),
+			tls: None,
+			max_idle_connections: DEFAULT_FTP_MAX_IDLE_CONNECTIONS,
+		}
+	}
+
+	pub fn address(&self) -> &str {
+		&self.client.address
+	}
+
+	pub fn root_path(&self) -> &str {
+		if self.client.root_path.is_empty() {
+			"/"
+		} else {
+			&self.client.root_path
+		}
+	}
+
+	pub fn is_tls(&self) -> bool {
+		self.client.tls.is_some()
+	}
+
+	/// How many control connections are idle right now.
+	pub fn idle_connections(&self) -> usize {
+		self.client.idle.lock().expect("poisoned lock").len()
+	}
+
+	/// Segments are percent-decoded into file names, one that decodes to something that is not a
+	/// single file name, such as `..` or one holding a `/` or a line break, could escape the root
+	/// or the command so is refused.
+	pub fn remote_path_from_url<'a>(&self, url: &'a Url) -> Result<String, SchemeError<'a>> {
+		let segments = url
+			.path_segments()
+			.ok_or(SchemeError::NodeDoesNotExist(Cow::Borrowed(url.path())))?;
+		let mut path = self.client.root_path.clone();
+		for segment in segments.filter(|segment| !segment.is_empty()) {
+			let name = percent_decode_str(segment).decode_utf8_lossy();
+			if name == "." || name == ".." || name.contains(['/', '\r', '\n']) {
+				return Err(SchemeError::UrlAccessError(Cow::Borrowed(url)));
+			}
+			path.push('/');
+			path.push_str(&name);
+		}
+		if path.is_empty() {
+			path.push('/');
+		}
+		Ok(path)
+	}
+
+	/// Runs `request` over a control connection, handing the connection back to the pool after
+	/// unless it was lost, in which case an idle one is replaced by a new one to run it again.
+	async fn request<'a, T>(
+		&self,
+		url: &'a Url,
+		request: impl for<'c> Fn(
+			&'c mut Control,
+			&'c FtpClient,
+		) -> Pin<Box<dyn Future<Output = Result<T, Failure>> + Send + 'c>>,
+	) -> Result<T, SchemeError<'a>> {
+		let client = &self.client;
+		let run = async {
+			let (mut control, mut idle) = client.connect().await?;
+			loop {
+				match request(&mut control, client).await {
+					Err(failure) if failure.is_closed() && idle => {
+						control = client.open().await?;
+						idle = false;
+					}
+					result => return client.release_after(control, result),
+				}
+			}
+		};
+		run.await.map_err(|failure| failure.into_scheme_error(url))
+	}
+}
+
+impl FtpSchemeBuilder {
+	/// Fails when `tls` is set and the host of the address is not a valid name for a certificate.
+	pub fn build(self) -> Result<FtpScheme, SchemeError<'static>> {
+		let host = match self.address.rsplit_once(':') {
+			Some((host, port)) if port.parse::<u16>().is_ok() => host,
+			_ => &self.address,
+		};
+		let host = host.trim_start_matches('[').trim_end_matches(']');
+		let address = if host.len() == self.address.len() {
+			format!("{}:21", self.address)
+		} else {
+			self.address.clone()
+		};
+		let tls = match self.tls {
+			Some(config) => {
+				let name = ServerName::try_from(host.to_owned()).map_err(|error| {
+					SchemeError::GenericError(
+						Some("FTP host is not a valid TLS server name"),
+						Some(Box::new(error)),
+					)
+				})?;
+				Some((TlsConnector::from(config), name))
+			}
+			None => None,
+		};
+		Ok(FtpScheme {
+			client: Arc::new(FtpClient {
+				address,
+				user: self.user,
+				password: self.password,
+				root_path: self.root_path,
+				tls,
+				max_idle_connections: self.max_idle_connections,
+				idle: Mutex::new(Vec::new()),
+			}),
+		})
+	}
+
+	pub fn login(mut self, user: impl Into<String>, password: impl Into<String>) -> Self {
+		self.user = user.into();
+		self.password = password.into();
+		self
+	}
+
+	/// The directory on the server that the root of the scheme is, `/` by default.
+	pub fn root_path(mut self, root_path: impl Into<String>) -> Self {
+		self.root_path = root_path.into().trim_end_matches('/').to_owned();
+		self
+	}
+
+	/// Uses explicit TLS for the control and data connections, checking the server against the
+	/// webpki roots.  The address must then name the host as its certificate does.
+	pub fn tls(self) -> Self {
+		let mut roots = RootCertStore::empty();
+		roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+		let config = ClientConfig::builder()
+			.with_root_certificates(roots)
+			.with_no_client_auth();
+		self.tls_config(Arc::new(config))
+	}
+
+	/// Like `tls` but with `config`, such as to trust a private certificate authority.
+	pub fn tls_config(mut self, config: Arc<ClientConfig>) -> Self {
+		self.tls = Some(config);
+		self
+	}
+
+	/// Defaults to `DEFAULT_FTP_MAX_IDLE_CONNECTIONS`, zero opens a connection for every request.
+	pub fn max_idle_connections(mut self, max_idle_connections: usize) -> Self {
+		self.max_idle_connections = max_idle_connections;
+		self
+	}
+}
+
+impl FtpError {
+	pub fn code(&self) -> u16 {
+		self.code
+	}
+
+	pub fn message(&self) -> &str {
+		&self.message
+	}
+}
+
+impl std::fmt::Display for FtpError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "FTP server replied {}: {}", self.code, self.message)
+	}
+}
+
+impl std::error::Error for FtpError {}
+
+impl From<std::io::Error> for Failure {
+	fn from(error: std::io::Error) -> Self {
+		Failure::Io(error)
+	}
+}
+
+impl Failure {
+	/// Whether the control connection is gone, such as one the server closed while it was idle.
+	fn is_closed(&self) -> bool {
+		match self {
+			Failure::Io(_error) => true,
+			Failure::Reply(reply) => reply.code == 421,
+			_ => false,
+		}
+	}
+
+	fn into_scheme_error(self, url: &Url) -> SchemeError<'_> {
+		match self {
+			Failure::Io(error) => SchemeError::IOErrorAt(Cow::Borrowed(url.path()), error),
+			Failure::Reply(error) if error.code == 550 => {
+				SchemeError::NodeDoesNotExist(Cow::Borrowed(url.path()))
+			}
+			Failure::Reply(error) if error.code == 530 => {
+				SchemeError::UrlAccessError(Cow::Borrowed(url))
+			}
+			Failure::Reply(error) => {
+				SchemeError::GenericError(Some("FTP request failed"), Some(Box::new(error)))
+			}
+			Failure::DoesNotExist => SchemeError::NodeDoesNotExist(Cow::Borrowed(url.path())),
+			Failure::AlreadyExists => SchemeError::NodeAlreadyExists(Cow::Borrowed(url.path())),
+			Failure::IsADirectory => SchemeError::IsADirectory(Cow::Borrowed(url.path())),
+		}
+	}
+
+	fn into_io(self) -> std::io::Error {
+		match self {
+			Failure::Io(error) => error,
+			Failure::Reply(error) => std::io::Error::other(error),
+			Failure::DoesNotExist => std::io::ErrorKind::NotFound.into(),
+			Failure::AlreadyExists => std::io::ErrorKind::AlreadyExists.into(),
+			Failure::IsADirectory => std::io::Error::other("is a directory"),
+		}
+	}
+}
+
+impl FtpClient {
+	/// An idle control connection if there is one, else a new one, and whether it was idle.
+	async fn connect(&self) -> Result<(Control, bool), Failure> {
+		let idle = self.idle.lock().expect("poisoned lock").pop();
+		match idle {
+			Some(control) => Ok((control, true)),
+			None => Ok((self.open().await?, false)),
+		}
+	}
+
+	/// A new logged in control connection.
+	async fn open(&self) -> Result<Control, Failure> {
+		let stream = TcpStream::connect(&self.address).await?;
+		let peer = stream.peer_addr()?.ip();
+		let mut control = Control {
+			stream: BufStream::new(FtpStream::Plain(stream)),
+			peer,
+		};
+		control.expect_reply(&[220]).await?;
+		if self.tls.is_some() {
+			control.expect("AUTH TLS", &[234]).await?;
+			let stream = match control.stream.into_inner() {
+				FtpStream::Plain(stream) => stream,
+				FtpStream::Tls(_stream) => {
+					unreachable!("the control connection is plain until now")
+				}
+			};
+			control.stream = BufStream::new(self.secure(stream).await?);
+		}
+		let reply = control.command(&format!("USER {}", self.user)).await?;
+		match reply.code {
+			230 => {}
+			331 => {
+				control
+					.expect(&format!("PASS {}", self.password), &[202, 230])
+					.await?;
+			}
+			_ => return Err(Failure::Reply(reply)),
+		}
+		if self.tls.is_some() {
+			control.expect("PBSZ 0", &[200]).await?;
+			control.expect("PROT P", &[200]).await?;
+		}
+		control.expect("TYPE I", &[200]).await?;
+		Ok(control)
+	}
+
+	/// Hands `control` back to the pool unless `result` says it was lost.
+	fn release_after<T>(&self, control: Control, result: Result<T, Failure>) -> Result<T, Failure> {
+		if !matches!(&result, Err(failure) if failure.is_closed()) {
+			self.release(control);
+		}
+		result
+	}
+
+	fn release(&self, control: Control) {
+		let mut idle = self.idle.lock().expect("poisoned lock");
+		if idle.len() < self.max_idle_connections {
+			idle.push(control);
+		}
+	}
+
+	async fn secure(&self, stream: TcpStream) -> std::io::Result<FtpStream> {
+		match &self.tls {
+			None => Ok(FtpStream::Plain(stream)),
+			Some((connector, name)) => {
+				let stream = connector.connect(name.clone(), stream).await?;
+				Ok(FtpStream::Tls(Box::new(stream)))
+			}
+		}
+	}
+
+	async fn store(&self, path: &str, content: &[u8]) -> Result<(), Failure> {
+		let (mut control, mut idle) = self.connect().await?;
+		loop {
+			match control.store(self, path, content).await {
+				Err(failure) if failure.is_closed() && idle => {
+					control = self.open().await?;
+					idle = false;
+				}
+				result => return self.release_after(control, result),
+			}
+		}
+	}
+}
+
+impl Control {
+	/// Reads a whole reply, joining the lines of a multi-line one.
+	async fn reply(&mut self) -> Result<FtpError, Failure> {
+		let mut line = String::new();
+		if self.stream.read_line(&mut line).await? == 0 {
+			return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+		}
+		let code = line
+			.get(..3)
+			.and_then(|code| code.parse::<u16>().ok())
+			.ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidData, line.clone()))?;
+		let mut message = line.get(4..).unwrap_or("").trim_end().to_owned();
+		if line.as_bytes().get(3) == Some(&b'-') {
+			let last = format!("{} ", code);
+			loop {
+				line.clear();
+				if self.stream.read_line(&mut line).await? == 0 {
+					return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+				}
+				message.push('\n');
+				message.push_str(line.strip_prefix(&last).unwrap_or(&line).trim_end());
+				if line.starts_with(&last) {
+					break;
+				}
+			}
+		}
+		Ok(FtpError { code, message })
+	}
+
+	async fn command(&mut self, command: &str) -> Result<FtpError, Failure> {
+		self.stream.write_all(command.as_bytes()).await?;
+		self.stream.write_all(b"\r\n").await?;
+		self.stream.flush().await?;
+		self.reply().await
+	}
+
+	async fn expect_reply(&mut self, codes: &[u16]) -> Result<FtpError, Failure> {
+		let reply = self.reply().await?;
+		if codes.contains(&reply.code) {
+			Ok(reply)
+		} else {
+			Err(Failure::Reply(reply))
+		}
+	}
+
+	async fn expect(&mut self, command: &str, codes: &[u16]) -> Result<FtpError, Failure> {
+		let reply = self.command(command).await?;
+		if codes.contains(&reply.code) {
+			Ok(reply)
+		} else {
+			Err(Failure::Reply(reply))
+		}
+	}
+
+	/// Opens a passive data connection, with `EPSV` else `PASV`, always to the host of the control
+	/// connection.
+	async fn passive(&mut self) -> Result<TcpStream, Failure> {
+		let reply = self.command("EPSV").await?;
+		let (reply, port) = if reply.code == 229 {
+			let port = parse_epsv(&reply.message);
+			(reply, port)
+		} else {
+			let reply = self.expect("PASV", &[227]).await?;
+			let port = parse_pasv(&reply.message);
+			(reply, port)
+		};
+		let port = port.ok_or(Failure::Reply(reply))?;
+		Ok(TcpStream::connect((self.peer, port)).await?)
+	}
+
+	/// Runs a transfer `command` over a new data connection, which is only secured once the
+	/// server accepted the command as it only then starts its side of the handshake.
+	async fn transfer(&mut self, client: &FtpClient, command: &str) -> Result<FtpStream, Failure> {
+		let data = self.passive().await?;
+		self.expect(command, &[125, 150]).await?;
+		Ok(client.secure(data).await?)
+	}
+
+	async fn retrieve(&mut self, client: &FtpClient, path: &str) -> Result<Vec<u8>, Failure> {
+		let mut data = self.transfer(client, &format!("RETR {}", path)).await?;
+		let mut content = Vec::new();
+		data.read_to_end(&mut content).await?;
+		drop(data);
+		self.expect_reply(&[226, 250]).await?;
+		Ok(content)
+	}
+
+	async fn store(
+		&mut self,
+		client: &FtpClient,
+		path: &str,
+		content: &[u8],
+	) -> Result<(), Failure> {
+		let mut data = self.transfer(client, &format!("STOR {}", path)).await?;
+		data.write_all(content).await?;
+		data.shutdown().await?;
+		drop(data);
+		self.expect_reply(&[226, 250]).await?;
+		Ok(())
+	}
+
+	async fn list(&mut self, client: &FtpClient, path: &str) -> Result<Vec<ListEntry>, Failure> {
+		let (mut data, names_only) = match self.transfer(client, &format!("MLSD {}", path)).await {
+			Ok(data) => (data, false),
+			Err(Failure::Reply(reply)) if (500..=502).contains(&reply.code) => (
+				self.transfer(client, &format!("NLST {}", path)).await?,
+				true,
+			),
+			Err(failure) => return Err(failure),
+		};
+		let mut listing = String::new();
+		data.read_to_string(&mut listing).await?;
+		drop(data);
+		self.expect_reply(&[226, 250]).await?;
+		Ok(listing
+			.lines()
+			.filter_map(|line| {
+				if names_only {
+					let name = line.rsplit('/').next()?;
+					(!name.is_empty() && name != "." && name != "..").then(|| ListEntry {
+						name: name.to_owned(),
+						metadata: None,
+					})
+				} else {
+					parse_mlsd(line)
+				}
+			})
+			.collect())
+	}
+
+	/// The size of the file at `path`, `None` if there is none, such as for a directory.
+	async fn size(&mut self, path: &str) -> Result<Option<usize>, Failure> {
+		let reply = self.command(&format!("SIZE {}", path)).await?;
+		match reply.code {
+			213 => Ok(reply.message.trim().parse().ok()),
+			550 => Ok(None),
+			_ => Err(Failure::Reply(reply)),
+		}
+	}
+
+	async fn modified(&mut self, path: &str) -> Result<Option<SystemTime>, Failure> {
+		let reply = self.command(&format!("MDTM {}", path)).await?;
+		Ok(match reply.code {
+			213 => parse_time(reply.message.trim()),
+			_ => None,
+		})
+	}
+
+	async fn is_dir(&mut self, path: &str) -> Result<bool, Failure> {
+		let reply = self.command(&format!("CWD {}", path)).await?;
+		match reply.code {
+			250 => Ok(true),
+			550 => Ok(false),
+			_ => Err(Failure::Reply(reply)),
+		}
+	}
+
+	async fn metadata(&mut self, path: &str) -> Result<NodeMetadata, Failure> {
+		if let Some(len) = self.size(path).await? {
+			return Ok(NodeMetadata {
+				is_node: true,
+				len: Some((len, Some(len))),
+				modified: self.modified(path).await?,
+				kind: Some(NodeKind::File),
+				..Default::default()
+			});
+		}
+		if self.is_dir(path).await? {
+			Ok(dir_metadata(None))
+		} else {
+			Err(Failure::DoesNotExist)
+		}
+	}
+
+	/// The content a node at `path` opened with `options` starts with, creating it if need be.
+	async fn open(
+		&mut self,
+		client: &FtpClient,
+		path: &str,
+		options: &NodeGetOptions,
+	) -> Result<Vec<u8>, Failure> {
+		let size = self.size(path).await?;
+		if size.is_none() && self.is_dir(path).await? {
+			return Err(Failure::IsADirectory);
+		}
+		match size {
+			Some(_len) if options.get_create_new() => Err(Failure::AlreadyExists),
+			Some(_len) if options.get_write() && options.get_truncate() => {
+				self.store(client, path, &[]).await?;
+				Ok(Vec::new())
+			}
+			Some(_len) => self.retrieve(client, path).await,
+			None if options.get_create() || options.get_create_new() => {
+				if options.get_create_parents() {
+					if let Some((parent, _name)) = path.rsplit_once('/') {
+						self.create_dir_all(parent).await?;
+					}
+				}
+				self.store(client, path, &[]).await?;
+				Ok(Vec::new())
+			}
+			None => Err(Failure::DoesNotExist),
+		}
+	}
+
+	/// Creates `path` and every missing directory above it, FTP only creates one at a time.
+	async fn create_dir_all(&mut self, path: &str) -> Result<(), Failure> {
+		let mut dir = String::new();
+		for name in path.split('/').filter(|name| !name.is_empty()) {
+			dir.push('/');
+			dir.push_str(name);
+			if !self.is_dir(&dir).await? {
+				self.expect(&format!("MKD {}", dir), &[257]).await?;
+			}
+		}
+		Ok(())
+	}
+
+	/// Removes what is at `path`, a directory with everything in it if `force` is set.
+	fn remove<'c>(
+		&'c mut self,
+		client: &'c FtpClient,
+		path: String,
+		force: bool,
+	) -> Pin<Box<dyn Future<Output = Result<(), Failure>> + Send + 'c>> {
+		Box::pin(async move {
+			if self.size(&path).await?.is_some() {
+				self.expect(&format!("DELE {}", path), &[250]).await?;
+				return Ok(());
+			}
+			if !self.is_dir(&path).await? {
+				return Ok(());
+			}
+			if force {
+				for entry in self.list(client, &path).await? {
+					self.remove(client, format!("{}/{}", path, entry.name), true)
+						.await?;
+				}
+			}
+			self.expect(&format!("RMD {}", path), &[250]).await?;
+			Ok(())
+		})
+	}
+}
+
+fn dir_metadata(modified: Option<SystemTime>) -> NodeMetadata {
+	NodeMetadata {
+		is_node: false,
+		len: None,
+		modified,
+		kind: Some(NodeKind::Directory),
+		..Default::default()
+	}
+}
+
+/// The port of an `EPSV` reply, such as `Entering Extended Passive Mode (|||6446|)`.
+fn parse_epsv(message: &str) -> Option<u16> {
+	let (_text, port) = message.split_once("|||")?;
+	port.split_once('|')?.0.parse().ok()
+}
+
+/// The port of a `PASV` reply, such as `Entering Passive Mode (127,0,0,1,25,46)`.  The address
+/// is ignored, servers behind a NAT often report one that cannot be reached.
+fn parse_pasv(message: &str) -> Option<u16> {
+	let start = message.find(|c: char| c.is_ascii_digit())?;
+	let numbers: Vec<u16> = message[start..]
+		.split(|c: char| !c.is_ascii_digit())
+		.filter(|number| !number.is_empty())
+		.take(6)
+		.map(|number| number.parse().ok())
+		.collect::<Option<_>>()?;
+	match numbers[..] {
+		[_, _, _, _, high, low] if high < 256 && low < 256 => Some(high * 256 + low),
+		_ => None,
+	}
+}
+
+/// A `YYYYMMDDHHMMSS` time in UTC, optionally with fractional seconds, as `MDTM` and `MLSD` give.
+fn parse_time(time: &str) -> Option<SystemTime> {
+	let (time, _fraction) = time.split_once('.').unwrap_or((time, ""));
+	if time.len() != 14 || !time.bytes().all(|b| b.is_ascii_digit()) {
+		return None;
+	}
+	let field = |range: std::ops::Range<usize>| time[range].parse::<i64>().ok();
+	let (year, month, day) = (field(0..4)?, field(4..6)?, field(6..8)?);
+	let seconds = field(8..10)? * 3600 + field(10..12)? * 60 + field(12..14)?;
+	// Days since the epoch of a proleptic Gregorian date, see Howard Hinnant's `days_from_civil`
+	let year = if month <= 2 { year - 1 } else { year };
+	let era = year.div_euclid(400);
+	let year_of_era = year - era * 400;
+	let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+	let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+	let days = era * 146_097 + day_of_era - 719_468;
+	let since_epoch = u64::try_from(days * 86_400 + seconds).ok()?;
+	Some(SystemTime::UNIX_EPOCH + Duration::from_secs(since_epoch))
+}
+
+/// An entry of a `MLSD` listing, such as `type=file;size=12;modify=20240101120000; name`, `None`
+/// for the entries of the directory itself and of its parent.
+fn parse_mlsd(line: &str) -> Option<ListEntry> {
+	let (facts, name) = line.split_once(' ')?;
+	let (mut kind, mut len, mut modified) = (None, None, None);
+	for fact in facts.split(';') {
+		let (key, value) = match fact.split_once('=') {
+			Some(fact) => fact,
+			None => continue,
+		};
+		match key.to_ascii_lowercase().as_str() {
+			"type" => kind = Some(value.to_ascii_lowercase()),
+			"size" => len = value.parse::<usize>().ok(),
+			"modify" => modified = parse_time(value),
+			_ => {}
+		}
+	}
+	let metadata = match kind.as_deref() {
+		Some("cdir") | Some("pdir") => return None,
+		Some("dir") => Some(dir_metadata(modified)),
+		Some("file") => Some(NodeMetadata {
+			is_node: true,
+			len: len.map(|len| (len, Some(len))),
+			modified,
+			kind: Some(NodeKind::File),
+			..Default::default()
+		}),
+		_ => None,
+	};
+	Some(ListEntry {
+		name: name.to_owned(),
+		metadata,
+	})
+}
+
+#[async_trait::async_trait]
+impl Scheme for FtpScheme {
+	async fn get_node<'a>(
+		&self,
+		_vfs: &Vfs,
+		url: &'a Url,
+		options: &NodeGetOptions,
+	) -> Result<PinnedNode, SchemeError<'a>> {
+		let path = self.remote_path_from_url(url)?;
+		let (open_path, open_options) = (path.clone(), options.clone());
+		let content = self
+			.request(url, |control, client| {
+				let (path, options) = (open_path.clone(), open_options.clone());
+				Box::pin(async move { control.open(client, &path, &options).await })
+			})
+			.await?;
+		let cursor = if options.get_append() {
+			content.len()
+		} else {
+			0
+		};
+		Ok(Box::pin(FtpNode {
+			client: self.client.clone(),
+			path,
+			content,
+			cursor,
+			read: options.get_read(),
+			write: options.get_write(),
+			append: options.get_append(),
+			dirty: false,
+			storing: None,
+		}))
+	}
+
+	async fn remove_node<'a>(
+		&self,
+		_vfs: &Vfs,
+		url: &'a Url,
+		force: bool,
+	) -> Result<(), SchemeError<'a>> {
+		let path = self.remote_path_from_url(url)?;
+		self.request(url, |control, client| {
+			control.remove(client, path.clone(), force)
+		})
+		.await
+	}
+
+	async fn metadata<'a>(
+		&self,
+		_vfs: &Vfs,
+		url: &'a Url,
+	) -> Result<NodeMetadata, SchemeError<'a>> {
+		let path = self.remote_path_from_url(url)?;
+		self.request(url, |control, _client| {
+			let path = path.clone();
+			Box::pin(async move { control.metadata(&path).await })
+		})
+		.await
+	}
+
+	async fn read_dir<'s, 'a>(
+		&'s self,
+		_vfs: &'s Vfs,
+		url: &'a Url,
+	) -> Result<BorrowedReadDirStream<'s>, SchemeError<'a>> {
+		let path = self.remote_path_from_url(url)?;
+		let entries = self
+			.request(url, |control, client| {
+				let path = path.clone();
+				Box::pin(async move { control.list(client, &path).await })
+			})
+			.await?;
+		let dir = url.clone();
+		Ok(Box::pin(futures_lite::stream::iter(
+			entries.into_iter().filter_map(move |entry| {
+				let node_entry = NodeEntry::child(&dir, [&entry.name])?;
+				Some(match entry.metadata {
+					Some(metadata) => node_entry.with_metadata(metadata),
+					None => node_entry,
+				})
+			}),
+		)))
+	}
+
+	async fn create_dir<'a>(
+		&self,
+		_vfs: &Vfs,
+		url: &'a Url,
+		parents: bool,
+	) -> Result<(), SchemeError<'a>> {
+		let path = self.remote_path_from_url(url)?;
+		self.request(url, |control, _client| {
+			let path = path.clone();
+			Box::pin(async move {
+				if parents {
+					control.create_dir_all(&path).await
+				} else if control.is_dir(&path).await? {
+					Err(Failure::AlreadyExists)
+				} else {
+					control.expect(&format!("MKD {}", path), &[257]).await?;
+					Ok(())
+				}
+			})
+		})
+		.await
+	}
+}
+
+type StoreFuture = Pin<Box<dyn Future<Output = Result<(), Failure>> + Send + Sync>>;
+
+pub struct FtpNode {
+	client: Arc<FtpClient>,
+	path: String,
+	content: Vec<u8>,
+	cursor: usize,
+	read: bool,
+	write: bool,
+	append: bool,
+	/// Whether `content` was written to since it was last stored.
+	dirty: bool,
+	storing: Option<StoreFuture>,
+}
+
+impl FtpNode {
+	/// Stores `content` if it was written to, once done the node is up to date on the server.
+	fn poll_store(&mut self, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+		if self.storing.is_none() {
+			if !self.dirty {
+				return Poll::Ready(Ok(()));
+			}
+			let client = self.client.clone();
+			let path = self.path.clone();
+			let content = self.content.clone();
+			self.dirty = false;
+			self.storing = Some(Box::pin(async move { client.store(&path, &content).await }));
+		}
+		let storing = self.storing.as_mut().expect("just set");
+		let result = futures_lite::ready!(storing.as_mut().poll(cx));
+		self.storing = None;
+		if result.is_err() {
+			self.dirty = true;
+		}
+		Poll::Ready(result.map_err(Failure::into_io))
+	}
+}
+
+#[async_trait::async_trait]
+impl Node for FtpNode {
+	fn is_reader(&self) -> bool {
+		self.read
+	}
+
+	fn is_writer(&self) -> bool {
+		self.write
+	}
+
+	fn is_seeker(&self) -> bool {
+		true
+	}
+
+	fn known_len(&self) -> Option<u64> {
+		Some(self.content.len() as u64)
+	}
+}
+
+impl AsyncRead for FtpNode {
+	fn poll_read(
+		self: Pin<&mut Self>,
+		_cx: &mut Context<'_>,
+		buf: &mut [u8],
+	) -> Poll<std::io::Result<usize>> {
+		let this = self.get_mut();
+		this.read.into_poll_io_then(|| {
+			let remaining = this.content.get(this.cursor..).unwrap_or(&[]);
+			let amt = remaining.len().min(buf.len());
+			buf[..amt].copy_from_slice(&remaining[..amt]);
+			this.cursor += amt;
+			Poll::Ready(Ok(amt))
+		})
+	}
+}
+
+impl AsyncWrite for FtpNode {
+	fn poll_write(
+		self: Pin<&mut Self>,
+		_cx: &mut Context<'_>,
+		buf: &[u8],
+	) -> Poll<std::io::Result<usize>> {
+		let this = self.get_mut();
+		this.write.into_poll_io_then(|| {
+			if this.append {
+				this.cursor = this.content.len();
+			}
+			if this.content.len() < this.cursor {
+				this.content.resize(this.cursor, 0);
+			}
+			let overwritten = (this.content.len() - this.cursor).min(buf.len());
+			this.content[this.cursor..this.cursor + overwritten]
+				.copy_from_slice(&buf[..overwritten]);
+			this.content.extend_from_slice(&buf[overwritten..]);
+			this.cursor += buf.len();
+			this.dirty = true;
+			Poll::Ready(Ok(buf.len()))
+		})
+	}
+
+	fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+		let this = self.get_mut();
+		this.write.into_poll_io_then(|| this.poll_store(cx))
+	}
+
+	fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+		self.get_mut().poll_store(cx)
+	}
+}
+
+impl AsyncSeek for FtpNode {
+	fn poll_seek(
+		self: Pin<&mut Self>,
+		_cx: &mut Context<'_>,
+		pos: SeekFrom,
+	) -> Poll<std::io::Result<u64>> {
+		let this = self.get_mut();
+		let position = seek_position(pos, this.cursor as u64, Some(this.content.len() as u64))?;
+		this.cursor = position as usize;
+		Poll::Ready(Ok(position))
+	}
+}
+
+impl tokio::io::AsyncRead for FtpStream {
+	fn poll_read(
+		self: Pin<&mut Self>,
+		cx: &mut Context<'_>,
+		buf: &mut ReadBuf<'_>,
+	) -> Poll<std::io::Result<()>> {
+		match self.get_mut() {
+			FtpStream::Plain(stream) => tokio::io::AsyncRead::poll_read(Pin::new(stream), cx, buf),
+			FtpStream::Tls(stream) => tokio::io::AsyncRead::poll_read(Pin::new(stream), cx, buf),
+		}
+	}
+}
+
+impl tokio::io::AsyncWrite for FtpStream {
+	fn poll_write(
+		self: Pin<&mut Self>,
+		cx: &mut Context<'_>,
+		buf: &[u8],
+	) -> Poll<std::io::Result<usize>> {
+		match self.get_mut() {
+			FtpStream::Plain(stream) => {
+				tokio::io::AsyncWrite::poll_write(Pin::new(stream), cx, buf)
+			}
+			FtpStream::Tls(stream) => tokio::io::AsyncWrite::poll_write(Pin::new(stream), cx, buf),
+		}
+	}
+
+	fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+		match self.get_mut() {
+			FtpStream::Plain(stream) => tokio::io::AsyncWrite::poll_flush(Pin::new(stream), cx),
+			FtpStream::Tls(stream) => tokio::io::AsyncWrite::poll_flush(Pin::new(stream), cx),
+		}
+	}
+
+	fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+		match self.get_mut() {
+			FtpStream::Plain(stream) => tokio::io::AsyncWrite::poll_shutdown(Pin::new(stream), cx),
+			FtpStream::Tls(stream) => tokio::io::AsyncWrite::poll_shutdown(Pin::new(stream), cx),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{parse_epsv, parse_mlsd, parse_pasv, parse_time};
+	use crate::scheme::NodeKind;
+	use crate::{FtpScheme, SchemeError};
+	use std::time::{Duration, SystemTime};
+	use url::Url;
+
+	#[test]
+	fn replies() {
+		assert_eq!(
+			parse_epsv("Entering Extended Passive Mode (|||6446|)"),
+			Some(6446)
+		);
+		assert_eq!(
+			parse_pasv("Entering Passive Mode (127,0,0,1,25,46)."),
+			Some(25 * 256 + 46)
+		);
+		assert_eq!(parse_pasv("Entering Passive Mode (127,0,0,1,25)"), None);
+		assert_eq!(
+			parse_time("20240101120000.123"),
+			Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1_704_110_400))
+		);
+		assert_eq!(parse_time("2024"), None);
+
+		let entry = parse_mlsd("type=file;Size=12;modify=19700101000001; a node").unwrap();
+		assert_eq!(entry.name, "a node");
+		let metadata = entry.metadata.unwrap();
+		assert_eq!(metadata.len, Some((12, Some(12))));
+		assert_eq!(
+			metadata.modified,
+			Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1))
+		);
+		let entry = parse_mlsd("type=dir;perm=el; dir").unwrap();
+		assert_eq!(entry.metadata.unwrap().kind, Some(NodeKind::Directory));
+		assert!(parse_mlsd("type=cdir; .").is_none());
+	}
+
+	#[test]
+	fn remote_paths() {
+		let scheme = FtpScheme::builder("localhost")
+			.root_path("/pub/")
+			.build()
+			.unwrap();
+		let path = |uri: &str| {
+			scheme
+				.remote_path_from_url(&Url::parse(uri).unwrap())
+				.map_err(SchemeError::into_owned)
+		};
+		assert_eq!(scheme.address(), "localhost:21");
+		assert_eq!(scheme.root_path(), "/pub");
+		assert_eq!(path("remote:/").unwrap(), "/pub");
+		assert_eq!(path("remote:/dir/a%20node").unwrap(), "/pub/dir/a node");
+		assert!(matches!(
+			path("remote:/node%0D%0ADELE%20x"),
+			Err(SchemeError::UrlAccessError(_))
+		));
+		assert!(matches!(
+			path("remote:/dir%2Fescape"),
+			Err(SchemeError::UrlAccessError(_))
+		));
+		assert_eq!(
+			FtpScheme::builder("[::1]:2121").build().unwrap().address(),
+			"[::1]:2121"
+		);
+		assert_eq!(FtpScheme::builder("").build().unwrap().root_path(), "/");
+	}
+
+	#[test]
+	fn tls_host_names() {
+		assert!(FtpScheme::builder("ftp.example.com:990")
+			.tls()
+			.build()
+			.unwrap()
+			.is_tls());
+		assert!(matches!(
+			FtpScheme::builder("not a host:990").tls().build(),
+			Err(SchemeError::GenericError(..))
+		));
+	}
+}
+
+#[cfg(test)]
+mod async_tokio_tests {
+	use crate::scheme::NodeGetOptions;
+	use crate::{FtpScheme, SchemeError, Vfs, VfsError};
+	use futures_lite::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt, StreamExt};
+	use std::collections::{BTreeMap, BTreeSet};
+	use std::io::SeekFrom;
+	use std::sync::{Arc, Mutex};
+	use tokio::io::{AsyncBufReadExt, AsyncReadExt as _, AsyncWriteExt as _, BufReader};
+	use tokio::net::{TcpListener, TcpStream};
+
+	#[derive(Default)]
+	struct Files {
+		files: BTreeMap<String, Vec<u8>>,
+		dirs: BTreeSet<String>,
+		/// Closes the control connection that gets the next command instead of replying.
+		hang_up: bool,
+	}
+
+	/// Serves a plain FTP server holding `/file`, with just enough of the protocol for the scheme.
+	/// Returns its address and what it serves.
+	async fn serve() -> (String, Arc<Mutex<Files>>) {
+		let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+		let address = listener.local_addr().unwrap().to_string();
+		let files = Arc::new(Mutex::new(Files::default()));
+		{
+			let mut files = files.lock().unwrap();
+			files.files.insert("/file".to_owned(), b"content".to_vec());
+			files.dirs.insert("/".to_owned());
+		}
+		let served = files.clone();
+		tokio::spawn(async move {
+			while let Ok((stream, _address)) = listener.accept().await {
+				tokio::spawn(control(stream, files.clone()));
+			}
+		});
+		(address, served)
+	}
+
+	async fn control(stream: TcpStream, files: Arc<Mutex<Files>>) {
+		let (reader, mut writer) = stream.into_split();
+		let mut lines = BufReader::new(reader).lines();
+		let mut passive: Option<TcpListener> = None;
+		writer
+			.write_all(b"220-Welcome\r\n220 Ready\r\n")
+			.await
+			.unwrap();
+		while let Ok(Some(line)) = lines.next_line().await {
+			if std::mem::take(&mut files.lock().unwrap().hang_up) {
+				return;
+			}
+			let (command, path) = line.split_once(' ').unwrap_or((&line, ""));
+			let path = path.to_owned();
+			let reply = match command {
+				"USER" => "331 Password".to_owned(),
+				"PASS" => "230 Logged in".to_owned(),
+				"TYPE" => "200 Ok".to_owned(),
+				"EPSV" => {
+					let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+					let port = listener.local_addr().unwrap().port();
+					passive = Some(listener);
+					format!("229 Entering Extended Passive Mode (|||{}|)", port)
+				}
+				"SIZE" => match files.lock().unwrap().files.get(&path) {
+					Some(content) => format!("213 {}", content.len()),
+					None => "550 No file".to_owned(),
+				},
+				"CWD" if files.lock().unwrap().dirs.contains(&path) => "250 Ok".to_owned(),
+				"MKD" => {
+					files.lock().unwrap().dirs.insert(path);
+					"257 Created".to_owned()
+				}
+				"DELE" if files.lock().unwrap().files.remove(&path).is_some() => {
+					"250 Ok".to_owned()
+				}
+				"RMD" if files.lock().unwrap().dirs.remove(&path) => "250 Ok".to_owned(),
+				"RETR" | "STOR" | "MLSD" => {
+					let content = match command {
+						"RETR" => files.lock().unwrap().files.get(&path).cloned(),
+						"MLSD" => {
+							let files = files.lock().unwrap();
+							let prefix = format!("{}/", path.trim_end_matches('/'));
+							let child = |name: &String| {
+								name.strip_prefix(&prefix)
+									.filter(|name| !name.is_empty() && !name.contains('/'))
+									.map(str::to_owned)
+							};
+							let mut listing = "type=cdir; .\r\n".to_owned();
+							for (name, content) in &files.files {
+								if let Some(name) = child(name) {
+									listing +=
+										&format!("type=file;size={}; {}\r\n", content.len(), name);
+								}
+							}
+							for name in files.dirs.iter().filter_map(child) {
+								listing += &format!("type=dir; {}\r\n", name);
+							}
+							Some(listing.into_bytes())
+						}
+						_ => Some(Vec::new()),
+					};
+					match (content, passive.take()) {
+						(Some(content), Some(listener)) => {
+							writer.write_all(b"150 Opening\r\n").await.unwrap();
+							let (mut data, _address) = listener.accept().await.unwrap();
+							if command == "STOR" {
+								let mut content = Vec::new();
+								data.read_to_end(&mut content).await.unwrap();
+								files.lock().unwrap().files.insert(path, content);
+							} else {
+								data.write_all(&content).await.unwrap();
+							}
+							drop(data);
+							"226 Done".to_owned()
+						}
+						_ => "550 No file".to_owned(),
+					}
+				}
+				"CWD" | "DELE" | "RMD" => "550 No such file".to_owned(),
+				_ => "502 Not implemented".to_owned(),
+			};
+			writer
+				.write_all(format!("{}\r\n", reply).as_bytes())
+				.await
+				.unwrap();
+		}
+	}
+
+	#[tokio::test]
+	async fn ftp_server() {
+		let (address, _files) = serve().await;
+		let vfs = Vfs::empty();
+		vfs.add_scheme("remote", FtpScheme::builder(&address).build().unwrap())
+			.unwrap();
+
+		assert_eq!(
+			vfs.read_to_vec_at("remote:/file").await.unwrap(),
+			b"content"
+		);
+		let metadata = vfs.metadata_at("remote:/file").await.unwrap();
+		assert_eq!(metadata.len, Some((7, Some(7))));
+		assert!(!vfs.metadata_at("remote:/").await.unwrap().is_node);
+		assert!(matches!(
+			vfs.metadata_at("remote:/none").await,
+			Err(VfsError::SchemeError(SchemeError::NodeDoesNotExist(_)))
+		));
+
+		let mut node = vfs
+			.get_node_at(
+				"remote:/dir/node",
+				&NodeGetOptions::new()
+					.write(true)
+					.create_new(true)
+					.create_parents(true),
+			)
+			.await
+			.unwrap();
+		node.write_all(b"new node").await.unwrap();
+		node.seek(SeekFrom::Start(4)).await.unwrap();
+		node.write_all(b"file").await.unwrap();
+		node.close().await.unwrap();
+		assert_eq!(
+			vfs.read_to_vec_at("remote:/dir/node").await.unwrap(),
+			b"new file"
+		);
+		assert!(matches!(
+			vfs.get_node_at("remote:/dir/node", &NodeGetOptions::new().create_new(true))
+				.await,
+			Err(VfsError::SchemeError(SchemeError::NodeAlreadyExists(_)))
+		));
+
+		let mut node = vfs
+			.get_node_at(
+				"remote:/file",
+				&NodeGetOptions::new().read(true).append(true).write(true),
+			)
+			.await
+			.unwrap();
+		node.write_all(b" appended").await.unwrap();
+		node.seek(SeekFrom::Start(0)).await.unwrap();
+		let mut content = String::new();
+		node.read_to_string(&mut content).await.unwrap();
+		assert_eq!(content, "content appended");
+		node.close().await.unwrap();
+
+		let mut listed: Vec<_> = vfs
+			.read_dir_at("remote:/")
+			.await
+			.unwrap()
+			.map(|entry| (entry.url.to_string(), entry.metadata.map(|m| m.is_node)))
+			.collect()
+			.await;
+		listed.sort();
+		assert_eq!(
+			listed,
+			[
+				("remote:/dir".to_owned(), Some(false)),
+				("remote:/file".to_owned(), Some(true))
+			]
+		);
+
+		vfs.remove_node_at("remote:/dir", true).await.unwrap();
+		assert!(matches!(
+			vfs.metadata_at("remote:/dir").await,
+			Err(VfsError::SchemeError(SchemeError::NodeDoesNotExist(_)))
+		));
+		let ftp = vfs.get_scheme_as::<FtpScheme>("remote").unwrap();
+		assert_eq!(ftp.idle_connections(), 1, "the connection is reused");
+	}
+
+	#[tokio::test]
+	async fn reconnect_closed_idle_connection() {
+		let (address, files) = serve().await;
+		let vfs = Vfs::empty();
+		vfs.add_scheme("remote", FtpScheme::builder(&address).build().unwrap())
+			.unwrap();
+		assert_eq!(
+			vfs.read_to_vec_at("remote:/file").await.unwrap(),
+			b"content"
+		);
+
+		files.lock().unwrap().hang_up = true;
+		assert_eq!(
+			vfs.read_to_vec_at("remote:/file").await.unwrap(),
+			b"content"
+		);
+		let ftp = vfs.get_scheme_as::<FtpScheme>("remote").unwrap();
+		assert_eq!(
+			ftp.idle_connections(),
+			1,
+			"the closed connection is replaced"
+		);
+
+		let mut node = vfs
+			.get_node_at(
+				"remote:/file",
+				&NodeGetOptions::new().write(true).truncate(true),
+			)
+			.await
+			.unwrap();
+		node.write_all(b"stored").await.unwrap();
+		files.lock().unwrap().hang_up = true;
+		node.close().await.unwrap();
+		assert_eq!(vfs.read_to_vec_at("remote:/file").await.unwrap(), b"stored");
+	}
+}
@@ -0,0 +1,404 @@
+#![allow(clippy::try_err)]
+
+use crate::node::poll_io_err;
+use crate::scheme::{BorrowedReadDirStream, NodeEntry, NodeGetOptions, NodeMetadata};
+use crate::{Node, PinnedNode, Scheme, SchemeError, Vfs};
+use futures_lite::{AsyncRead, AsyncSeek, AsyncWrite};
+use git2::{ObjectType, Repository};
+use std::borrow::Cow;
+use std::io::SeekFrom;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::task::{Context, Poll};
+use url::Url;
+
+/// Read-only access to the blobs and trees of a git repository's object database, the working tree
+/// is never touched.  Urls are of the form `git:/<rev>/path/to/blob` where `<rev>` is anything
+/// `git rev-parse` accepts that leads to a tree, such as a branch, tag, or commit SHA.  As `<rev>`
+/// is a single path segment any `/` in it, like in `feature/thing`, must be percent-encoded.
+///
+/// libgit2 is synchronous, so each operation blocks while reading from the object database.
+pub struct GitScheme {
+	repo: Mutex<Repository>,
+}
+
+enum GitObject {
+	Blob(Vec<u8>),
+	Tree(Vec<String>),
+}
+
+fn git_error(error: git2::Error) -> SchemeError<'static> {
+	if error.code() == git2::ErrorCode::NotFound {
+		SchemeError::NodeDoesNotExist(Cow::Owned(error.message().to_owned()))
+	} else {
+		("git error", Box::new(error) as Box<_>).into()
+	}
+}
+
+impl GitScheme {
+	/// Opens the repository at, or the bare repository that is, `path`.
+	pub fn open(path: impl AsRef<Path>) -> Result<Self, SchemeError<'static>> {
+		Ok(Self::from_repository(
+			Repository::open(path).map_err(git_error)?,
+		))
+	}
+
+	pub fn from_repository(repo: Repository) -> Self {
+		Self {
+			repo: Mutex::new(repo),
+		}
+	}
+
+	/// Splits a url path into its revision and the path within that revision's tree.
+	fn split_url(url: &Url) -> Result<(String, String), SchemeError<'_>> {
+		let path = url
+			.path()
+			.strip_prefix('/')
+			.ok_or(SchemeError::UrlAccessError(Cow::Borrowed(url)))?;
+		let (rev, path) = path.split_once('/').unwrap_or((path, ""));
+		if rev.is_empty() {
+			return Err(SchemeError::UrlAccessError(Cow::Borrowed(url)));
+		}
+		let decode = |s: &str| {
+			percent_encoding::percent_decode_str(s)
+				.decode_utf8()
+				.map(Cow::into_owned)
+				.map_err(|_| SchemeError::UrlAccessError(Cow::Borrowed(url)))
+		};
+		Ok((decode(rev)?, decode(path.trim_end_matches('/'))?))
+	}
+
+	/// Resolves the url to its object, only copying out the blob data if `read_blob` is set.
+	fn resolve<'a>(
+		&self,
+		url: &'a Url,
+		read_blob: bool,
+	) -> Result<(GitObject, usize), SchemeError<'a>> {
+		let (rev, path) = Self::split_url(url)?;
+		let repo = self.repo.lock().expect("poisoned lock");
+		let tree = repo
+			.revparse_single(&rev)
+			.and_then(|object| object.peel_to_tree())
+			.map_err(git_error)?;
+		let object = if path.is_empty() {
+			tree.into_object()
+		} else {
+			tree.get_path(Path::new(&path))
+				.and_then(|entry| entry.to_object(&repo))
+				.map_err(git_error)?
+		};
+		match object.kind() {
+			Some(ObjectType::Blob) => {
+				let blob = object.peel_to_blob().map_err(git_error)?;
+				let data = if read_blob {
+					blob.content().to_vec()
+				} else {
+					Vec::new()
+				};
+				Ok((GitObject::Blob(data), blob.size()))
+			}
+			Some(ObjectType::Tree) => {
+				let tree = object.peel_to_tree().map_err(git_error)?;
+				let names: Vec<String> = tree
+					.iter()
+					.filter_map(|entry| entry.name().map(str::to_owned))
+					.collect();
+				let len = names.len();
+				Ok((GitObject::Tree(names), len))
+			}
+			_ => Err(SchemeError::NodeDoesNotExist(Cow::Borrowed(url.path()))),
+		}
+	}
+}
+
+#[async_trait::async_trait]
+impl Scheme for GitScheme {
+	async fn get_node<'a>(
+		&self,
+		_vfs: &Vfs,
+		url: &'a Url,
+		options: &NodeGetOptions,
+	) -> Result<PinnedNode, SchemeError<'a>> {
+		if !options.get_read() || options.get_write() {
+			return Err(SchemeError::UrlAccessError(Cow::Borrowed(url)));
+		}
+		match self.resolve(url, true)? {
+			(GitObject::Blob(data), _len) => Ok(Box::pin(GitNode { data, cursor: 0 })),
+			(GitObject::Tree(_), _len) => Err(SchemeError::IsADirectory(Cow::Borrowed(url.path()))),
+		}
+	}
+
+	async fn remove_node<'a>(
+		&self,
+		_vfs: &Vfs,
+		url: &'a Url,
+		_force: bool,
+	) -> Result<(), SchemeError<'a>> {
+		Err(SchemeError::UrlAccessError(Cow::Borrowed(url)))
+	}
+
+	async fn metadata<'a>(
+		&self,
+		_vfs: &Vfs,
+		url: &'a Url,
+	) -> Result<NodeMetadata, SchemeError<'a>> {
+		match self.resolve(url, false)? {
+			(GitObject::Blob(_), len) => Ok(NodeMetadata {
+				is_node: true,
+				len: Some((len, Some(len))),
+				modified: None,
+				..Default::default()
+			}),
+			(GitObject::Tree(_), _len) => Ok(NodeMetadata {
+				is_node: false,
+				len: None,
+				modified: None,
+				..Default::default()
+			}),
+		}
+	}
+
+	async fn read_dir<'s, 'a>(
+		&'s self,
+		_vfs: &'s Vfs,
+		url: &'a Url,
+	) -> Result<BorrowedReadDirStream<'s>, SchemeError<'a>> {
+		let names = match self.resolve(url, false)? {
+			(GitObject::Tree(names), _len) => names,
+			(GitObject::Blob(_), _len) => {
+				return Err(SchemeError::NotADirectory(Cow::Borrowed(url.path())))
+			}
+		};
+		let base_path = url.path().trim_end_matches('/');
+		let entries: Vec<NodeEntry> = names
+			.into_iter()
+			.map(|name| {
+				let mut url = url.clone();
+				url.set_path(&format!("{}/{}", base_path, name));
+				NodeEntry::new(url)
+			})
+			.collect();
+		Ok(Box::pin(futures_lite::stream::iter(entries)))
+	}
+}
+
+pub struct GitNode {
+	data: Vec<u8>,
+	cursor: usize,
+}
+
+#[async_trait::async_trait]
+impl Node for GitNode {
+	fn is_reader(&self) -> bool {
+		true
+	}
+
+	fn is_writer(&self) -> bool {
+		false
+	}
+
+	fn is_seeker(&self) -> bool {
+		true
+	}
+
+	fn is_at_end(&self) -> Option<bool> {
+		Some(self.cursor >= self.data.len())
+	}
+}
+
+impl AsyncRead for GitNode {
+	fn poll_read(
+		mut self: Pin<&mut Self>,
+		_cx: &mut Context<'_>,
+		buf: &mut [u8],
+	) -> Poll<std::io::Result<usize>> {
+		if self.cursor >= self.data.len() {
+			return Poll::Ready(Ok(0));
+		}
+
+		let amt = std::cmp::min(self.data.len() - self.cursor, buf.len());
+		buf[..amt].copy_from_slice(&self.data[self.cursor..(self.cursor + amt)]);
+		self.cursor += amt;
+
+		Poll::Ready(Ok(amt))
+	}
+}
+
+impl AsyncWrite for GitNode {
+	fn poll_write(
+		self: Pin<&mut Self>,
+		_cx: &mut Context<'_>,
+		_buf: &[u8],
+	) -> Poll<std::io::Result<usize>> {
+		poll_io_err()
+	}
+
+	fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+		poll_io_err()
+	}
+
+	fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+		poll_io_err()
+	}
+}
+
+impl AsyncSeek for GitNode {
+	fn poll_seek(
+		mut self: Pin<&mut Self>,
+		_cx: &mut Context<'_>,
+		pos: SeekFrom,
+	) -> Poll<std::io::Result<u64>> {
+		match pos {
+			SeekFrom::Start(pos) => {
+				if pos > self.data.len() as u64 {
+					self.cursor = self.data.len();
+				} else {
+					self.cursor = pos as usize;
+				}
+			}
+			SeekFrom::End(end_pos) => {
+				if end_pos > 0 {
+					self.cursor = self.data.len();
+				} else if (-end_pos) as usize > self.data.len() {
+					self.cursor = 0;
+				} else {
+					self.cursor = self.data.len() - ((-end_pos) as usize);
+				}
+			}
+			SeekFrom::Current(offset) => {
+				let new_cur = self.cursor as i64 + offset;
+				if new_cur < 0 {
+					self.cursor = 0;
+				} else if new_cur as usize > self.data.len() {
+					self.cursor = self.data.len();
+				} else {
+					self.cursor = new_cur as usize;
+				}
+			}
+		};
+		Poll::Ready(Ok(self.cursor as u64))
+	}
+}
+
+#[cfg(test)]
+#[cfg(feature = "backend_tokio")]
+mod async_tokio_tests {
+	use crate::scheme::NodeGetOptions;
+	use crate::{GitScheme, SchemeError, Vfs, VfsError};
+	use futures_lite::{AsyncReadExt, StreamExt};
+	use git2::{Repository, Signature};
+	use std::path::Path;
+
+	/// Creates a repository with one commit on `main` and a `v1` tag, the working tree is left
+	/// empty so anything read must come from the object database.
+	fn fixture_repo(name: &str) -> Repository {
+		let path = std::env::current_dir().unwrap().join("target").join(name);
+		let _ = std::fs::remove_dir_all(&path);
+		let repo = Repository::init(&path).unwrap();
+		{
+			let readme = repo.blob(b"fixture readme").unwrap();
+			let lib = repo.blob(b"fn main() {}").unwrap();
+			let mut src = repo.treebuilder(None).unwrap();
+			src.insert("lib.rs", lib, 0o100644).unwrap();
+			let src = src.write().unwrap();
+			let mut root = repo.treebuilder(None).unwrap();
+			root.insert("README.md", readme, 0o100644).unwrap();
+			root.insert("src", src, 0o040000).unwrap();
+			let tree = repo.find_tree(root.write().unwrap()).unwrap();
+			let signature = Signature::now("Fixture", "fixture@example.com").unwrap();
+			let commit = repo
+				.commit(
+					Some("refs/heads/main"),
+					&signature,
+					&signature,
+					"fixture",
+					&tree,
+					&[],
+				)
+				.unwrap();
+			repo.set_head("refs/heads/main").unwrap();
+			let commit = repo.find_object(commit, None).unwrap();
+			repo.tag_lightweight("v1", &commit, false).unwrap();
+		}
+		assert!(!Path::new(&path).join("README.md").exists());
+		repo
+	}
+
+	async fn read(vfs: &Vfs, uri: &str) -> String {
+		let mut buffer = String::new();
+		vfs.get_node_at(uri, &NodeGetOptions::new().read(true))
+			.await
+			.unwrap()
+			.read_to_string(&mut buffer)
+			.await
+			.unwrap();
+		buffer
+	}
+
+	#[tokio::test]
+	async fn git_read() {
+		let repo = fixture_repo("test_git_read");
+		let head = repo.head().unwrap().target().unwrap().to_string();
+		let vfs = Vfs::empty();
+		vfs.add_scheme("git", GitScheme::from_repository(repo))
+			.unwrap();
+		assert_eq!(read(&vfs, "git:/HEAD/README.md").await, "fixture readme");
+		assert_eq!(read(&vfs, "git:/main/src/lib.rs").await, "fn main() {}");
+		assert_eq!(read(&vfs, "git:/v1/src/lib.rs").await, "fn main() {}");
+		assert_eq!(
+			read(&vfs, &format!("git:/{}/README.md", head)).await,
+			"fixture readme"
+		);
+		let metadata = vfs.metadata_at("git:/HEAD/README.md").await.unwrap();
+		assert!(metadata.is_node);
+		assert_eq!(metadata.len, Some((14, Some(14))));
+		assert!(!vfs.metadata_at("git:/HEAD/src").await.unwrap().is_node);
+		assert!(matches!(
+			vfs.get_node_at("git:/HEAD/src", &NodeGetOptions::new().read(true))
+				.await,
+			Err(VfsError::SchemeError(SchemeError::IsADirectory(_)))
+		));
+		assert!(matches!(
+			vfs.get_node_at("git:/HEAD/missing", &NodeGetOptions::new().read(true))
+				.await,
+			Err(VfsError::SchemeError(SchemeError::NodeDoesNotExist(_)))
+		));
+		assert!(vfs
+			.get_node_at("git:/nobranch/README.md", &NodeGetOptions::new().read(true))
+			.await
+			.is_err());
+		assert!(vfs
+			.get_node_at("git:/HEAD/README.md", &NodeGetOptions::new().write(true))
+			.await
+			.is_err());
+	}
+
+	#[tokio::test]
+	async fn git_read_dir() {
+		let vfs = Vfs::empty();
+		vfs.add_scheme(
+			"git",
+			GitScheme::from_repository(fixture_repo("test_git_read_dir")),
+		)
+		.unwrap();
+		let mut root: Vec<_> = vfs
+			.read_dir_at("git:/HEAD/")
+			.await
+			.unwrap()
+			.map(|e| e.url.path().to_owned())
+			.collect()
+			.await;
+		root.sort();
+		assert_eq!(root, ["/HEAD/README.md", "/HEAD/src"]);
+		let src: Vec<_> = vfs
+			.read_dir_at("git:/HEAD/src")
+			.await
+			.unwrap()
+			.map(|e| e.url.path().to_owned())
+			.collect()
+			.await;
+		assert_eq!(src, ["/HEAD/src/lib.rs"]);
+		assert!(vfs.read_dir_at("git:/HEAD/README.md").await.is_err());
+	}
+}
@@ -0,0 +1,392 @@
+use crate::node::{poll_io_err, seek_position};
+use crate::scheme::{BorrowedReadDirStream, NodeGetOptions, NodeMetadata};
+use crate::{Node, PinnedNode, Scheme, SchemeError, Vfs};
+use bytes::Bytes;
+use futures_lite::{ready, AsyncRead, AsyncSeek, AsyncWrite, Future, Stream};
+use reqwest::header::{ACCEPT_RANGES, CONTENT_LENGTH, CONTENT_RANGE, RANGE};
+use reqwest::{RequestBuilder, Response, StatusCode};
+use std::borrow::Cow;
+use std::io::SeekFrom;
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::task::{Context, Poll};
+use url::Url;
+
+/// Serves `http:` and `https:` urls read-only by GET requests, register it under both names to
+/// use both.  `metadata` is a HEAD request that only knows the length the server reports, and
+/// nodes can be seeked when the server accepts range requests, each seek starting a new request
+/// from the new position.  Listing directories is not supported, and opening for writing or
+/// removing fail with `UrlAccessError`.  The requests need a tokio runtime.
+#[derive(Clone, Default)]
+pub struct HttpScheme {
+	client: reqwest::Client,
+}
+
+impl HttpScheme {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Sends every request through `client`, such as to set a user agent, timeouts, or proxies.
+	pub fn with_client(client: reqwest::Client) -> Self {
+		Self { client }
+	}
+
+	pub fn client(&self) -> &reqwest::Client {
+		&self.client
+	}
+
+	async fn send<'a>(
+		&self,
+		request: RequestBuilder,
+		url: &'a Url,
+	) -> Result<Response, SchemeError<'a>> {
+		let response = request.send().await.map_err(request_failed)?;
+		match response.status() {
+			StatusCode::NOT_FOUND | StatusCode::GONE => {
+				Err(SchemeError::NodeDoesNotExist(Cow::Borrowed(url.path())))
+			}
+			status if status.is_success() => Ok(response),
+			_ => Err(response
+				.error_for_status()
+				.err()
+				.map_or_else(|| "unexpected HTTP status".into(), request_failed)),
+		}
+	}
+}
+
+fn request_failed(error: reqwest::Error) -> SchemeError<'static> {
+	SchemeError::GenericError(Some("HTTP request failed"), Some(Box::new(error)))
+}
+
+fn io_error(error: reqwest::Error) -> std::io::Error {
+	std::io::Error::other(error)
+}
+
+/// The length the server reports, read from the header as the body of a HEAD response is empty.
+fn header_len(response: &Response) -> Option<u64> {
+	response
+		.headers()
+		.get(CONTENT_LENGTH)?
+		.to_str()
+		.ok()?
+		.parse()
+		.ok()
+}
+
+/// The start and, if given, the total length of a `Content-Range: bytes <start>-<end>/<total>`.
+fn content_range(response: &Response) -> Option<(u64, Option<u64>)> {
+	let value = response.headers().get(CONTENT_RANGE)?.to_str().ok()?;
+	let (range, total) = value.strip_prefix("bytes ")?.split_once('/')?;
+	let start = range.split_once('-')?.0.parse().ok()?;
+	Some((start, total.parse().ok()))
+}
+
+#[async_trait::async_trait]
+impl Scheme for HttpScheme {
+	async fn get_node<'a>(
+		&self,
+		_vfs: &Vfs,
+		url: &'a Url,
+		options: &NodeGetOptions,
+	) -> Result<PinnedNode, SchemeError<'a>> {
+		if options.get_write() {
+			return Err(SchemeError::UrlAccessError(Cow::Borrowed(url)));
+		}
+		let mut request = self.client.get(url.clone());
+		match options.get_range() {
+			Some((start, None)) => request = request.header(RANGE, format!("bytes={}-", start)),
+			Some((start, Some(end))) if end > start => {
+				request = request.header(RANGE, format!("bytes={}-{}", start, end - 1))
+			}
+			_ => (),
+		}
+		let response = self.send(request, url).await?;
+		let (position, len) = if response.status() == StatusCode::PARTIAL_CONTENT {
+			content_range(&response).ok_or("invalid Content-Range of an HTTP response")?
+		} else {
+			(0, header_len(&response))
+		};
+		let seekable = response.status() == StatusCode::PARTIAL_CONTENT
+			|| response
+				.headers()
+				.get(ACCEPT_RANGES)
+				.is_some_and(|accepted| accepted == "bytes");
+		Ok(Box::pin(HttpNode {
+			client: self.client.clone(),
+			url: url.clone(),
+			body: Mutex::new(Body::Streaming(Box::pin(response.bytes_stream()))),
+			chunk: Bytes::new(),
+			position,
+			len,
+			seekable,
+		}))
+	}
+
+	async fn remove_node<'a>(
+		&self,
+		_vfs: &Vfs,
+		url: &'a Url,
+		_force: bool,
+	) -> Result<(), SchemeError<'a>> {
+		Err(SchemeError::UrlAccessError(Cow::Borrowed(url)))
+	}
+
+	async fn metadata<'a>(
+		&self,
+		_vfs: &Vfs,
+		url: &'a Url,
+	) -> Result<NodeMetadata, SchemeError<'a>> {
+		let response = self.send(self.client.head(url.clone()), url).await?;
+		let len = header_len(&response).map(|len| len as usize);
+		Ok(NodeMetadata {
+			is_node: true,
+			len: len.map(|len| (len, Some(len))),
+			modified: None,
+			..Default::default()
+		})
+	}
+
+	async fn read_dir<'s, 'a>(
+		&'s self,
+		_vfs: &'s Vfs,
+		_url: &'a Url,
+	) -> Result<BorrowedReadDirStream<'s>, SchemeError<'a>> {
+		Err(SchemeError::Unsupported("read_dir"))
+	}
+}
+
+type PendingResponse = Pin<Box<dyn Future<Output = reqwest::Result<Response>> + Send>>;
+type BodyStream = Pin<Box<dyn Stream<Item = reqwest::Result<Bytes>> + Send>>;
+
+enum Body {
+	/// A request from a seeked to position that has not been answered yet.
+	Requesting(PendingResponse),
+	Streaming(BodyStream),
+}
+
+pub struct HttpNode {
+	client: reqwest::Client,
+	url: Url,
+	/// Only ever used through `Mutex::get_mut`, it is only there as the body is not `Sync`.
+	body: Mutex<Body>,
+	/// What is left of the last chunk of the body that was received.
+	chunk: Bytes,
+	position: u64,
+	len: Option<u64>,
+	seekable: bool,
+}
+
+#[async_trait::async_trait]
+impl Node for HttpNode {
+	fn is_reader(&self) -> bool {
+		true
+	}
+
+	fn is_writer(&self) -> bool {
+		false
+	}
+
+	fn is_seeker(&self) -> bool {
+		self.seekable
+	}
+
+	fn known_len(&self) -> Option<u64> {
+		self.len
+	}
+
+	fn is_at_end(&self) -> Option<bool> {
+		self.len.map(|len| self.position >= len)
+	}
+}
+
+impl AsyncRead for HttpNode {
+	fn poll_read(
+		self: Pin<&mut Self>,
+		cx: &mut Context<'_>,
+		buf: &mut [u8],
+	) -> Poll<std::io::Result<usize>> {
+		let this = self.get_mut();
+		loop {
+			if !this.chunk.is_empty() || buf.is_empty() {
+				let amt = this.chunk.len().min(buf.len());
+				buf[..amt].copy_from_slice(&this.chunk.split_to(amt));
+				this.position += amt as u64;
+				return Poll::Ready(Ok(amt));
+			}
+			let body = this.body.get_mut().expect("poisoned lock");
+			match body {
+				Body::Requesting(pending) => {
+					let response = ready!(pending.as_mut().poll(cx)).map_err(io_error)?;
+					if response.status() != StatusCode::PARTIAL_CONTENT {
+						return Poll::Ready(Err(std::io::Error::other(
+							"HTTP server did not answer the range request of a seek",
+						)));
+					}
+					*body = Body::Streaming(Box::pin(response.bytes_stream()));
+				}
+				Body::Streaming(stream) => match ready!(stream.as_mut().poll_next(cx)) {
+					Some(chunk) => this.chunk = chunk.map_err(io_error)?,
+					None => return Poll::Ready(Ok(0)),
+				},
+			}
+		}
+	}
+}
+
+impl AsyncWrite for HttpNode {
+	fn poll_write(
+		self: Pin<&mut Self>,
+		_cx: &mut Context<'_>,
+		_buf: &[u8],
+	) -> Poll<std::io::Result<usize>> {
+		poll_io_err()
+	}
+
+	fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+		poll_io_err()
+	}
+
+	fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+		poll_io_err()
+	}
+}
+
+impl AsyncSeek for HttpNode {
+	fn poll_seek(
+		self: Pin<&mut Self>,
+		_cx: &mut Context<'_>,
+		pos: SeekFrom,
+	) -> Poll<std::io::Result<u64>> {
+		let this = self.get_mut();
+		let position = seek_position(pos, this.position, this.len)?;
+		if position == this.position {
+			return Poll::Ready(Ok(position));
+		}
+		if !this.seekable {
+			return poll_io_err();
+		}
+		// A range starting at the end is not satisfiable, so there is nothing to request
+		let body = if this.len.is_some_and(|len| position >= len) {
+			Body::Streaming(Box::pin(futures_lite::stream::empty()))
+		} else {
+			let request = this
+				.client
+				.get(this.url.clone())
+				.header(RANGE, format!("bytes={}-", position));
+			Body::Requesting(Box::pin(request.send()))
+		};
+		*this.body.get_mut().expect("poisoned lock") = body;
+		this.chunk = Bytes::new();
+		this.position = position;
+		Poll::Ready(Ok(position))
+	}
+}
+
+#[cfg(test)]
+#[cfg(feature = "backend_tokio")]
+mod async_tokio_tests {
+	use crate::scheme::NodeGetOptions;
+	use crate::{HttpScheme, SchemeError, Vfs, VfsError};
+	use futures_lite::{AsyncReadExt, AsyncSeekExt};
+	use std::io::SeekFrom;
+	use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _};
+	use tokio::net::TcpListener;
+
+	const CONTENT: &[u8] = b"hello remote world";
+
+	/// Serves `CONTENT` at `/file`, with range requests, and nothing else, one request per
+	/// connection.  Returns the url of the server.
+	async fn serve() -> String {
+		let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+		let address = listener.local_addr().unwrap();
+		tokio::spawn(async move {
+			while let Ok((mut stream, _address)) = listener.accept().await {
+				let mut request = Vec::new();
+				let mut buffer = [0; 1024];
+				while !request.ends_with(b"\r\n\r\n") {
+					match stream.read(&mut buffer).await {
+						Ok(0) | Err(_) => break,
+						Ok(amt) => request.extend_from_slice(&buffer[..amt]),
+					}
+				}
+				let request = String::from_utf8_lossy(&request).to_lowercase();
+				let start = request
+					.lines()
+					.find_map(|line| line.strip_prefix("range: bytes="))
+					.and_then(|range| range.trim_end_matches('-').parse::<usize>().ok());
+				let (status, headers, body) = if !request.contains(" /file ") {
+					("404 Not Found", String::new(), &[][..])
+				} else if let Some(start) = start {
+					let headers = format!(
+						"Content-Range: bytes {}-{}/{}\r\n",
+						start,
+						CONTENT.len() - 1,
+						CONTENT.len()
+					);
+					("206 Partial Content", headers, &CONTENT[start..])
+				} else {
+					("200 OK", "Accept-Ranges: bytes\r\n".to_owned(), CONTENT)
+				};
+				let head = format!(
+					"HTTP/1.1 {}\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n",
+					status,
+					headers,
+					body.len()
+				);
+				let _ = stream.write_all(head.as_bytes()).await;
+				if !request.starts_with("head ") {
+					let _ = stream.write_all(body).await;
+				}
+			}
+		});
+		format!("http://{}", address)
+	}
+
+	#[tokio::test]
+	async fn http_get() {
+		let server = serve().await;
+		let vfs = Vfs::empty();
+		vfs.add_scheme("http", HttpScheme::new()).unwrap();
+		let file = format!("{}/file", server);
+
+		assert_eq!(vfs.read_to_vec_at(&file).await.unwrap(), CONTENT);
+		assert_eq!(
+			vfs.metadata_at(&file).await.unwrap().len,
+			Some((CONTENT.len(), Some(CONTENT.len())))
+		);
+
+		let mut node = vfs
+			.get_node_at(&file, &NodeGetOptions::new().read(true))
+			.await
+			.unwrap();
+		assert!(node.is_seeker());
+		assert_eq!(node.known_len(), Some(CONTENT.len() as u64));
+		node.seek(SeekFrom::Start(6)).await.unwrap();
+		let mut rest = String::new();
+		node.read_to_string(&mut rest).await.unwrap();
+		assert_eq!(rest, "remote world");
+		assert_eq!(node.is_at_end(), Some(true));
+
+		let mut node = vfs
+			.get_node_at(
+				&file,
+				&NodeGetOptions::new().read(true).range(Some((13, None))),
+			)
+			.await
+			.unwrap();
+		let mut ranged = String::new();
+		node.read_to_string(&mut ranged).await.unwrap();
+		assert_eq!(ranged, "world");
+
+		assert!(matches!(
+			vfs.metadata_at(&format!("{}/missing", server)).await,
+			Err(VfsError::SchemeError(SchemeError::NodeDoesNotExist(_)))
+		));
+		assert!(matches!(
+			vfs.get_node_at(&file, &NodeGetOptions::new().write(true))
+				.await,
+			Err(VfsError::SchemeError(SchemeError::UrlAccessError(_)))
+		));
+	}
+}
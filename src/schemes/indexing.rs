@@ -0,0 +1,369 @@
+use crate::node::{poll_io_err, seek_position};
+use crate::scheme::{BorrowedReadDirStream, NodeGetOptions, NodeMetadata};
+use crate::{Node, PinnedNode, Scheme, SchemeError, Vfs};
+use futures_lite::{AsyncRead, AsyncSeek, AsyncWrite, StreamExt};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::io::SeekFrom;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::SystemTime;
+use url::Url;
+
+/// How an `IndexingScheme` renders the listing of a directory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IndexFormat {
+	/// An array of `{"name": .., "is_node": .., "len": ..}` objects, where `len` is `null` when
+	/// unknown.
+	Json,
+	/// A `<ul>` of links to the entries.
+	Html,
+}
+
+/// Wraps a scheme so that opening one of its directories for reading returns a read-only node with
+/// a rendered listing of it, sorted by name, instead of failing, such as to serve a browsable
+/// directory over HTTP.  Only the immediate children are listed, the names of directories end in
+/// `/`.  Everything else passes through to the wrapped scheme.
+pub struct IndexingScheme {
+	scheme: Box<dyn Scheme>,
+	format: IndexFormat,
+}
+
+struct IndexEntry {
+	is_node: bool,
+	len: Option<usize>,
+}
+
+impl IndexingScheme {
+	pub fn new(scheme: impl Scheme, format: IndexFormat) -> Self {
+		Self::new_boxed(Box::new(scheme), format)
+	}
+
+	pub fn new_boxed(scheme: Box<dyn Scheme>, format: IndexFormat) -> Self {
+		Self { scheme, format }
+	}
+
+	pub fn inner(&self) -> &dyn Scheme {
+		&*self.scheme
+	}
+
+	pub fn format(&self) -> IndexFormat {
+		self.format
+	}
+
+	async fn index<'a>(&self, vfs: &Vfs, url: &'a Url) -> Result<Vec<u8>, SchemeError<'a>> {
+		let mut dir = url.clone();
+		if !dir.path().ends_with('/') {
+			dir.set_path(&format!("{}/", url.path()));
+		}
+		let mut entries = BTreeMap::new();
+		let mut listing = self
+			.scheme
+			.read_dir(vfs, &dir)
+			.await
+			.map_err(SchemeError::into_owned)?;
+		while let Some(entry) = listing.next().await {
+			let name = match entry.url.path().strip_prefix(dir.path()) {
+				Some(name) if !name.is_empty() => name,
+				_ => continue,
+			};
+			// Listings may go deeper than the immediate children, those show as their directory
+			if let Some(pos) = name.find('/') {
+				let index_entry = IndexEntry {
+					is_node: false,
+					len: None,
+				};
+				entries.insert(name[..=pos].to_owned(), index_entry);
+			} else if !entries.contains_key(name) {
+				let metadata = self.scheme.metadata(vfs, &entry.url).await.ok();
+				let index_entry = IndexEntry {
+					is_node: metadata.as_ref().is_none_or(|metadata| metadata.is_node),
+					len: metadata.and_then(|metadata| metadata.len).map(|len| len.0),
+				};
+				let name = if index_entry.is_node {
+					name.to_owned()
+				} else {
+					format!("{}/", name)
+				};
+				entries.insert(name, index_entry);
+			}
+		}
+		Ok(match self.format {
+			IndexFormat::Json => render_json(&entries),
+			IndexFormat::Html => render_html(dir.path(), &entries),
+		}
+		.into_bytes())
+	}
+}
+
+fn render_json(entries: &BTreeMap<String, IndexEntry>) -> String {
+	let mut json = String::from("[");
+	for (i, (name, entry)) in entries.iter().enumerate() {
+		if i > 0 {
+			json.push(',');
+		}
+		json.push_str("{\"name\":\"");
+		for c in name.chars() {
+			match c {
+				'"' => json.push_str("\\\""),
+				'\\' => json.push_str("\\\\"),
+				c if c.is_control() => {
+					let _ = write!(json, "\\u{:04x}", c as u32);
+				}
+				c => json.push(c),
+			}
+		}
+		let _ = write!(json, "\",\"is_node\":{},\"len\":", entry.is_node);
+		match entry.len {
+			Some(len) => {
+				let _ = write!(json, "{}", len);
+			}
+			None => json.push_str("null"),
+		}
+		json.push('}');
+	}
+	json.push(']');
+	json
+}
+
+fn escape_html(text: &str) -> String {
+	let mut escaped = String::with_capacity(text.len());
+	for c in text.chars() {
+		match c {
+			'&' => escaped.push_str("&amp;"),
+			'<' => escaped.push_str("&lt;"),
+			'>' => escaped.push_str("&gt;"),
+			'"' => escaped.push_str("&quot;"),
+			'\'' => escaped.push_str("&#39;"),
+			c => escaped.push(c),
+		}
+	}
+	escaped
+}
+
+fn render_html(path: &str, entries: &BTreeMap<String, IndexEntry>) -> String {
+	let path = escape_html(path);
+	let mut html = format!(
+		"<!DOCTYPE html>\n<html><head><title>Index of {0}</title></head><body>\n<h1>Index of {0}</h1>\n<ul>\n",
+		path
+	);
+	for name in entries.keys() {
+		// Names are url path segments, so they are already percent-encoded for the link
+		let name = escape_html(name);
+		let _ = writeln!(html, "<li><a href=\"{0}\">{0}</a></li>", name);
+	}
+	html.push_str("</ul>\n</body></html>\n");
+	html
+}
+
+#[async_trait::async_trait]
+impl Scheme for IndexingScheme {
+	async fn get_node<'a>(
+		&self,
+		vfs: &Vfs,
+		url: &'a Url,
+		options: &NodeGetOptions,
+	) -> Result<PinnedNode, SchemeError<'a>> {
+		if options.get_read() && !options.get_write() {
+			if let Ok(metadata) = self.scheme.metadata(vfs, url).await {
+				if !metadata.is_node {
+					let data = self.index(vfs, url).await?;
+					return Ok(Box::pin(IndexNode { data, cursor: 0 }));
+				}
+			}
+		}
+		self.scheme.get_node(vfs, url, options).await
+	}
+
+	async fn remove_node<'a>(
+		&self,
+		vfs: &Vfs,
+		url: &'a Url,
+		force: bool,
+	) -> Result<(), SchemeError<'a>> {
+		self.scheme.remove_node(vfs, url, force).await
+	}
+
+	async fn metadata<'a>(&self, vfs: &Vfs, url: &'a Url) -> Result<NodeMetadata, SchemeError<'a>> {
+		self.scheme.metadata(vfs, url).await
+	}
+
+	async fn read_dir<'s, 'a>(
+		&'s self,
+		vfs: &'s Vfs,
+		url: &'a Url,
+	) -> Result<BorrowedReadDirStream<'s>, SchemeError<'a>> {
+		self.scheme.read_dir(vfs, url).await
+	}
+
+	async fn read_small_file<'a>(
+		&self,
+		vfs: &Vfs,
+		url: &'a Url,
+		max_len: usize,
+	) -> Result<Option<Vec<u8>>, SchemeError<'a>> {
+		match self.scheme.metadata(vfs, url).await {
+			// Let `Vfs::read_to_vec` open the index through `get_node`
+			Ok(metadata) if !metadata.is_node => Ok(None),
+			_ => self.scheme.read_small_file(vfs, url, max_len).await,
+		}
+	}
+
+	#[cfg(feature = "bytes")]
+	async fn read_bytes<'a>(
+		&self,
+		vfs: &Vfs,
+		url: &'a Url,
+	) -> Result<Option<bytes::Bytes>, SchemeError<'a>> {
+		match self.scheme.metadata(vfs, url).await {
+			Ok(metadata) if !metadata.is_node => Ok(None),
+			_ => self.scheme.read_bytes(vfs, url).await,
+		}
+	}
+
+	async fn canonicalize<'a>(&self, vfs: &Vfs, url: &'a Url) -> Result<Url, SchemeError<'a>> {
+		self.scheme.canonicalize(vfs, url).await
+	}
+
+	async fn set_modified<'a>(
+		&self,
+		vfs: &Vfs,
+		url: &'a Url,
+		modified: SystemTime,
+	) -> Result<(), SchemeError<'a>> {
+		self.scheme.set_modified(vfs, url, modified).await
+	}
+}
+
+pub struct IndexNode {
+	data: Vec<u8>,
+	cursor: usize,
+}
+
+#[async_trait::async_trait]
+impl Node for IndexNode {
+	fn is_reader(&self) -> bool {
+		true
+	}
+
+	fn is_writer(&self) -> bool {
+		false
+	}
+
+	fn is_seeker(&self) -> bool {
+		true
+	}
+
+	fn known_len(&self) -> Option<u64> {
+		Some(self.data.len() as u64)
+	}
+}
+
+impl AsyncRead for IndexNode {
+	fn poll_read(
+		mut self: Pin<&mut Self>,
+		_cx: &mut Context<'_>,
+		buf: &mut [u8],
+	) -> Poll<std::io::Result<usize>> {
+		let remaining = &self.data[self.cursor.min(self.data.len())..];
+		let amt = remaining.len().min(buf.len());
+		buf[..amt].copy_from_slice(&remaining[..amt]);
+		self.cursor += amt;
+		Poll::Ready(Ok(amt))
+	}
+}
+
+impl AsyncWrite for IndexNode {
+	fn poll_write(
+		self: Pin<&mut Self>,
+		_cx: &mut Context<'_>,
+		_buf: &[u8],
+	) -> Poll<std::io::Result<usize>> {
+		poll_io_err()
+	}
+
+	fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+		poll_io_err()
+	}
+
+	fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+		poll_io_err()
+	}
+}
+
+impl AsyncSeek for IndexNode {
+	fn poll_seek(
+		mut self: Pin<&mut Self>,
+		_cx: &mut Context<'_>,
+		pos: SeekFrom,
+	) -> Poll<std::io::Result<u64>> {
+		let position = seek_position(pos, self.cursor as u64, self.known_len())?;
+		self.cursor = position as usize;
+		Poll::Ready(Ok(position))
+	}
+}
+
+#[cfg(test)]
+#[cfg(all(feature = "backend_tokio", feature = "in_memory"))]
+mod async_tokio_tests {
+	use crate::scheme::NodeGetOptions;
+	use crate::{IndexFormat, IndexingScheme, MemoryScheme, Vfs};
+	use futures_lite::AsyncWriteExt;
+
+	async fn indexed_vfs(format: IndexFormat) -> Vfs {
+		let vfs = Vfs::empty();
+		vfs.add_scheme("mem", IndexingScheme::new(MemoryScheme::default(), format))
+			.unwrap();
+		for (path, content) in [
+			("mem:/site/index.txt", "hello"),
+			("mem:/site/a\"b.txt", ""),
+			("mem:/site/images/logo.png", "png"),
+			("mem:/site/images/icons/small.png", "png"),
+		] {
+			let mut node = vfs
+				.get_node_at(path, &NodeGetOptions::new().create_new(true))
+				.await
+				.unwrap();
+			node.write_all(content.as_bytes()).await.unwrap();
+		}
+		vfs
+	}
+
+	#[tokio::test]
+	async fn index_json() {
+		let vfs = indexed_vfs(IndexFormat::Json).await;
+		let index = vfs.read_to_vec_at("mem:/site/").await.unwrap();
+		assert_eq!(
+			std::str::from_utf8(&index).unwrap(),
+			concat!(
+				r#"[{"name":"a%22b.txt","is_node":true,"len":0},"#,
+				r#"{"name":"images/","is_node":false,"len":null},"#,
+				r#"{"name":"index.txt","is_node":true,"len":5}]"#,
+			)
+		);
+		assert_eq!(
+			vfs.read_to_vec_at("mem:/site").await.unwrap(),
+			index,
+			"with or without the trailing `/`"
+		);
+		assert_eq!(
+			vfs.read_to_vec_at("mem:/site/images/").await.unwrap(),
+			br#"[{"name":"icons/","is_node":false,"len":null},{"name":"logo.png","is_node":true,"len":3}]"#
+		);
+		assert_eq!(
+			vfs.read_to_vec_at("mem:/site/index.txt").await.unwrap(),
+			b"hello"
+		);
+		assert!(vfs.read_to_vec_at("mem:/nothing").await.is_err());
+	}
+
+	#[tokio::test]
+	async fn index_html() {
+		let vfs = indexed_vfs(IndexFormat::Html).await;
+		let index = vfs.read_to_vec_at("mem:/site/images/").await.unwrap();
+		let index = std::str::from_utf8(&index).unwrap();
+		assert!(index.contains("<h1>Index of /site/images/</h1>"));
+		assert!(index.contains("<li><a href=\"icons/\">icons/</a></li>"));
+		assert!(index.contains("<li><a href=\"logo.png\">logo.png</a></li>"));
+	}
+}
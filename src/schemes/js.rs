@@ -0,0 +1,38 @@
+//! What the schemes running on the JavaScript APIs of a browser share.
+use futures_lite::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use wasm_bindgen::{JsCast, JsValue};
+
+// `fetch` needs a page to fetch from and OPFS is only in browsers, not in node
+#[cfg(test)]
+wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
+
+/// Lets futures and nodes holding JavaScript values be used where the `Scheme` and `Node` traits
+/// want them `Send` and `Sync`.  JavaScript values never leave the thread that made them, but a
+/// `wasm32` module without threads only ever has the one thread, so nothing is actually shared.
+pub(crate) struct JsSend<T>(pub(crate) T);
+
+// SAFETY: see above, this is only compiled for `wasm32`, where there is only one thread
+unsafe impl<T> Send for JsSend<T> {}
+unsafe impl<T> Sync for JsSend<T> {}
+
+impl<F: Future + Unpin> Future for JsSend<F> {
+	type Output = F::Output;
+
+	fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+		Pin::new(&mut self.0).poll(cx)
+	}
+}
+
+/// The message of a thrown JavaScript value, whatever was thrown.
+pub(crate) fn js_message(error: &JsValue) -> String {
+	error
+		.as_string()
+		.or_else(|| {
+			error
+				.dyn_ref::<js_sys::Error>()
+				.map(|error| error.message().into())
+		})
+		.unwrap_or_else(|| format!("{:?}", error))
+}
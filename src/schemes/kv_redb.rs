@@ -0,0 +1,409 @@
+use crate::scheme::{BorrowedReadDirStream, NodeEntry, NodeGetOptions, NodeMetadata};
+use crate::{Node, PinnedNode, Scheme, SchemeError, Vfs};
+use futures_lite::{AsyncRead, AsyncSeek, AsyncWrite};
+use redb::{Database, TableDefinition};
+use std::borrow::Cow;
+use std::collections::BTreeSet;
+use std::io::SeekFrom;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use url::Url;
+
+const NODES: TableDefinition<&str, &[u8]> = TableDefinition::new("vfs_nodes");
+
+fn kv_error(error: impl Into<redb::Error>) -> SchemeError<'static> {
+	("kv store error", Box::new(error.into()) as Box<_>).into()
+}
+
+/// Stores nodes as values in a `redb` database keyed by their url path, directories are implied by
+/// the paths of the nodes in them.  Nodes are read into memory when opened and written back when
+/// flushed or closed, each write-back, creation and removal is its own transaction, so a node is
+/// always replaced as a whole and readers never see a partial write-back.
+pub struct KvScheme {
+	db: Arc<Database>,
+}
+
+impl KvScheme {
+	/// Uses an already open database, the nodes are stored in their own `vfs_nodes` table.
+	pub fn new(db: Arc<Database>) -> Result<Self, SchemeError<'static>> {
+		// Make sure the table exists so read transactions can always open it
+		let txn = db.begin_write().map_err(kv_error)?;
+		txn.open_table(NODES).map_err(kv_error)?;
+		txn.commit().map_err(kv_error)?;
+		Ok(Self { db })
+	}
+
+	/// Opens, or creates, the database file at `path`.
+	pub fn open(path: impl AsRef<Path>) -> Result<Self, SchemeError<'static>> {
+		Self::new(Arc::new(Database::create(path).map_err(kv_error)?))
+	}
+
+	/// A database that only lives in memory, mostly useful for tests.
+	pub fn in_memory() -> Result<Self, SchemeError<'static>> {
+		let db = Database::builder()
+			.create_with_backend(redb::backends::InMemoryBackend::new())
+			.map_err(kv_error)?;
+		Self::new(Arc::new(db))
+	}
+
+	pub fn db(&self) -> &Arc<Database> {
+		&self.db
+	}
+
+	fn get(&self, key: &str) -> Result<Option<Vec<u8>>, SchemeError<'static>> {
+		let txn = self.db.begin_read().map_err(kv_error)?;
+		let table = txn.open_table(NODES).map_err(kv_error)?;
+		let value = table.get(key).map_err(kv_error)?;
+		Ok(value.map(|value| value.value().to_vec()))
+	}
+
+	/// The keys of every node under the directory `prefix`, which ends in `/`.
+	fn keys_under(&self, prefix: &str) -> Result<Vec<String>, SchemeError<'static>> {
+		let txn = self.db.begin_read().map_err(kv_error)?;
+		let table = txn.open_table(NODES).map_err(kv_error)?;
+		let mut keys = Vec::new();
+		for item in table.range(prefix..).map_err(kv_error)? {
+			let (key, _value) = item.map_err(kv_error)?;
+			let key = key.value();
+			if !key.starts_with(prefix) {
+				break;
+			}
+			keys.push(key.to_owned());
+		}
+		Ok(keys)
+	}
+}
+
+fn dir_prefix(path: &str) -> String {
+	if path.ends_with('/') {
+		path.to_owned()
+	} else {
+		format!("{}/", path)
+	}
+}
+
+fn write_back(db: &Database, key: &str, data: &[u8]) -> Result<(), SchemeError<'static>> {
+	let txn = db.begin_write().map_err(kv_error)?;
+	txn.open_table(NODES)
+		.map_err(kv_error)?
+		.insert(key, data)
+		.map_err(kv_error)?;
+	txn.commit().map_err(kv_error)?;
+	Ok(())
+}
+
+#[async_trait::async_trait]
+impl Scheme for KvScheme {
+	async fn get_node<'a>(
+		&self,
+		_vfs: &Vfs,
+		url: &'a Url,
+		options: &NodeGetOptions,
+	) -> Result<PinnedNode, SchemeError<'a>> {
+		let key = url.path();
+		let data = match self.get(key)? {
+			Some(_data) if options.get_create_new() => {
+				return Err(SchemeError::NodeAlreadyExists(Cow::Borrowed(url.path())));
+			}
+			Some(_data) if options.get_truncate() => {
+				write_back(&self.db, key, &[])?;
+				Vec::new()
+			}
+			Some(data) => data,
+			None if !self.keys_under(&dir_prefix(key))?.is_empty() => {
+				return Err(SchemeError::IsADirectory(Cow::Borrowed(url.path())));
+			}
+			None if options.get_create() => {
+				write_back(&self.db, key, &[])?;
+				Vec::new()
+			}
+			None => return Err(SchemeError::NodeDoesNotExist(Cow::Borrowed(url.path()))),
+		};
+		let cursor = if options.get_append() { data.len() } else { 0 };
+		Ok(Box::pin(KvNode {
+			db: self.db.clone(),
+			key: key.to_owned(),
+			data,
+			cursor,
+			read: options.get_read(),
+			write: options.get_write(),
+			dirty: false,
+		}))
+	}
+
+	async fn remove_node<'a>(
+		&self,
+		_vfs: &Vfs,
+		url: &'a Url,
+		force: bool,
+	) -> Result<(), SchemeError<'a>> {
+		let key = url.path();
+		// Forcing also removes every node under it as a directory
+		let under = if force {
+			self.keys_under(&dir_prefix(key))?
+		} else {
+			Vec::new()
+		};
+		let txn = self.db.begin_write().map_err(kv_error)?;
+		let removed = {
+			let mut table = txn.open_table(NODES).map_err(kv_error)?;
+			let mut removed = table.remove(key).map_err(kv_error)?.is_some();
+			for key in &under {
+				removed |= table.remove(key.as_str()).map_err(kv_error)?.is_some();
+			}
+			removed
+		};
+		txn.commit().map_err(kv_error)?;
+		if removed {
+			Ok(())
+		} else {
+			Err(SchemeError::NodeDoesNotExist(Cow::Borrowed(url.path())))
+		}
+	}
+
+	async fn metadata<'a>(
+		&self,
+		_vfs: &Vfs,
+		url: &'a Url,
+	) -> Result<NodeMetadata, SchemeError<'a>> {
+		if let Some(data) = self.get(url.path())? {
+			Ok(NodeMetadata {
+				is_node: true,
+				len: Some((data.len(), Some(data.len()))),
+				modified: None,
+				..Default::default()
+			})
+		} else if url.path() == "/" || !self.keys_under(&dir_prefix(url.path()))?.is_empty() {
+			Ok(NodeMetadata {
+				is_node: false,
+				len: None,
+				modified: None,
+				..Default::default()
+			})
+		} else {
+			Err(SchemeError::NodeDoesNotExist(Cow::Borrowed(url.path())))
+		}
+	}
+
+	async fn read_dir<'s, 'a>(
+		&'s self,
+		_vfs: &'s Vfs,
+		url: &'a Url,
+	) -> Result<BorrowedReadDirStream<'s>, SchemeError<'a>> {
+		if !url.path().ends_with('/') && self.get(url.path())?.is_some() {
+			return Err(SchemeError::NotADirectory(Cow::Borrowed(url.path())));
+		}
+		let prefix = dir_prefix(url.path());
+		// Only the immediate children, a deeper key lists the directory it is in
+		let children: BTreeSet<String> = self
+			.keys_under(&prefix)?
+			.into_iter()
+			.map(|key| match key[prefix.len()..].find('/') {
+				Some(pos) => key[..prefix.len() + pos].to_owned(),
+				None => key,
+			})
+			.collect();
+		let entries: Vec<NodeEntry> = children
+			.into_iter()
+			.map(|path| {
+				let mut url = url.clone();
+				url.set_path(&path);
+				NodeEntry::new(url)
+			})
+			.collect();
+		Ok(Box::pin(futures_lite::stream::iter(entries)))
+	}
+}
+
+pub struct KvNode {
+	db: Arc<Database>,
+	key: String,
+	data: Vec<u8>,
+	cursor: usize,
+	read: bool,
+	write: bool,
+	/// Whether `data` has changes that have not been written back yet.
+	dirty: bool,
+}
+
+impl KvNode {
+	fn write_back(&mut self) -> std::io::Result<()> {
+		if self.dirty {
+			write_back(&self.db, &self.key, &self.data).map_err(std::io::Error::other)?;
+			self.dirty = false;
+		}
+		Ok(())
+	}
+}
+
+impl Drop for KvNode {
+	fn drop(&mut self) {
+		// Best effort, close the node to find out whether the write-back failed
+		let _ = self.write_back();
+	}
+}
+
+#[async_trait::async_trait]
+impl Node for KvNode {
+	fn is_reader(&self) -> bool {
+		self.read
+	}
+
+	fn is_writer(&self) -> bool {
+		self.write
+	}
+
+	fn is_seeker(&self) -> bool {
+		self.read || self.write
+	}
+
+	fn is_at_end(&self) -> Option<bool> {
+		Some(self.cursor >= self.data.len())
+	}
+}
+
+impl AsyncRead for KvNode {
+	fn poll_read(
+		mut self: Pin<&mut Self>,
+		_cx: &mut Context<'_>,
+		buf: &mut [u8],
+	) -> Poll<std::io::Result<usize>> {
+		if !self.read {
+			return Poll::Ready(Err(std::io::Error::from_raw_os_error(13)));
+		}
+		if self.cursor >= self.data.len() {
+			return Poll::Ready(Ok(0));
+		}
+
+		let amt = std::cmp::min(self.data.len() - self.cursor, buf.len());
+		buf[..amt].copy_from_slice(&self.data[self.cursor..(self.cursor + amt)]);
+		self.cursor += amt;
+
+		Poll::Ready(Ok(amt))
+	}
+}
+
+impl AsyncWrite for KvNode {
+	fn poll_write(
+		mut self: Pin<&mut Self>,
+		_cx: &mut Context<'_>,
+		buf: &[u8],
+	) -> Poll<std::io::Result<usize>> {
+		if !self.write {
+			return Poll::Ready(Err(std::io::Error::from_raw_os_error(13)));
+		}
+		let this = &mut *self;
+		let end = this.cursor + buf.len();
+		if end > this.data.len() {
+			this.data.resize(end, 0);
+		}
+		this.data[this.cursor..end].copy_from_slice(buf);
+		this.cursor = end;
+		this.dirty = true;
+		Poll::Ready(Ok(buf.len()))
+	}
+
+	fn poll_flush(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+		if !self.write {
+			return Poll::Ready(Err(std::io::Error::from_raw_os_error(13)));
+		}
+		Poll::Ready(self.write_back())
+	}
+
+	fn poll_close(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+		if !self.write {
+			return Poll::Ready(Err(std::io::Error::from_raw_os_error(13)));
+		}
+		Poll::Ready(self.write_back())
+	}
+}
+
+impl AsyncSeek for KvNode {
+	fn poll_seek(
+		mut self: Pin<&mut Self>,
+		_cx: &mut Context<'_>,
+		pos: SeekFrom,
+	) -> Poll<std::io::Result<u64>> {
+		if !self.read && !self.write {
+			return Poll::Ready(Err(std::io::Error::from_raw_os_error(13)));
+		}
+		let len = self.data.len();
+		self.cursor = match pos {
+			SeekFrom::Start(pos) => std::cmp::min(pos, len as u64) as usize,
+			SeekFrom::End(end_pos) if end_pos > 0 => len,
+			SeekFrom::End(end_pos) => len.saturating_sub((-end_pos) as usize),
+			SeekFrom::Current(offset) => {
+				(self.cursor as i64 + offset).clamp(0, len as i64) as usize
+			}
+		};
+		Poll::Ready(Ok(self.cursor as u64))
+	}
+}
+
+#[cfg(test)]
+#[cfg(feature = "backend_tokio")]
+mod async_tokio_tests {
+	use crate::scheme::NodeGetOptions;
+	use crate::{KvScheme, SchemeError, Vfs, VfsError};
+	use futures_lite::{AsyncReadExt, AsyncWriteExt, StreamExt};
+
+	async fn create(vfs: &Vfs, uri: &str, content: &str) {
+		let mut node = vfs
+			.get_node_at(uri, &NodeGetOptions::new().create_new(true))
+			.await
+			.unwrap();
+		node.write_all(content.as_bytes()).await.unwrap();
+		node.close().await.unwrap();
+	}
+
+	async fn listing(vfs: &Vfs, uri: &str) -> Vec<String> {
+		vfs.read_dir_at(uri)
+			.await
+			.unwrap()
+			.map(|entry| entry.url.path().to_owned())
+			.collect()
+			.await
+	}
+
+	#[tokio::test]
+	async fn kv_create_list_remove() {
+		let vfs = Vfs::empty();
+		vfs.add_scheme("kv", KvScheme::in_memory().unwrap())
+			.unwrap();
+		create(&vfs, "kv:/config.toml", "config").await;
+		create(&vfs, "kv:/assets/a.png", "a").await;
+		create(&vfs, "kv:/assets/deep/b.png", "bb").await;
+
+		let mut buffer = String::new();
+		vfs.get_node_at("kv:/config.toml", &NodeGetOptions::new().read(true))
+			.await
+			.unwrap()
+			.read_to_string(&mut buffer)
+			.await
+			.unwrap();
+		assert_eq!(&buffer, "config");
+		assert_eq!(
+			vfs.metadata_at("kv:/assets/deep/b.png").await.unwrap().len,
+			Some((2, Some(2)))
+		);
+		assert!(!vfs.metadata_at("kv:/assets").await.unwrap().is_node);
+		assert_eq!(listing(&vfs, "kv:/").await, ["/assets", "/config.toml"]);
+		assert_eq!(
+			listing(&vfs, "kv:/assets").await,
+			["/assets/a.png", "/assets/deep"]
+		);
+		assert!(matches!(
+			vfs.get_node_at("kv:/config.toml", &NodeGetOptions::new().create_new(true))
+				.await,
+			Err(VfsError::SchemeError(SchemeError::NodeAlreadyExists(_)))
+		));
+
+		vfs.remove_node_at("kv:/config.toml", false).await.unwrap();
+		assert!(vfs.metadata_at("kv:/config.toml").await.is_err());
+		vfs.remove_node_at("kv:/assets", true).await.unwrap();
+		assert!(listing(&vfs, "kv:/").await.is_empty());
+		assert!(vfs.remove_node_at("kv:/assets", true).await.is_err());
+	}
+}
@@ -0,0 +1,396 @@
+use crate::scheme::{BorrowedReadDirStream, NodeEntry, NodeGetOptions, NodeMetadata};
+use crate::{Node, PinnedNode, Scheme, SchemeError, Vfs};
+use futures_lite::{AsyncRead, AsyncSeek, AsyncWrite};
+use std::borrow::Cow;
+use std::collections::BTreeSet;
+use std::io::SeekFrom;
+use std::path::Path;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use url::Url;
+
+fn kv_error(error: sled::Error) -> SchemeError<'static> {
+	("kv store error", Box::new(error) as Box<_>).into()
+}
+
+/// Stores nodes as values in a `sled` tree keyed by their url path, directories are implied by the
+/// paths of the nodes in them and listed by prefix scans.  Nodes are read into memory when opened
+/// and written back when flushed or closed, each write-back, creation and removal is atomic and
+/// flushed to disk before it returns, so a crash never leaves a node partially written back.
+pub struct SledScheme {
+	tree: sled::Tree,
+}
+
+impl SledScheme {
+	/// Uses an already open tree, such as one from `sled::Db::open_tree`.
+	pub fn new(tree: sled::Tree) -> Self {
+		Self { tree }
+	}
+
+	/// Opens, or creates, the database at `path`, the nodes are stored in its own `vfs_nodes` tree.
+	pub fn open(path: impl AsRef<Path>) -> Result<Self, SchemeError<'static>> {
+		let db = sled::open(path).map_err(kv_error)?;
+		Ok(Self::new(db.open_tree("vfs_nodes").map_err(kv_error)?))
+	}
+
+	/// A temporary database that is removed once dropped, mostly useful for tests.
+	pub fn temporary() -> Result<Self, SchemeError<'static>> {
+		let db = sled::Config::new()
+			.temporary(true)
+			.open()
+			.map_err(kv_error)?;
+		Ok(Self::new(db.open_tree("vfs_nodes").map_err(kv_error)?))
+	}
+
+	pub fn tree(&self) -> &sled::Tree {
+		&self.tree
+	}
+
+	fn get(&self, key: &str) -> Result<Option<Vec<u8>>, SchemeError<'static>> {
+		let value = self.tree.get(key).map_err(kv_error)?;
+		Ok(value.map(|value| value.to_vec()))
+	}
+
+	/// The keys of every node under the directory `prefix`, which ends in `/`.
+	fn keys_under(&self, prefix: &str) -> Result<Vec<String>, SchemeError<'static>> {
+		self.tree
+			.scan_prefix(prefix)
+			.keys()
+			.map(|key| {
+				let key = key.map_err(kv_error)?;
+				Ok(String::from_utf8_lossy(&key).into_owned())
+			})
+			.collect()
+	}
+}
+
+fn dir_prefix(path: &str) -> String {
+	if path.ends_with('/') {
+		path.to_owned()
+	} else {
+		format!("{}/", path)
+	}
+}
+
+fn write_back(tree: &sled::Tree, key: &str, data: &[u8]) -> Result<(), SchemeError<'static>> {
+	tree.insert(key, data).map_err(kv_error)?;
+	tree.flush().map_err(kv_error)?;
+	Ok(())
+}
+
+#[async_trait::async_trait]
+impl Scheme for SledScheme {
+	async fn get_node<'a>(
+		&self,
+		_vfs: &Vfs,
+		url: &'a Url,
+		options: &NodeGetOptions,
+	) -> Result<PinnedNode, SchemeError<'a>> {
+		let key = url.path();
+		let data = match self.get(key)? {
+			Some(_data) if options.get_create_new() => {
+				return Err(SchemeError::NodeAlreadyExists(Cow::Borrowed(url.path())));
+			}
+			Some(_data) if options.get_truncate() => {
+				write_back(&self.tree, key, &[])?;
+				Vec::new()
+			}
+			Some(data) => data,
+			None if !self.keys_under(&dir_prefix(key))?.is_empty() => {
+				return Err(SchemeError::IsADirectory(Cow::Borrowed(url.path())));
+			}
+			None if options.get_create() => {
+				write_back(&self.tree, key, &[])?;
+				Vec::new()
+			}
+			None => return Err(SchemeError::NodeDoesNotExist(Cow::Borrowed(url.path()))),
+		};
+		let cursor = if options.get_append() { data.len() } else { 0 };
+		Ok(Box::pin(SledNode {
+			tree: self.tree.clone(),
+			key: key.to_owned(),
+			data,
+			cursor,
+			read: options.get_read(),
+			write: options.get_write(),
+			dirty: false,
+		}))
+	}
+
+	async fn remove_node<'a>(
+		&self,
+		_vfs: &Vfs,
+		url: &'a Url,
+		force: bool,
+	) -> Result<(), SchemeError<'a>> {
+		let key = url.path();
+		// Forcing also removes every node under it as a directory
+		let under = if force {
+			self.keys_under(&dir_prefix(key))?
+		} else {
+			Vec::new()
+		};
+		let removed = self.tree.contains_key(key).map_err(kv_error)? || !under.is_empty();
+		let mut batch = sled::Batch::default();
+		batch.remove(key);
+		for key in under {
+			batch.remove(key.as_str());
+		}
+		self.tree.apply_batch(batch).map_err(kv_error)?;
+		self.tree.flush().map_err(kv_error)?;
+		if removed {
+			Ok(())
+		} else {
+			Err(SchemeError::NodeDoesNotExist(Cow::Borrowed(url.path())))
+		}
+	}
+
+	async fn metadata<'a>(
+		&self,
+		_vfs: &Vfs,
+		url: &'a Url,
+	) -> Result<NodeMetadata, SchemeError<'a>> {
+		if let Some(data) = self.get(url.path())? {
+			Ok(NodeMetadata {
+				is_node: true,
+				len: Some((data.len(), Some(data.len()))),
+				modified: None,
+				..Default::default()
+			})
+		} else if url.path() == "/" || !self.keys_under(&dir_prefix(url.path()))?.is_empty() {
+			Ok(NodeMetadata {
+				is_node: false,
+				len: None,
+				modified: None,
+				..Default::default()
+			})
+		} else {
+			Err(SchemeError::NodeDoesNotExist(Cow::Borrowed(url.path())))
+		}
+	}
+
+	async fn read_dir<'s, 'a>(
+		&'s self,
+		_vfs: &'s Vfs,
+		url: &'a Url,
+	) -> Result<BorrowedReadDirStream<'s>, SchemeError<'a>> {
+		if !url.path().ends_with('/') && self.get(url.path())?.is_some() {
+			return Err(SchemeError::NotADirectory(Cow::Borrowed(url.path())));
+		}
+		let prefix = dir_prefix(url.path());
+		// Only the immediate children, a deeper key lists the directory it is in
+		let children: BTreeSet<String> = self
+			.keys_under(&prefix)?
+			.into_iter()
+			.map(|key| match key[prefix.len()..].find('/') {
+				Some(pos) => key[..prefix.len() + pos].to_owned(),
+				None => key,
+			})
+			.collect();
+		let entries: Vec<NodeEntry> = children
+			.into_iter()
+			.map(|path| {
+				let mut url = url.clone();
+				url.set_path(&path);
+				NodeEntry::new(url)
+			})
+			.collect();
+		Ok(Box::pin(futures_lite::stream::iter(entries)))
+	}
+}
+
+pub struct SledNode {
+	tree: sled::Tree,
+	key: String,
+	data: Vec<u8>,
+	cursor: usize,
+	read: bool,
+	write: bool,
+	/// Whether `data` has changes that have not been written back yet.
+	dirty: bool,
+}
+
+impl SledNode {
+	fn write_back(&mut self) -> std::io::Result<()> {
+		if self.dirty {
+			write_back(&self.tree, &self.key, &self.data).map_err(std::io::Error::other)?;
+			self.dirty = false;
+		}
+		Ok(())
+	}
+}
+
+impl Drop for SledNode {
+	fn drop(&mut self) {
+		// Best effort, close the node to find out whether the write-back failed
+		let _ = self.write_back();
+	}
+}
+
+#[async_trait::async_trait]
+impl Node for SledNode {
+	fn is_reader(&self) -> bool {
+		self.read
+	}
+
+	fn is_writer(&self) -> bool {
+		self.write
+	}
+
+	fn is_seeker(&self) -> bool {
+		self.read || self.write
+	}
+
+	fn is_at_end(&self) -> Option<bool> {
+		Some(self.cursor >= self.data.len())
+	}
+}
+
+impl AsyncRead for SledNode {
+	fn poll_read(
+		mut self: Pin<&mut Self>,
+		_cx: &mut Context<'_>,
+		buf: &mut [u8],
+	) -> Poll<std::io::Result<usize>> {
+		if !self.read {
+			return Poll::Ready(Err(std::io::Error::from_raw_os_error(13)));
+		}
+		if self.cursor >= self.data.len() {
+			return Poll::Ready(Ok(0));
+		}
+
+		let amt = std::cmp::min(self.data.len() - self.cursor, buf.len());
+		buf[..amt].copy_from_slice(&self.data[self.cursor..(self.cursor + amt)]);
+		self.cursor += amt;
+
+		Poll::Ready(Ok(amt))
+	}
+}
+
+impl AsyncWrite for SledNode {
+	fn poll_write(
+		mut self: Pin<&mut Self>,
+		_cx: &mut Context<'_>,
+		buf: &[u8],
+	) -> Poll<std::io::Result<usize>> {
+		if !self.write {
+			return Poll::Ready(Err(std::io::Error::from_raw_os_error(13)));
+		}
+		let this = &mut *self;
+		let end = this.cursor + buf.len();
+		if end > this.data.len() {
+			this.data.resize(end, 0);
+		}
+		this.data[this.cursor..end].copy_from_slice(buf);
+		this.cursor = end;
+		this.dirty = true;
+		Poll::Ready(Ok(buf.len()))
+	}
+
+	fn poll_flush(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+		if !self.write {
+			return Poll::Ready(Err(std::io::Error::from_raw_os_error(13)));
+		}
+		Poll::Ready(self.write_back())
+	}
+
+	fn poll_close(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+		if !self.write {
+			return Poll::Ready(Err(std::io::Error::from_raw_os_error(13)));
+		}
+		Poll::Ready(self.write_back())
+	}
+}
+
+impl AsyncSeek for SledNode {
+	fn poll_seek(
+		mut self: Pin<&mut Self>,
+		_cx: &mut Context<'_>,
+		pos: SeekFrom,
+	) -> Poll<std::io::Result<u64>> {
+		if !self.read && !self.write {
+			return Poll::Ready(Err(std::io::Error::from_raw_os_error(13)));
+		}
+		let len = self.data.len();
+		self.cursor = match pos {
+			SeekFrom::Start(pos) => std::cmp::min(pos, len as u64) as usize,
+			SeekFrom::End(end_pos) if end_pos > 0 => len,
+			SeekFrom::End(end_pos) => len.saturating_sub((-end_pos) as usize),
+			SeekFrom::Current(offset) => {
+				(self.cursor as i64 + offset).clamp(0, len as i64) as usize
+			}
+		};
+		Poll::Ready(Ok(self.cursor as u64))
+	}
+}
+
+#[cfg(test)]
+#[cfg(feature = "backend_tokio")]
+mod async_tokio_tests {
+	use crate::scheme::NodeGetOptions;
+	use crate::{SchemeError, SledScheme, Vfs, VfsError};
+	use futures_lite::{AsyncReadExt, AsyncWriteExt, StreamExt};
+
+	async fn create(vfs: &Vfs, uri: &str, content: &str) {
+		let mut node = vfs
+			.get_node_at(uri, &NodeGetOptions::new().create_new(true))
+			.await
+			.unwrap();
+		node.write_all(content.as_bytes()).await.unwrap();
+		node.close().await.unwrap();
+	}
+
+	async fn listing(vfs: &Vfs, uri: &str) -> Vec<String> {
+		vfs.read_dir_at(uri)
+			.await
+			.unwrap()
+			.map(|entry| entry.url.path().to_owned())
+			.collect()
+			.await
+	}
+
+	#[tokio::test]
+	async fn sled_create_list_remove() {
+		let vfs = Vfs::empty();
+		vfs.add_scheme("sled", SledScheme::temporary().unwrap())
+			.unwrap();
+		create(&vfs, "sled:/config.toml", "config").await;
+		create(&vfs, "sled:/assets/a.png", "a").await;
+		create(&vfs, "sled:/assets/deep/b.png", "bb").await;
+
+		let mut buffer = String::new();
+		vfs.get_node_at("sled:/config.toml", &NodeGetOptions::new().read(true))
+			.await
+			.unwrap()
+			.read_to_string(&mut buffer)
+			.await
+			.unwrap();
+		assert_eq!(&buffer, "config");
+		assert_eq!(
+			vfs.metadata_at("sled:/assets/deep/b.png")
+				.await
+				.unwrap()
+				.len,
+			Some((2, Some(2)))
+		);
+		assert!(!vfs.metadata_at("sled:/assets").await.unwrap().is_node);
+		assert_eq!(listing(&vfs, "sled:/").await, ["/assets", "/config.toml"]);
+		assert_eq!(
+			listing(&vfs, "sled:/assets").await,
+			["/assets/a.png", "/assets/deep"]
+		);
+		assert!(matches!(
+			vfs.get_node_at("sled:/config.toml", &NodeGetOptions::new().create_new(true))
+				.await,
+			Err(VfsError::SchemeError(SchemeError::NodeAlreadyExists(_)))
+		));
+
+		vfs.remove_node_at("sled:/config.toml", false)
+			.await
+			.unwrap();
+		assert!(vfs.metadata_at("sled:/config.toml").await.is_err());
+		vfs.remove_node_at("sled:/assets", true).await.unwrap();
+		assert!(listing(&vfs, "sled:/").await.is_empty());
+		assert!(vfs.remove_node_at("sled:/assets", true).await.is_err());
+	}
+}
@@ -0,0 +1,240 @@
+use crate::scheme::{BorrowedReadDirStream, NodeGetOptions, NodeMetadata, WatchStream};
+use crate::{PinnedNode, Scheme, SchemeError, Vfs};
+use std::time::SystemTime;
+use url::Url;
+
+type ErrorMapper = Box<dyn Fn(&Url, SchemeError<'static>) -> SchemeError<'static> + Send + Sync>;
+
+/// Wraps a scheme and passes every error it returns, along with the url of the request, through a
+/// mapping closure, such as to normalize the errors of a third-party scheme at a composition
+/// boundary.  Successful results pass through untouched.
+pub struct MapErrScheme {
+	scheme: Box<dyn Scheme>,
+	mapper: ErrorMapper,
+}
+
+impl MapErrScheme {
+	pub fn new<F>(scheme: impl Scheme, mapper: F) -> Self
+	where
+		F: Fn(&Url, SchemeError<'static>) -> SchemeError<'static> + Send + Sync + 'static,
+	{
+		Self::new_boxed(Box::new(scheme), mapper)
+	}
+
+	pub fn new_boxed<F>(scheme: Box<dyn Scheme>, mapper: F) -> Self
+	where
+		F: Fn(&Url, SchemeError<'static>) -> SchemeError<'static> + Send + Sync + 'static,
+	{
+		Self {
+			scheme,
+			mapper: Box::new(mapper),
+		}
+	}
+
+	pub fn inner(&self) -> &dyn Scheme {
+		&*self.scheme
+	}
+
+	pub fn into_inner(self) -> Box<dyn Scheme> {
+		self.scheme
+	}
+
+	fn map<T>(
+		&self,
+		url: &Url,
+		result: Result<T, SchemeError<'_>>,
+	) -> Result<T, SchemeError<'static>> {
+		result.map_err(|error| (self.mapper)(url, error.into_owned()))
+	}
+}
+
+#[async_trait::async_trait]
+impl Scheme for MapErrScheme {
+	async fn get_node<'a>(
+		&self,
+		vfs: &Vfs,
+		url: &'a Url,
+		options: &NodeGetOptions,
+	) -> Result<PinnedNode, SchemeError<'a>> {
+		self.map(url, self.scheme.get_node(vfs, url, options).await)
+	}
+
+	async fn remove_node<'a>(
+		&self,
+		vfs: &Vfs,
+		url: &'a Url,
+		force: bool,
+	) -> Result<(), SchemeError<'a>> {
+		self.map(url, self.scheme.remove_node(vfs, url, force).await)
+	}
+
+	async fn metadata<'a>(&self, vfs: &Vfs, url: &'a Url) -> Result<NodeMetadata, SchemeError<'a>> {
+		self.map(url, self.scheme.metadata(vfs, url).await)
+	}
+
+	async fn metadata_many(
+		&self,
+		vfs: &Vfs,
+		urls: &[Url],
+	) -> Vec<Result<NodeMetadata, SchemeError<'static>>> {
+		let results = self.scheme.metadata_many(vfs, urls).await;
+		urls.iter()
+			.zip(results)
+			.map(|(url, result)| self.map(url, result))
+			.collect()
+	}
+
+	async fn read_dir<'s, 'a>(
+		&'s self,
+		vfs: &'s Vfs,
+		url: &'a Url,
+	) -> Result<BorrowedReadDirStream<'s>, SchemeError<'a>> {
+		self.map(url, self.scheme.read_dir(vfs, url).await)
+	}
+
+	async fn read_small_file<'a>(
+		&self,
+		vfs: &Vfs,
+		url: &'a Url,
+		max_len: usize,
+	) -> Result<Option<Vec<u8>>, SchemeError<'a>> {
+		self.map(url, self.scheme.read_small_file(vfs, url, max_len).await)
+	}
+
+	#[cfg(feature = "bytes")]
+	async fn read_bytes<'a>(
+		&self,
+		vfs: &Vfs,
+		url: &'a Url,
+	) -> Result<Option<bytes::Bytes>, SchemeError<'a>> {
+		self.map(url, self.scheme.read_bytes(vfs, url).await)
+	}
+
+	async fn stat_and_open<'a>(
+		&self,
+		vfs: &Vfs,
+		url: &'a Url,
+		options: &NodeGetOptions,
+	) -> Result<(NodeMetadata, PinnedNode), SchemeError<'a>> {
+		self.map(url, self.scheme.stat_and_open(vfs, url, options).await)
+	}
+
+	async fn get_node_with_metadata<'a>(
+		&self,
+		vfs: &Vfs,
+		url: &'a Url,
+		options: &NodeGetOptions,
+		metadata: &NodeMetadata,
+	) -> Result<PinnedNode, SchemeError<'a>> {
+		self.map(
+			url,
+			self.scheme
+				.get_node_with_metadata(vfs, url, options, metadata)
+				.await,
+		)
+	}
+
+	async fn canonicalize<'a>(&self, vfs: &Vfs, url: &'a Url) -> Result<Url, SchemeError<'a>> {
+		self.map(url, self.scheme.canonicalize(vfs, url).await)
+	}
+
+	async fn set_modified<'a>(
+		&self,
+		vfs: &Vfs,
+		url: &'a Url,
+		modified: SystemTime,
+	) -> Result<(), SchemeError<'a>> {
+		self.map(url, self.scheme.set_modified(vfs, url, modified).await)
+	}
+
+	async fn create_dir<'a>(
+		&self,
+		vfs: &Vfs,
+		url: &'a Url,
+		parents: bool,
+	) -> Result<(), SchemeError<'a>> {
+		self.map(url, self.scheme.create_dir(vfs, url, parents).await)
+	}
+
+	async fn copy_node<'a>(
+		&self,
+		vfs: &Vfs,
+		from: &'a Url,
+		to: &'a Url,
+	) -> Result<(), SchemeError<'a>> {
+		self.map(from, self.scheme.copy_node(vfs, from, to).await)
+	}
+
+	async fn rename_node<'a>(
+		&self,
+		vfs: &Vfs,
+		from: &'a Url,
+		to: &'a Url,
+	) -> Result<(), SchemeError<'a>> {
+		self.map(from, self.scheme.rename_node(vfs, from, to).await)
+	}
+
+	async fn get_node_split<'a>(
+		&self,
+		vfs: &Vfs,
+		url: &'a Url,
+		options: &NodeGetOptions,
+	) -> Result<(PinnedNode, PinnedNode), SchemeError<'a>> {
+		self.map(url, self.scheme.get_node_split(vfs, url, options).await)
+	}
+
+	async fn watch<'a>(&self, vfs: &Vfs, url: &'a Url) -> Result<WatchStream, SchemeError<'a>> {
+		self.map(url, self.scheme.watch(vfs, url).await)
+	}
+}
+
+#[cfg(test)]
+#[cfg(feature = "backend_tokio")]
+mod async_tokio_tests {
+	use crate::scheme::{NodeGetOptions, NodeMetadata};
+	use crate::{FnScheme, MapErrScheme, SchemeError, Vfs, VfsError};
+	use std::borrow::Cow;
+
+	fn third_party_scheme() -> FnScheme {
+		FnScheme::new()
+			.on_get_node(|_vfs, _url, _options| Box::pin(async { Err("not found".into()) }))
+			.on_metadata(|_vfs, _url| {
+				Box::pin(async {
+					Ok(NodeMetadata {
+						is_node: true,
+						len: None,
+						modified: None,
+						..Default::default()
+					})
+				})
+			})
+	}
+
+	#[tokio::test]
+	async fn map_generic_to_does_not_exist() {
+		let vfs = Vfs::empty();
+		vfs.add_scheme(
+			"third",
+			MapErrScheme::new(third_party_scheme(), |url, error| match error {
+				SchemeError::GenericError(Some("not found"), _) => {
+					SchemeError::NodeDoesNotExist(Cow::Owned(url.path().to_owned()))
+				}
+				error => error,
+			}),
+		)
+		.unwrap();
+		assert!(matches!(
+			vfs.get_node_at("third:/missing", &NodeGetOptions::new().read(true))
+				.await,
+			Err(VfsError::SchemeError(SchemeError::NodeDoesNotExist(path))) if path == "/missing"
+		));
+		assert!(
+			vfs.metadata_at("third:/missing").await.unwrap().is_node,
+			"success passes through"
+		);
+		assert!(matches!(
+			vfs.read_dir_at("third:/").await,
+			Err(VfsError::SchemeError(SchemeError::Unsupported("read_dir")))
+		));
+	}
+}
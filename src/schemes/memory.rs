@@ -1,25 +1,408 @@
-use crate::scheme::{NodeEntry, NodeGetOptions, NodeMetadata, ReadDirStream};
+use crate::clock;
+use crate::scheme::{
+	BorrowedReadDirStream, NodeEntry, NodeGetOptions, NodeKind, NodeMetadata, WatchEvent,
+	WatchEventKind, WatchStream,
+};
 use crate::{Node, PinnedNode, Scheme, SchemeError, Vfs};
-use dashmap::DashMap;
 use futures_lite::{AsyncRead, AsyncSeek, AsyncWrite, Stream};
+use percent_encoding::percent_decode_str;
 use std::borrow::Cow;
-use std::io::SeekFrom;
+use std::collections::btree_map::Entry;
+use std::collections::BTreeMap;
+use std::io::{IoSlice, SeekFrom};
 use std::option::Option::None;
-use std::path::{Path, PathBuf};
 use std::pin::Pin;
-use std::sync::{Arc, RwLock};
+use std::sync::{Arc, Mutex, RwLock};
 use std::task::{Context, Poll};
+use std::time::SystemTime;
 use url::Url;
 
+/// The data of a stored node along with when it was last modified, shared between the scheme and
+/// any nodes opened on it so writes through a node are visible to `metadata`.
+struct MemoryEntry {
+	data: Vec<u8>,
+	modified: SystemTime,
+	created: SystemTime,
+}
+
+impl MemoryEntry {
+	fn new() -> Self {
+		let now = clock::now();
+		Self {
+			data: Vec::new(),
+			modified: now,
+			created: now,
+		}
+	}
+
+	fn metadata(&self) -> NodeMetadata {
+		let size = self.data.len();
+		NodeMetadata {
+			is_node: true,
+			len: Some((size, Some(size))),
+			modified: Some(self.modified),
+			kind: Some(NodeKind::File),
+			created: Some(self.created),
+			accessed: None,
+			read_only: Some(false),
+		}
+	}
+}
+
+/// Directories hold nothing of their own, only whether they exist is known.
+fn dir_metadata() -> NodeMetadata {
+	NodeMetadata {
+		is_node: false,
+		len: None,
+		modified: None,
+		kind: Some(NodeKind::Directory),
+		created: None,
+		accessed: None,
+		read_only: Some(false),
+	}
+}
+
+/// A stored node along with the url path segment it was created with, which can differ from its
+/// key when a key normalizer is in use.
+struct StoredEntry {
+	name: String,
+	entry: Arc<RwLock<MemoryEntry>>,
+}
+
+/// A directory, either made with `create_dir` or implied by something stored below it.  Implied
+/// directories are removed again along with the last thing below them.
+#[derive(Default)]
+struct MemoryDir {
+	name: String,
+	explicit: bool,
+	children: BTreeMap<String, MemoryChild>,
+}
+
+enum MemoryChild {
+	Node(StoredEntry),
+	Dir(MemoryDir),
+}
+
+/// A segment of a url path, by the key it is stored under and the percent-encoded name it was
+/// given.
+#[derive(Clone)]
+struct Segment {
+	key: String,
+	name: String,
+}
+
+/// Why there is no directory at some segments, with the index of the segment at fault.
+enum Missing {
+	Nothing(usize),
+	Node(usize),
+}
+
+impl MemoryDir {
+	fn new(name: &str, explicit: bool) -> Self {
+		Self {
+			name: name.to_owned(),
+			explicit,
+			children: BTreeMap::new(),
+		}
+	}
+
+	fn dir(&self, segments: &[Segment]) -> Result<&MemoryDir, Missing> {
+		let mut dir = self;
+		for (index, segment) in segments.iter().enumerate() {
+			dir = match dir.children.get(&segment.key) {
+				Some(MemoryChild::Dir(child)) => child,
+				Some(MemoryChild::Node(_stored)) => return Err(Missing::Node(index)),
+				None => return Err(Missing::Nothing(index)),
+			};
+		}
+		Ok(dir)
+	}
+
+	fn dir_mut(&mut self, segments: &[Segment]) -> Result<&mut MemoryDir, Missing> {
+		let mut dir = self;
+		for (index, segment) in segments.iter().enumerate() {
+			dir = match dir.children.get_mut(&segment.key) {
+				Some(MemoryChild::Dir(child)) => child,
+				Some(MemoryChild::Node(_stored)) => return Err(Missing::Node(index)),
+				None => return Err(Missing::Nothing(index)),
+			};
+		}
+		Ok(dir)
+	}
+
+	/// The directory at `segments`, making those missing along the way.  Fails with the index of
+	/// the segment that is a node instead.
+	fn make_dir(&mut self, segments: &[Segment], explicit: bool) -> Result<&mut MemoryDir, usize> {
+		let mut dir = self;
+		for (index, segment) in segments.iter().enumerate() {
+			let child = dir
+				.children
+				.entry(segment.key.clone())
+				.or_insert_with(|| MemoryChild::Dir(MemoryDir::new(&segment.name, explicit)));
+			dir = match child {
+				MemoryChild::Dir(child) => {
+					child.explicit |= explicit;
+					child
+				}
+				MemoryChild::Node(_stored) => return Err(index),
+			};
+		}
+		Ok(dir)
+	}
+
+	fn child(&self, segments: &[Segment]) -> Option<&MemoryChild> {
+		let (last, parents) = segments.split_last()?;
+		self.dir(parents).ok()?.children.get(&last.key)
+	}
+
+	/// Takes out what is at `segments`, along with the implied directories left empty above it.
+	fn remove(&mut self, segments: &[Segment]) -> Option<MemoryChild> {
+		let (first, rest) = segments.split_first()?;
+		if rest.is_empty() {
+			return self.children.remove(&first.key);
+		}
+		let removed = match self.children.get_mut(&first.key)? {
+			MemoryChild::Dir(dir) => dir.remove(rest)?,
+			MemoryChild::Node(_stored) => return None,
+		};
+		if let Some(MemoryChild::Dir(dir)) = self.children.get(&first.key) {
+			if !dir.explicit && dir.children.is_empty() {
+				self.children.remove(&first.key);
+			}
+		}
+		Some(removed)
+	}
+
+	/// The names of the directories along `segments`, for urls naming them as they were created.
+	fn names<'s>(&'s self, segments: &[Segment]) -> Vec<&'s str> {
+		segments
+			.iter()
+			.scan(self, |dir, segment| match dir.children.get(&segment.key) {
+				Some(MemoryChild::Dir(child)) => {
+					*dir = child;
+					Some(child.name.as_str())
+				}
+				_ => None,
+			})
+			.collect()
+	}
+}
+
+impl MemoryChild {
+	fn name(&self) -> &str {
+		match self {
+			MemoryChild::Node(stored) => &stored.name,
+			MemoryChild::Dir(dir) => &dir.name,
+		}
+	}
+
+	fn metadata(&self) -> NodeMetadata {
+		match self {
+			MemoryChild::Node(stored) => stored.entry.read().expect("poisoned lock").metadata(),
+			MemoryChild::Dir(_dir) => dir_metadata(),
+		}
+	}
+
+	/// Drops the data of every node, so nodes still open on them see them emptied.
+	fn clear(self) {
+		match self {
+			MemoryChild::Node(stored) => {
+				let mut entry = stored.entry.write().expect("poisoned lock");
+				entry.data.clear();
+				entry.data.shrink_to_fit();
+			}
+			MemoryChild::Dir(dir) => dir.children.into_values().for_each(MemoryChild::clear),
+		}
+	}
+}
+
+/// A `watch` of a memory scheme, by the keys of the path it watches.
+struct MemoryWatch {
+	keys: Vec<String>,
+	sender: async_channel::Sender<WatchEvent>,
+}
+
+#[derive(Default)]
+struct Watchers(Mutex<Vec<MemoryWatch>>);
+
+impl Watchers {
+	/// Tells the watches of `segments`, or of a directory above it, that `url` changed, and forgets
+	/// the watches whose streams were dropped.
+	fn notify(&self, segments: &[Segment], url: &Url, kind: WatchEventKind) {
+		let mut url = url.clone();
+		url.set_query(None);
+		url.set_fragment(None);
+		let mut watches = self.0.lock().expect("poisoned lock");
+		watches.retain(|watch| {
+			let watched = watch.keys.len() <= segments.len()
+				&& watch
+					.keys
+					.iter()
+					.zip(segments)
+					.all(|(key, segment)| *key == segment.key);
+			if watched {
+				let event = WatchEvent {
+					url: url.clone(),
+					kind,
+				};
+				watch.sender.try_send(event).is_ok()
+			} else {
+				!watch.sender.is_closed()
+			}
+		});
+	}
+}
+
+/// Where a writable node reports that it was written to, once flushed, closed, or dropped.
+struct NodeChanges {
+	watchers: Arc<Watchers>,
+	segments: Vec<Segment>,
+	url: Url,
+	written: bool,
+}
+
+impl NodeChanges {
+	fn report(&mut self) {
+		if std::mem::take(&mut self.written) {
+			self.watchers
+				.notify(&self.segments, &self.url, WatchEventKind::Modified);
+		}
+	}
+
+	fn unwritten(&self) -> Self {
+		Self {
+			watchers: self.watchers.clone(),
+			segments: self.segments.clone(),
+			url: self.url.clone(),
+			written: false,
+		}
+	}
+}
+
+impl Drop for NodeChanges {
+	fn drop(&mut self) {
+		self.report();
+	}
+}
+
+/// The path of the first `index + 1` segments, for errors about a directory along the way.
+fn path_to(segments: &[Segment], index: usize) -> Cow<'static, str> {
+	let names: Vec<&str> = segments[..=index]
+		.iter()
+		.map(|segment| segment.name.as_str())
+		.collect();
+	Cow::Owned(format!("/{}", names.join("/")))
+}
+
+type KeyNormalizer = Box<dyn Fn(&str) -> String + Send + Sync>;
+
+/// Stores nodes in a tree of directories in memory.  Directories that `create_parents` makes above
+/// a created node are implied, and go away again with the last node below them unless they were
+/// made with `create_dir`.
 #[derive(Default)]
 pub struct MemoryScheme {
-	storage: DashMap<PathBuf, Arc<RwLock<Vec<u8>>>>,
+	root: RwLock<MemoryDir>,
+	normalizer: Option<KeyNormalizer>,
+	watchers: Arc<Watchers>,
 }
 
 impl MemoryScheme {
 	pub fn new() -> Self {
 		Self::default()
 	}
+
+	/// Passes every url path through `normalizer` before it is used as a storage key, such as to
+	/// lowercase paths or collapse duplicate slashes, so all paths normalizing to the same key
+	/// address the same node.  `read_dir` lists nodes by the path they were created with.
+	pub fn with_key_normalizer<F>(normalizer: F) -> Self
+	where
+		F: Fn(&str) -> String + Send + Sync + 'static,
+	{
+		Self {
+			root: RwLock::default(),
+			normalizer: Some(Box::new(normalizer)),
+			watchers: Arc::default(),
+		}
+	}
+
+	/// The segments of `path` along with their keys, empty segments such as of a trailing `/` are
+	/// skipped.
+	fn segments(&self, path: &str) -> Vec<Segment> {
+		let names: Vec<&str> = path.split('/').filter(|name| !name.is_empty()).collect();
+		let normalized = self.normalizer.as_ref().map(|normalizer| normalizer(path));
+		let keys: Vec<&str> = match &normalized {
+			Some(normalized) => normalized
+				.split('/')
+				.filter(|key| !key.is_empty())
+				.collect(),
+			None => names.clone(),
+		};
+		// A normalizer changing how many segments there are leaves only the keys to name them by
+		let names = if keys.len() == names.len() {
+			names
+		} else {
+			keys.clone()
+		};
+		keys.into_iter()
+			.zip(names)
+			.map(|(key, name)| Segment {
+				key: key.to_owned(),
+				name: name.to_owned(),
+			})
+			.collect()
+	}
+
+	fn node_metadata<'a>(&self, url: &'a Url) -> Result<NodeMetadata, SchemeError<'a>> {
+		let segments = self.segments(url.path());
+		if segments.is_empty() {
+			return Ok(dir_metadata());
+		}
+		match self.root.read().expect("poisoned lock").child(&segments) {
+			Some(child) => Ok(child.metadata()),
+			None => Err(SchemeError::NodeDoesNotExist(Cow::Borrowed(url.path()))),
+		}
+	}
+
+	/// Stores a new node at `segments`, or hands back the one stored there meanwhile.
+	fn insert_node<'a>(
+		&self,
+		url: &'a Url,
+		segments: &[Segment],
+		create_new: bool,
+		create_parents: bool,
+	) -> Result<Arc<RwLock<MemoryEntry>>, SchemeError<'a>> {
+		let (last, parents) = segments
+			.split_last()
+			.ok_or(SchemeError::IsADirectory(Cow::Borrowed(url.path())))?;
+		let mut root = self.root.write().expect("poisoned lock");
+		let dir = if create_parents {
+			root.make_dir(parents, false)
+				.map_err(|index| SchemeError::NotADirectory(path_to(segments, index)))?
+		} else {
+			root.dir_mut(parents).map_err(|missing| match missing {
+				Missing::Node(index) => SchemeError::NotADirectory(path_to(segments, index)),
+				Missing::Nothing(index) => SchemeError::NodeDoesNotExist(path_to(segments, index)),
+			})?
+		};
+		match dir.children.entry(last.key.clone()) {
+			Entry::Vacant(vacant) => {
+				let entry = Arc::new(RwLock::new(MemoryEntry::new()));
+				vacant.insert(MemoryChild::Node(StoredEntry {
+					name: last.name.clone(),
+					entry: entry.clone(),
+				}));
+				self.watchers.notify(segments, url, WatchEventKind::Created);
+				Ok(entry)
+			}
+			Entry::Occupied(occupied) => match occupied.get() {
+				MemoryChild::Node(stored) if !create_new => Ok(stored.entry.clone()),
+				MemoryChild::Node(_stored) => {
+					Err(SchemeError::NodeAlreadyExists(Cow::Borrowed(url.path())))
+				}
+				MemoryChild::Dir(_dir) => Err(SchemeError::IsADirectory(Cow::Borrowed(url.path()))),
+			},
+		}
+	}
 }
 
 #[async_trait::async_trait]
@@ -30,57 +413,112 @@ impl Scheme for MemoryScheme {
 		url: &'a Url,
 		options: &NodeGetOptions,
 	) -> Result<PinnedNode, SchemeError<'a>> {
-		let path = Path::new(url.path());
-		let data = if let Some(data) = self.storage.get(path) {
+		let path = url.path();
+		let segments = self.segments(path);
+		let (last, parents) = match segments.split_last() {
+			Some(split) if !path.ends_with('/') => split,
+			_ => return Err(SchemeError::IsADirectory(Cow::Borrowed(path))),
+		};
+		let found = match self.root.read().expect("poisoned lock").dir(parents) {
+			Ok(dir) => match dir.children.get(&last.key) {
+				Some(MemoryChild::Node(stored)) => Some(stored.entry.clone()),
+				Some(MemoryChild::Dir(_dir)) => {
+					return Err(SchemeError::IsADirectory(Cow::Borrowed(path)))
+				}
+				None => None,
+			},
+			Err(Missing::Node(index)) => {
+				return Err(SchemeError::NotADirectory(path_to(&segments, index)))
+			}
+			Err(Missing::Nothing(_index)) => None,
+		};
+		let entry = if let Some(entry) = found {
 			if options.get_create_new() {
 				// Only create a new one, and it exists, so return
-				return Err(SchemeError::NodeAlreadyExists(Cow::Borrowed(url.path())));
+				return Err(SchemeError::NodeAlreadyExists(Cow::Borrowed(path)));
 			}
 			if options.get_truncate() {
-				data.write().expect("poisoned lock").clear();
+				let mut truncated = entry.write().expect("poisoned lock");
+				truncated.data.clear();
+				truncated.modified = clock::now();
+				drop(truncated);
+				self.watchers
+					.notify(&segments, url, WatchEventKind::Modified);
 			}
-			data.clone()
+			entry
 		} else {
 			if !options.get_create() {
 				// Don't create if missing
-				return Err(SchemeError::NodeDoesNotExist(Cow::Borrowed(url.path())));
+				return Err(SchemeError::NodeDoesNotExist(Cow::Borrowed(path)));
 			}
-			let data = Arc::new(RwLock::new(Vec::new()));
-			self.storage.insert(path.to_owned(), data.clone());
-			data
+			self.insert_node(
+				url,
+				&segments,
+				options.get_create_new(),
+				options.get_create_parents(),
+			)?
+		};
+
+		let entry = if options.get_snapshot() && !options.get_write() {
+			let shared = entry.read().expect("poisoned lock");
+			Arc::new(RwLock::new(MemoryEntry {
+				data: shared.data.clone(),
+				modified: shared.modified,
+				created: shared.created,
+			}))
+		} else {
+			entry
 		};
 
 		let cursor = if options.get_append() {
-			data.read().expect("poisoned lock").len()
+			entry.read().expect("poisoned lock").data.len()
 		} else {
 			0
 		};
+		let changes = options.get_write().then(|| NodeChanges {
+			watchers: self.watchers.clone(),
+			segments,
+			url: url.clone(),
+			written: false,
+		});
 		let node = MemoryNode {
-			data,
+			entry,
 			cursor,
 			read: options.get_read(),
 			write: options.get_write(),
+			changes,
 		};
 		Ok(Box::pin(node))
 	}
 
+	/// Removes a node or a directory, `force` removes directories that are not empty along with
+	/// everything in them, and empties what was removed for any nodes still open on it.
 	async fn remove_node<'a>(
 		&self,
 		_vfs: &Vfs,
 		url: &'a Url,
 		force: bool,
 	) -> Result<(), SchemeError<'a>> {
-		let path = Path::new(url.path());
-		if let Some((_path, data)) = self.storage.remove(path) {
-			if force {
-				let mut data = data.write().expect("poisoned lock");
-				data.clear();
-				data.shrink_to_fit();
+		let segments = self.segments(url.path());
+		if segments.is_empty() {
+			return Err("the root directory cannot be removed".into());
+		}
+		let mut root = self.root.write().expect("poisoned lock");
+		match root.child(&segments) {
+			None => return Err(SchemeError::NodeDoesNotExist(Cow::Borrowed(url.path()))),
+			Some(MemoryChild::Dir(dir)) if !force && !dir.children.is_empty() => {
+				return Err("directory is not empty".into())
 			}
-			Ok(())
-		} else {
-			return Err(SchemeError::NodeDoesNotExist(Cow::Borrowed(url.path())));
+			Some(_child) => {}
 		}
+		let removed = root.remove(&segments);
+		drop(root);
+		if let (true, Some(removed)) = (force, removed) {
+			removed.clear();
+		}
+		self.watchers
+			.notify(&segments, url, WatchEventKind::Removed);
+		Ok(())
 	}
 
 	async fn metadata<'a>(
@@ -88,81 +526,283 @@ impl Scheme for MemoryScheme {
 		_vfs: &Vfs,
 		url: &'a Url,
 	) -> Result<NodeMetadata, SchemeError<'a>> {
-		let path = Path::new(url.path());
-		if let Some(data) = self.storage.get(path) {
-			let size = data.read().expect("poisoned lock").len();
-			Ok(NodeMetadata {
-				is_node: true,
-				len: Some((size, Some(size))),
-			})
-		} else {
-			Err(SchemeError::NodeDoesNotExist(Cow::Borrowed(url.path())))
+		self.node_metadata(url)
+	}
+
+	/// Looks every url up in one go, without an await per url.
+	async fn metadata_many(
+		&self,
+		_vfs: &Vfs,
+		urls: &[Url],
+	) -> Vec<Result<NodeMetadata, SchemeError<'static>>> {
+		urls.iter()
+			.map(|url| self.node_metadata(url).map_err(SchemeError::into_owned))
+			.collect()
+	}
+
+	async fn set_modified<'a>(
+		&self,
+		_vfs: &Vfs,
+		url: &'a Url,
+		modified: SystemTime,
+	) -> Result<(), SchemeError<'a>> {
+		let segments = self.segments(url.path());
+		match self.root.read().expect("poisoned lock").child(&segments) {
+			Some(MemoryChild::Node(stored)) => {
+				stored.entry.write().expect("poisoned lock").modified = modified;
+				self.watchers
+					.notify(&segments, url, WatchEventKind::Modified);
+				Ok(())
+			}
+			_ => Err(SchemeError::NodeDoesNotExist(Cow::Borrowed(url.path()))),
 		}
 	}
 
-	async fn read_dir<'a>(
+	async fn create_dir<'a>(
 		&self,
 		_vfs: &Vfs,
 		url: &'a Url,
-	) -> Result<ReadDirStream, SchemeError<'a>> {
-		let mut path = url.path();
-		if !path.ends_with('/') {
-			if let Some(pos) = path.rfind('/') {
-				path = &path[..pos];
-			} else {
-				path = "/";
+		parents: bool,
+	) -> Result<(), SchemeError<'a>> {
+		let segments = self.segments(url.path());
+		let (last, above) = match segments.split_last() {
+			Some(split) => split,
+			None if parents => return Ok(()),
+			None => return Err(SchemeError::NodeAlreadyExists(Cow::Borrowed(url.path()))),
+		};
+		let mut root = self.root.write().expect("poisoned lock");
+		let dir = if parents {
+			root.make_dir(above, true)
+				.map_err(|index| SchemeError::NotADirectory(path_to(&segments, index)))?
+		} else {
+			root.dir_mut(above).map_err(|missing| match missing {
+				Missing::Nothing(index) => SchemeError::NodeDoesNotExist(path_to(&segments, index)),
+				Missing::Node(index) => SchemeError::NotADirectory(path_to(&segments, index)),
+			})?
+		};
+		match dir.children.entry(last.key.clone()) {
+			Entry::Vacant(vacant) => {
+				vacant.insert(MemoryChild::Dir(MemoryDir::new(&last.name, true)));
+				self.watchers
+					.notify(&segments, url, WatchEventKind::Created);
+				Ok(())
+			}
+			Entry::Occupied(mut occupied) => match occupied.get_mut() {
+				MemoryChild::Dir(existing) if parents => {
+					existing.explicit = true;
+					Ok(())
+				}
+				_ => Err(SchemeError::NodeAlreadyExists(Cow::Borrowed(url.path()))),
+			},
+		}
+	}
+
+	async fn copy_node<'a>(
+		&self,
+		_vfs: &Vfs,
+		from: &'a Url,
+		to: &'a Url,
+	) -> Result<(), SchemeError<'a>> {
+		let to_segments = self.segments(to.path());
+		let (to_last, to_parents) = to_segments
+			.split_last()
+			.ok_or(SchemeError::IsADirectory(Cow::Borrowed(to.path())))?;
+		// Cloned out first so the tree is only locked for writing to insert the copy
+		let data = match self
+			.root
+			.read()
+			.expect("poisoned lock")
+			.child(&self.segments(from.path()))
+		{
+			Some(MemoryChild::Node(stored)) => {
+				stored.entry.read().expect("poisoned lock").data.clone()
+			}
+			Some(MemoryChild::Dir(_dir)) => {
+				return Err(SchemeError::IsADirectory(Cow::Borrowed(from.path())))
+			}
+			None => return Err(SchemeError::NodeDoesNotExist(Cow::Borrowed(from.path()))),
+		};
+		let mut root = self.root.write().expect("poisoned lock");
+		let dir = root
+			.make_dir(to_parents, false)
+			.map_err(|index| SchemeError::NotADirectory(path_to(&to_segments, index)))?;
+		let kind = match dir.children.entry(to_last.key.clone()) {
+			// Replaced in place so nodes already open on the destination see the copy
+			Entry::Occupied(occupied) => match occupied.get() {
+				MemoryChild::Node(stored) => {
+					let mut entry = stored.entry.write().expect("poisoned lock");
+					entry.data = data;
+					entry.modified = clock::now();
+					WatchEventKind::Modified
+				}
+				MemoryChild::Dir(_dir) => {
+					return Err(SchemeError::IsADirectory(Cow::Borrowed(to.path())))
+				}
+			},
+			Entry::Vacant(vacant) => {
+				vacant.insert(MemoryChild::Node(StoredEntry {
+					name: to_last.name.clone(),
+					entry: Arc::new(RwLock::new(MemoryEntry {
+						data,
+						..MemoryEntry::new()
+					})),
+				}));
+				WatchEventKind::Created
+			}
+		};
+		self.watchers.notify(&to_segments, to, kind);
+		Ok(())
+	}
+
+	async fn rename_node<'a>(
+		&self,
+		_vfs: &Vfs,
+		from: &'a Url,
+		to: &'a Url,
+	) -> Result<(), SchemeError<'a>> {
+		let from_segments = self.segments(from.path());
+		let to_segments = self.segments(to.path());
+		let (to_last, to_parents) = to_segments
+			.split_last()
+			.ok_or(SchemeError::IsADirectory(Cow::Borrowed(to.path())))?;
+		let mut root = self.root.write().expect("poisoned lock");
+		// Checked before the node is taken out so a failed rename leaves it where it was
+		match root.dir(to_parents) {
+			Err(Missing::Node(index)) => {
+				return Err(SchemeError::NotADirectory(path_to(&to_segments, index)))
 			}
+			Ok(dir) if matches!(dir.children.get(&to_last.key), Some(MemoryChild::Dir(_))) => {
+				return Err(SchemeError::IsADirectory(Cow::Borrowed(to.path())))
+			}
+			_ => {}
 		}
-		// Yes, a clone, maybe make this more efficient in future, but it's probably fine anyway
-		// since the data itself is stored out-of-band in an Arc anyway, although the PathBuf's are
-		// probably the more expensive clone anyway, hrmm...  This for now anyway...
-		Ok(Box::pin(MemoryReadDir(
-			self.storage.clone().into_iter(),
-			Url::parse(&format!("{}:{}", url.scheme(), path))?,
-		)))
+		match root.child(&from_segments) {
+			Some(MemoryChild::Node(_stored)) => {}
+			Some(MemoryChild::Dir(_dir)) => {
+				return Err(SchemeError::IsADirectory(Cow::Borrowed(from.path())))
+			}
+			None => return Err(SchemeError::NodeDoesNotExist(Cow::Borrowed(from.path()))),
+		}
+		let entry = match root.remove(&from_segments) {
+			Some(MemoryChild::Node(stored)) => stored.entry,
+			_ => unreachable!("checked to be a node above"),
+		};
+		let dir = root
+			.make_dir(to_parents, false)
+			.map_err(|index| SchemeError::NotADirectory(path_to(&to_segments, index)))?;
+		let stored = StoredEntry {
+			name: to_last.name.clone(),
+			entry,
+		};
+		dir.children
+			.insert(to_last.key.clone(), MemoryChild::Node(stored));
+		self.watchers
+			.notify(&from_segments, from, WatchEventKind::Removed);
+		self.watchers
+			.notify(&to_segments, to, WatchEventKind::Created);
+		Ok(())
+	}
+
+	async fn read_dir<'s, 'a>(
+		&'s self,
+		_vfs: &'s Vfs,
+		url: &'a Url,
+	) -> Result<BorrowedReadDirStream<'s>, SchemeError<'a>> {
+		let path = url.path();
+		let segments = self.segments(path);
+		let root = self.root.read().expect("poisoned lock");
+		let dir = root.dir(&segments).map_err(|missing| match missing {
+			Missing::Node(index) if index + 1 == segments.len() => {
+				SchemeError::NotADirectory(Cow::Borrowed(path))
+			}
+			Missing::Node(index) => SchemeError::NotADirectory(path_to(&segments, index)),
+			Missing::Nothing(_index) => SchemeError::NodeDoesNotExist(Cow::Borrowed(path)),
+		})?;
+		// Listed by the names everything was created with, which a key normalizer can differ from
+		let names: String = root
+			.names(&segments)
+			.into_iter()
+			.map(|name| format!("{}/", name))
+			.collect();
+		let dir_url = Url::parse(&format!("{}:/{}", url.scheme(), names))?;
+		// Cloned out so the stream holds no lock and knows its exact length
+		let children: Vec<(String, NodeMetadata)> = dir
+			.children
+			.values()
+			.map(|child| (child.name().to_owned(), child.metadata()))
+			.collect();
+		Ok(Box::pin(MemoryReadDir(children.into_iter(), dir_url)))
+	}
+
+	/// Changes made through this scheme, including writes through its nodes, which are reported
+	/// once flushed, closed, or dropped.  The url does not have to exist yet.
+	async fn watch<'a>(&self, _vfs: &Vfs, url: &'a Url) -> Result<WatchStream, SchemeError<'a>> {
+		let keys = self
+			.segments(url.path())
+			.into_iter()
+			.map(|segment| segment.key)
+			.collect();
+		let (sender, receiver) = async_channel::unbounded();
+		self.watchers
+			.0
+			.lock()
+			.expect("poisoned lock")
+			.push(MemoryWatch { keys, sender });
+		Ok(Box::pin(receiver))
+	}
+
+	async fn get_node_split<'a>(
+		&self,
+		vfs: &Vfs,
+		url: &'a Url,
+		options: &NodeGetOptions,
+	) -> Result<(PinnedNode, PinnedNode), SchemeError<'a>> {
+		// Open the writer first so it can create the node the reader then opens
+		let writer = self
+			.get_node(vfs, url, &options.clone().read(false).write(true))
+			.await?;
+		let reader = self
+			.get_node(vfs, url, &NodeGetOptions::new().read(true))
+			.await?;
+		Ok((reader, writer))
 	}
 }
 
-struct MemoryReadDir(
-	dashmap::iter::OwningIter<PathBuf, Arc<RwLock<Vec<u8>>>>,
-	Url,
-);
+/// The names of the children of a directory with their metadata, and the url of the directory.
+struct MemoryReadDir(std::vec::IntoIter<(String, NodeMetadata)>, Url);
 
 impl Stream for MemoryReadDir {
 	type Item = NodeEntry;
 
 	fn poll_next(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
 		let this = self.get_mut();
-		let root_path = this.1.path();
-		loop {
-			if let Some((path, _data)) = this.0.next() {
-				let path = path
-					.to_str()
-					.expect("somehow a non-url-safe path was added to a Memory scheme");
-				// TODO:  Just return things in the current 'directory', probably want something better than a single dashmap
-				if path.starts_with(root_path) {
-					let mut url = this.1.clone();
-					url.set_path(path);
-					break Poll::Ready(Some(NodeEntry { url }));
-				} else {
-					continue;
-				}
+		for (name, metadata) in &mut this.0 {
+			// These are the already percent-encoded segments of the urls they were created with,
+			// directories are listed with their trailing `/`
+			let name = percent_decode_str(&name).decode_utf8_lossy();
+			let segments: &[&str] = if metadata.is_node {
+				&[&name]
 			} else {
-				break Poll::Ready(None);
+				&[&name, ""]
+			};
+			if let Some(entry) = NodeEntry::child(&this.1, segments) {
+				return Poll::Ready(Some(entry.with_metadata(metadata)));
 			}
 		}
+		Poll::Ready(None)
 	}
 
 	fn size_hint(&self) -> (usize, Option<usize>) {
-		todo!()
+		self.0.size_hint()
 	}
 }
 
 pub struct MemoryNode {
-	data: Arc<RwLock<Vec<u8>>>,
+	entry: Arc<RwLock<MemoryEntry>>,
 	cursor: usize,
 	read: bool,
 	write: bool,
+	changes: Option<NodeChanges>,
 }
 
 #[async_trait::async_trait]
@@ -178,6 +818,20 @@ impl Node for MemoryNode {
 	fn is_seeker(&self) -> bool {
 		self.read || self.write
 	}
+
+	fn is_at_end(&self) -> Option<bool> {
+		Some(self.cursor >= self.entry.read().expect("poisoned lock").data.len())
+	}
+
+	async fn try_clone(self: Pin<&mut Self>) -> Result<PinnedNode, SchemeError<'static>> {
+		Ok(Box::pin(MemoryNode {
+			entry: self.entry.clone(),
+			cursor: 0,
+			read: self.read,
+			write: self.write,
+			changes: self.changes.as_ref().map(NodeChanges::unwritten),
+		}))
+	}
 	// async fn read<'s>(&'s mut self) -> Option<&'s mut (dyn AsyncRead + Unpin)> {
 	// 	if self.read {
 	// 		Some(self)
@@ -212,20 +866,42 @@ impl AsyncRead for MemoryNode {
 		if !self.read {
 			return Poll::Ready(Err(std::io::Error::from_raw_os_error(13)));
 		}
-		let data = self.data.read().expect("poisoned lock");
+		// The length is checked and the bytes copied under the same read lock, so no writer can
+		// shrink the data in between.  The cursor may be past the end from before another node
+		// truncated it, that reads as EOF, as do writes at such a cursor append at the end.
+		let entry = self.entry.read().expect("poisoned lock");
+		let data = &entry.data;
 		if self.cursor >= data.len() {
 			return Poll::Ready(Ok(0));
 		}
 
 		let amt = std::cmp::min(data.len() - self.cursor, buf.len());
 		buf[..amt].copy_from_slice(&data[self.cursor..(self.cursor + amt)]);
-		drop(data); // Minimize the life of the lock
+		drop(entry); // Minimize the life of the lock
 		self.cursor += amt;
 
 		Poll::Ready(Ok(amt))
 	}
 }
 
+/// Writes `buf` into `data` at `cursor`, overwriting what is there and extending past the end as
+/// needed, returning the new cursor.  All of `buf` is always written, which is why the writes of
+/// `MemoryNode` never come up short and return the full length of what they were given.
+fn write_at(data: &mut Vec<u8>, cursor: usize, buf: &[u8]) -> usize {
+	if cursor >= data.len() {
+		data.extend_from_slice(buf);
+		data.len()
+	} else if cursor + buf.len() <= data.len() {
+		data[cursor..cursor + buf.len()].copy_from_slice(buf);
+		cursor + buf.len()
+	} else {
+		let (inside, outside) = buf.split_at(data.len() - cursor);
+		data[cursor..].copy_from_slice(inside);
+		data.extend_from_slice(outside);
+		data.len()
+	}
+}
+
 impl AsyncWrite for MemoryNode {
 	fn poll_write(
 		mut self: Pin<&mut Self>,
@@ -235,53 +911,58 @@ impl AsyncWrite for MemoryNode {
 		if !self.write {
 			return Poll::Ready(Err(std::io::Error::from_raw_os_error(13)));
 		}
-		let mut data = self.data.write().expect("poisoned lock");
-		if self.cursor >= data.len() {
-			data.extend_from_slice(buf);
-			let len = data.len();
-			drop(data);
-			self.cursor = len;
-		} else if self.cursor + buf.len() < data.len() {
-			data.as_mut_slice()[self.cursor..self.cursor + buf.len()].copy_from_slice(buf);
-			drop(data);
-			self.cursor += buf.len()
-		} else {
-			let at = buf.len() - ((self.cursor + buf.len()) - data.len());
-			let (inside, outside) = buf.split_at(at);
-			data.as_mut_slice()[self.cursor..].copy_from_slice(inside);
-			data.extend_from_slice(outside);
-			let len = data.len();
-			drop(data);
-			self.cursor = len;
+		let mut entry = self.entry.write().expect("poisoned lock");
+		let cursor = write_at(&mut entry.data, self.cursor, buf);
+		entry.modified = clock::now();
+		drop(entry); // Minimize the life of the lock
+		self.cursor = cursor;
+		if let Some(changes) = &mut self.changes {
+			changes.written = true;
 		}
 		Poll::Ready(Ok(buf.len()))
 	}
 
-	// fn poll_write_vectored(
-	// 	self: Pin<&mut Self>,
-	// 	_cx: &mut Context<'_>,
-	// 	bufs: &[IoSlice<'_>],
-	// ) -> Poll<std::io::Result<usize>> {
-	// 	let mut amt = 0;
-	// 	let mut data = self.data.write().expect("poisoned lock");
-	// 	for buf in bufs {
-	// 		amt += buf.len();
-	// 		data.extend_from_slice(&*buf);
-	// 	}
-	// 	Poll::Ready(Ok(amt))
-	// }
+	fn poll_write_vectored(
+		mut self: Pin<&mut Self>,
+		_cx: &mut Context<'_>,
+		bufs: &[IoSlice<'_>],
+	) -> Poll<std::io::Result<usize>> {
+		if !self.write {
+			return Poll::Ready(Err(std::io::Error::from_raw_os_error(13)));
+		}
+		let mut entry = self.entry.write().expect("poisoned lock");
+		let mut cursor = self.cursor;
+		let mut amt = 0;
+		for buf in bufs {
+			cursor = write_at(&mut entry.data, cursor, buf);
+			amt += buf.len();
+		}
+		entry.modified = clock::now();
+		drop(entry); // Minimize the life of the lock
+		self.cursor = cursor;
+		if let Some(changes) = &mut self.changes {
+			changes.written = true;
+		}
+		Poll::Ready(Ok(amt))
+	}
 
-	fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+	fn poll_flush(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
 		if !self.write {
 			return Poll::Ready(Err(std::io::Error::from_raw_os_error(13)));
 		}
+		if let Some(changes) = &mut self.changes {
+			changes.report();
+		}
 		Poll::Ready(Ok(()))
 	}
 
-	fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+	fn poll_close(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
 		if !self.write {
 			return Poll::Ready(Err(std::io::Error::from_raw_os_error(13)));
 		}
+		if let Some(changes) = &mut self.changes {
+			changes.report();
+		}
 		Poll::Ready(Ok(()))
 	}
 }
@@ -298,21 +979,23 @@ impl AsyncSeek for MemoryNode {
 		let this = self.get_mut();
 		match pos {
 			SeekFrom::Start(pos) => {
-				let data = this.data.read().expect("poisoned lock");
+				let entry = this.entry.read().expect("poisoned lock");
+				let data = &entry.data;
 				if pos > data.len() as u64 {
 					this.cursor = data.len();
 				} else {
-					drop(data); // Minimize the life of the lock
+					drop(entry); // Minimize the life of the lock
 					this.cursor = pos as usize;
 				}
 			}
 			SeekFrom::End(end_pos) => {
 				if end_pos > 0 {
-					this.cursor = this.data.read().expect("poisoned lock").len();
+					this.cursor = this.entry.read().expect("poisoned lock").data.len();
 				} else {
-					let data = this.data.read().expect("poisoned lock");
+					let entry = this.entry.read().expect("poisoned lock");
+					let data = &entry.data;
 					if (-end_pos) as usize > data.len() {
-						drop(data); // Minimize the life of the lock
+						drop(entry); // Minimize the life of the lock
 						this.cursor = 0;
 					} else {
 						this.cursor = data.len() - ((-end_pos) as usize);
@@ -324,11 +1007,12 @@ impl AsyncSeek for MemoryNode {
 				if new_cur < 0 {
 					this.cursor = 0;
 				} else {
-					let data = this.data.read().expect("poisoned lock");
+					let entry = this.entry.read().expect("poisoned lock");
+					let data = &entry.data;
 					if new_cur as usize > data.len() {
 						this.cursor = data.len();
 					} else {
-						drop(data); // Minimize the life of the lock
+						drop(entry); // Minimize the life of the lock
 						this.cursor = new_cur as usize;
 					}
 				}
@@ -341,19 +1025,71 @@ impl AsyncSeek for MemoryNode {
 #[cfg(test)]
 #[cfg(feature = "backend_tokio")]
 mod async_tokio_tests {
-	use crate::scheme::NodeGetOptions;
-	use crate::{MemoryScheme, Vfs};
+	use crate::scheme::{NodeGetOptions, NodeKind};
+	use crate::{MemoryScheme, SchemeError, Vfs, VfsError};
 	use futures_lite::io::SeekFrom;
 	use futures_lite::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt, StreamExt};
+	use std::io::IoSlice;
 	use url::Url;
 
 	fn u(s: &str) -> Url {
 		Url::parse(s).unwrap()
 	}
 
+	#[test]
+	fn concurrent_read_write_remove() {
+		use futures_lite::future::block_on;
+		use std::sync::Arc;
+		let vfs = Vfs::empty();
+		vfs.add_scheme("mem", MemoryScheme::default()).unwrap();
+		let vfs = Arc::new(vfs);
+		let create = NodeGetOptions::new().create(true).truncate(true);
+		let read = NodeGetOptions::new().read(true).write(true);
+		block_on(vfs.get_node_at("mem:/stress", &create)).unwrap();
+
+		let mut threads = Vec::new();
+		for worker in 0..6usize {
+			let vfs = vfs.clone();
+			let (create, read) = (create.clone(), read.clone());
+			threads.push(std::thread::spawn(move || {
+				block_on(async {
+					for i in 0..300usize {
+						match worker {
+							0 => {
+								let _ = vfs.remove_node_at("mem:/stress", true).await;
+								let _ = vfs.get_node_at("mem:/stress", &create).await;
+							}
+							1 | 2 => {
+								if let Ok(mut node) = vfs.get_node_at("mem:/stress", &create).await
+								{
+									let len = (i * 37 + worker) % 512;
+									node.write_all(&vec![worker as u8; len]).await.unwrap();
+								}
+							}
+							_ => {
+								// Nodes opened read-write can seek past what another truncates
+								if let Ok(mut node) = vfs.get_node_at("mem:/stress", &read).await {
+									node.seek(SeekFrom::Start((i % 7 * 64) as u64))
+										.await
+										.unwrap();
+									let mut buffer = Vec::new();
+									node.read_to_end(&mut buffer).await.unwrap();
+									node.write_all(b"tail").await.unwrap();
+								}
+							}
+						}
+					}
+				})
+			}));
+		}
+		for thread in threads {
+			thread.join().expect("a worker panicked");
+		}
+	}
+
 	#[tokio::test]
 	async fn node_reading() {
-		let mut vfs = Vfs::empty();
+		let vfs = Vfs::empty();
 		vfs.add_scheme("mem", MemoryScheme::default()).unwrap();
 		let mut node = vfs
 			.get_node_at(
@@ -369,7 +1105,7 @@ mod async_tokio_tests {
 
 	#[tokio::test]
 	async fn node_writing() {
-		let mut vfs = Vfs::empty();
+		let vfs = Vfs::empty();
 		vfs.add_scheme("mem", MemoryScheme::default()).unwrap();
 		let mut node = vfs
 			.get_node_at(
@@ -390,7 +1126,7 @@ mod async_tokio_tests {
 
 	#[tokio::test]
 	async fn node_stored() {
-		let mut vfs = Vfs::empty();
+		let vfs = Vfs::empty();
 		vfs.add_scheme("mem", MemoryScheme::default()).unwrap();
 		{
 			let mut node = vfs
@@ -422,7 +1158,7 @@ mod async_tokio_tests {
 
 	#[tokio::test]
 	async fn node_seeking() {
-		let mut vfs = Vfs::empty();
+		let vfs = Vfs::empty();
 		vfs.add_scheme("mem", MemoryScheme::default()).unwrap();
 		let mut node = vfs
 			.get_node(
@@ -446,7 +1182,7 @@ mod async_tokio_tests {
 	}
 	#[tokio::test]
 	async fn node_read_dir() {
-		let mut vfs = Vfs::empty();
+		let vfs = Vfs::empty();
 		vfs.add_scheme("mem", MemoryScheme::default()).unwrap();
 
 		async fn add_empty_entry(vfs: &Vfs, name: &str) {
@@ -463,11 +1199,560 @@ mod async_tokio_tests {
 		add_empty_entry(&vfs, "/test/blah0").await;
 		add_empty_entry(&vfs, "/test/blah1").await;
 
-		assert_eq!(vfs.read_dir_at("mem:/").await.unwrap().count().await, 5);
-		assert_eq!(vfs.read_dir_at("mem:/test").await.unwrap().count().await, 5);
+		let mut listed: Vec<String> = vfs
+			.read_dir_at("mem:/")
+			.await
+			.unwrap()
+			.map(|entry| entry.url.to_string())
+			.collect()
+			.await;
+		listed.sort();
+		assert_eq!(
+			listed,
+			["mem:/test/", "mem:/test0", "mem:/test1", "mem:/test2"],
+			"only the direct children"
+		);
+		assert_eq!(
+			vfs.read_dir_at("mem:/test/").await.unwrap().size_hint(),
+			(2, Some(2))
+		);
+		assert!(matches!(
+			vfs.read_dir_at("mem:/nothing/").await,
+			Err(VfsError::SchemeError(SchemeError::NodeDoesNotExist(_)))
+		));
+		assert_eq!(
+			vfs.read_dir_at("mem:/test").await.unwrap().count().await,
+			2,
+			"the directory itself rather than its parent"
+		);
+		assert!(matches!(
+			vfs.read_dir_at("mem:/test0").await,
+			Err(VfsError::SchemeError(SchemeError::NotADirectory(_)))
+		));
 		assert_eq!(
 			vfs.read_dir_at("mem:/test/").await.unwrap().count().await,
 			2
 		);
+
+		let mut entries = vfs.read_dir_at("mem:/test/").await.unwrap();
+		while entries.next().await.is_some() {}
+		assert!(entries.next().await.is_none(), "fused");
+		assert!(entries.next().await.is_none(), "fused");
+
+		assert!(vfs.remove_node_at("mem:/test", false).await.is_err());
+		vfs.remove_node_at("mem:/test/blah0", false).await.unwrap();
+		vfs.remove_node_at("mem:/test", true).await.unwrap();
+		assert!(vfs.metadata_at("mem:/test/blah1").await.is_err());
+		assert!(vfs.metadata_at("mem:/test").await.is_err());
+		add_empty_entry(&vfs, "/implied/node").await;
+		vfs.remove_node_at("mem:/implied/node", false)
+			.await
+			.unwrap();
+		assert!(
+			vfs.metadata_at("mem:/implied").await.is_err(),
+			"implied directories go away with the last node in them"
+		);
+	}
+
+	#[tokio::test]
+	async fn create_dir() {
+		let vfs = Vfs::empty();
+		vfs.add_scheme("mem", MemoryScheme::default()).unwrap();
+		vfs.create_dir_at("mem:/empty").await.unwrap();
+		assert!(!vfs.metadata_at("mem:/empty").await.unwrap().is_node);
+		assert!(matches!(
+			vfs.create_dir_at("mem:/empty").await,
+			Err(VfsError::SchemeError(SchemeError::NodeAlreadyExists(_)))
+		));
+		assert!(matches!(
+			vfs.create_dir_at("mem:/missing/dir").await,
+			Err(VfsError::SchemeError(SchemeError::NodeDoesNotExist(_)))
+		));
+		vfs.create_dir_all_at("mem:/deep/er/dir").await.unwrap();
+		vfs.create_dir_all_at("mem:/deep/er").await.unwrap();
+		assert!(!vfs.metadata_at("mem:/deep").await.unwrap().is_node);
+		assert!(matches!(
+			vfs.get_node_at("mem:/empty", &NodeGetOptions::new().create(true))
+				.await,
+			Err(VfsError::SchemeError(SchemeError::IsADirectory(_)))
+		));
+
+		let listed = |uri: &'static str| {
+			let vfs = &vfs;
+			async move {
+				let mut listed: Vec<String> = vfs
+					.read_dir_at(uri)
+					.await
+					.unwrap()
+					.map(|entry| entry.url.to_string())
+					.collect()
+					.await;
+				listed.sort();
+				listed
+			}
+		};
+		assert_eq!(listed("mem:/").await, ["mem:/deep/", "mem:/empty/"]);
+		assert_eq!(listed("mem:/deep/er").await, ["mem:/deep/er/dir/"]);
+		vfs.get_node_at("mem:/empty/node", &NodeGetOptions::new().create_new(true))
+			.await
+			.unwrap();
+		assert_eq!(listed("mem:/empty").await, ["mem:/empty/node"]);
+		assert!(vfs.remove_node_at("mem:/empty", false).await.is_err());
+		vfs.remove_node_at("mem:/empty/node", false).await.unwrap();
+		vfs.remove_node_at("mem:/empty", false).await.unwrap();
+		assert!(vfs.metadata_at("mem:/empty").await.is_err());
+	}
+
+	#[tokio::test]
+	async fn node_create_parents() {
+		let vfs = Vfs::empty();
+		vfs.add_scheme("mem", MemoryScheme::default()).unwrap();
+		let no_parents = NodeGetOptions::new().create_new(true).create_parents(false);
+		assert!(matches!(
+			vfs.get_node_at("mem:/dir/inner/node", &no_parents).await,
+			Err(VfsError::SchemeError(SchemeError::NodeDoesNotExist(path))) if path == "/dir"
+		));
+		assert!(vfs.metadata_at("mem:/dir").await.is_err());
+
+		vfs.create_dir_all_at("mem:/dir/inner").await.unwrap();
+		vfs.get_node_at("mem:/dir/inner/node", &no_parents)
+			.await
+			.unwrap();
+		assert!(matches!(
+			vfs.get_node_at("mem:/dir/inner/node/child", &no_parents)
+				.await,
+			Err(VfsError::SchemeError(SchemeError::NotADirectory(_)))
+		));
+		vfs.get_node_at("mem:/other/node", &NodeGetOptions::new().create_new(true))
+			.await
+			.unwrap();
+		assert!(!vfs.metadata_at("mem:/other").await.unwrap().is_node);
+	}
+
+	#[tokio::test]
+	async fn watch() {
+		use crate::scheme::{WatchEventKind, WatchStream};
+		let vfs = Vfs::empty();
+		vfs.add_scheme("mem", MemoryScheme::default()).unwrap();
+		let mut events = vfs.watch_at("mem:/dir").await.unwrap();
+		let mut node = vfs
+			.get_node_at("mem:/dir/a", &NodeGetOptions::new().create_new(true))
+			.await
+			.unwrap();
+		node.write_all(b"written").await.unwrap();
+		node.close().await.unwrap();
+		drop(node);
+		vfs.remove_node_at("mem:/dir/a", false).await.unwrap();
+		vfs.get_node_at("mem:/other", &NodeGetOptions::new().create_new(true))
+			.await
+			.unwrap();
+		vfs.create_dir_all_at("mem:/dir/b").await.unwrap();
+
+		async fn next(events: &mut WatchStream) -> (String, WatchEventKind) {
+			let event = events.next().await.unwrap();
+			(event.url.to_string(), event.kind)
+		}
+		assert_eq!(
+			next(&mut events).await,
+			("mem:/dir/a".to_owned(), WatchEventKind::Created)
+		);
+		assert_eq!(
+			next(&mut events).await,
+			("mem:/dir/a".to_owned(), WatchEventKind::Modified),
+			"reported once when closed"
+		);
+		assert_eq!(
+			next(&mut events).await,
+			("mem:/dir/a".to_owned(), WatchEventKind::Removed)
+		);
+		assert_eq!(
+			next(&mut events).await,
+			("mem:/dir/b".to_owned(), WatchEventKind::Created),
+			"nothing outside of the watched directory"
+		);
+	}
+
+	#[tokio::test]
+	async fn read_dir_encoded_names() {
+		let vfs = Vfs::empty();
+		vfs.add_scheme("mem", MemoryScheme::default()).unwrap();
+		let mut node = vfs
+			.get_node_at(
+				"mem:/dir/with space ü.txt",
+				&NodeGetOptions::new().create_new(true).write(true),
+			)
+			.await
+			.unwrap();
+		node.write_all(b"spaced").await.unwrap();
+		node.close().await.unwrap();
+		let entries: Vec<_> = vfs.read_dir_at("mem:/dir").await.unwrap().collect().await;
+		assert_eq!(entries.len(), 1);
+		let metadata = entries[0].metadata.as_ref().expect("listed with metadata");
+		assert_eq!(metadata.len, Some((6, Some(6))));
+		assert_eq!(metadata.kind, Some(NodeKind::File));
+		let url = Url::parse(entries[0].url.as_str()).unwrap();
+		assert_eq!(url.as_str(), "mem:/dir/with%20space%20%C3%BC.txt");
+		assert_eq!(vfs.read_to_vec(&url).await.unwrap(), b"spaced");
+	}
+
+	#[tokio::test]
+	async fn metadata_many() {
+		let vfs = Vfs::empty();
+		vfs.add_scheme("mem", MemoryScheme::default()).unwrap();
+		let mut node = vfs
+			.get_node_at("mem:/dir/a", &NodeGetOptions::new().create_new(true))
+			.await
+			.unwrap();
+		node.write_all(b"abc").await.unwrap();
+		let metadata = vfs
+			.metadata_many_at(&[
+				"mem:/dir/a",
+				"mem:/missing",
+				"mem:/dir",
+				"nope:/a",
+				"not a url",
+			])
+			.await;
+		assert_eq!(metadata.len(), 5);
+		assert_eq!(metadata[0].as_ref().unwrap().len, Some((3, Some(3))));
+		assert!(matches!(
+			metadata[1],
+			Err(VfsError::SchemeError(SchemeError::NodeDoesNotExist(_)))
+		));
+		assert!(!metadata[2].as_ref().unwrap().is_node);
+		assert!(matches!(metadata[3], Err(VfsError::SchemeNotFound(_))));
+		assert!(matches!(metadata[4], Err(VfsError::UrlParseFailed(_))));
+	}
+
+	#[tokio::test]
+	async fn node_is_at_end() {
+		let vfs = Vfs::empty();
+		vfs.add_scheme("mem", MemoryScheme::default()).unwrap();
+		let mut node = vfs
+			.get_node_at(
+				"mem:/test",
+				&NodeGetOptions::new()
+					.create_new(true)
+					.read(true)
+					.write(true),
+			)
+			.await
+			.unwrap();
+		assert_eq!(node.is_at_end(), Some(true), "empty");
+		node.write_all(b"test").await.unwrap();
+		node.seek(SeekFrom::Start(0)).await.unwrap();
+		assert_eq!(node.is_at_end(), Some(false));
+		let mut buffer = [0; 3];
+		node.read_exact(&mut buffer).await.unwrap();
+		assert_eq!(node.is_at_end(), Some(false));
+		let mut buffer = Vec::new();
+		node.read_to_end(&mut buffer).await.unwrap();
+		assert_eq!(buffer, b"t");
+		assert_eq!(node.is_at_end(), Some(true));
+	}
+
+	#[tokio::test]
+	async fn node_directories() {
+		let vfs = Vfs::empty();
+		vfs.add_scheme("mem", MemoryScheme::default()).unwrap();
+		vfs.get_node_at("mem:/test/blah", &NodeGetOptions::new().create_new(true))
+			.await
+			.unwrap();
+		let read = &NodeGetOptions::new().read(true);
+		for uri in ["mem:/", "mem:/test", "mem:/test/"] {
+			assert!(
+				matches!(
+					vfs.get_node_at(uri, read).await,
+					Err(VfsError::SchemeError(SchemeError::IsADirectory(_)))
+				),
+				"{}",
+				uri
+			);
+			assert!(!vfs.metadata_at(uri).await.unwrap().is_node, "{}", uri);
+		}
+		assert!(
+			matches!(
+				vfs.get_node_at("mem:/new/", &NodeGetOptions::new().create(true))
+					.await,
+				Err(VfsError::SchemeError(SchemeError::IsADirectory(_)))
+			),
+			"directories cannot be created as nodes"
+		);
+		assert!(vfs.metadata_at("mem:/test/blah").await.unwrap().is_node);
+	}
+
+	#[tokio::test]
+	async fn key_normalizer() {
+		let vfs = Vfs::empty();
+		vfs.add_scheme(
+			"mem",
+			MemoryScheme::with_key_normalizer(|path| path.to_lowercase()),
+		)
+		.unwrap();
+		vfs.get_node_at("mem:/Dir/File.txt", &NodeGetOptions::new().create_new(true))
+			.await
+			.unwrap()
+			.write_all(b"hello")
+			.await
+			.unwrap();
+		let mut buffer = String::new();
+		vfs.get_node_at("mem:/dir/FILE.TXT", &NodeGetOptions::new().read(true))
+			.await
+			.unwrap()
+			.read_to_string(&mut buffer)
+			.await
+			.unwrap();
+		assert_eq!(&buffer, "hello");
+		assert!(matches!(
+			vfs.get_node_at("mem:/DIR/file.txt", &NodeGetOptions::new().create_new(true))
+				.await,
+			Err(VfsError::SchemeError(SchemeError::NodeAlreadyExists(_)))
+		));
+		assert_eq!(
+			vfs.metadata_at("mem:/DIR/FILE.txt").await.unwrap().len,
+			Some((5, Some(5)))
+		);
+		assert!(!vfs.metadata_at("mem:/DIR").await.unwrap().is_node);
+
+		let listed: Vec<_> = vfs
+			.read_dir_at("mem:/DIR/")
+			.await
+			.unwrap()
+			.map(|entry| entry.url.path().to_owned())
+			.collect()
+			.await;
+		assert_eq!(listed, ["/Dir/File.txt"], "listed by the original path");
+
+		vfs.remove_node_at("mem:/dir/file.TXT", false)
+			.await
+			.unwrap();
+		assert!(vfs.metadata_at("mem:/Dir/File.txt").await.is_err());
+	}
+
+	#[tokio::test]
+	async fn fallback_scheme() {
+		let mut vfs = Vfs::empty();
+		assert!(matches!(
+			vfs.get_node_at("weird:/x", &NodeGetOptions::new().read(true))
+				.await,
+			Err(VfsError::SchemeNotFound(_))
+		));
+		vfs.set_fallback_scheme(Box::new(MemoryScheme::default()));
+		let mut node = vfs
+			.get_node_at("weird:/x", &NodeGetOptions::new().create_new(true))
+			.await
+			.unwrap();
+		node.write_all(b"fallback").await.unwrap();
+		drop(node);
+		let mut buffer = String::new();
+		vfs.get_node_at("weird:/x", &NodeGetOptions::new().read(true))
+			.await
+			.unwrap()
+			.read_to_string(&mut buffer)
+			.await
+			.unwrap();
+		assert_eq!(&buffer, "fallback");
+		assert!(vfs.get_scheme("weird").is_err());
+		assert!(vfs.remove_fallback_scheme().is_some());
+		assert!(vfs.metadata_at("weird:/x").await.is_err());
+	}
+
+	#[tokio::test]
+	async fn node_write_vectored() {
+		let vfs = Vfs::empty();
+		vfs.add_scheme("mem", MemoryScheme::default()).unwrap();
+		let mut node = vfs
+			.get_node_at(
+				"mem:test",
+				&NodeGetOptions::new().read(true).create_new(true),
+			)
+			.await
+			.unwrap();
+		let bufs = [
+			IoSlice::new(b"one"),
+			IoSlice::new(b"two"),
+			IoSlice::new(b"three"),
+		];
+		assert_eq!(node.write_vectored(&bufs).await.unwrap(), 11);
+		node.seek(SeekFrom::Start(0)).await.unwrap();
+		let mut buffer = String::new();
+		node.read_to_string(&mut buffer).await.unwrap();
+		assert_eq!(&buffer, "onetwothree");
+
+		// Straddles the current end, overwriting "three" then extending
+		node.seek(SeekFrom::Start(6)).await.unwrap();
+		let bufs = [IoSlice::new(b"THR"), IoSlice::new(b"EE!!!")];
+		assert_eq!(node.write_vectored(&bufs).await.unwrap(), 8);
+		node.seek(SeekFrom::Start(0)).await.unwrap();
+		buffer.clear();
+		node.read_to_string(&mut buffer).await.unwrap();
+		assert_eq!(&buffer, "onetwoTHREE!!!");
+	}
+
+	#[tokio::test]
+	async fn node_write_count() {
+		let vfs = Vfs::empty();
+		vfs.add_scheme("mem", MemoryScheme::default()).unwrap();
+		let mut node = vfs
+			.get_node_at(
+				"mem:test",
+				&NodeGetOptions::new().read(true).create_new(true),
+			)
+			.await
+			.unwrap();
+		assert_eq!(node.write(b"abcdef").await.unwrap(), 6);
+		// Overwrites "ef" then extends by four
+		node.seek(SeekFrom::Start(4)).await.unwrap();
+		let amt = node.write(b"EFGHIJ").await.unwrap();
+		assert_eq!(amt, 6);
+		assert_eq!(
+			vfs.metadata_at("mem:test").await.unwrap().len,
+			Some((10, Some(10)))
+		);
+		assert_eq!(
+			node.seek(SeekFrom::Current(0)).await.unwrap(),
+			4 + amt as u64
+		);
+		node.seek(SeekFrom::Start(0)).await.unwrap();
+		let mut buffer = String::new();
+		node.read_to_string(&mut buffer).await.unwrap();
+		assert_eq!(&buffer, "abcdEFGHIJ");
+	}
+
+	#[tokio::test]
+	async fn node_split() {
+		let vfs = Vfs::empty();
+		vfs.add_scheme("mem", MemoryScheme::default()).unwrap();
+		let (mut reader, mut writer) = vfs
+			.get_node_split_at("mem:/log", &NodeGetOptions::new().append(true).create(true))
+			.await
+			.unwrap();
+		assert!(reader.is_reader() && !reader.is_writer());
+		assert!(writer.is_writer() && !writer.is_reader());
+
+		let mut buffer = String::new();
+		writer.write_all(b"first ").await.unwrap();
+		reader.read_to_string(&mut buffer).await.unwrap();
+		assert_eq!(&buffer, "first ");
+		writer.write_all(b"second").await.unwrap();
+		buffer.clear();
+		reader.read_to_string(&mut buffer).await.unwrap();
+		assert_eq!(
+			&buffer, "second",
+			"the reader continues from its own cursor"
+		);
+
+		vfs.add_scheme("data", crate::DataLoaderScheme::default())
+			.unwrap();
+		assert!(matches!(
+			vfs.get_node_split_at("data:blah", &NodeGetOptions::new())
+				.await,
+			Err(VfsError::SchemeError(SchemeError::Unsupported(_)))
+		));
+	}
+
+	#[tokio::test]
+	async fn node_snapshot() {
+		let vfs = Vfs::empty();
+		vfs.add_scheme("mem", MemoryScheme::default()).unwrap();
+		let mut writer = vfs
+			.get_node_at("mem:/snapshot", &NodeGetOptions::new().create_new(true))
+			.await
+			.unwrap();
+		writer.write_all(b"original").await.unwrap();
+		let read = NodeGetOptions::new().read(true);
+		let mut snapshot = vfs
+			.get_node_at("mem:/snapshot", &read.clone().snapshot(true))
+			.await
+			.unwrap();
+		let mut live = vfs.get_node_at("mem:/snapshot", &read).await.unwrap();
+
+		let mut buffer = [0; 4];
+		snapshot.read_exact(&mut buffer).await.unwrap();
+		assert_eq!(&buffer, b"orig");
+		writer.seek(SeekFrom::Start(0)).await.unwrap();
+		writer.write_all(b"replaced").await.unwrap();
+		let mut rest = String::new();
+		snapshot.read_to_string(&mut rest).await.unwrap();
+		assert_eq!(&rest, "inal");
+		rest.clear();
+		live.read_to_string(&mut rest).await.unwrap();
+		assert_eq!(&rest, "replaced");
+	}
+
+	#[tokio::test]
+	async fn node_modified() {
+		let vfs = Vfs::empty();
+		vfs.add_scheme("mem", MemoryScheme::default()).unwrap();
+		let mut node = vfs
+			.get_node_at("mem:/test", &NodeGetOptions::new().create_new(true))
+			.await
+			.unwrap();
+		let created = vfs
+			.metadata_at("mem:/test")
+			.await
+			.unwrap()
+			.modified
+			.unwrap();
+		std::thread::sleep(std::time::Duration::from_millis(10));
+		node.write_all(b"test").await.unwrap();
+		node.as_mut().sync_all().await.unwrap();
+		let written = vfs
+			.metadata_at("mem:/test")
+			.await
+			.unwrap()
+			.modified
+			.unwrap();
+		assert!(
+			written > created,
+			"writing through the node bumps the modified time"
+		);
+		let metadata = vfs.metadata_at("mem:/test").await.unwrap();
+		assert_eq!(metadata.created, Some(created), "but not the creation time");
+		assert_eq!(metadata.kind, Some(NodeKind::File));
+		assert_eq!(metadata.read_only, Some(false));
+		let metadata = vfs.metadata_at("mem:/").await.unwrap();
+		assert_eq!(metadata.modified, None);
+		assert_eq!(metadata.kind, Some(NodeKind::Directory));
+	}
+}
+
+#[cfg(test)]
+#[cfg(target_arch = "wasm32")]
+mod wasm_tests {
+	use crate::scheme::NodeGetOptions;
+	use crate::{MemoryScheme, Vfs};
+	use futures_lite::{AsyncWriteExt, StreamExt};
+	use std::time::UNIX_EPOCH;
+	use wasm_bindgen_test::wasm_bindgen_test;
+
+	#[wasm_bindgen_test]
+	async fn memory_scheme() {
+		let vfs = Vfs::default();
+		vfs.add_scheme("mem", MemoryScheme::new()).unwrap();
+		let mut node = vfs
+			.get_node_at("mem:/dir/node", &NodeGetOptions::new().create_new(true))
+			.await
+			.unwrap();
+		node.write_all(b"in the browser").await.unwrap();
+		node.close().await.unwrap();
+
+		assert_eq!(
+			vfs.read_to_vec_at("mem:/dir/node").await.unwrap(),
+			b"in the browser"
+		);
+		assert_eq!(vfs.read_to_vec_at("data:,data").await.unwrap(), b"data");
+		let modified = vfs.metadata_at("mem:/dir/node").await.unwrap().modified;
+		assert!(modified.unwrap() > UNIX_EPOCH);
+		let listed: Vec<String> = vfs
+			.read_dir_at("mem:/dir/")
+			.await
+			.unwrap()
+			.map(|entry| entry.url.to_string())
+			.collect()
+			.await;
+		assert_eq!(listed, ["mem:/dir/node"]);
+		vfs.remove_node_at("mem:/dir/node", true).await.unwrap();
+		assert!(vfs.metadata_at("mem:/dir/node").await.is_err());
 	}
 }
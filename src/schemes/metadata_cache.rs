@@ -0,0 +1,301 @@
+use crate::clock::Instant;
+use crate::scheme::{BorrowedReadDirStream, NodeGetOptions, NodeMetadata, WatchStream};
+use crate::{PinnedNode, Scheme, SchemeError, Vfs};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+use url::Url;
+
+/// How long a `MetadataCacheScheme` made with `MetadataCacheScheme::new` trusts a cached result.
+pub const METADATA_CACHE_DEFAULT_TTL: Duration = Duration::from_secs(1);
+
+/// Wraps a scheme and remembers its successful `metadata` results for a short time, so repeated
+/// lookups of the same url, such as from `walk_dir` or a UI, don't reach the wrapped scheme.
+/// Opening a url for writing, removing it, or setting its modification time forgets it, but
+/// changes made behind the back of the vfs or through a node that is still open are only seen
+/// once the result expires.  Everything else passes through, only metadata is cached.
+pub struct MetadataCacheScheme {
+	scheme: Box<dyn Scheme>,
+	ttl: Duration,
+	cache: Mutex<HashMap<Url, (Instant, NodeMetadata)>>,
+}
+
+impl MetadataCacheScheme {
+	pub fn new(scheme: impl Scheme) -> Self {
+		Self::new_boxed(Box::new(scheme), METADATA_CACHE_DEFAULT_TTL)
+	}
+
+	pub fn with_ttl(scheme: impl Scheme, ttl: Duration) -> Self {
+		Self::new_boxed(Box::new(scheme), ttl)
+	}
+
+	pub fn new_boxed(scheme: Box<dyn Scheme>, ttl: Duration) -> Self {
+		Self {
+			scheme,
+			ttl,
+			cache: Mutex::default(),
+		}
+	}
+
+	pub fn inner(&self) -> &dyn Scheme {
+		&*self.scheme
+	}
+
+	pub fn into_inner(self) -> Box<dyn Scheme> {
+		self.scheme
+	}
+
+	pub fn ttl(&self) -> Duration {
+		self.ttl
+	}
+
+	/// Forgets the cached metadata of `url` and, as it may be a directory, of everything below it.
+	pub fn invalidate(&self, url: &Url) {
+		let below = crate::walk::dir_url(url);
+		self.cache
+			.lock()
+			.expect("poisoned lock")
+			.retain(|cached, _| cached != url && !cached.as_str().starts_with(below.as_str()));
+	}
+
+	pub fn clear(&self) {
+		self.cache.lock().expect("poisoned lock").clear();
+	}
+
+	fn cached(&self, url: &Url) -> Option<NodeMetadata> {
+		let mut cache = self.cache.lock().expect("poisoned lock");
+		match cache.get(url) {
+			Some((at, metadata)) if at.elapsed() < self.ttl => Some(metadata.clone()),
+			Some(_expired) => {
+				cache.remove(url);
+				None
+			}
+			None => None,
+		}
+	}
+
+	fn remember(&self, url: &Url, metadata: &NodeMetadata) {
+		self.cache
+			.lock()
+			.expect("poisoned lock")
+			.insert(url.clone(), (Instant::now(), metadata.clone()));
+	}
+}
+
+#[async_trait::async_trait]
+impl Scheme for MetadataCacheScheme {
+	async fn get_node<'a>(
+		&self,
+		vfs: &Vfs,
+		url: &'a Url,
+		options: &NodeGetOptions,
+	) -> Result<PinnedNode, SchemeError<'a>> {
+		if options.get_write() {
+			self.invalidate(url);
+		}
+		self.scheme.get_node(vfs, url, options).await
+	}
+
+	async fn remove_node<'a>(
+		&self,
+		vfs: &Vfs,
+		url: &'a Url,
+		force: bool,
+	) -> Result<(), SchemeError<'a>> {
+		// Forgotten even on failure, as a forced removal may have removed part of a directory
+		self.invalidate(url);
+		self.scheme.remove_node(vfs, url, force).await
+	}
+
+	async fn metadata<'a>(&self, vfs: &Vfs, url: &'a Url) -> Result<NodeMetadata, SchemeError<'a>> {
+		if let Some(metadata) = self.cached(url) {
+			return Ok(metadata);
+		}
+		let metadata = self.scheme.metadata(vfs, url).await?;
+		self.remember(url, &metadata);
+		Ok(metadata)
+	}
+
+	async fn read_dir<'s, 'a>(
+		&'s self,
+		vfs: &'s Vfs,
+		url: &'a Url,
+	) -> Result<BorrowedReadDirStream<'s>, SchemeError<'a>> {
+		self.scheme.read_dir(vfs, url).await
+	}
+
+	async fn read_small_file<'a>(
+		&self,
+		vfs: &Vfs,
+		url: &'a Url,
+		max_len: usize,
+	) -> Result<Option<Vec<u8>>, SchemeError<'a>> {
+		self.scheme.read_small_file(vfs, url, max_len).await
+	}
+
+	#[cfg(feature = "bytes")]
+	async fn read_bytes<'a>(
+		&self,
+		vfs: &Vfs,
+		url: &'a Url,
+	) -> Result<Option<bytes::Bytes>, SchemeError<'a>> {
+		self.scheme.read_bytes(vfs, url).await
+	}
+
+	async fn stat_and_open<'a>(
+		&self,
+		vfs: &Vfs,
+		url: &'a Url,
+		options: &NodeGetOptions,
+	) -> Result<(NodeMetadata, PinnedNode), SchemeError<'a>> {
+		if options.get_write() {
+			self.invalidate(url);
+			return self.scheme.stat_and_open(vfs, url, options).await;
+		}
+		let (metadata, node) = self.scheme.stat_and_open(vfs, url, options).await?;
+		self.remember(url, &metadata);
+		Ok((metadata, node))
+	}
+
+	async fn get_node_with_metadata<'a>(
+		&self,
+		vfs: &Vfs,
+		url: &'a Url,
+		options: &NodeGetOptions,
+		metadata: &NodeMetadata,
+	) -> Result<PinnedNode, SchemeError<'a>> {
+		if options.get_write() {
+			self.invalidate(url);
+		}
+		self.scheme
+			.get_node_with_metadata(vfs, url, options, metadata)
+			.await
+	}
+
+	async fn canonicalize<'a>(&self, vfs: &Vfs, url: &'a Url) -> Result<Url, SchemeError<'a>> {
+		self.scheme.canonicalize(vfs, url).await
+	}
+
+	async fn set_modified<'a>(
+		&self,
+		vfs: &Vfs,
+		url: &'a Url,
+		modified: SystemTime,
+	) -> Result<(), SchemeError<'a>> {
+		self.invalidate(url);
+		self.scheme.set_modified(vfs, url, modified).await
+	}
+
+	async fn create_dir<'a>(
+		&self,
+		vfs: &Vfs,
+		url: &'a Url,
+		parents: bool,
+	) -> Result<(), SchemeError<'a>> {
+		self.invalidate(url);
+		self.scheme.create_dir(vfs, url, parents).await
+	}
+
+	async fn copy_node<'a>(
+		&self,
+		vfs: &Vfs,
+		from: &'a Url,
+		to: &'a Url,
+	) -> Result<(), SchemeError<'a>> {
+		self.invalidate(to);
+		self.scheme.copy_node(vfs, from, to).await
+	}
+
+	async fn rename_node<'a>(
+		&self,
+		vfs: &Vfs,
+		from: &'a Url,
+		to: &'a Url,
+	) -> Result<(), SchemeError<'a>> {
+		self.invalidate(from);
+		self.invalidate(to);
+		self.scheme.rename_node(vfs, from, to).await
+	}
+
+	async fn get_node_split<'a>(
+		&self,
+		vfs: &Vfs,
+		url: &'a Url,
+		options: &NodeGetOptions,
+	) -> Result<(PinnedNode, PinnedNode), SchemeError<'a>> {
+		if options.get_write() {
+			self.invalidate(url);
+		}
+		self.scheme.get_node_split(vfs, url, options).await
+	}
+
+	async fn watch<'a>(&self, vfs: &Vfs, url: &'a Url) -> Result<WatchStream, SchemeError<'a>> {
+		self.scheme.watch(vfs, url).await
+	}
+}
+
+#[cfg(test)]
+#[cfg(feature = "backend_tokio")]
+mod async_tokio_tests {
+	use crate::scheme::{NodeGetOptions, NodeMetadata};
+	use crate::{FnScheme, MetadataCacheScheme, Vfs};
+	use std::sync::atomic::{AtomicUsize, Ordering};
+	use std::sync::Arc;
+	use std::time::Duration;
+
+	fn counting_scheme(calls: Arc<AtomicUsize>) -> FnScheme {
+		FnScheme::new()
+			.on_get_node(|_vfs, _url, _options| Box::pin(async { Err("no nodes".into()) }))
+			.on_metadata(move |_vfs, _url| {
+				let calls = calls.fetch_add(1, Ordering::SeqCst) + 1;
+				Box::pin(async move {
+					Ok(NodeMetadata {
+						is_node: true,
+						len: Some((calls, Some(calls))),
+						modified: None,
+						..Default::default()
+					})
+				})
+			})
+	}
+
+	#[tokio::test]
+	async fn metadata_cached() {
+		let calls = Arc::new(AtomicUsize::new(0));
+		let vfs = Vfs::empty();
+		vfs.add_scheme(
+			"counted",
+			MetadataCacheScheme::with_ttl(counting_scheme(calls.clone()), Duration::from_secs(60)),
+		)
+		.unwrap();
+		let first = vfs.metadata_at("counted:/node").await.unwrap();
+		let second = vfs.metadata_at("counted:/node").await.unwrap();
+		assert_eq!(calls.load(Ordering::SeqCst), 1);
+		assert_eq!(first.len, second.len);
+
+		vfs.metadata_at("counted:/other").await.unwrap();
+		assert_eq!(calls.load(Ordering::SeqCst), 2, "cached per url");
+
+		let _ = vfs
+			.get_node_at("counted:/node", &NodeGetOptions::new().write(true))
+			.await;
+		vfs.metadata_at("counted:/node").await.unwrap();
+		assert_eq!(calls.load(Ordering::SeqCst), 3, "forgotten on write");
+		vfs.metadata_at("counted:/node").await.unwrap();
+		assert_eq!(calls.load(Ordering::SeqCst), 3);
+	}
+
+	#[tokio::test]
+	async fn metadata_expires() {
+		let calls = Arc::new(AtomicUsize::new(0));
+		let vfs = Vfs::empty();
+		vfs.add_scheme(
+			"counted",
+			MetadataCacheScheme::with_ttl(counting_scheme(calls.clone()), Duration::ZERO),
+		)
+		.unwrap();
+		vfs.metadata_at("counted:/node").await.unwrap();
+		vfs.metadata_at("counted:/node").await.unwrap();
+		assert_eq!(calls.load(Ordering::SeqCst), 2);
+	}
+}
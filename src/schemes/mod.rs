@@ -1,20 +1,119 @@
+pub mod asset_container;
+pub mod buffer;
+pub mod caching;
+pub mod config;
 pub mod data_loader;
 #[cfg(feature = "embedded")]
 pub mod embedded;
+#[cfg(feature = "encryption")]
+pub mod encrypted;
+pub mod env;
+#[cfg(all(feature = "fetch", target_arch = "wasm32"))]
+pub mod fetch;
 pub mod filesystem;
+pub mod fn_scheme;
+#[cfg(feature = "ftp")]
+pub mod ftp;
+#[cfg(feature = "git")]
+pub mod git;
+#[cfg(feature = "http")]
+pub mod http;
+pub mod indexing;
+#[cfg(all(any(feature = "fetch", feature = "opfs"), target_arch = "wasm32"))]
+mod js;
+#[cfg(feature = "kv_redb")]
+pub mod kv_redb;
+#[cfg(feature = "kv_sled")]
+pub mod kv_sled;
+pub mod map_err;
 #[cfg(feature = "in_memory")]
 pub mod memory;
+pub mod metadata_cache;
+pub mod nested_vfs;
+#[cfg(all(feature = "opfs", target_arch = "wasm32"))]
+pub mod opfs;
 pub mod overlay;
+pub mod pipe;
+#[cfg(feature = "process_tokio")]
+pub mod process_tokio;
+pub mod quota;
+pub mod recording;
+pub mod resolver;
+pub mod sequence;
+#[cfg(feature = "sftp")]
+pub mod sftp;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+pub mod static_route;
+pub mod subdir;
 pub mod symlink;
+#[cfg(feature = "archive_tar")]
+pub mod tar_archive;
+pub mod tee;
+pub mod template;
+pub mod traced;
+#[cfg(feature = "webdav")]
+pub mod webdav;
+#[cfg(feature = "archive_zip")]
+pub mod zip_archive;
 
 pub mod prelude {
 	use super::*;
+	pub use asset_container::*;
+	pub use buffer::*;
+	pub use caching::*;
+	pub use config::*;
 	pub use data_loader::*;
 	#[cfg(feature = "embedded")]
 	pub use embedded::*;
+	#[cfg(feature = "encryption")]
+	pub use encrypted::*;
+	pub use env::*;
+	#[cfg(all(feature = "fetch", target_arch = "wasm32"))]
+	pub use fetch::*;
+	#[allow(unused_imports)] // Empty when no filesystem backend is enabled
 	pub use filesystem::prelude::*;
+	pub use fn_scheme::*;
+	#[cfg(feature = "ftp")]
+	pub use ftp::*;
+	#[cfg(feature = "git")]
+	pub use git::*;
+	#[cfg(feature = "http")]
+	pub use http::*;
+	pub use indexing::*;
+	#[cfg(feature = "kv_redb")]
+	pub use kv_redb::*;
+	#[cfg(feature = "kv_sled")]
+	pub use kv_sled::*;
+	pub use map_err::*;
 	#[cfg(feature = "in_memory")]
 	pub use memory::*;
+	pub use metadata_cache::*;
+	pub use nested_vfs::*;
+	#[cfg(all(feature = "opfs", target_arch = "wasm32"))]
+	pub use opfs::*;
 	pub use overlay::*;
+	pub use pipe::*;
+	#[cfg(feature = "process_tokio")]
+	pub use process_tokio::*;
+	pub use quota::*;
+	pub use recording::*;
+	pub use resolver::*;
+	pub use sequence::*;
+	#[cfg(feature = "sftp")]
+	pub use sftp::*;
+	#[cfg(feature = "sqlite")]
+	pub use sqlite::*;
+	pub use static_route::*;
+	pub use subdir::*;
 	pub use symlink::*;
+	#[cfg(feature = "archive_tar")]
+	pub use tar_archive::*;
+	pub use tee::*;
+	pub use template::*;
+	pub use traced::*;
+	#[cfg(feature = "webdav")]
+	pub use webdav::*;
+	#[cfg(feature = "archive_zip")]
+	pub use zip_archive::*;
 }
@@ -0,0 +1,266 @@
+use crate::scheme::{
+	BorrowedReadDirStream, NodeEntry, NodeGetOptions, NodeMetadata, WatchEvent, WatchStream,
+};
+use crate::{PinnedNode, Scheme, SchemeError, Vfs};
+use futures_lite::StreamExt;
+use std::borrow::Cow;
+use std::sync::Arc;
+use std::time::SystemTime;
+use url::Url;
+
+/// Mounts a whole, already configured, `Vfs` under a single scheme name of another, such as to
+/// hand a plugin one scheme that bundles several.  The first path segment names the scheme of the
+/// inner vfs and the rest is the path within it, so `outer:/mem/dir/node` is `mem:/dir/node` of
+/// the inner vfs.  The root of the mount is a directory listing the inner scheme names.  Urls
+/// handed back, such as by `read_dir`, are translated to the outer form.
+pub struct NestedVfsScheme {
+	vfs: Arc<Vfs>,
+}
+
+impl NestedVfsScheme {
+	pub fn new(vfs: impl Into<Arc<Vfs>>) -> Self {
+		Self { vfs: vfs.into() }
+	}
+
+	pub fn vfs(&self) -> &Arc<Vfs> {
+		&self.vfs
+	}
+
+	/// The url of the inner vfs that `url` names, `None` for the root of the mount.
+	pub fn inner_url<'a>(&self, url: &'a Url) -> Result<Option<Url>, SchemeError<'a>> {
+		let path = url.path().trim_start_matches('/');
+		let (scheme, path) = path.split_once('/').unwrap_or((path, ""));
+		if scheme.is_empty() {
+			return Ok(None);
+		}
+		let mut inner = Url::parse(&format!("{}:/{}", scheme, path))
+			.map_err(|_error| SchemeError::UrlAccessError(Cow::Borrowed(url)))?;
+		inner.set_query(url.query());
+		Ok(Some(inner))
+	}
+
+	/// The url under the mount named `scheme` that names `inner`, a url of the inner vfs.
+	pub fn outer_url(scheme: &str, inner: &Url) -> Option<Url> {
+		let mut outer =
+			Url::parse(&format!("{}:/{}{}", scheme, inner.scheme(), inner.path())).ok()?;
+		outer.set_query(inner.query());
+		Some(outer)
+	}
+
+	fn mounted<'a>(&self, url: &'a Url) -> Result<Url, SchemeError<'a>> {
+		self.inner_url(url)?
+			.ok_or(SchemeError::IsADirectory(Cow::Borrowed(url.path())))
+	}
+}
+
+#[async_trait::async_trait]
+impl Scheme for NestedVfsScheme {
+	async fn get_node<'a>(
+		&self,
+		_vfs: &Vfs,
+		url: &'a Url,
+		options: &NodeGetOptions,
+	) -> Result<PinnedNode, SchemeError<'a>> {
+		let inner = self.mounted(url)?;
+		Ok(self.vfs.get_node(&inner, options).await?)
+	}
+
+	async fn remove_node<'a>(
+		&self,
+		_vfs: &Vfs,
+		url: &'a Url,
+		force: bool,
+	) -> Result<(), SchemeError<'a>> {
+		let inner = self.mounted(url)?;
+		Ok(self.vfs.remove_node(&inner, force).await?)
+	}
+
+	async fn metadata<'a>(
+		&self,
+		_vfs: &Vfs,
+		url: &'a Url,
+	) -> Result<NodeMetadata, SchemeError<'a>> {
+		match self.inner_url(url)? {
+			Some(inner) => Ok(self.vfs.metadata(&inner).await?),
+			None => Ok(NodeMetadata {
+				is_node: false,
+				len: None,
+				modified: None,
+				..Default::default()
+			}),
+		}
+	}
+
+	async fn read_dir<'s, 'a>(
+		&'s self,
+		_vfs: &'s Vfs,
+		url: &'a Url,
+	) -> Result<BorrowedReadDirStream<'s>, SchemeError<'a>> {
+		let scheme = url.scheme().to_owned();
+		match self.inner_url(url)? {
+			Some(inner) => {
+				let entries = self.vfs.read_dir(&inner).await?;
+				Ok(Box::pin(entries.filter_map(move |entry| {
+					Self::outer_url(&scheme, &entry.url).map(|url| NodeEntry {
+						url,
+						metadata: entry.metadata,
+					})
+				})))
+			}
+			None => {
+				let mut names: Vec<String> = self.vfs.scheme_names().collect();
+				names.sort_unstable();
+				let entries: Vec<NodeEntry> = names
+					.into_iter()
+					.filter_map(|name| Url::parse(&format!("{}:/{}/", scheme, name)).ok())
+					.map(NodeEntry::new)
+					.collect();
+				Ok(Box::pin(futures_lite::stream::iter(entries)))
+			}
+		}
+	}
+
+	async fn stat_and_open<'a>(
+		&self,
+		_vfs: &Vfs,
+		url: &'a Url,
+		options: &NodeGetOptions,
+	) -> Result<(NodeMetadata, PinnedNode), SchemeError<'a>> {
+		let inner = self.mounted(url)?;
+		Ok(self.vfs.stat_and_open(&inner, options).await?)
+	}
+
+	async fn get_node_with_metadata<'a>(
+		&self,
+		_vfs: &Vfs,
+		url: &'a Url,
+		options: &NodeGetOptions,
+		metadata: &NodeMetadata,
+	) -> Result<PinnedNode, SchemeError<'a>> {
+		let inner = self.mounted(url)?;
+		Ok(self
+			.vfs
+			.open_with_metadata(&inner, options, metadata)
+			.await?)
+	}
+
+	async fn canonicalize<'a>(&self, _vfs: &Vfs, url: &'a Url) -> Result<Url, SchemeError<'a>> {
+		let inner = match self.inner_url(url)? {
+			Some(inner) => inner,
+			None => return Ok(url.clone()),
+		};
+		let canonical = self.vfs.canonicalize(&inner).await?;
+		Self::outer_url(url.scheme(), &canonical)
+			.ok_or(SchemeError::UrlAccessError(Cow::Borrowed(url)))
+	}
+
+	async fn set_modified<'a>(
+		&self,
+		_vfs: &Vfs,
+		url: &'a Url,
+		modified: SystemTime,
+	) -> Result<(), SchemeError<'a>> {
+		let inner = self.mounted(url)?;
+		Ok(self.vfs.set_modified(&inner, modified).await?)
+	}
+
+	async fn create_dir<'a>(
+		&self,
+		_vfs: &Vfs,
+		url: &'a Url,
+		parents: bool,
+	) -> Result<(), SchemeError<'a>> {
+		let inner = match self.inner_url(url)? {
+			Some(inner) => inner,
+			None if parents => return Ok(()),
+			None => return Err(SchemeError::NodeAlreadyExists(Cow::Borrowed(url.path()))),
+		};
+		if parents {
+			Ok(self.vfs.create_dir_all(&inner).await?)
+		} else {
+			Ok(self.vfs.create_dir(&inner).await?)
+		}
+	}
+
+	async fn get_node_split<'a>(
+		&self,
+		_vfs: &Vfs,
+		url: &'a Url,
+		options: &NodeGetOptions,
+	) -> Result<(PinnedNode, PinnedNode), SchemeError<'a>> {
+		let inner = self.mounted(url)?;
+		Ok(self.vfs.get_node_split(&inner, options).await?)
+	}
+
+	async fn watch<'a>(&self, _vfs: &Vfs, url: &'a Url) -> Result<WatchStream, SchemeError<'a>> {
+		let inner = self.mounted(url)?;
+		let scheme = url.scheme().to_owned();
+		let events = self.vfs.watch(&inner).await?;
+		Ok(Box::pin(events.filter_map(move |event| {
+			Self::outer_url(&scheme, &event.url).map(|url| WatchEvent {
+				url,
+				kind: event.kind,
+			})
+		})))
+	}
+}
+
+#[cfg(test)]
+#[cfg(feature = "backend_tokio")]
+#[cfg(feature = "in_memory")]
+mod async_tokio_tests {
+	use crate::scheme::NodeGetOptions;
+	use crate::{MemoryScheme, NestedVfsScheme, SchemeError, Vfs, VfsError};
+	use futures_lite::{AsyncWriteExt, StreamExt};
+
+	#[tokio::test]
+	async fn nested_memory() {
+		let inner = Vfs::empty();
+		inner.add_scheme("mem", MemoryScheme::default()).unwrap();
+		let vfs = Vfs::empty();
+		vfs.add_scheme("plugin", NestedVfsScheme::new(inner))
+			.unwrap();
+
+		let mut node = vfs
+			.get_node_at(
+				"plugin:/mem/dir/node",
+				&NodeGetOptions::new().create_new(true),
+			)
+			.await
+			.unwrap();
+		node.write_all(b"nested").await.unwrap();
+		node.close().await.unwrap();
+		let nested = vfs.get_scheme_as::<NestedVfsScheme>("plugin").unwrap();
+		assert_eq!(
+			nested.vfs().read_to_vec_at("mem:/dir/node").await.unwrap(),
+			b"nested"
+		);
+		assert_eq!(
+			vfs.read_to_vec_at("plugin:/mem/dir/node").await.unwrap(),
+			b"nested"
+		);
+
+		let listed = |uri: &'static str| {
+			let vfs = &vfs;
+			async move {
+				vfs.read_dir_at(uri)
+					.await
+					.unwrap()
+					.map(|entry| entry.url.to_string())
+					.collect::<Vec<_>>()
+					.await
+			}
+		};
+		assert_eq!(listed("plugin:/").await, ["plugin:/mem/"]);
+		assert_eq!(listed("plugin:/mem/dir").await, ["plugin:/mem/dir/node"]);
+		assert!(matches!(
+			vfs.get_node_at("plugin:/", &NodeGetOptions::new().read(true))
+				.await,
+			Err(VfsError::SchemeError(SchemeError::IsADirectory(_)))
+		));
+		assert!(matches!(
+			vfs.metadata_at("plugin:/none/node").await,
+			Err(VfsError::SchemeError(SchemeError::GenericError(..)))
+		));
+	}
+}
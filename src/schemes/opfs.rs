@@ -0,0 +1,597 @@
+use crate::node::poll_io_err;
+use crate::scheme::{BorrowedReadDirStream, NodeEntry, NodeGetOptions, NodeKind, NodeMetadata};
+use crate::schemes::js::{js_message, JsSend};
+use crate::{Node, PinnedNode, Scheme, SchemeError, Vfs};
+use futures_lite::{ready, AsyncRead, AsyncSeek, AsyncWrite, Future};
+use js_sys::{IteratorNext, Promise, Uint8Array};
+use percent_encoding::percent_decode_str;
+use std::borrow::Cow;
+use std::io::SeekFrom;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{Duration, UNIX_EPOCH};
+use url::Url;
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{
+	DomException, File, FileSystemDirectoryHandle, FileSystemFileHandle,
+	FileSystemGetDirectoryOptions, FileSystemGetFileOptions, FileSystemHandle,
+	FileSystemHandleKind, FileSystemRemoveOptions, FileSystemWritableFileStream, Window,
+	WorkerGlobalScope,
+};
+
+type WriteBack = JsSend<Pin<Box<dyn Future<Output = Result<(), JsValue>>>>>;
+
+/// Stores nodes as files in the Origin Private File System of the browser, the storage each origin
+/// gets from `navigator.storage.getDirectory()`, so a browser build has a writable mount that
+/// outlives the page like a `FilesystemScheme` does natively, such as for saves and settings.
+/// Directories are real directories, made with `create_dir` and listed with `read_dir`, and urls
+/// need a path, `opfs:/saves/slot1.sav`, whose segments are percent-decoded into the names of the
+/// entries.  Nodes are read into memory when opened and written back when flushed or closed, each
+/// write-back replacing the whole file once the browser commits it, so a page closed mid-write
+/// leaves the previous content rather than a partial one.
+#[derive(Clone, Default)]
+pub struct OpfsScheme {
+	root: Vec<String>,
+}
+
+impl OpfsScheme {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Keeps every node in the directory `path` of the origin's storage instead of its top level,
+	/// such as `/game/saves` to share the origin with other storage; it is made when first needed.
+	pub fn with_root(path: &str) -> Self {
+		Self {
+			root: path
+				.split('/')
+				.filter(|name| !name.is_empty())
+				.map(str::to_owned)
+				.collect(),
+		}
+	}
+
+	/// The names of the entries from the storage root down to what `url` names.
+	fn names<'a>(&self, url: &'a Url) -> Result<Vec<String>, SchemeError<'a>> {
+		if url.cannot_be_a_base() {
+			return Err(SchemeError::UrlAccessError(Cow::Borrowed(url)));
+		}
+		let mut names = self.root.clone();
+		for segment in url.path().split('/').filter(|segment| !segment.is_empty()) {
+			let name = percent_decode_str(segment).decode_utf8_lossy();
+			if name == "." || name == ".." || name.contains(['/', '\\']) {
+				return Err(SchemeError::UrlAccessError(Cow::Borrowed(url)));
+			}
+			names.push(name.into_owned());
+		}
+		Ok(names)
+	}
+
+	/// The names of the directory `url` is in and the name of its entry, `None` for the root.
+	fn split<'a>(&self, url: &'a Url) -> Result<Option<(Vec<String>, String)>, SchemeError<'a>> {
+		let mut names = self.names(url)?;
+		if names.len() == self.root.len() {
+			return Ok(None);
+		}
+		let name = names.pop().expect("more names than the root has");
+		Ok(Some((names, name)))
+	}
+}
+
+async fn call(promise: Promise) -> Result<JsValue, JsValue> {
+	JsFuture::from(promise).await
+}
+
+/// The `navigator.storage` of the window, or of the worker when not run in a window.
+async fn storage_root() -> Result<FileSystemDirectoryHandle, JsValue> {
+	let global = js_sys::global();
+	let storage = if let Some(window) = global.dyn_ref::<Window>() {
+		window.navigator().storage()
+	} else if let Some(worker) = global.dyn_ref::<WorkerGlobalScope>() {
+		worker.navigator().storage()
+	} else {
+		return Err(JsValue::from_str(
+			"the origin private file system outside of a window or worker",
+		));
+	};
+	Ok(call(storage.get_directory()).await?.unchecked_into())
+}
+
+/// Walks down `names` from the storage root, making the first `made` of them if they are missing,
+/// such as the root given to `with_root`, while like a native filesystem the rest has to exist.
+async fn open_dir(names: &[String], made: usize) -> Result<FileSystemDirectoryHandle, JsValue> {
+	let mut dir = storage_root().await?;
+	for (index, name) in names.iter().enumerate() {
+		let options = FileSystemGetDirectoryOptions::new();
+		options.set_create(index < made);
+		dir = call(dir.get_directory_handle_with_options(name, &options))
+			.await?
+			.unchecked_into();
+	}
+	Ok(dir)
+}
+
+async fn open_file(
+	dir: &FileSystemDirectoryHandle,
+	name: &str,
+	create: bool,
+) -> Result<FileSystemFileHandle, JsValue> {
+	let options = FileSystemGetFileOptions::new();
+	options.set_create(create);
+	Ok(call(dir.get_file_handle_with_options(name, &options))
+		.await?
+		.unchecked_into())
+}
+
+async fn read_file(handle: &FileSystemFileHandle) -> Result<Vec<u8>, JsValue> {
+	let file: File = call(handle.get_file()).await?.unchecked_into();
+	Ok(Uint8Array::new(&call(file.array_buffer()).await?).to_vec())
+}
+
+/// Replaces the content of the file, which the browser only commits once the stream is closed.
+async fn write_file(handle: FileSystemFileHandle, data: Vec<u8>) -> Result<(), JsValue> {
+	let stream: FileSystemWritableFileStream =
+		call(handle.create_writable()).await?.unchecked_into();
+	if let Err(error) = call(stream.write_with_u8_array(&data)?).await {
+		let _ = call(stream.abort()).await;
+		return Err(error);
+	}
+	call(stream.close()).await?;
+	Ok(())
+}
+
+fn error_name(error: &JsValue) -> Option<String> {
+	error
+		.dyn_ref::<DomException>()
+		.map(|exception| exception.name())
+}
+
+/// Maps what the storage threw for `url`, `mismatch` being what an entry of the wrong kind means.
+fn opfs_error<'a>(
+	url: &'a Url,
+	mismatch: fn(Cow<'a, str>) -> SchemeError<'a>,
+) -> impl Fn(JsValue) -> SchemeError<'a> {
+	move |error| match error_name(&error).as_deref() {
+		Some("NotFoundError") => SchemeError::NodeDoesNotExist(Cow::Borrowed(url.path())),
+		Some("TypeMismatchError") => mismatch(Cow::Borrowed(url.path())),
+		Some("InvalidModificationError") => "directory is not empty".into(),
+		Some("QuotaExceededError") => SchemeError::QuotaExceeded(Cow::Borrowed(url.path())),
+		Some("NotAllowedError") | Some("SecurityError") => {
+			SchemeError::UrlAccessError(Cow::Borrowed(url))
+		}
+		_ => SchemeError::GenericError(
+			Some("origin private file system operation failed"),
+			Some(js_message(&error).into()),
+		),
+	}
+}
+
+fn io_error(error: JsValue) -> std::io::Error {
+	std::io::Error::other(js_message(&error))
+}
+
+fn file_metadata(file: &File) -> NodeMetadata {
+	let len = file.size() as usize;
+	NodeMetadata {
+		is_node: true,
+		len: Some((len, Some(len))),
+		modified: Some(UNIX_EPOCH + Duration::from_millis(file.last_modified() as u64)),
+		kind: Some(NodeKind::File),
+		..Default::default()
+	}
+}
+
+fn dir_metadata() -> NodeMetadata {
+	NodeMetadata {
+		is_node: false,
+		kind: Some(NodeKind::Directory),
+		..Default::default()
+	}
+}
+
+#[async_trait::async_trait]
+impl Scheme for OpfsScheme {
+	async fn get_node<'a>(
+		&self,
+		_vfs: &Vfs,
+		url: &'a Url,
+		options: &NodeGetOptions,
+	) -> Result<PinnedNode, SchemeError<'a>> {
+		let (dir, name) = self
+			.split(url)?
+			.ok_or(SchemeError::IsADirectory(Cow::Borrowed(url.path())))?;
+		let root_len = self.root.len();
+		let (handle, data) = JsSend(Box::pin(async move {
+			let not_a_directory = opfs_error(url, SchemeError::NotADirectory);
+			let is_a_directory = opfs_error(url, SchemeError::IsADirectory);
+			let dir = open_dir(&dir, root_len).await.map_err(not_a_directory)?;
+			match open_file(&dir, &name, false).await {
+				Ok(_handle) if options.get_create_new() => {
+					Err(SchemeError::NodeAlreadyExists(Cow::Borrowed(url.path())))
+				}
+				Ok(handle) if options.get_truncate() => {
+					write_file(handle.clone(), Vec::new())
+						.await
+						.map_err(&is_a_directory)?;
+					Ok((handle, Vec::new()))
+				}
+				Ok(handle) => {
+					let data = read_file(&handle).await.map_err(&is_a_directory)?;
+					Ok((handle, data))
+				}
+				Err(error)
+					if error_name(&error).as_deref() == Some("NotFoundError")
+						&& (options.get_create() || options.get_create_new()) =>
+				{
+					let handle = open_file(&dir, &name, true)
+						.await
+						.map_err(&is_a_directory)?;
+					Ok((handle, Vec::new()))
+				}
+				Err(error) => Err(is_a_directory(error)),
+			}
+		}))
+		.await?;
+		let cursor = if options.get_append() { data.len() } else { 0 };
+		Ok(Box::pin(OpfsNode {
+			handle: JsSend(handle),
+			data,
+			cursor,
+			read: options.get_read(),
+			write: options.get_write(),
+			dirty: false,
+			writing: None,
+		}))
+	}
+
+	/// Removes a node or a directory, `force` removes directories that are not empty along with
+	/// everything in them.  Nodes still open on what was removed write it back again when flushed.
+	async fn remove_node<'a>(
+		&self,
+		_vfs: &Vfs,
+		url: &'a Url,
+		force: bool,
+	) -> Result<(), SchemeError<'a>> {
+		let (dir, name) = self
+			.split(url)?
+			.ok_or("the root directory cannot be removed")?;
+		JsSend(Box::pin(async move {
+			let dir = open_dir(&dir, 0)
+				.await
+				.map_err(opfs_error(url, SchemeError::NotADirectory))?;
+			let options = FileSystemRemoveOptions::new();
+			options.set_recursive(force);
+			call(dir.remove_entry_with_options(&name, &options))
+				.await
+				.map_err(opfs_error(url, SchemeError::NotADirectory))?;
+			Ok(())
+		}))
+		.await
+	}
+
+	async fn metadata<'a>(
+		&self,
+		_vfs: &Vfs,
+		url: &'a Url,
+	) -> Result<NodeMetadata, SchemeError<'a>> {
+		let (dir, name) = match self.split(url)? {
+			Some(split) => split,
+			None => return Ok(dir_metadata()),
+		};
+		JsSend(Box::pin(async move {
+			let not_a_directory = opfs_error(url, SchemeError::NotADirectory);
+			let dir = open_dir(&dir, 0).await.map_err(&not_a_directory)?;
+			match open_file(&dir, &name, false).await {
+				Ok(handle) => {
+					let file: File = call(handle.get_file())
+						.await
+						.map_err(&not_a_directory)?
+						.unchecked_into();
+					Ok(file_metadata(&file))
+				}
+				// Then it is a directory, or nothing at all
+				Err(error) if error_name(&error).as_deref() == Some("TypeMismatchError") => {
+					let options = FileSystemGetDirectoryOptions::new();
+					call(dir.get_directory_handle_with_options(&name, &options))
+						.await
+						.map_err(&not_a_directory)?;
+					Ok(dir_metadata())
+				}
+				Err(error) => Err(not_a_directory(error)),
+			}
+		}))
+		.await
+	}
+
+	async fn read_dir<'s, 'a>(
+		&'s self,
+		_vfs: &'s Vfs,
+		url: &'a Url,
+	) -> Result<BorrowedReadDirStream<'s>, SchemeError<'a>> {
+		let names = self.names(url)?;
+		let root_len = self.root.len();
+		let children = JsSend(Box::pin(async move {
+			let not_a_directory = opfs_error(url, SchemeError::NotADirectory);
+			// The root given to `with_root` lists as empty until something was stored in it
+			let dir = open_dir(&names, root_len).await.map_err(&not_a_directory)?;
+			let handles = dir.values();
+			let mut children = Vec::new();
+			loop {
+				let next: IteratorNext = call(handles.next().map_err(&not_a_directory)?)
+					.await
+					.map_err(&not_a_directory)?
+					.unchecked_into();
+				if next.done() {
+					break;
+				}
+				let handle: FileSystemHandle = next.value().unchecked_into();
+				let metadata = if handle.kind() == FileSystemHandleKind::File {
+					let handle: FileSystemFileHandle = handle.clone().unchecked_into();
+					let file: File = call(handle.get_file())
+						.await
+						.map_err(&not_a_directory)?
+						.unchecked_into();
+					file_metadata(&file)
+				} else {
+					dir_metadata()
+				};
+				children.push((handle.name(), metadata));
+			}
+			Ok::<_, SchemeError<'a>>(children)
+		}))
+		.await?;
+		let mut children = children;
+		children.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+		let entries: Vec<NodeEntry> = children
+			.into_iter()
+			.map(|(name, metadata)| {
+				let mut url = url.clone();
+				url.path_segments_mut()
+					.expect("urls without a path were refused")
+					.pop_if_empty()
+					.push(&name);
+				NodeEntry::new(url).with_metadata(metadata)
+			})
+			.collect();
+		Ok(Box::pin(futures_lite::stream::iter(entries)))
+	}
+
+	async fn create_dir<'a>(
+		&self,
+		_vfs: &Vfs,
+		url: &'a Url,
+		parents: bool,
+	) -> Result<(), SchemeError<'a>> {
+		let (dir, name) = match self.split(url)? {
+			Some(split) => split,
+			None if parents => return Ok(()),
+			None => return Err(SchemeError::NodeAlreadyExists(Cow::Borrowed(url.path()))),
+		};
+		let root_len = self.root.len();
+		JsSend(Box::pin(async move {
+			let not_a_directory = opfs_error(url, SchemeError::NotADirectory);
+			let made = if parents { dir.len() } else { root_len };
+			let dir = open_dir(&dir, made).await.map_err(&not_a_directory)?;
+			let existing = FileSystemGetDirectoryOptions::new();
+			match call(dir.get_directory_handle_with_options(&name, &existing)).await {
+				Ok(_existing) if parents => return Ok(()),
+				Ok(_existing) => {
+					return Err(SchemeError::NodeAlreadyExists(Cow::Borrowed(url.path())))
+				}
+				Err(error) if error_name(&error).as_deref() == Some("TypeMismatchError") => {
+					return Err(SchemeError::NodeAlreadyExists(Cow::Borrowed(url.path())))
+				}
+				Err(_missing) => (),
+			}
+			let create = FileSystemGetDirectoryOptions::new();
+			create.set_create(true);
+			call(dir.get_directory_handle_with_options(&name, &create))
+				.await
+				.map_err(&not_a_directory)?;
+			Ok(())
+		}))
+		.await
+	}
+}
+
+pub struct OpfsNode {
+	handle: JsSend<FileSystemFileHandle>,
+	data: Vec<u8>,
+	cursor: usize,
+	read: bool,
+	write: bool,
+	/// Whether `data` has changes that have not been written back yet.
+	dirty: bool,
+	/// The write-back in progress, a flush or close waits for it.
+	writing: Option<WriteBack>,
+}
+
+impl OpfsNode {
+	fn write_back(&self) -> WriteBack {
+		JsSend(Box::pin(write_file(
+			self.handle.0.clone(),
+			self.data.clone(),
+		)))
+	}
+
+	/// Writes `data` back until nothing changed since the last write-back.
+	fn poll_write_back(&mut self, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+		loop {
+			if let Some(writing) = &mut self.writing {
+				let result = ready!(Pin::new(writing).poll(cx));
+				self.writing = None;
+				result.map_err(io_error)?;
+			} else if self.dirty {
+				self.writing = Some(self.write_back());
+				self.dirty = false;
+			} else {
+				return Poll::Ready(Ok(()));
+			}
+		}
+	}
+}
+
+impl Drop for OpfsNode {
+	fn drop(&mut self) {
+		// Best effort, close the node to find out whether the write-back failed
+		let writing = self.writing.take();
+		let pending = self.dirty.then(|| self.write_back());
+		if writing.is_some() || pending.is_some() {
+			wasm_bindgen_futures::spawn_local(async move {
+				if let Some(writing) = writing {
+					let _ = writing.await;
+				}
+				if let Some(pending) = pending {
+					let _ = pending.await;
+				}
+			});
+		}
+	}
+}
+
+#[async_trait::async_trait]
+impl Node for OpfsNode {
+	fn is_reader(&self) -> bool {
+		self.read
+	}
+
+	fn is_writer(&self) -> bool {
+		self.write
+	}
+
+	fn is_seeker(&self) -> bool {
+		self.read || self.write
+	}
+
+	fn known_len(&self) -> Option<u64> {
+		Some(self.data.len() as u64)
+	}
+
+	fn is_at_end(&self) -> Option<bool> {
+		Some(self.cursor >= self.data.len())
+	}
+}
+
+impl AsyncRead for OpfsNode {
+	fn poll_read(
+		mut self: Pin<&mut Self>,
+		_cx: &mut Context<'_>,
+		buf: &mut [u8],
+	) -> Poll<std::io::Result<usize>> {
+		if !self.read {
+			return poll_io_err();
+		}
+		let remaining = self.data.get(self.cursor..).unwrap_or(&[]);
+		let amt = remaining.len().min(buf.len());
+		buf[..amt].copy_from_slice(&remaining[..amt]);
+		self.cursor += amt;
+		Poll::Ready(Ok(amt))
+	}
+}
+
+impl AsyncWrite for OpfsNode {
+	fn poll_write(
+		mut self: Pin<&mut Self>,
+		_cx: &mut Context<'_>,
+		buf: &[u8],
+	) -> Poll<std::io::Result<usize>> {
+		if !self.write {
+			return poll_io_err();
+		}
+		let this = &mut *self;
+		let end = this.cursor + buf.len();
+		if end > this.data.len() {
+			this.data.resize(end, 0);
+		}
+		this.data[this.cursor..end].copy_from_slice(buf);
+		this.cursor = end;
+		this.dirty = true;
+		Poll::Ready(Ok(buf.len()))
+	}
+
+	fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+		if !self.write {
+			return poll_io_err();
+		}
+		self.poll_write_back(cx)
+	}
+
+	fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+		if !self.write {
+			return poll_io_err();
+		}
+		self.poll_write_back(cx)
+	}
+}
+
+impl AsyncSeek for OpfsNode {
+	fn poll_seek(
+		mut self: Pin<&mut Self>,
+		_cx: &mut Context<'_>,
+		pos: SeekFrom,
+	) -> Poll<std::io::Result<u64>> {
+		if !self.read && !self.write {
+			return poll_io_err();
+		}
+		let len = self.data.len();
+		self.cursor = match pos {
+			SeekFrom::Start(pos) => std::cmp::min(pos, len as u64) as usize,
+			SeekFrom::End(end_pos) if end_pos > 0 => len,
+			SeekFrom::End(end_pos) => len.saturating_sub((-end_pos) as usize),
+			SeekFrom::Current(offset) => {
+				(self.cursor as i64 + offset).clamp(0, len as i64) as usize
+			}
+		};
+		Poll::Ready(Ok(self.cursor as u64))
+	}
+}
+
+#[cfg(test)]
+mod wasm_tests {
+	use crate::scheme::NodeGetOptions;
+	use crate::{OpfsScheme, Vfs};
+	use futures_lite::{AsyncWriteExt, StreamExt};
+	use wasm_bindgen_test::wasm_bindgen_test;
+
+	#[wasm_bindgen_test]
+	async fn opfs_scheme() {
+		let vfs = Vfs::empty();
+		vfs.add_scheme("opfs", OpfsScheme::with_root("/vfs_nodes_test"))
+			.unwrap();
+		// The storage outlives the page, so clear whatever an earlier run left behind
+		let _ = vfs.remove_node_at("opfs:/saves", true).await;
+
+		vfs.create_dir_at("opfs:/saves").await.unwrap();
+		let mut node = vfs
+			.get_node_at(
+				"opfs:/saves/slot1.sav",
+				&NodeGetOptions::new().create_new(true),
+			)
+			.await
+			.unwrap();
+		node.write_all(b"saved").await.unwrap();
+		node.close().await.unwrap();
+
+		assert_eq!(
+			vfs.read_to_vec_at("opfs:/saves/slot1.sav").await.unwrap(),
+			b"saved"
+		);
+		let metadata = vfs.metadata_at("opfs:/saves/slot1.sav").await.unwrap();
+		assert_eq!(metadata.len, Some((5, Some(5))));
+		let listed: Vec<String> = vfs
+			.read_dir_at("opfs:/saves/")
+			.await
+			.unwrap()
+			.map(|entry| entry.url.to_string())
+			.collect()
+			.await;
+		assert_eq!(listed, ["opfs:/saves/slot1.sav"]);
+
+		vfs.remove_node_at("opfs:/saves/slot1.sav", false)
+			.await
+			.unwrap();
+		assert!(vfs.metadata_at("opfs:/saves/slot1.sav").await.is_err());
+		vfs.remove_node_at("opfs:/saves", false).await.unwrap();
+	}
+}
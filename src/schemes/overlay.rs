@@ -1,10 +1,10 @@
-use crate::scheme::{NodeEntry, NodeGetOptions, NodeMetadata, ReadDirStream};
-use crate::{PinnedNode, Scheme, SchemeError, Vfs};
-use futures_lite::Stream;
+use crate::scheme::{BorrowedReadDirStream, NodeEntry, NodeGetOptions, NodeMetadata};
+use crate::{io_util, PinnedNode, Scheme, SchemeError, Vfs};
+use futures_lite::{AsyncWriteExt, Stream, StreamExt};
 use std::borrow::Cow;
+use std::collections::HashSet;
 use std::option::Option::None;
 use std::pin::Pin;
-use std::task::{Context, Poll};
 use url::Url;
 
 #[derive(Debug)]
@@ -28,29 +28,107 @@ enum OverlayAccess {
 	ReadWrite(Box<dyn Scheme>),
 }
 
+/// Which operations an overlay layer takes part in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverlayRole {
+	Read,
+	Write,
+	ReadWrite,
+}
+
+impl OverlayAccess {
+	fn new(role: OverlayRole, scheme: Box<dyn Scheme>) -> Self {
+		match role {
+			OverlayRole::Read => OverlayAccess::Read(scheme),
+			OverlayRole::Write => OverlayAccess::Write(scheme),
+			OverlayRole::ReadWrite => OverlayAccess::ReadWrite(scheme),
+		}
+	}
+
+	fn role(&self) -> OverlayRole {
+		match self {
+			OverlayAccess::Read(_) => OverlayRole::Read,
+			OverlayAccess::Write(_) => OverlayRole::Write,
+			OverlayAccess::ReadWrite(_) => OverlayRole::ReadWrite,
+		}
+	}
+
+	fn scheme(&self) -> &dyn Scheme {
+		match self {
+			OverlayAccess::Read(scheme) => &**scheme,
+			OverlayAccess::Write(scheme) => &**scheme,
+			OverlayAccess::ReadWrite(scheme) => &**scheme,
+		}
+	}
+
+	fn into_parts(self) -> (OverlayRole, Box<dyn Scheme>) {
+		let role = self.role();
+		match self {
+			OverlayAccess::Read(scheme) => (role, scheme),
+			OverlayAccess::Write(scheme) => (role, scheme),
+			OverlayAccess::ReadWrite(scheme) => (role, scheme),
+		}
+	}
+}
+
+/// Stacks layers of schemes, index 0 on top.  Reads are served by the topmost readable layer that
+/// can open the node.  Write opens only go to writable layers: to the topmost one where the node
+/// already exists, so it is updated in place, else with `create` to the topmost writable layer.
+/// The error of the layer chosen is returned as-is, so a `create_new` of a node that exists in a
+/// lower writable layer fails rather than shadowing it.  A node only in read layers cannot be
+/// opened for writing unless `set_copy_up` is set, nor removed unless `set_whiteouts` is set.
 pub struct OverlayScheme {
 	overlays: Vec<OverlayAccess>,
+	sorted_listing: bool,
+	dedup_listing: bool,
+	copy_up: bool,
+	whiteouts: bool,
+}
+
+/// A `read_dir` entry of an overlay along with the index of the layer that listed it.
+#[derive(Debug, Clone)]
+pub struct LayeredNodeEntry {
+	pub layer: usize,
+	pub entry: NodeEntry,
 }
 
+pub type LayeredReadDirStream<'s> = Pin<Box<dyn Stream<Item = LayeredNodeEntry> + Send + 's>>;
+
 pub struct OverlaySchemeBuilder {
 	overlays: Vec<OverlayAccess>,
+	sorted_listing: bool,
+	dedup_listing: bool,
+	copy_up: bool,
+	whiteouts: bool,
 }
 
 impl OverlayScheme {
 	pub fn builder_boxed_read(first_overlay: Box<dyn Scheme>) -> OverlaySchemeBuilder {
 		OverlaySchemeBuilder {
+			sorted_listing: false,
+			dedup_listing: false,
+			copy_up: false,
+			whiteouts: false,
 			overlays: vec![OverlayAccess::Read(first_overlay)],
 		}
 	}
 
 	pub fn builder_boxed_write(first_overlay: Box<dyn Scheme>) -> OverlaySchemeBuilder {
 		OverlaySchemeBuilder {
+			sorted_listing: false,
+			dedup_listing: false,
+			copy_up: false,
+			whiteouts: false,
 			overlays: vec![OverlayAccess::Write(first_overlay)],
 		}
 	}
 
 	pub fn builder_boxed_read_write(first_overlay: Box<dyn Scheme>) -> OverlaySchemeBuilder {
 		OverlaySchemeBuilder {
+			sorted_listing: false,
+			dedup_listing: false,
+			copy_up: false,
+			whiteouts: false,
 			overlays: vec![OverlayAccess::ReadWrite(first_overlay)],
 		}
 	}
@@ -120,15 +198,354 @@ impl OverlayScheme {
 	pub fn prepend_read_write(&mut self, overlay: impl Scheme) -> &mut Self {
 		self.prepend_boxed_read_write(Box::new(overlay))
 	}
+
+	/// The number of layers, index 0 is the top layer that is tried first.
+	pub fn layer_count(&self) -> usize {
+		self.overlays.len()
+	}
+
+	pub fn layer_role(&self, idx: usize) -> Option<OverlayRole> {
+		self.overlays.get(idx).map(OverlayAccess::role)
+	}
+
+	pub fn layer_scheme(&self, idx: usize) -> Option<&dyn Scheme> {
+		self.overlays.get(idx).map(OverlayAccess::scheme)
+	}
+
+	/// Swaps the layers at `a` and `b`, panics if either is out of bounds.
+	pub fn swap_layers(&mut self, a: usize, b: usize) -> &mut Self {
+		self.overlays.swap(a, b);
+		self
+	}
+
+	pub fn remove_layer(&mut self, idx: usize) -> Option<(OverlayRole, Box<dyn Scheme>)> {
+		if idx < self.overlays.len() {
+			Some(self.overlays.remove(idx).into_parts())
+		} else {
+			None
+		}
+	}
+
+	/// Inserts a layer so it ends up at `idx`, panics if `idx > layer_count()`.
+	pub fn insert_boxed_layer(
+		&mut self,
+		idx: usize,
+		role: OverlayRole,
+		overlay: Box<dyn Scheme>,
+	) -> &mut Self {
+		self.overlays.insert(idx, OverlayAccess::new(role, overlay));
+		self
+	}
+
+	/// Inserts a layer so it ends up at `idx`, panics if `idx > layer_count()`.
+	pub fn insert_layer(
+		&mut self,
+		idx: usize,
+		role: OverlayRole,
+		overlay: impl Scheme,
+	) -> &mut Self {
+		self.insert_boxed_layer(idx, role, Box::new(overlay))
+	}
+
+	/// When set `read_dir` yields each url once, for the topmost layer that lists it, sorted by
+	/// path, so a listing is the same on every run however the layers order their own.  This
+	/// lists every layer and holds all of their entries in memory before yielding the first, where
+	/// otherwise lower layers are only listed once the ones above them are exhausted.
+	pub fn set_sorted_listing(&mut self, sorted: bool) -> &mut Self {
+		self.sorted_listing = sorted;
+		self
+	}
+
+	pub fn is_sorted_listing(&self) -> bool {
+		self.sorted_listing
+	}
+
+	/// When set `read_dir` yields each path once, for the topmost layer that lists it, while still
+	/// only listing lower layers once the ones above them are exhausted.  Only the paths seen so
+	/// far are held, not the entries.  A sorted listing is always deduplicated.
+	pub fn set_dedup_listing(&mut self, dedup: bool) -> &mut Self {
+		self.dedup_listing = dedup;
+		self
+	}
+
+	pub fn is_dedup_listing(&self) -> bool {
+		self.dedup_listing
+	}
+
+	/// When set, opening for writing a node that only a read layer has first copies it into the top
+	/// writable layer, like overlayfs, so the write changes that copy and the read layer is left as
+	/// it was.  The copy then shadows the read layer for reads as well.  A `create_new` of such a
+	/// node fails as it already exists, where otherwise it would be created to shadow it.
+	pub fn set_copy_up(&mut self, copy_up: bool) -> &mut Self {
+		self.copy_up = copy_up;
+		self
+	}
+
+	pub fn is_copy_up(&self) -> bool {
+		self.copy_up
+	}
+
+	/// When set, removing a url that a lower layer still has, such as a read layer, leaves an
+	/// empty whiteout node in the top writable layer, named for the removed one with
+	/// `WHITEOUT_PREFIX` in front, like `/dir/.wh.name` for `/dir/name`.  A url that is, or is
+	/// below, a whited out one is then missing to `get_node`, `metadata` and `read_dir`, and the
+	/// whiteout nodes are left out of listings.  Creating the node again removes its whiteout.
+	pub fn set_whiteouts(&mut self, whiteouts: bool) -> &mut Self {
+		self.whiteouts = whiteouts;
+		self
+	}
+
+	pub fn is_whiteouts(&self) -> bool {
+		self.whiteouts
+	}
+
+	/// Lists `url` like `read_dir` but tags each entry with the layer it came from, such as to
+	/// debug which layer shadows which.  With `dedup` an entry is only yielded for the topmost
+	/// layer that lists its path, the one that would serve it, whether or not the layers list a
+	/// directory with its trailing `/`.
+	pub fn read_dir_layers<'s>(
+		&'s self,
+		vfs: &'s Vfs,
+		url: &Url,
+		dedup: bool,
+	) -> LayeredReadDirStream<'s> {
+		let stream = self.layered_read_dir(vfs, url);
+		if !dedup {
+			return stream;
+		}
+		let mut seen = HashSet::new();
+		Box::pin(stream.filter(move |layered| {
+			let path = layered.entry.url.path().trim_end_matches('/');
+			!seen.contains(path) && seen.insert(path.to_owned())
+		}))
+	}
+
+	fn layered_read_dir<'s>(&'s self, vfs: &'s Vfs, url: &Url) -> LayeredReadDirStream<'s> {
+		// Each layer is only listed once the layers above it are exhausted, so a caller that stops
+		// early never touches the, possibly expensive, lower layers
+		let state = (
+			self.overlays.iter().enumerate(),
+			url.clone(),
+			None::<(usize, BorrowedReadDirStream<'s>)>,
+		);
+		Box::pin(
+			futures_lite::stream::unfold(state, move |(mut layers, url, mut current)| async move {
+				loop {
+					if let Some((layer, stream)) = &mut current {
+						if let Some(entry) = stream.next().await {
+							let layer = *layer;
+							return Some((
+								LayeredNodeEntry { layer, entry },
+								(layers, url, current),
+							));
+						}
+					}
+					let (layer, overlay) = layers.next()?;
+					current = overlay
+						.scheme()
+						.read_dir(vfs, &url)
+						.await
+						.ok()
+						.map(|stream| (layer, stream));
+				}
+			})
+			// `unfold` panics when polled after it ended
+			.fuse(),
+		)
+	}
+}
+
+impl OverlayScheme {
+	pub const WHITEOUT_PREFIX: &'static str = ".wh.";
+
+	/// The url of the whiteout node of `url`, `None` for the root.
+	fn whiteout_url(url: &Url) -> Option<Url> {
+		let path = url.path().trim_end_matches('/');
+		let (dir, name) = path.rsplit_once('/')?;
+		let mut whiteout = url.clone();
+		whiteout.set_path(&format!("{}/{}{}", dir, Self::WHITEOUT_PREFIX, name));
+		whiteout.set_query(None);
+		whiteout.set_fragment(None);
+		Some(whiteout)
+	}
+
+	/// The whiteout in the top writable layer that hides `url`, its own or that of a directory
+	/// above it.
+	async fn whiteout_of(&self, vfs: &Vfs, url: &Url) -> Option<Url> {
+		if !self.whiteouts {
+			return None;
+		}
+		let top = self.writable_layers().next()?;
+		let mut current = Some(url.clone());
+		while let Some(url) = current {
+			let whiteout = Self::whiteout_url(&url)?;
+			if top.metadata(vfs, &whiteout).await.is_ok() {
+				return Some(whiteout);
+			}
+			let mut parent = url.clone();
+			parent.set_path(url.path().trim_end_matches('/').rsplit_once('/')?.0);
+			current = Some(parent);
+		}
+		None
+	}
+
+	/// The names, as percent-encoded segments, that the top writable layer whites out in the
+	/// directory `url`.
+	async fn whiteouts_in(&self, vfs: &Vfs, url: &Url) -> HashSet<String> {
+		let top = match self.writable_layers().next() {
+			Some(top) => top,
+			None => return HashSet::new(),
+		};
+		let entries = match top.read_dir(vfs, url).await {
+			Ok(entries) => entries,
+			Err(_error) => return HashSet::new(),
+		};
+		entries
+			.filter_map(|entry| {
+				let name = Self::entry_name(&entry)?;
+				Some(name.strip_prefix(Self::WHITEOUT_PREFIX)?.to_owned())
+			})
+			.collect()
+			.await
+	}
+
+	fn entry_name(entry: &NodeEntry) -> Option<String> {
+		let path = entry.url.path().trim_end_matches('/');
+		path.rsplit_once('/').map(|(_dir, name)| name.to_owned())
+	}
+
+	fn writable_layers(&self) -> impl Iterator<Item = &dyn Scheme> {
+		self.overlays
+			.iter()
+			.filter(|overlay| overlay.role() != OverlayRole::Read)
+			.map(OverlayAccess::scheme)
+	}
+
+	async fn get_node_for_write<'a>(
+		&self,
+		vfs: &Vfs,
+		url: &'a Url,
+		options: &NodeGetOptions,
+	) -> Result<PinnedNode, SchemeError<'a>> {
+		if let Some(whiteout) = self.whiteout_of(vfs, url).await {
+			// Only its own whiteout can be lifted, one of a directory above leaves no parent to
+			// create it in
+			let top = self.writable_layers().next();
+			return match top {
+				Some(top)
+					if options.get_create()
+						&& Self::whiteout_url(url).as_ref() == Some(&whiteout) =>
+				{
+					top.remove_node(vfs, &whiteout, false)
+						.await
+						.map_err(SchemeError::into_owned)?;
+					top.get_node(vfs, url, options).await
+				}
+				_ => Err(SchemeError::NodeDoesNotExist(Cow::Borrowed(url.path()))),
+			};
+		}
+		// Asking each layer for metadata first means a write never opens, or creates, the node in
+		// a layer above the one that already has it
+		let mut is_dir = false;
+		for scheme in self.writable_layers() {
+			match scheme.metadata(vfs, url).await {
+				Ok(metadata) if metadata.is_node => {
+					return scheme.get_node(vfs, url, options).await
+				}
+				Ok(_metadata) => is_dir = true,
+				Err(_error) => (),
+			}
+		}
+		if let (true, false, Some(top)) = (self.copy_up, is_dir, self.writable_layers().next()) {
+			if self.copy_up_node(vfs, url, top, options).await? {
+				return top.get_node(vfs, url, options).await;
+			}
+		}
+		match self.writable_layers().next() {
+			Some(scheme) if options.get_create() && !is_dir => {
+				scheme.get_node(vfs, url, options).await
+			}
+			_ if is_dir => Err(SchemeError::IsADirectory(Cow::Borrowed(url.path()))),
+			_ => Err(SchemeError::NodeDoesNotExist(Cow::Borrowed(url.path()))),
+		}
+	}
+
+	/// Copies the node at `url` from the topmost read layer that has it into `top`, returning
+	/// whether any read layer had it.
+	async fn copy_up_node<'a>(
+		&self,
+		vfs: &Vfs,
+		url: &'a Url,
+		top: &dyn Scheme,
+		options: &NodeGetOptions,
+	) -> Result<bool, SchemeError<'a>> {
+		let read_layers = self
+			.overlays
+			.iter()
+			.filter(|overlay| overlay.role() == OverlayRole::Read)
+			.map(OverlayAccess::scheme);
+		for scheme in read_layers {
+			match scheme.metadata(vfs, url).await {
+				Ok(metadata) if metadata.is_node => (),
+				_ => continue,
+			}
+			if options.get_create_new() {
+				return Err(SchemeError::NodeAlreadyExists(Cow::Borrowed(url.path())));
+			}
+			let create = NodeGetOptions::new()
+				.create(true)
+				.truncate(true)
+				.create_parents(true);
+			let mut writer = top.get_node(vfs, url, &create).await?;
+			// The open truncates it right away, so there is nothing worth copying
+			if !options.get_truncate() {
+				let mut reader = scheme
+					.get_node(vfs, url, &NodeGetOptions::new().read(true))
+					.await?;
+				io_util::copy(&mut reader, &mut writer).await?;
+			}
+			writer.close().await?;
+			return Ok(true);
+		}
+		Ok(false)
+	}
 }
 
 impl OverlaySchemeBuilder {
 	pub fn build(self) -> OverlayScheme {
 		OverlayScheme {
 			overlays: self.overlays,
+			sorted_listing: self.sorted_listing,
+			dedup_listing: self.dedup_listing,
+			copy_up: self.copy_up,
+			whiteouts: self.whiteouts,
 		}
 	}
 
+	/// See `OverlayScheme::set_whiteouts`.
+	pub fn whiteouts(mut self, whiteouts: bool) -> Self {
+		self.whiteouts = whiteouts;
+		self
+	}
+
+	/// See `OverlayScheme::set_copy_up`.
+	pub fn copy_up(mut self, copy_up: bool) -> Self {
+		self.copy_up = copy_up;
+		self
+	}
+
+	/// See `OverlayScheme::set_dedup_listing`.
+	pub fn dedup_listing(mut self, dedup: bool) -> Self {
+		self.dedup_listing = dedup;
+		self
+	}
+
+	/// See `OverlayScheme::set_sorted_listing`.
+	pub fn sorted_listing(mut self, sorted: bool) -> Self {
+		self.sorted_listing = sorted;
+		self
+	}
+
 	pub fn boxed_read(mut self, overlay: Box<dyn Scheme>) -> Self {
 		self.overlays.push(OverlayAccess::Read(overlay));
 		self
@@ -169,6 +586,13 @@ impl Scheme for OverlayScheme {
 		url: &'a Url,
 		options: &NodeGetOptions,
 	) -> Result<PinnedNode, SchemeError<'a>> {
+		if options.get_write() {
+			return self.get_node_for_write(vfs, url, options).await;
+		}
+		if self.whiteout_of(vfs, url).await.is_some() {
+			return Err(SchemeError::NodeDoesNotExist(Cow::Borrowed(url.path())));
+		}
+		let mut is_dir = false;
 		for overlay in self.overlays.iter() {
 			let node = match overlay {
 				OverlayAccess::Read(scheme) if options.get_read() => {
@@ -183,12 +607,18 @@ impl Scheme for OverlayScheme {
 				_ => None,
 			};
 			if let Some(node) = node {
-				if let Ok(node) = node.await {
-					return Ok(node);
+				match node.await {
+					Ok(node) => return Ok(node),
+					Err(SchemeError::IsADirectory(_)) => is_dir = true,
+					Err(_) => (),
 				}
 			}
 		}
-		Err(SchemeError::NodeDoesNotExist(Cow::Borrowed(url.path())))
+		if is_dir {
+			Err(SchemeError::IsADirectory(Cow::Borrowed(url.path())))
+		} else {
+			Err(SchemeError::NodeDoesNotExist(Cow::Borrowed(url.path())))
+		}
 	}
 
 	async fn remove_node<'a>(
@@ -197,6 +627,10 @@ impl Scheme for OverlayScheme {
 		url: &'a Url,
 		force: bool,
 	) -> Result<(), SchemeError<'a>> {
+		if self.whiteout_of(vfs, url).await.is_some() {
+			return Err(SchemeError::NodeDoesNotExist(Cow::Borrowed(url.path())));
+		}
+		let mut removed = false;
 		for overlay in self.overlays.iter() {
 			let node = match overlay {
 				OverlayAccess::Read(_scheme) => None,
@@ -204,21 +638,50 @@ impl Scheme for OverlayScheme {
 				OverlayAccess::ReadWrite(scheme) => Some(scheme.remove_node(vfs, url, force)),
 			};
 			if let Some(node) = node {
-				if let Ok(node) = node.await {
-					return Ok(node);
+				if node.await.is_ok() {
+					removed = true;
+					break;
 				}
 			}
 		}
-		Err(SchemeError::NodeDoesNotExist(Cow::Borrowed(url.path())))
+		if self.whiteouts {
+			// Whited out if any layer still shows it, whether or not a layer could remove it
+			let mut shown = false;
+			for scheme in self.overlays.iter().map(OverlayAccess::scheme) {
+				if scheme.metadata(vfs, url).await.is_ok() {
+					shown = true;
+					break;
+				}
+			}
+			if let (true, Some(top), Some(whiteout)) = (
+				shown,
+				self.writable_layers().next(),
+				Self::whiteout_url(url),
+			) {
+				let create = NodeGetOptions::new()
+					.create(true)
+					.truncate(true)
+					.create_parents(true);
+				let mut marker = top
+					.get_node(vfs, &whiteout, &create)
+					.await
+					.map_err(SchemeError::into_owned)?;
+				marker.close().await?;
+				return Ok(());
+			}
+		}
+		if removed {
+			Ok(())
+		} else {
+			Err(SchemeError::NodeDoesNotExist(Cow::Borrowed(url.path())))
+		}
 	}
 
 	async fn metadata<'a>(&self, vfs: &Vfs, url: &'a Url) -> Result<NodeMetadata, SchemeError<'a>> {
-		for overlay in self.overlays.iter() {
-			let scheme = match overlay {
-				OverlayAccess::Read(scheme) => scheme,
-				OverlayAccess::Write(scheme) => scheme,
-				OverlayAccess::ReadWrite(scheme) => scheme,
-			};
+		if self.whiteout_of(vfs, url).await.is_some() {
+			return Err(SchemeError::NodeDoesNotExist(Cow::Borrowed(url.path())));
+		}
+		for scheme in self.overlays.iter().map(OverlayAccess::scheme) {
 			match scheme.metadata(vfs, url).await {
 				Ok(metadata) => return Ok(metadata),
 				Err(_error) => continue,
@@ -227,60 +690,52 @@ impl Scheme for OverlayScheme {
 		Err(SchemeError::NodeDoesNotExist(Cow::Borrowed(url.path())))
 	}
 
-	async fn read_dir<'a>(
-		&self,
-		vfs: &Vfs,
+	async fn read_dir<'s, 'a>(
+		&'s self,
+		vfs: &'s Vfs,
 		url: &'a Url,
-	) -> Result<ReadDirStream, SchemeError<'a>> {
-		let mut streams = Vec::with_capacity(self.overlays.len());
-		for scheme in self.overlays.iter().rev().map(|overlay| match overlay {
-			OverlayAccess::Read(scheme) => scheme,
-			OverlayAccess::Write(scheme) => scheme,
-			OverlayAccess::ReadWrite(scheme) => scheme,
-		}) {
-			if let Ok(stream) = scheme.read_dir(vfs, url).await {
-				streams.push(stream);
-			}
+	) -> Result<BorrowedReadDirStream<'s>, SchemeError<'a>> {
+		let entries: BorrowedReadDirStream<'s> = if !self.sorted_listing {
+			Box::pin(
+				self.read_dir_layers(vfs, url, self.dedup_listing)
+					.map(|layered| layered.entry),
+			)
+		} else {
+			let mut entries: Vec<NodeEntry> = self
+				.read_dir_layers(vfs, url, true)
+				.map(|layered| layered.entry)
+				.collect()
+				.await;
+			entries.sort_by(|a, b| a.url.path().cmp(b.url.path()));
+			Box::pin(futures_lite::stream::iter(entries))
+		};
+		if !self.whiteouts {
+			return Ok(entries);
 		}
-		Ok(Box::pin(OverlayReadDir(streams)))
-	}
-}
-
-struct OverlayReadDir(Vec<ReadDirStream>);
-
-impl Stream for OverlayReadDir {
-	type Item = NodeEntry;
-
-	fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-		loop {
-			if self.0.is_empty() {
-				return Poll::Ready(None);
-			}
-			match self.0.last_mut().unwrap().as_mut().poll_next(cx) {
-				Poll::Pending => return Poll::Pending,
-				Poll::Ready(None) => {
-					self.0.pop();
-				}
-				Poll::Ready(Some(entry)) => return Poll::Ready(Some(entry)),
-			}
+		if self.whiteout_of(vfs, url).await.is_some() {
+			return Err(SchemeError::NodeDoesNotExist(Cow::Borrowed(url.path())));
 		}
-	}
-
-	fn size_hint(&self) -> (usize, Option<usize>) {
-		self.0
-			.iter()
-			.map(|s| s.size_hint())
-			.reduce(|(ls, le), (rs, re)| (ls + rs, le.and_then(|le| re.map(|re| re + le))))
-			.unwrap_or((0, Some(0)))
+		let hidden = self.whiteouts_in(vfs, url).await;
+		Ok(Box::pin(entries.filter(
+			move |entry| match Self::entry_name(entry) {
+				Some(name) => !name.starts_with(Self::WHITEOUT_PREFIX) && !hidden.contains(&name),
+				None => true,
+			},
+		)))
 	}
 }
 
 #[cfg(test)]
 #[cfg(feature = "backend_tokio")]
 mod async_tokio_tests {
-	use crate::scheme::NodeGetOptions;
-	use crate::{DataLoaderScheme, OverlayScheme, TokioFileSystemScheme, Vfs};
-	use futures_lite::StreamExt;
+	use crate::scheme::{NodeEntry, NodeGetOptions, ReadDirStream};
+	use crate::{
+		DataLoaderScheme, FnScheme, OverlayRole, OverlayScheme, SchemeError, TemplateScheme,
+		TokioFileSystemScheme, Vfs, VfsError,
+	};
+	use futures_lite::{AsyncReadExt, StreamExt};
+	use std::sync::atomic::{AtomicBool, Ordering};
+	use std::sync::Arc;
 	use url::Url;
 
 	fn u(s: &str) -> Url {
@@ -289,7 +744,7 @@ mod async_tokio_tests {
 
 	#[tokio::test]
 	async fn read_only_depth() {
-		let mut vfs = Vfs::default();
+		let vfs = Vfs::default();
 		vfs.add_scheme(
 			"overlay",
 			OverlayScheme::builder_read(DataLoaderScheme::default())
@@ -309,11 +764,16 @@ mod async_tokio_tests {
 			.get_node(&u("fs:/does/not/exist"), &NodeGetOptions::new().read(true))
 			.await
 			.is_err(),);
+		assert!(matches!(
+			vfs.get_node(&u("overlay:/src"), &NodeGetOptions::new().read(true))
+				.await,
+			Err(VfsError::SchemeError(SchemeError::IsADirectory(_)))
+		));
 	}
 
 	#[tokio::test]
 	async fn read_dir() {
-		let mut vfs = Vfs::default();
+		let vfs = Vfs::default();
 		vfs.add_scheme(
 			"overlay",
 			OverlayScheme::builder_read(DataLoaderScheme::default())
@@ -337,5 +797,365 @@ mod async_tokio_tests {
 			vfs.read_dir_at("overlay:/").await.unwrap().count().await,
 			data + errors + filesystem
 		);
+		let (lower, upper) = vfs.read_dir_at("overlay:/").await.unwrap().size_hint();
+		assert!(lower <= data + errors + filesystem);
+		assert_eq!(upper, None, "filesystem layers do not know their length");
+
+		let mut entries = vfs.read_dir_at("overlay:/").await.unwrap();
+		while entries.next().await.is_some() {}
+		assert!(entries.next().await.is_none(), "fused");
+		assert!(entries.next().await.is_none(), "fused");
+	}
+
+	#[tokio::test]
+	async fn read_dir_lazy() {
+		let lower_listed = Arc::new(AtomicBool::new(false));
+		let lower = {
+			let lower_listed = lower_listed.clone();
+			FnScheme::new().on_read_dir(move |_vfs, url| {
+				lower_listed.store(true, Ordering::SeqCst);
+				let mut url = url.clone();
+				url.set_path("/lower");
+				Box::pin(async move {
+					let stream: ReadDirStream =
+						Box::pin(futures_lite::stream::iter(vec![NodeEntry::new(url)]));
+					Ok(stream)
+				})
+			})
+		};
+		let mut upper = layer("upper");
+		upper.list("/file").unwrap();
+		let vfs = Vfs::empty();
+		vfs.add_scheme(
+			"overlay",
+			OverlayScheme::builder_read(upper).read(lower).build(),
+		)
+		.unwrap();
+
+		let mut stream = vfs.read_dir_at("overlay:/").await.unwrap();
+		assert_eq!(stream.next().await.unwrap().url.path(), "/file");
+		assert!(!lower_listed.load(Ordering::SeqCst));
+		assert_eq!(stream.next().await.unwrap().url.path(), "/lower");
+		assert!(lower_listed.load(Ordering::SeqCst));
+		assert!(stream.next().await.is_none());
+	}
+
+	fn layer(content: &'static str) -> TemplateScheme {
+		let mut scheme = TemplateScheme::new();
+		scheme
+			.register("/file", move |_| Ok(content.as_bytes().to_vec()))
+			.unwrap();
+		scheme
+	}
+
+	async fn read_file(vfs: &Vfs) -> String {
+		let mut buffer = String::new();
+		vfs.get_node_at("overlay:/file", &NodeGetOptions::new().read(true))
+			.await
+			.unwrap()
+			.read_to_string(&mut buffer)
+			.await
+			.unwrap();
+		buffer
+	}
+
+	#[tokio::test]
+	async fn layer_mutation() {
+		let mut vfs = Vfs::empty();
+		vfs.add_scheme(
+			"overlay",
+			OverlayScheme::builder_read(layer("first"))
+				.read(layer("second"))
+				.build(),
+		)
+		.unwrap();
+		assert_eq!(read_file(&vfs).await, "first");
+
+		let overlay = vfs.get_scheme_mut_as::<OverlayScheme>("overlay").unwrap();
+		assert_eq!(overlay.layer_count(), 2);
+		assert_eq!(overlay.layer_role(1), Some(OverlayRole::Read));
+		assert_eq!(overlay.layer_role(2), None);
+		overlay.swap_layers(0, 1);
+		assert_eq!(read_file(&vfs).await, "second");
+
+		let overlay = vfs.get_scheme_mut_as::<OverlayScheme>("overlay").unwrap();
+		overlay.insert_layer(1, OverlayRole::ReadWrite, layer("third"));
+		assert_eq!(overlay.layer_role(1), Some(OverlayRole::ReadWrite));
+		let (role, _scheme) = overlay.remove_layer(0).unwrap();
+		assert_eq!(role, OverlayRole::Read);
+		assert!(overlay.remove_layer(5).is_none());
+		assert_eq!(overlay.layer_count(), 2);
+		assert_eq!(read_file(&vfs).await, "third");
+
+		let overlay = vfs.get_scheme_mut_as::<OverlayScheme>("overlay").unwrap();
+		overlay.insert_layer(0, OverlayRole::Write, layer("write only"));
+		assert_eq!(
+			read_file(&vfs).await,
+			"third",
+			"write layers are skipped for reads"
+		);
+	}
+
+	#[cfg(feature = "in_memory")]
+	async fn writable_layers() -> Vfs {
+		use crate::{MemoryScheme, Scheme};
+		use futures_lite::AsyncWriteExt;
+
+		let lower = MemoryScheme::default();
+		let vfs = Vfs::empty();
+		vfs.add_scheme("lower", MemoryScheme::default()).unwrap();
+		lower
+			.get_node(
+				&vfs,
+				&u("lower:/existing"),
+				&NodeGetOptions::new().create(true),
+			)
+			.await
+			.unwrap()
+			.write_all(b"lower")
+			.await
+			.unwrap();
+		vfs.add_scheme(
+			"overlay",
+			OverlayScheme::builder_read_write(MemoryScheme::default())
+				.read(layer("read only"))
+				.read_write(lower)
+				.build(),
+		)
+		.unwrap();
+		vfs
+	}
+
+	#[cfg(feature = "in_memory")]
+	async fn layer_has(vfs: &Vfs, layer: usize, url: &str) -> bool {
+		let overlay = vfs.get_scheme_as::<OverlayScheme>("overlay").unwrap();
+		let scheme = overlay.layer_scheme(layer).unwrap();
+		scheme.metadata(vfs, &u(url)).await.is_ok()
+	}
+
+	#[cfg(feature = "in_memory")]
+	#[tokio::test]
+	async fn write_updates_existing_layer() {
+		use futures_lite::AsyncWriteExt;
+
+		let vfs = writable_layers().await;
+		for options in [
+			NodeGetOptions::new().write(true).truncate(true),
+			NodeGetOptions::new().create(true).truncate(true),
+		] {
+			let mut node = vfs
+				.get_node_at("overlay:/existing", &options)
+				.await
+				.unwrap();
+			node.write_all(b"updated").await.unwrap();
+			drop(node);
+			assert!(!layer_has(&vfs, 0, "overlay:/existing").await);
+			assert_eq!(
+				vfs.read_to_vec_at("overlay:/existing").await.unwrap(),
+				b"updated"
+			);
+		}
+
+		assert!(matches!(
+			vfs.get_node_at("overlay:/existing", &NodeGetOptions::new().create_new(true))
+				.await,
+			Err(VfsError::SchemeError(SchemeError::NodeAlreadyExists(_)))
+		));
+		assert!(!layer_has(&vfs, 0, "overlay:/existing").await);
+	}
+
+	#[cfg(feature = "in_memory")]
+	#[tokio::test]
+	async fn write_creates_in_top_layer() {
+		let vfs = writable_layers().await;
+		assert!(matches!(
+			vfs.get_node_at("overlay:/new", &NodeGetOptions::new().write(true))
+				.await,
+			Err(VfsError::SchemeError(SchemeError::NodeDoesNotExist(_)))
+		));
+		assert!(vfs.metadata_at("overlay:/new").await.is_err());
+
+		vfs.get_node_at("overlay:/new", &NodeGetOptions::new().create_new(true))
+			.await
+			.unwrap();
+		assert!(layer_has(&vfs, 0, "overlay:/new").await);
+		assert!(!layer_has(&vfs, 2, "overlay:/new").await);
+
+		// Only in a read layer, so it is created in the top layer and shadows it there
+		vfs.get_node_at("overlay:/file", &NodeGetOptions::new().create(true))
+			.await
+			.unwrap();
+		assert!(layer_has(&vfs, 0, "overlay:/file").await);
+	}
+
+	#[cfg(feature = "in_memory")]
+	#[tokio::test]
+	async fn write_copies_up() {
+		use futures_lite::AsyncWriteExt;
+
+		let mut vfs = writable_layers().await;
+		assert!(vfs
+			.get_node_at("overlay:/file", &NodeGetOptions::new().write(true))
+			.await
+			.is_err());
+		vfs.get_scheme_mut_as::<OverlayScheme>("overlay")
+			.unwrap()
+			.set_copy_up(true);
+
+		let mut node = vfs
+			.get_node_at("overlay:/file", &NodeGetOptions::new().write(true))
+			.await
+			.unwrap();
+		node.write_all(b"READ").await.unwrap();
+		node.close().await.unwrap();
+		drop(node);
+		assert!(layer_has(&vfs, 0, "overlay:/file").await);
+		assert_eq!(read_file(&vfs).await, "READ only", "copied before written");
+		let overlay = vfs.get_scheme_as::<OverlayScheme>("overlay").unwrap();
+		let mut buffer = String::new();
+		overlay
+			.layer_scheme(1)
+			.unwrap()
+			.get_node(&vfs, &u("overlay:/file"), &NodeGetOptions::new().read(true))
+			.await
+			.unwrap()
+			.read_to_string(&mut buffer)
+			.await
+			.unwrap();
+		assert_eq!(buffer, "read only", "the read layer is left as it was");
+
+		assert!(matches!(
+			vfs.get_node_at("overlay:/file", &NodeGetOptions::new().create_new(true))
+				.await,
+			Err(VfsError::SchemeError(SchemeError::NodeAlreadyExists(_)))
+		));
+		assert!(matches!(
+			vfs.get_node_at("overlay:/missing", &NodeGetOptions::new().write(true))
+				.await,
+			Err(VfsError::SchemeError(SchemeError::NodeDoesNotExist(_)))
+		));
+	}
+
+	#[cfg(feature = "in_memory")]
+	#[tokio::test]
+	async fn remove_whites_out() {
+		let mut vfs = writable_layers().await;
+		assert!(vfs.remove_node_at("overlay:/file", false).await.is_err());
+		vfs.get_scheme_mut_as::<OverlayScheme>("overlay")
+			.unwrap()
+			.set_whiteouts(true);
+
+		vfs.remove_node_at("overlay:/file", false).await.unwrap();
+		assert!(layer_has(&vfs, 0, "overlay:/.wh.file").await);
+		assert!(layer_has(&vfs, 1, "overlay:/file").await);
+		assert!(vfs.metadata_at("overlay:/file").await.is_err());
+		assert!(vfs
+			.get_node_at("overlay:/file", &NodeGetOptions::new().read(true))
+			.await
+			.is_err());
+		assert!(vfs.remove_node_at("overlay:/file", false).await.is_err());
+		let listed: Vec<String> = vfs
+			.read_dir_at("overlay:/")
+			.await
+			.unwrap()
+			.map(|entry| entry.url.path().to_owned())
+			.collect()
+			.await;
+		assert_eq!(listed, ["/existing"], "neither the node nor its whiteout");
+
+		// Nothing else shows it once removed from its only layer, so no whiteout is left
+		vfs.remove_node_at("overlay:/existing", false)
+			.await
+			.unwrap();
+		assert!(!layer_has(&vfs, 0, "overlay:/.wh.existing").await);
+
+		vfs.get_node_at("overlay:/file", &NodeGetOptions::new().create(true))
+			.await
+			.unwrap();
+		assert!(!layer_has(&vfs, 0, "overlay:/.wh.file").await);
+		assert_eq!(read_file(&vfs).await, "", "created anew over the whiteout");
+	}
+
+	#[tokio::test]
+	async fn read_dir_layers() {
+		let listing = |paths: &[&str]| {
+			let mut scheme = TemplateScheme::new();
+			scheme.register("/{name}", |_| Ok(Vec::new())).unwrap();
+			for path in paths {
+				scheme.list(path).unwrap();
+			}
+			scheme
+		};
+		let vfs = Vfs::empty();
+		vfs.add_scheme(
+			"overlay",
+			OverlayScheme::builder_read(listing(&["/config.toml"]))
+				.read(listing(&["/config.toml"]))
+				.read(listing(&["/data.bin", "/config.toml"]))
+				.build(),
+		)
+		.unwrap();
+		let overlay = vfs.get_scheme_as::<OverlayScheme>("overlay").unwrap();
+		let layers = |dedup| {
+			overlay
+				.read_dir_layers(&vfs, &u("overlay:/"), dedup)
+				.map(|layered| (layered.layer, layered.entry.url.path().to_owned()))
+				.collect::<Vec<_>>()
+		};
+		assert_eq!(
+			layers(true).await,
+			[(0, "/config.toml".to_owned()), (2, "/data.bin".to_owned())]
+		);
+		assert_eq!(layers(false).await.len(), 4);
+	}
+
+	#[tokio::test]
+	async fn read_dir_sorted() {
+		let listing = |paths: &[&str]| {
+			let mut scheme = TemplateScheme::new();
+			scheme.register("/{name}", |_| Ok(Vec::new())).unwrap();
+			for path in paths {
+				scheme.list(path).unwrap();
+			}
+			scheme
+		};
+		let mut vfs = Vfs::empty();
+		vfs.add_scheme(
+			"overlay",
+			OverlayScheme::builder_read(listing(&["/zebra", "/config.toml"]))
+				.read(listing(&["/data.bin", "/config.toml", "/apple"]))
+				.sorted_listing(true)
+				.build(),
+		)
+		.unwrap();
+		let listed: Vec<_> = vfs
+			.read_dir_at("overlay:/")
+			.await
+			.unwrap()
+			.map(|entry| entry.url.path().to_owned())
+			.collect()
+			.await;
+		assert_eq!(listed, ["/apple", "/config.toml", "/data.bin", "/zebra"]);
+
+		vfs.get_scheme_mut_as::<OverlayScheme>("overlay")
+			.unwrap()
+			.set_sorted_listing(false);
+		assert_eq!(vfs.read_dir_at("overlay:/").await.unwrap().count().await, 5);
+
+		vfs.get_scheme_mut_as::<OverlayScheme>("overlay")
+			.unwrap()
+			.set_dedup_listing(true);
+		let listed: Vec<_> = vfs
+			.read_dir_at("overlay:/")
+			.await
+			.unwrap()
+			.map(|entry| entry.url.path().to_owned())
+			.collect()
+			.await;
+		assert_eq!(
+			listed,
+			["/zebra", "/config.toml", "/data.bin", "/apple"],
+			"in layer order, each path once"
+		);
 	}
 }
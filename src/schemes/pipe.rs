@@ -0,0 +1,396 @@
+use crate::node::poll_io_err;
+use crate::scheme::{BorrowedReadDirStream, NodeEntry, NodeGetOptions, NodeMetadata};
+use crate::{Node, PinnedNode, Scheme, SchemeError, Vfs};
+use futures_lite::{AsyncRead, AsyncSeek, AsyncWrite};
+use std::borrow::Cow;
+use std::collections::{HashMap, VecDeque};
+use std::io::SeekFrom;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use url::Url;
+
+/// How many bytes a pipe of `PipeScheme::default` buffers before its writers wait on a reader.
+pub const PIPE_DEFAULT_CAPACITY: usize = 64 * 1024;
+
+/// In-process named pipes, opening a url for writing returns a writer end and opening it for
+/// reading returns a reader end of the same pipe, so bytes written to one are read from the other
+/// like a FIFO.  A pipe buffers at most its capacity, writers wait for a reader to make room.
+/// Readers get EOF once every writer that opened the pipe closed or dropped its end, and writes
+/// fail with `BrokenPipe` once every reader that opened it dropped its end.  A pipe exists from
+/// when the first end is opened until the last end is dropped, `read_dir` lists those.
+pub struct PipeScheme {
+	capacity: usize,
+	pipes: Arc<Mutex<PipeMap>>,
+}
+
+type PipeMap = HashMap<String, Arc<Mutex<Pipe>>>;
+
+impl Default for PipeScheme {
+	fn default() -> Self {
+		Self::new(PIPE_DEFAULT_CAPACITY)
+	}
+}
+
+impl PipeScheme {
+	/// A zero `capacity` is treated as one so a write can always make progress.
+	pub fn new(capacity: usize) -> Self {
+		Self {
+			capacity: capacity.max(1),
+			pipes: Arc::default(),
+		}
+	}
+
+	pub fn capacity(&self) -> usize {
+		self.capacity
+	}
+}
+
+#[derive(Default)]
+struct Pipe {
+	buffer: VecDeque<u8>,
+	readers: usize,
+	writers: usize,
+	had_reader: bool,
+	had_writer: bool,
+	read_wakers: Vec<Waker>,
+	write_wakers: Vec<Waker>,
+}
+
+impl Pipe {
+	fn wake(wakers: &mut Vec<Waker>) {
+		for waker in wakers.drain(..) {
+			waker.wake();
+		}
+	}
+}
+
+/// Which end of a pipe a `PipeNode` is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum PipeEnd {
+	Reader,
+	Writer,
+}
+
+#[async_trait::async_trait]
+impl Scheme for PipeScheme {
+	async fn get_node<'a>(
+		&self,
+		_vfs: &Vfs,
+		url: &'a Url,
+		options: &NodeGetOptions,
+	) -> Result<PinnedNode, SchemeError<'a>> {
+		let end = match (options.get_read(), options.get_write()) {
+			(true, false) => PipeEnd::Reader,
+			(false, true) => PipeEnd::Writer,
+			_ => return Err(SchemeError::UrlAccessError(Cow::Borrowed(url))),
+		};
+		let mut pipes = self.pipes.lock().expect("poisoned lock");
+		let pipe = pipes.entry(url.path().to_owned()).or_default().clone();
+		{
+			let mut state = pipe.lock().expect("poisoned lock");
+			match end {
+				PipeEnd::Reader => {
+					state.readers += 1;
+					state.had_reader = true;
+				}
+				PipeEnd::Writer => {
+					state.writers += 1;
+					state.had_writer = true;
+				}
+			}
+		}
+		Ok(Box::pin(PipeNode {
+			pipes: self.pipes.clone(),
+			path: url.path().to_owned(),
+			pipe,
+			capacity: self.capacity,
+			end,
+			open: true,
+		}))
+	}
+
+	async fn remove_node<'a>(
+		&self,
+		_vfs: &Vfs,
+		url: &'a Url,
+		_force: bool,
+	) -> Result<(), SchemeError<'a>> {
+		// Open ends keep using the removed pipe, the next open of the url starts a new one
+		match self.pipes.lock().expect("poisoned lock").remove(url.path()) {
+			Some(_pipe) => Ok(()),
+			None => Err(SchemeError::NodeDoesNotExist(Cow::Borrowed(url.path()))),
+		}
+	}
+
+	async fn metadata<'a>(
+		&self,
+		_vfs: &Vfs,
+		url: &'a Url,
+	) -> Result<NodeMetadata, SchemeError<'a>> {
+		if self
+			.pipes
+			.lock()
+			.expect("poisoned lock")
+			.contains_key(url.path())
+		{
+			Ok(NodeMetadata {
+				is_node: true,
+				len: None,
+				modified: None,
+				..Default::default()
+			})
+		} else {
+			Err(SchemeError::NodeDoesNotExist(Cow::Borrowed(url.path())))
+		}
+	}
+
+	async fn read_dir<'s, 'a>(
+		&'s self,
+		_vfs: &'s Vfs,
+		url: &'a Url,
+	) -> Result<BorrowedReadDirStream<'s>, SchemeError<'a>> {
+		let path = url.path();
+		let path = if path.ends_with('/') {
+			Cow::Borrowed(path)
+		} else if self.pipes.lock().expect("poisoned lock").contains_key(path) {
+			return Err(SchemeError::NotADirectory(Cow::Borrowed(path)));
+		} else {
+			Cow::Owned(format!("{}/", path))
+		};
+		let mut names: Vec<String> = self
+			.pipes
+			.lock()
+			.expect("poisoned lock")
+			.keys()
+			.filter(|name| name.starts_with(&*path))
+			.cloned()
+			.collect();
+		names.sort();
+		let url = url.clone();
+		let entries = names.into_iter().map(move |name| {
+			let mut url = url.clone();
+			url.set_path(&name);
+			NodeEntry::new(url)
+		});
+		Ok(Box::pin(futures_lite::stream::iter(entries)))
+	}
+}
+
+pub struct PipeNode {
+	pipes: Arc<Mutex<PipeMap>>,
+	path: String,
+	pipe: Arc<Mutex<Pipe>>,
+	capacity: usize,
+	end: PipeEnd,
+	/// Cleared once a writer end is closed, so it stops counting as a writer before it is dropped.
+	open: bool,
+}
+
+impl PipeNode {
+	fn release(&mut self) {
+		if !self.open {
+			return;
+		}
+		self.open = false;
+		let mut pipes = self.pipes.lock().expect("poisoned lock");
+		let mut state = self.pipe.lock().expect("poisoned lock");
+		match self.end {
+			PipeEnd::Reader => {
+				state.readers -= 1;
+				if state.readers == 0 {
+					Pipe::wake(&mut state.write_wakers);
+				}
+			}
+			PipeEnd::Writer => {
+				state.writers -= 1;
+				if state.writers == 0 {
+					Pipe::wake(&mut state.read_wakers);
+				}
+			}
+		}
+		if state.readers == 0
+			&& state.writers == 0
+			&& pipes
+				.get(&self.path)
+				.is_some_and(|pipe| Arc::ptr_eq(pipe, &self.pipe))
+		{
+			pipes.remove(&self.path);
+		}
+	}
+}
+
+impl Drop for PipeNode {
+	fn drop(&mut self) {
+		self.release();
+	}
+}
+
+#[async_trait::async_trait]
+impl Node for PipeNode {
+	fn is_reader(&self) -> bool {
+		self.end == PipeEnd::Reader
+	}
+
+	fn is_writer(&self) -> bool {
+		self.end == PipeEnd::Writer
+	}
+
+	fn is_seeker(&self) -> bool {
+		false
+	}
+}
+
+impl AsyncRead for PipeNode {
+	fn poll_read(
+		self: Pin<&mut Self>,
+		cx: &mut Context<'_>,
+		buf: &mut [u8],
+	) -> Poll<std::io::Result<usize>> {
+		if self.end != PipeEnd::Reader {
+			return poll_io_err();
+		}
+		let mut state = self.pipe.lock().expect("poisoned lock");
+		if state.buffer.is_empty() {
+			if state.had_writer && state.writers == 0 {
+				return Poll::Ready(Ok(0));
+			}
+			state.read_wakers.push(cx.waker().clone());
+			return Poll::Pending;
+		}
+		let amt = state.buffer.len().min(buf.len());
+		for (byte, buffered) in buf.iter_mut().zip(state.buffer.drain(..amt)) {
+			*byte = buffered;
+		}
+		Pipe::wake(&mut state.write_wakers);
+		Poll::Ready(Ok(amt))
+	}
+}
+
+impl AsyncWrite for PipeNode {
+	fn poll_write(
+		self: Pin<&mut Self>,
+		cx: &mut Context<'_>,
+		buf: &[u8],
+	) -> Poll<std::io::Result<usize>> {
+		if self.end != PipeEnd::Writer || !self.open {
+			return poll_io_err();
+		}
+		let capacity = self.capacity;
+		let mut state = self.pipe.lock().expect("poisoned lock");
+		if state.had_reader && state.readers == 0 {
+			return Poll::Ready(Err(std::io::ErrorKind::BrokenPipe.into()));
+		}
+		let amt = capacity.saturating_sub(state.buffer.len()).min(buf.len());
+		if amt == 0 && !buf.is_empty() {
+			state.write_wakers.push(cx.waker().clone());
+			return Poll::Pending;
+		}
+		state.buffer.extend(&buf[..amt]);
+		Pipe::wake(&mut state.read_wakers);
+		Poll::Ready(Ok(amt))
+	}
+
+	fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+		if self.end != PipeEnd::Writer {
+			return poll_io_err();
+		}
+		Poll::Ready(Ok(()))
+	}
+
+	/// Stops this end counting as a writer, once no writers are left readers get EOF.
+	fn poll_close(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+		if self.end != PipeEnd::Writer {
+			return poll_io_err();
+		}
+		self.release();
+		Poll::Ready(Ok(()))
+	}
+}
+
+impl AsyncSeek for PipeNode {
+	fn poll_seek(
+		self: Pin<&mut Self>,
+		_cx: &mut Context<'_>,
+		_pos: SeekFrom,
+	) -> Poll<std::io::Result<u64>> {
+		poll_io_err()
+	}
+}
+
+#[cfg(test)]
+#[cfg(feature = "backend_tokio")]
+mod async_tokio_tests {
+	use crate::scheme::NodeGetOptions;
+	use crate::{PipeScheme, Vfs};
+	use futures_lite::{AsyncReadExt, AsyncWriteExt, StreamExt};
+
+	#[tokio::test]
+	async fn pipe_between_tasks() {
+		let vfs = Vfs::empty();
+		// Small enough that the writer has to wait on the reader
+		vfs.add_scheme("pipe", PipeScheme::new(4)).unwrap();
+		let mut reader = vfs
+			.get_node_at("pipe:/channel", &NodeGetOptions::new().read(true))
+			.await
+			.unwrap();
+		let mut writer = vfs
+			.get_node_at("pipe:/channel", &NodeGetOptions::new().write(true))
+			.await
+			.unwrap();
+		let listed: Vec<_> = vfs
+			.read_dir_at("pipe:/")
+			.await
+			.unwrap()
+			.map(|entry| entry.url.to_string())
+			.collect()
+			.await;
+		assert_eq!(listed, ["pipe:/channel"]);
+
+		let writing = tokio::spawn(async move {
+			for i in 0..100 {
+				writer
+					.write_all(format!("line {}\n", i).as_bytes())
+					.await
+					.unwrap();
+			}
+		});
+		let reading = tokio::spawn(async move {
+			let mut read = String::new();
+			reader.read_to_string(&mut read).await.unwrap();
+			read
+		});
+		writing.await.unwrap();
+		let read = reading.await.unwrap();
+		let expected: String = (0..100).map(|i| format!("line {}\n", i)).collect();
+		assert_eq!(read, expected);
+		assert!(
+			vfs.metadata_at("pipe:/channel").await.is_err(),
+			"gone once both ends are dropped"
+		);
+	}
+
+	#[tokio::test]
+	async fn pipe_broken() {
+		let vfs = Vfs::empty();
+		vfs.add_scheme("pipe", PipeScheme::default()).unwrap();
+		let reader = vfs
+			.get_node_at("pipe:/channel", &NodeGetOptions::new().read(true))
+			.await
+			.unwrap();
+		let mut writer = vfs
+			.get_node_at("pipe:/channel", &NodeGetOptions::new().write(true))
+			.await
+			.unwrap();
+		drop(reader);
+		let error = writer.write_all(b"nobody").await.unwrap_err();
+		assert_eq!(error.kind(), std::io::ErrorKind::BrokenPipe);
+		assert!(vfs
+			.get_node_at(
+				"pipe:/channel",
+				&NodeGetOptions::new().read(true).write(true)
+			)
+			.await
+			.is_err());
+	}
+}
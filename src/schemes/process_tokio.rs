@@ -0,0 +1,379 @@
+use crate::node::IsAllowed;
+use crate::scheme::{BorrowedReadDirStream, NodeEntry, NodeGetOptions, NodeMetadata};
+use crate::{Node, PinnedNode, Scheme, SchemeError, Vfs};
+use futures_lite::{ready, AsyncRead, AsyncSeek, AsyncWrite, Future};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::io::SeekFrom;
+use std::pin::Pin;
+use std::process::{ExitStatus, Stdio};
+use std::task::{Context, Poll};
+use tokio::process::{ChildStdin, ChildStdout};
+use url::Url;
+
+enum ProcessArg {
+	Fixed(OsString),
+	Query(String),
+}
+
+/// The template of a command that a `TokioProcessScheme` is allowed to run.  The program and the
+/// fixed arguments are set here, a url can only fill in the arguments declared with `query_arg`,
+/// each of which becomes exactly one argument.  The process is spawned directly and never through a
+/// shell, so a query value cannot add arguments or run anything else.
+pub struct ProcessCommand {
+	program: OsString,
+	args: Vec<ProcessArg>,
+	writable: bool,
+}
+
+impl ProcessCommand {
+	pub fn new(program: impl Into<OsString>) -> Self {
+		Self {
+			program: program.into(),
+			args: Vec::new(),
+			writable: false,
+		}
+	}
+
+	/// Appends a fixed argument.
+	pub fn arg(mut self, arg: impl Into<OsString>) -> Self {
+		self.args.push(ProcessArg::Fixed(arg.into()));
+		self
+	}
+
+	/// Appends an argument taken from the `name` query parameter of the url, which then has to be
+	/// given exactly once.
+	pub fn query_arg(mut self, name: impl Into<String>) -> Self {
+		self.args.push(ProcessArg::Query(name.into()));
+		self
+	}
+
+	/// Whether nodes can be opened for writing, which pipes what is written to the stdin of the
+	/// process, `false` by default.
+	pub fn writable(self, writable: bool) -> Self {
+		Self { writable, ..self }
+	}
+
+	fn argv(&self, url: &Url) -> Result<Vec<OsString>, SchemeError<'static>> {
+		let mut query: BTreeMap<String, String> = BTreeMap::new();
+		for (name, value) in url.query_pairs() {
+			let declared = self
+				.args
+				.iter()
+				.any(|arg| matches!(arg, ProcessArg::Query(arg_name) if *arg_name == name));
+			if !declared {
+				return Err("query parameter is not an argument of the command".into());
+			}
+			if query
+				.insert(name.into_owned(), value.into_owned())
+				.is_some()
+			{
+				return Err("query parameter given more than once".into());
+			}
+		}
+		self.args
+			.iter()
+			.map(|arg| match arg {
+				ProcessArg::Fixed(arg) => Ok(arg.clone()),
+				ProcessArg::Query(name) => query
+					.get(name)
+					.map(OsString::from)
+					.ok_or_else(|| "query parameter for a command argument is missing".into()),
+			})
+			.collect()
+	}
+}
+
+/// Presents the output of registered commands as read-only nodes, `proc:/name?arg=value` runs the
+/// command registered as `name` and the node streams its stdout.  Only registered commands can run,
+/// see `ProcessCommand` for how a url fills in their arguments.  A process that exits with a failure
+/// fails the read that reaches the end of its output, and it is killed if the node is dropped
+/// before then.
+#[derive(Default)]
+pub struct TokioProcessScheme {
+	commands: BTreeMap<String, ProcessCommand>,
+}
+
+impl TokioProcessScheme {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Registers `command` to run for the url path `/name`.
+	pub fn command(mut self, name: impl Into<String>, command: ProcessCommand) -> Self {
+		self.commands.insert(name.into(), command);
+		self
+	}
+
+	fn command_for<'a>(&self, url: &'a Url) -> Result<&ProcessCommand, SchemeError<'a>> {
+		url.path()
+			.strip_prefix('/')
+			.and_then(|name| self.commands.get(name))
+			.ok_or(SchemeError::NodeDoesNotExist(Cow::Borrowed(url.path())))
+	}
+}
+
+#[async_trait::async_trait]
+impl Scheme for TokioProcessScheme {
+	async fn get_node<'a>(
+		&self,
+		_vfs: &Vfs,
+		url: &'a Url,
+		options: &NodeGetOptions,
+	) -> Result<PinnedNode, SchemeError<'a>> {
+		let command = self.command_for(url)?;
+		if options.get_write() && !command.writable {
+			return Err(SchemeError::Unsupported("write"));
+		}
+		let argv = command.argv(url)?;
+		let pipe = |piped: bool| if piped { Stdio::piped() } else { Stdio::null() };
+		let mut child = tokio::process::Command::new(&command.program)
+			.args(argv)
+			.stdin(pipe(options.get_write()))
+			.stdout(pipe(options.get_read()))
+			.stderr(Stdio::null())
+			.kill_on_drop(true)
+			.spawn()?;
+		let stdin = child.stdin.take();
+		let stdout = child.stdout.take();
+		Ok(Box::pin(TokioProcessNode {
+			stdin,
+			stdout,
+			status: Some(Box::pin(async move { child.wait().await })),
+		}))
+	}
+
+	async fn remove_node<'a>(
+		&self,
+		_vfs: &Vfs,
+		_url: &'a Url,
+		_force: bool,
+	) -> Result<(), SchemeError<'a>> {
+		Err(SchemeError::Unsupported("remove_node"))
+	}
+
+	async fn metadata<'a>(
+		&self,
+		_vfs: &Vfs,
+		url: &'a Url,
+	) -> Result<NodeMetadata, SchemeError<'a>> {
+		if url.path() == "/" {
+			return Ok(NodeMetadata {
+				is_node: false,
+				len: None,
+				modified: None,
+				..Default::default()
+			});
+		}
+		self.command_for(url)?;
+		// How much a process outputs is only known once it exited
+		Ok(NodeMetadata {
+			is_node: true,
+			len: None,
+			modified: None,
+			..Default::default()
+		})
+	}
+
+	async fn read_dir<'s, 'a>(
+		&'s self,
+		_vfs: &'s Vfs,
+		url: &'a Url,
+	) -> Result<BorrowedReadDirStream<'s>, SchemeError<'a>> {
+		if url.path() != "/" {
+			return Err(SchemeError::NodeDoesNotExist(Cow::Borrowed(url.path())));
+		}
+		let entries: Vec<NodeEntry> = self
+			.commands
+			.keys()
+			.filter_map(|name| url.join(name).ok())
+			.map(NodeEntry::new)
+			.collect();
+		Ok(Box::pin(futures_lite::stream::iter(entries)))
+	}
+}
+
+type ExitFuture = Pin<Box<dyn Future<Output = std::io::Result<ExitStatus>> + Send + Sync>>;
+
+pub struct TokioProcessNode {
+	/// `None` when not opened for writing or once closed.
+	stdin: Option<ChildStdin>,
+	/// `None` when not opened for reading.
+	stdout: Option<ChildStdout>,
+	/// Owns the child, so dropping the node kills it, `None` once it exited.
+	status: Option<ExitFuture>,
+}
+
+#[async_trait::async_trait]
+impl Node for TokioProcessNode {
+	fn is_reader(&self) -> bool {
+		self.stdout.is_some()
+	}
+
+	fn is_writer(&self) -> bool {
+		self.stdin.is_some()
+	}
+
+	fn is_seeker(&self) -> bool {
+		false
+	}
+}
+
+impl AsyncRead for TokioProcessNode {
+	fn poll_read(
+		mut self: Pin<&mut Self>,
+		cx: &mut Context<'_>,
+		buf: &mut [u8],
+	) -> Poll<std::io::Result<usize>> {
+		let this = &mut *self;
+		let stdout = match &mut this.stdout {
+			Some(stdout) => stdout,
+			None => return Poll::Ready(Err(std::io::Error::from_raw_os_error(13))),
+		};
+		let amt = {
+			let mut buf = tokio::io::ReadBuf::new(buf);
+			ready!(tokio::io::AsyncRead::poll_read(
+				Pin::new(stdout),
+				cx,
+				&mut buf
+			))?;
+			buf.filled().len()
+		};
+		if amt > 0 || buf.is_empty() {
+			return Poll::Ready(Ok(amt));
+		}
+		// The end of the output, which only counts once the process exited successfully
+		if let Some(status) = &mut this.status {
+			let status = ready!(status.as_mut().poll(cx))?;
+			this.status = None;
+			if !status.success() {
+				return Poll::Ready(Err(std::io::Error::other(format!(
+					"process exited with {}",
+					status
+				))));
+			}
+		}
+		Poll::Ready(Ok(0))
+	}
+}
+
+impl AsyncWrite for TokioProcessNode {
+	fn poll_write(
+		mut self: Pin<&mut Self>,
+		cx: &mut Context<'_>,
+		buf: &[u8],
+	) -> Poll<std::io::Result<usize>> {
+		match &mut self.stdin {
+			Some(stdin) => tokio::io::AsyncWrite::poll_write(Pin::new(stdin), cx, buf),
+			None => Poll::Ready(Err(std::io::Error::from_raw_os_error(13))),
+		}
+	}
+
+	fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+		match &mut self.stdin {
+			Some(stdin) => tokio::io::AsyncWrite::poll_flush(Pin::new(stdin), cx),
+			None => Poll::Ready(Err(std::io::Error::from_raw_os_error(13))),
+		}
+	}
+
+	fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+		// Closing stdin is how the process learns there is no more input
+		if let Some(stdin) = &mut self.stdin {
+			ready!(tokio::io::AsyncWrite::poll_shutdown(Pin::new(stdin), cx))?;
+			self.stdin = None;
+		}
+		Poll::Ready(Ok(()))
+	}
+}
+
+impl AsyncSeek for TokioProcessNode {
+	fn poll_seek(
+		self: Pin<&mut Self>,
+		_cx: &mut Context<'_>,
+		_pos: SeekFrom,
+	) -> Poll<std::io::Result<u64>> {
+		false.into_poll_io(0)
+	}
+}
+
+#[cfg(test)]
+#[cfg(unix)]
+mod async_tokio_tests {
+	use crate::scheme::NodeGetOptions;
+	use crate::{ProcessCommand, SchemeError, TokioProcessScheme, Vfs, VfsError};
+	use futures_lite::{AsyncReadExt, AsyncWriteExt};
+
+	fn process_vfs() -> Vfs {
+		let vfs = Vfs::empty();
+		vfs.add_scheme(
+			"proc",
+			TokioProcessScheme::new()
+				.command("echo", ProcessCommand::new("echo").query_arg("text"))
+				.command("false", ProcessCommand::new("false"))
+				.command("cat", ProcessCommand::new("cat").writable(true)),
+		)
+		.unwrap();
+		vfs
+	}
+
+	#[tokio::test]
+	async fn process_output() {
+		let vfs = process_vfs();
+		let mut node = vfs
+			.get_node_at(
+				"proc:/echo?text=hello%20there%3B%20ls",
+				&NodeGetOptions::new().read(true),
+			)
+			.await
+			.unwrap();
+		let mut buffer = String::new();
+		node.read_to_string(&mut buffer).await.unwrap();
+		assert_eq!(buffer, "hello there; ls\n");
+		assert_eq!(vfs.metadata_at("proc:/echo").await.unwrap().len, None);
+
+		let mut node = vfs
+			.get_node_at("proc:/false", &NodeGetOptions::new().read(true))
+			.await
+			.unwrap();
+		assert!(node.read_to_end(&mut Vec::new()).await.is_err());
+	}
+
+	#[tokio::test]
+	async fn process_input() {
+		let vfs = process_vfs();
+		let mut node = vfs
+			.get_node_at("proc:/cat", &NodeGetOptions::new().read(true).write(true))
+			.await
+			.unwrap();
+		node.write_all(b"piped").await.unwrap();
+		node.close().await.unwrap();
+		let mut buffer = String::new();
+		node.read_to_string(&mut buffer).await.unwrap();
+		assert_eq!(buffer, "piped");
+	}
+
+	#[tokio::test]
+	async fn process_allowlist() {
+		let vfs = process_vfs();
+		let read = NodeGetOptions::new().read(true);
+		assert!(matches!(
+			vfs.get_node_at("proc:/sh?c=ls", &read).await,
+			Err(VfsError::SchemeError(SchemeError::NodeDoesNotExist(_)))
+		));
+		assert!(vfs.get_node_at("proc:/echo", &read).await.is_err());
+		assert!(vfs
+			.get_node_at("proc:/echo?text=a&text=b", &read)
+			.await
+			.is_err());
+		assert!(vfs
+			.get_node_at("proc:/echo?text=a&other=b", &read)
+			.await
+			.is_err());
+		assert!(matches!(
+			vfs.get_node_at("proc:/echo?text=a", &read.clone().write(true))
+				.await,
+			Err(VfsError::SchemeError(SchemeError::Unsupported(_)))
+		));
+	}
+}
@@ -0,0 +1,496 @@
+use crate::scheme::{BorrowedReadDirStream, NodeGetOptions, NodeMetadata, WatchStream};
+use crate::walk::WalkOptions;
+use crate::{Node, PinnedNode, Scheme, SchemeError, Vfs, VfsError};
+use futures_lite::{AsyncRead, AsyncSeek, AsyncWrite, StreamExt};
+use std::borrow::Cow;
+use std::io::SeekFrom;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::SystemTime;
+use url::Url;
+
+/// How much of the wrapped scheme of a `QuotaScheme` is in use.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct QuotaUsage {
+	/// The total length of the nodes.
+	pub bytes: u64,
+	/// How many nodes there are, directories are not counted.
+	pub nodes: u64,
+}
+
+/// Wraps a scheme and keeps the total length and the count of its nodes under configured limits,
+/// such as for the storage of a sandboxed user script.  Creating a node past the node limit fails
+/// with `SchemeError::QuotaExceeded`, and so does a write that would grow the nodes past the byte
+/// limit, as the error of an IO error, once whatever still fits is written.  Usage starts at zero
+/// and is then kept up to date with what is written, truncated, copied and removed through the
+/// scheme, a scheme that already holds nodes should be measured with `recount` first.  Changes
+/// made behind the back of the vfs are not seen, and nodes written at the same time may each
+/// write up to the limit before seeing what the others wrote.  Reading passes through untouched.
+pub struct QuotaScheme {
+	scheme: Box<dyn Scheme>,
+	max_bytes: Option<u64>,
+	max_nodes: Option<u64>,
+	usage: Arc<Mutex<QuotaUsage>>,
+}
+
+impl QuotaScheme {
+	pub fn new(scheme: impl Scheme) -> Self {
+		Self::new_boxed(Box::new(scheme))
+	}
+
+	pub fn new_boxed(scheme: Box<dyn Scheme>) -> Self {
+		Self {
+			scheme,
+			max_bytes: None,
+			max_nodes: None,
+			usage: Arc::default(),
+		}
+	}
+
+	/// The most the lengths of all nodes may add up to, unlimited by default.
+	pub fn with_max_bytes(self, max_bytes: u64) -> Self {
+		Self {
+			max_bytes: Some(max_bytes),
+			..self
+		}
+	}
+
+	/// The most nodes there may be, unlimited by default.
+	pub fn with_max_nodes(self, max_nodes: u64) -> Self {
+		Self {
+			max_nodes: Some(max_nodes),
+			..self
+		}
+	}
+
+	/// Starts from `usage` instead of from zero, such as one saved from an earlier run.
+	pub fn with_usage(self, usage: QuotaUsage) -> Self {
+		*self.usage.lock().expect("poisoned lock") = usage;
+		self
+	}
+
+	pub fn inner(&self) -> &dyn Scheme {
+		&*self.scheme
+	}
+
+	pub fn max_bytes(&self) -> Option<u64> {
+		self.max_bytes
+	}
+
+	pub fn max_nodes(&self) -> Option<u64> {
+		self.max_nodes
+	}
+
+	pub fn usage(&self) -> QuotaUsage {
+		*self.usage.lock().expect("poisoned lock")
+	}
+
+	/// Measures the usage by walking the directory at `url`, a url of this scheme such as its root,
+	/// and replaces the tracked usage with it.
+	pub async fn recount<'a>(&self, vfs: &Vfs, url: &'a Url) -> Result<QuotaUsage, VfsError<'a>> {
+		let usage = self.measure(vfs, url).await?;
+		*self.usage.lock().expect("poisoned lock") = usage;
+		Ok(usage)
+	}
+
+	async fn measure<'a>(&self, vfs: &Vfs, url: &'a Url) -> Result<QuotaUsage, VfsError<'a>> {
+		let mut usage = QuotaUsage::default();
+		let mut entries = vfs.walk_dir(url, WalkOptions::new()).await?;
+		while let Some(entry) = entries.next().await {
+			let entry = entry?;
+			let metadata = match entry.metadata {
+				Some(metadata) => metadata,
+				None => self.scheme.metadata(vfs, &entry.url).await?,
+			};
+			usage.bytes += node_len(&metadata);
+			usage.nodes += 1;
+		}
+		Ok(usage)
+	}
+
+	/// The usage of the node or directory at `url` of the wrapped scheme, `None` if there is none.
+	async fn usage_at(&self, vfs: &Vfs, url: &Url) -> Option<QuotaUsage> {
+		let metadata = self.scheme.metadata(vfs, url).await.ok()?;
+		if metadata.is_node {
+			Some(QuotaUsage {
+				bytes: node_len(&metadata),
+				nodes: 1,
+			})
+		} else {
+			self.measure(vfs, url).await.ok()
+		}
+	}
+
+	/// Adds `added` and takes away `removed`, failing instead if that would go past a limit.
+	fn apply<'a>(
+		&self,
+		url: &'a Url,
+		added: QuotaUsage,
+		removed: QuotaUsage,
+	) -> Result<(), SchemeError<'a>> {
+		let mut usage = self.usage.lock().expect("poisoned lock");
+		let bytes = (usage.bytes + added.bytes).saturating_sub(removed.bytes);
+		let nodes = (usage.nodes + added.nodes).saturating_sub(removed.nodes);
+		let over = |used: u64, current: u64, max: Option<u64>| {
+			used > current && max.is_some_and(|max| used > max)
+		};
+		if over(bytes, usage.bytes, self.max_bytes) || over(nodes, usage.nodes, self.max_nodes) {
+			return Err(SchemeError::QuotaExceeded(Cow::Borrowed(url.path())));
+		}
+		*usage = QuotaUsage { bytes, nodes };
+		Ok(())
+	}
+
+	fn release(&self, removed: QuotaUsage) {
+		let mut usage = self.usage.lock().expect("poisoned lock");
+		usage.bytes = usage.bytes.saturating_sub(removed.bytes);
+		usage.nodes = usage.nodes.saturating_sub(removed.nodes);
+	}
+}
+
+fn node_len(metadata: &NodeMetadata) -> u64 {
+	metadata.len.map_or(0, |(len, _)| len as u64)
+}
+
+#[async_trait::async_trait]
+impl Scheme for QuotaScheme {
+	async fn get_node<'a>(
+		&self,
+		vfs: &Vfs,
+		url: &'a Url,
+		options: &NodeGetOptions,
+	) -> Result<PinnedNode, SchemeError<'a>> {
+		if !options.get_write() && !options.get_append() {
+			return self.scheme.get_node(vfs, url, options).await;
+		}
+		let existing = self.usage_at(vfs, url).await;
+		let created = match existing {
+			None if options.get_create() => QuotaUsage { bytes: 0, nodes: 1 },
+			_ => QuotaUsage::default(),
+		};
+		// The new node is counted before it is created so two creates cannot both take the last
+		self.apply(url, created, QuotaUsage::default())?;
+		let node = match self.scheme.get_node(vfs, url, options).await {
+			Ok(node) => node,
+			Err(error) => {
+				self.release(created);
+				return Err(error);
+			}
+		};
+		let mut len = existing.map_or(0, |existing| existing.bytes);
+		if options.get_truncate() {
+			self.release(QuotaUsage {
+				bytes: len,
+				nodes: 0,
+			});
+			len = 0;
+		}
+		Ok(Box::pin(QuotaNode {
+			node,
+			path: url.path().to_owned(),
+			usage: self.usage.clone(),
+			max_bytes: self.max_bytes,
+			len,
+			cursor: 0,
+			append: options.get_append(),
+		}))
+	}
+
+	async fn remove_node<'a>(
+		&self,
+		vfs: &Vfs,
+		url: &'a Url,
+		force: bool,
+	) -> Result<(), SchemeError<'a>> {
+		let removed = self.usage_at(vfs, url).await.unwrap_or_default();
+		self.scheme.remove_node(vfs, url, force).await?;
+		self.release(removed);
+		Ok(())
+	}
+
+	async fn metadata<'a>(&self, vfs: &Vfs, url: &'a Url) -> Result<NodeMetadata, SchemeError<'a>> {
+		self.scheme.metadata(vfs, url).await
+	}
+
+	async fn read_dir<'s, 'a>(
+		&'s self,
+		vfs: &'s Vfs,
+		url: &'a Url,
+	) -> Result<BorrowedReadDirStream<'s>, SchemeError<'a>> {
+		self.scheme.read_dir(vfs, url).await
+	}
+
+	async fn read_small_file<'a>(
+		&self,
+		vfs: &Vfs,
+		url: &'a Url,
+		max_len: usize,
+	) -> Result<Option<Vec<u8>>, SchemeError<'a>> {
+		self.scheme.read_small_file(vfs, url, max_len).await
+	}
+
+	#[cfg(feature = "bytes")]
+	async fn read_bytes<'a>(
+		&self,
+		vfs: &Vfs,
+		url: &'a Url,
+	) -> Result<Option<bytes::Bytes>, SchemeError<'a>> {
+		self.scheme.read_bytes(vfs, url).await
+	}
+
+	async fn canonicalize<'a>(&self, vfs: &Vfs, url: &'a Url) -> Result<Url, SchemeError<'a>> {
+		self.scheme.canonicalize(vfs, url).await
+	}
+
+	async fn set_modified<'a>(
+		&self,
+		vfs: &Vfs,
+		url: &'a Url,
+		modified: SystemTime,
+	) -> Result<(), SchemeError<'a>> {
+		self.scheme.set_modified(vfs, url, modified).await
+	}
+
+	async fn create_dir<'a>(
+		&self,
+		vfs: &Vfs,
+		url: &'a Url,
+		parents: bool,
+	) -> Result<(), SchemeError<'a>> {
+		self.scheme.create_dir(vfs, url, parents).await
+	}
+
+	async fn copy_node<'a>(
+		&self,
+		vfs: &Vfs,
+		from: &'a Url,
+		to: &'a Url,
+	) -> Result<(), SchemeError<'a>> {
+		let copied = self.usage_at(vfs, from).await.unwrap_or_default();
+		let replaced = self.usage_at(vfs, to).await.unwrap_or_default();
+		self.apply(to, copied, replaced)?;
+		if let Err(error) = self.scheme.copy_node(vfs, from, to).await {
+			let mut usage = self.usage.lock().expect("poisoned lock");
+			usage.bytes = (usage.bytes + replaced.bytes).saturating_sub(copied.bytes);
+			usage.nodes = (usage.nodes + replaced.nodes).saturating_sub(copied.nodes);
+			return Err(error);
+		}
+		Ok(())
+	}
+
+	async fn rename_node<'a>(
+		&self,
+		vfs: &Vfs,
+		from: &'a Url,
+		to: &'a Url,
+	) -> Result<(), SchemeError<'a>> {
+		let replaced = self.usage_at(vfs, to).await.unwrap_or_default();
+		self.scheme.rename_node(vfs, from, to).await?;
+		self.release(replaced);
+		Ok(())
+	}
+
+	async fn watch<'a>(&self, vfs: &Vfs, url: &'a Url) -> Result<WatchStream, SchemeError<'a>> {
+		self.scheme.watch(vfs, url).await
+	}
+}
+
+/// A node of a `QuotaScheme` opened for writing, tracking its length to account for growth.
+pub struct QuotaNode {
+	node: PinnedNode,
+	path: String,
+	usage: Arc<Mutex<QuotaUsage>>,
+	max_bytes: Option<u64>,
+	len: u64,
+	cursor: u64,
+	append: bool,
+}
+
+impl Node for QuotaNode {
+	fn is_reader(&self) -> bool {
+		self.node.is_reader()
+	}
+
+	fn is_writer(&self) -> bool {
+		self.node.is_writer()
+	}
+
+	fn is_seeker(&self) -> bool {
+		self.node.is_seeker()
+	}
+
+	fn known_len(&self) -> Option<u64> {
+		self.node.known_len()
+	}
+
+	fn is_at_end(&self) -> Option<bool> {
+		self.node.is_at_end()
+	}
+}
+
+impl AsyncRead for QuotaNode {
+	fn poll_read(
+		self: Pin<&mut Self>,
+		cx: &mut Context<'_>,
+		buf: &mut [u8],
+	) -> Poll<std::io::Result<usize>> {
+		let this = self.get_mut();
+		let amt = futures_lite::ready!(this.node.as_mut().poll_read(cx, buf))?;
+		this.cursor += amt as u64;
+		Poll::Ready(Ok(amt))
+	}
+}
+
+impl AsyncWrite for QuotaNode {
+	fn poll_write(
+		self: Pin<&mut Self>,
+		cx: &mut Context<'_>,
+		buf: &[u8],
+	) -> Poll<std::io::Result<usize>> {
+		let this = self.get_mut();
+		if this.append {
+			this.cursor = this.len;
+		}
+		let mut buf = buf;
+		if let Some(max_bytes) = this.max_bytes {
+			let used = this.usage.lock().expect("poisoned lock").bytes;
+			let fits = (this.len + max_bytes.saturating_sub(used)).saturating_sub(this.cursor);
+			if fits == 0 && !buf.is_empty() {
+				let error = SchemeError::QuotaExceeded(Cow::Owned(this.path.clone()));
+				return Poll::Ready(Err(std::io::Error::other(error)));
+			}
+			buf = &buf[..buf.len().min(fits as usize)];
+		}
+		let amt = futures_lite::ready!(this.node.as_mut().poll_write(cx, buf))?;
+		this.cursor += amt as u64;
+		if this.cursor > this.len {
+			this.usage.lock().expect("poisoned lock").bytes += this.cursor - this.len;
+			this.len = this.cursor;
+		}
+		Poll::Ready(Ok(amt))
+	}
+
+	fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+		self.get_mut().node.as_mut().poll_flush(cx)
+	}
+
+	fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+		self.get_mut().node.as_mut().poll_close(cx)
+	}
+}
+
+impl AsyncSeek for QuotaNode {
+	fn poll_seek(
+		self: Pin<&mut Self>,
+		cx: &mut Context<'_>,
+		pos: SeekFrom,
+	) -> Poll<std::io::Result<u64>> {
+		let this = self.get_mut();
+		let position = futures_lite::ready!(this.node.as_mut().poll_seek(cx, pos))?;
+		this.cursor = position;
+		Poll::Ready(Ok(position))
+	}
+}
+
+#[cfg(test)]
+#[cfg(feature = "backend_tokio")]
+#[cfg(feature = "in_memory")]
+mod async_tokio_tests {
+	use crate::scheme::NodeGetOptions;
+	use crate::transfer::ConflictPolicy;
+	use crate::{MemoryScheme, QuotaScheme, QuotaUsage, SchemeError, Vfs, VfsError};
+	use futures_lite::AsyncWriteExt;
+	use url::Url;
+
+	fn quota_exceeded(error: &std::io::Error) -> bool {
+		matches!(
+			error
+				.get_ref()
+				.and_then(|error| error.downcast_ref::<SchemeError>()),
+			Some(SchemeError::QuotaExceeded(_))
+		)
+	}
+
+	#[tokio::test]
+	async fn quota_limits() {
+		let vfs = Vfs::empty();
+		vfs.add_scheme(
+			"sandbox",
+			QuotaScheme::new(MemoryScheme::default())
+				.with_max_bytes(10)
+				.with_max_nodes(2),
+		)
+		.unwrap();
+		let usage = || vfs.get_scheme_as::<QuotaScheme>("sandbox").unwrap().usage();
+
+		let mut node = vfs
+			.get_node_at("sandbox:/a", &NodeGetOptions::new().create_new(true))
+			.await
+			.unwrap();
+		node.write_all(b"123456").await.unwrap();
+		node.close().await.unwrap();
+		assert_eq!(usage(), QuotaUsage { bytes: 6, nodes: 1 });
+
+		let mut node = vfs
+			.get_node_at("sandbox:/b", &NodeGetOptions::new().create_new(true))
+			.await
+			.unwrap();
+		assert_eq!(node.write(b"abcdef").await.unwrap(), 4, "only what fits");
+		let error = node.write(b"ef").await.unwrap_err();
+		assert!(quota_exceeded(&error), "{:?}", error);
+		node.close().await.unwrap();
+		assert_eq!(
+			usage(),
+			QuotaUsage {
+				bytes: 10,
+				nodes: 2
+			}
+		);
+		assert_eq!(vfs.read_to_vec_at("sandbox:/b").await.unwrap(), b"abcd");
+
+		assert!(matches!(
+			vfs.get_node_at("sandbox:/c", &NodeGetOptions::new().create_new(true))
+				.await,
+			Err(VfsError::SchemeError(SchemeError::QuotaExceeded(_)))
+		));
+		assert!(matches!(
+			vfs.copy_node_at("sandbox:/a", "sandbox:/c", ConflictPolicy::Fail)
+				.await,
+			Err(VfsError::SchemeError(SchemeError::QuotaExceeded(_)))
+		));
+		assert_eq!(
+			usage(),
+			QuotaUsage {
+				bytes: 10,
+				nodes: 2
+			}
+		);
+
+		// Overwriting in place and truncating free what they replace
+		let mut node = vfs
+			.get_node_at("sandbox:/a", &NodeGetOptions::new().truncate(true))
+			.await
+			.unwrap();
+		node.write_all(b"12").await.unwrap();
+		node.close().await.unwrap();
+		assert_eq!(usage(), QuotaUsage { bytes: 6, nodes: 2 });
+		vfs.remove_node_at("sandbox:/b", false).await.unwrap();
+		assert_eq!(usage(), QuotaUsage { bytes: 2, nodes: 1 });
+		vfs.copy_node_at("sandbox:/a", "sandbox:/c", ConflictPolicy::Fail)
+			.await
+			.unwrap();
+		assert_eq!(usage(), QuotaUsage { bytes: 4, nodes: 2 });
+		vfs.rename_node_at("sandbox:/c", "sandbox:/a")
+			.await
+			.unwrap();
+		assert_eq!(usage(), QuotaUsage { bytes: 2, nodes: 1 });
+
+		let quota = vfs.get_scheme_as::<QuotaScheme>("sandbox").unwrap();
+		let recounted = quota
+			.recount(&vfs, &Url::parse("sandbox:/").unwrap())
+			.await
+			.unwrap();
+		assert_eq!(recounted, QuotaUsage { bytes: 2, nodes: 1 });
+	}
+}
@@ -0,0 +1,343 @@
+#![allow(clippy::try_err)]
+
+use crate::io_util;
+use crate::node::poll_io_err;
+use crate::scheme::{BorrowedReadDirStream, NodeGetOptions, NodeMetadata};
+use crate::{Node, PinnedNode, Scheme, SchemeError, Vfs};
+use futures_lite::{AsyncRead, AsyncSeek, AsyncWrite};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::io::SeekFrom;
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::task::{Context, Poll};
+use url::Url;
+
+/// The content of every node a `RecordingScheme` read, keyed by url, for a `ReplayScheme` to
+/// serve later.  With the `serde` feature it can be saved alongside the tests that replay it.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Cassette {
+	nodes: BTreeMap<String, Vec<u8>>,
+}
+
+impl Cassette {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	pub fn get(&self, url: &Url) -> Option<&[u8]> {
+		self.nodes.get(url.as_str()).map(Vec::as_slice)
+	}
+
+	pub fn insert(&mut self, url: &Url, data: Vec<u8>) -> Option<Vec<u8>> {
+		self.nodes.insert(url.as_str().to_owned(), data)
+	}
+
+	pub fn len(&self) -> usize {
+		self.nodes.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.nodes.is_empty()
+	}
+
+	pub fn urls(&self) -> impl Iterator<Item = &str> {
+		self.nodes.keys().map(String::as_str)
+	}
+}
+
+/// Wraps a scheme and records the whole content of every node opened read-only through it into a
+/// `Cassette`.  The node is read in full when opened and served from memory.  Opens that can write
+/// and every other operation pass through to the wrapped scheme unrecorded.
+pub struct RecordingScheme {
+	scheme: Box<dyn Scheme>,
+	cassette: Mutex<Cassette>,
+}
+
+impl RecordingScheme {
+	pub fn new(scheme: impl Scheme) -> Self {
+		Self::new_boxed(Box::new(scheme))
+	}
+
+	pub fn new_boxed(scheme: Box<dyn Scheme>) -> Self {
+		Self {
+			scheme,
+			cassette: Mutex::new(Cassette::new()),
+		}
+	}
+
+	pub fn inner(&self) -> &dyn Scheme {
+		&*self.scheme
+	}
+
+	/// A copy of everything recorded so far.
+	pub fn cassette(&self) -> Cassette {
+		self.cassette.lock().expect("poisoned lock").clone()
+	}
+
+	pub fn into_cassette(self) -> Cassette {
+		self.cassette.into_inner().expect("poisoned lock")
+	}
+}
+
+#[async_trait::async_trait]
+impl Scheme for RecordingScheme {
+	async fn get_node<'a>(
+		&self,
+		vfs: &Vfs,
+		url: &'a Url,
+		options: &NodeGetOptions,
+	) -> Result<PinnedNode, SchemeError<'a>> {
+		let mut node = self.scheme.get_node(vfs, url, options).await?;
+		if options.get_write() {
+			return Ok(node);
+		}
+		let mut data = Vec::new();
+		io_util::read_to_end(&mut node, &mut data).await?;
+		self.cassette
+			.lock()
+			.expect("poisoned lock")
+			.insert(url, data.clone());
+		Ok(Box::pin(ReplayNode { data, cursor: 0 }))
+	}
+
+	async fn remove_node<'a>(
+		&self,
+		vfs: &Vfs,
+		url: &'a Url,
+		force: bool,
+	) -> Result<(), SchemeError<'a>> {
+		self.scheme.remove_node(vfs, url, force).await
+	}
+
+	async fn metadata<'a>(&self, vfs: &Vfs, url: &'a Url) -> Result<NodeMetadata, SchemeError<'a>> {
+		self.scheme.metadata(vfs, url).await
+	}
+
+	async fn read_dir<'s, 'a>(
+		&'s self,
+		vfs: &'s Vfs,
+		url: &'a Url,
+	) -> Result<BorrowedReadDirStream<'s>, SchemeError<'a>> {
+		self.scheme.read_dir(vfs, url).await
+	}
+}
+
+/// Serves the nodes of a previously recorded `Cassette` read-only, so tests can run without the
+/// scheme that was recorded.  Urls are matched in full, so the replay has to be registered under
+/// the same scheme name as the recording was.  Any url that was not recorded is an error.
+pub struct ReplayScheme {
+	cassette: Cassette,
+}
+
+impl ReplayScheme {
+	pub fn new(cassette: Cassette) -> Self {
+		Self { cassette }
+	}
+
+	pub fn cassette(&self) -> &Cassette {
+		&self.cassette
+	}
+
+	#[allow(clippy::needless_lifetimes)] // Clippy is wrong here, it is necessary
+	fn recorded<'a>(&self, url: &'a Url) -> Result<&[u8], SchemeError<'a>> {
+		Ok(self
+			.cassette
+			.get(url)
+			.ok_or("url was not recorded in the cassette")?)
+	}
+}
+
+#[async_trait::async_trait]
+impl Scheme for ReplayScheme {
+	async fn get_node<'a>(
+		&self,
+		_vfs: &Vfs,
+		url: &'a Url,
+		options: &NodeGetOptions,
+	) -> Result<PinnedNode, SchemeError<'a>> {
+		if options.get_write() {
+			return Err(SchemeError::UrlAccessError(Cow::Borrowed(url)));
+		}
+		let data = self.recorded(url)?.to_vec();
+		Ok(Box::pin(ReplayNode { data, cursor: 0 }))
+	}
+
+	async fn remove_node<'a>(
+		&self,
+		_vfs: &Vfs,
+		url: &'a Url,
+		_force: bool,
+	) -> Result<(), SchemeError<'a>> {
+		Err(SchemeError::UrlAccessError(Cow::Borrowed(url)))
+	}
+
+	async fn metadata<'a>(
+		&self,
+		_vfs: &Vfs,
+		url: &'a Url,
+	) -> Result<NodeMetadata, SchemeError<'a>> {
+		let len = self.recorded(url)?.len();
+		Ok(NodeMetadata {
+			is_node: true,
+			len: Some((len, Some(len))),
+			modified: None,
+			..Default::default()
+		})
+	}
+
+	async fn read_dir<'s, 'a>(
+		&'s self,
+		_vfs: &'s Vfs,
+		_url: &'a Url,
+	) -> Result<BorrowedReadDirStream<'s>, SchemeError<'a>> {
+		Err(SchemeError::Unsupported("read_dir"))
+	}
+}
+
+pub struct ReplayNode {
+	data: Vec<u8>,
+	cursor: usize,
+}
+
+#[async_trait::async_trait]
+impl Node for ReplayNode {
+	fn is_reader(&self) -> bool {
+		true
+	}
+
+	fn is_writer(&self) -> bool {
+		false
+	}
+
+	fn is_seeker(&self) -> bool {
+		true
+	}
+
+	fn is_at_end(&self) -> Option<bool> {
+		Some(self.cursor >= self.data.len())
+	}
+}
+
+impl AsyncRead for ReplayNode {
+	fn poll_read(
+		mut self: Pin<&mut Self>,
+		_cx: &mut Context<'_>,
+		buf: &mut [u8],
+	) -> Poll<std::io::Result<usize>> {
+		if self.cursor >= self.data.len() {
+			return Poll::Ready(Ok(0));
+		}
+
+		let amt = std::cmp::min(self.data.len() - self.cursor, buf.len());
+		buf[..amt].copy_from_slice(&self.data[self.cursor..(self.cursor + amt)]);
+		self.cursor += amt;
+
+		Poll::Ready(Ok(amt))
+	}
+}
+
+impl AsyncWrite for ReplayNode {
+	fn poll_write(
+		self: Pin<&mut Self>,
+		_cx: &mut Context<'_>,
+		_buf: &[u8],
+	) -> Poll<std::io::Result<usize>> {
+		poll_io_err()
+	}
+
+	fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+		poll_io_err()
+	}
+
+	fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+		poll_io_err()
+	}
+}
+
+impl AsyncSeek for ReplayNode {
+	fn poll_seek(
+		mut self: Pin<&mut Self>,
+		_cx: &mut Context<'_>,
+		pos: SeekFrom,
+	) -> Poll<std::io::Result<u64>> {
+		match pos {
+			SeekFrom::Start(pos) => {
+				if pos > self.data.len() as u64 {
+					self.cursor = self.data.len();
+				} else {
+					self.cursor = pos as usize;
+				}
+			}
+			SeekFrom::End(end_pos) => {
+				if end_pos > 0 {
+					self.cursor = self.data.len();
+				} else if (-end_pos) as usize > self.data.len() {
+					self.cursor = 0;
+				} else {
+					self.cursor = self.data.len() - ((-end_pos) as usize);
+				}
+			}
+			SeekFrom::Current(offset) => {
+				let new_cur = self.cursor as i64 + offset;
+				if new_cur < 0 {
+					self.cursor = 0;
+				} else if new_cur as usize > self.data.len() {
+					self.cursor = self.data.len();
+				} else {
+					self.cursor = new_cur as usize;
+				}
+			}
+		};
+		Poll::Ready(Ok(self.cursor as u64))
+	}
+}
+
+#[cfg(test)]
+#[cfg(feature = "backend_tokio")]
+mod async_tokio_tests {
+	use crate::scheme::NodeGetOptions;
+	use crate::{DataLoaderScheme, RecordingScheme, ReplayScheme, SchemeError, Vfs, VfsError};
+	use futures_lite::AsyncReadExt;
+
+	const RECORDED: &str = "data:text/plain;base64,SGVsbG8gV29ybGQh";
+
+	async fn read(vfs: &Vfs, uri: &str) -> Result<String, VfsError<'static>> {
+		let mut buffer = String::new();
+		vfs.get_node_at(uri, &NodeGetOptions::new().read(true))
+			.await?
+			.read_to_string(&mut buffer)
+			.await
+			.map_err(SchemeError::from)?;
+		Ok(buffer)
+	}
+
+	#[tokio::test]
+	async fn record_then_replay() {
+		let vfs = Vfs::empty();
+		vfs.add_scheme("data", RecordingScheme::new(DataLoaderScheme::default()))
+			.unwrap();
+		assert_eq!(read(&vfs, RECORDED).await.unwrap(), "Hello World!");
+		let cassette = vfs
+			.get_scheme_as::<RecordingScheme>("data")
+			.unwrap()
+			.cassette();
+		assert_eq!(cassette.len(), 1);
+		#[cfg(feature = "serde")]
+		let cassette = serde_json::from_str(&serde_json::to_string(&cassette).unwrap()).unwrap();
+
+		// No data scheme this time, only what was recorded
+		let vfs = Vfs::empty();
+		vfs.add_scheme("data", ReplayScheme::new(cassette)).unwrap();
+		assert_eq!(read(&vfs, RECORDED).await.unwrap(), "Hello World!");
+		assert_eq!(
+			vfs.metadata_at(RECORDED).await.unwrap().len,
+			Some((12, Some(12)))
+		);
+		assert!(matches!(
+			read(&vfs, "data:,unrecorded").await,
+			Err(VfsError::SchemeError(SchemeError::GenericError(..)))
+		));
+	}
+}
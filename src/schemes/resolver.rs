@@ -0,0 +1,288 @@
+use crate::node::{poll_io_err, seek_position};
+use crate::scheme::{BorrowedReadDirStream, NodeEntry, NodeGetOptions, NodeMetadata};
+use crate::{Node, PinnedNode, Scheme, SchemeError, Vfs};
+use futures_lite::{AsyncRead, AsyncSeek, AsyncWrite};
+use std::borrow::Cow;
+use std::io::SeekFrom;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use url::Url;
+
+/// What a `UrlResolver` found at a url.
+pub enum Resolution {
+	/// A node with all of its content at hand, it can be seeked.
+	Bytes(Vec<u8>),
+	/// A node that is read as it streams in, it cannot be seeked and its length is unknown.
+	Stream(Pin<Box<dyn AsyncRead + Send + Sync>>),
+	/// A directory holding the entries of these names, each a single raw path segment.
+	Directory(Vec<String>),
+	NotFound,
+}
+
+/// The one method to implement to serve a read-only backend through a `ResolverScheme`.
+#[async_trait::async_trait]
+pub trait UrlResolver: Send + Sync + 'static {
+	async fn resolve(&self, url: &Url) -> Resolution;
+}
+
+/// Adapts a `UrlResolver` into a read-only `Scheme`, so a read-mostly backend only has to say
+/// what is at a url rather than implement every `Scheme` method.  Every operation resolves the
+/// url anew, so `metadata` of a `Resolution::Stream` opens the stream only to drop it, resolvers
+/// that are expensive to resolve should be wrapped in a `MetadataCacheScheme`.  Opening for
+/// writing and removing fail with `UrlAccessError`.
+pub struct ResolverScheme {
+	resolver: Box<dyn UrlResolver>,
+}
+
+impl ResolverScheme {
+	pub fn new(resolver: impl UrlResolver) -> Self {
+		Self::new_boxed(Box::new(resolver))
+	}
+
+	pub fn new_boxed(resolver: Box<dyn UrlResolver>) -> Self {
+		Self { resolver }
+	}
+
+	pub fn resolver(&self) -> &dyn UrlResolver {
+		&*self.resolver
+	}
+}
+
+#[async_trait::async_trait]
+impl Scheme for ResolverScheme {
+	async fn get_node<'a>(
+		&self,
+		_vfs: &Vfs,
+		url: &'a Url,
+		options: &NodeGetOptions,
+	) -> Result<PinnedNode, SchemeError<'a>> {
+		if options.get_write() {
+			return Err(SchemeError::UrlAccessError(Cow::Borrowed(url)));
+		}
+		match self.resolver.resolve(url).await {
+			Resolution::Bytes(data) => Ok(Box::pin(ResolvedNode::Bytes { data, cursor: 0 })),
+			Resolution::Stream(stream) => Ok(Box::pin(ResolvedNode::Stream(stream))),
+			Resolution::Directory(_names) => {
+				Err(SchemeError::IsADirectory(Cow::Borrowed(url.path())))
+			}
+			Resolution::NotFound => Err(SchemeError::NodeDoesNotExist(Cow::Borrowed(url.path()))),
+		}
+	}
+
+	async fn remove_node<'a>(
+		&self,
+		_vfs: &Vfs,
+		url: &'a Url,
+		_force: bool,
+	) -> Result<(), SchemeError<'a>> {
+		Err(SchemeError::UrlAccessError(Cow::Borrowed(url)))
+	}
+
+	async fn metadata<'a>(
+		&self,
+		_vfs: &Vfs,
+		url: &'a Url,
+	) -> Result<NodeMetadata, SchemeError<'a>> {
+		let (is_node, len) = match self.resolver.resolve(url).await {
+			Resolution::Bytes(data) => (true, Some((data.len(), Some(data.len())))),
+			Resolution::Stream(_stream) => (true, None),
+			Resolution::Directory(_names) => (false, None),
+			Resolution::NotFound => {
+				return Err(SchemeError::NodeDoesNotExist(Cow::Borrowed(url.path())))
+			}
+		};
+		Ok(NodeMetadata {
+			is_node,
+			len,
+			modified: None,
+			..Default::default()
+		})
+	}
+
+	async fn read_dir<'s, 'a>(
+		&'s self,
+		_vfs: &'s Vfs,
+		url: &'a Url,
+	) -> Result<BorrowedReadDirStream<'s>, SchemeError<'a>> {
+		match self.resolver.resolve(url).await {
+			Resolution::Directory(names) => {
+				let entries: Vec<NodeEntry> = names
+					.iter()
+					.filter_map(|name| NodeEntry::child(url, [name]))
+					.collect();
+				Ok(Box::pin(futures_lite::stream::iter(entries)))
+			}
+			Resolution::Bytes(_) | Resolution::Stream(_) => {
+				Err(SchemeError::NotADirectory(Cow::Borrowed(url.path())))
+			}
+			Resolution::NotFound => Err(SchemeError::NodeDoesNotExist(Cow::Borrowed(url.path()))),
+		}
+	}
+
+	async fn read_small_file<'a>(
+		&self,
+		_vfs: &Vfs,
+		url: &'a Url,
+		max_len: usize,
+	) -> Result<Option<Vec<u8>>, SchemeError<'a>> {
+		match self.resolver.resolve(url).await {
+			Resolution::Bytes(data) if data.len() <= max_len => Ok(Some(data)),
+			_ => Ok(None),
+		}
+	}
+}
+
+pub enum ResolvedNode {
+	Bytes { data: Vec<u8>, cursor: usize },
+	Stream(Pin<Box<dyn AsyncRead + Send + Sync>>),
+}
+
+#[async_trait::async_trait]
+impl Node for ResolvedNode {
+	fn is_reader(&self) -> bool {
+		true
+	}
+
+	fn is_writer(&self) -> bool {
+		false
+	}
+
+	fn is_seeker(&self) -> bool {
+		matches!(self, ResolvedNode::Bytes { .. })
+	}
+
+	fn known_len(&self) -> Option<u64> {
+		match self {
+			ResolvedNode::Bytes { data, .. } => Some(data.len() as u64),
+			ResolvedNode::Stream(_stream) => None,
+		}
+	}
+
+	fn is_at_end(&self) -> Option<bool> {
+		match self {
+			ResolvedNode::Bytes { data, cursor } => Some(*cursor >= data.len()),
+			ResolvedNode::Stream(_stream) => None,
+		}
+	}
+}
+
+impl AsyncRead for ResolvedNode {
+	fn poll_read(
+		self: Pin<&mut Self>,
+		cx: &mut Context<'_>,
+		buf: &mut [u8],
+	) -> Poll<std::io::Result<usize>> {
+		match self.get_mut() {
+			ResolvedNode::Bytes { data, cursor } => {
+				let remaining = data.get(*cursor..).unwrap_or_default();
+				let amt = remaining.len().min(buf.len());
+				buf[..amt].copy_from_slice(&remaining[..amt]);
+				*cursor += amt;
+				Poll::Ready(Ok(amt))
+			}
+			ResolvedNode::Stream(stream) => stream.as_mut().poll_read(cx, buf),
+		}
+	}
+}
+
+impl AsyncWrite for ResolvedNode {
+	fn poll_write(
+		self: Pin<&mut Self>,
+		_cx: &mut Context<'_>,
+		_buf: &[u8],
+	) -> Poll<std::io::Result<usize>> {
+		poll_io_err()
+	}
+
+	fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+		poll_io_err()
+	}
+
+	fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+		poll_io_err()
+	}
+}
+
+impl AsyncSeek for ResolvedNode {
+	fn poll_seek(
+		self: Pin<&mut Self>,
+		_cx: &mut Context<'_>,
+		pos: SeekFrom,
+	) -> Poll<std::io::Result<u64>> {
+		match self.get_mut() {
+			ResolvedNode::Bytes { data, cursor } => {
+				let position = seek_position(pos, *cursor as u64, Some(data.len() as u64))?;
+				*cursor = position as usize;
+				Poll::Ready(Ok(position))
+			}
+			ResolvedNode::Stream(_stream) => poll_io_err(),
+		}
+	}
+}
+
+#[cfg(test)]
+#[cfg(feature = "backend_tokio")]
+mod async_tokio_tests {
+	use super::{Resolution, UrlResolver};
+	use crate::scheme::NodeGetOptions;
+	use crate::{ResolverScheme, SchemeError, Vfs, VfsError};
+	use futures_lite::{AsyncReadExt, StreamExt};
+	use url::Url;
+
+	struct Fixed;
+
+	#[async_trait::async_trait]
+	impl UrlResolver for Fixed {
+		async fn resolve(&self, url: &Url) -> Resolution {
+			match url.path() {
+				"/" => Resolution::Directory(vec!["hello".to_owned(), "streamed".to_owned()]),
+				"/hello" => Resolution::Bytes(b"hello world".to_vec()),
+				"/streamed" => Resolution::Stream(Box::pin(futures_lite::io::Cursor::new(
+					b"streamed in".to_vec(),
+				))),
+				_ => Resolution::NotFound,
+			}
+		}
+	}
+
+	#[tokio::test]
+	async fn resolved() {
+		let vfs = Vfs::empty();
+		vfs.add_scheme("fixed", ResolverScheme::new(Fixed)).unwrap();
+		assert_eq!(
+			vfs.read_to_vec_at("fixed:/hello").await.unwrap(),
+			b"hello world"
+		);
+		let mut buffer = String::new();
+		let mut node = vfs
+			.get_node_at("fixed:/streamed", &NodeGetOptions::new().read(true))
+			.await
+			.unwrap();
+		assert!(!node.is_seeker());
+		node.read_to_string(&mut buffer).await.unwrap();
+		assert_eq!(buffer, "streamed in");
+		assert_eq!(
+			vfs.metadata_at("fixed:/hello").await.unwrap().len,
+			Some((11, Some(11)))
+		);
+		assert!(!vfs.metadata_at("fixed:/").await.unwrap().is_node);
+		let listed: Vec<_> = vfs
+			.read_dir_at("fixed:/")
+			.await
+			.unwrap()
+			.map(|entry| entry.url.to_string())
+			.collect()
+			.await;
+		assert_eq!(listed, ["fixed:/hello", "fixed:/streamed"]);
+		assert!(matches!(
+			vfs.get_node_at("fixed:/missing", &NodeGetOptions::new().read(true))
+				.await,
+			Err(VfsError::SchemeError(SchemeError::NodeDoesNotExist(_)))
+		));
+		assert!(matches!(
+			vfs.get_node_at("fixed:/hello", &NodeGetOptions::new().write(true))
+				.await,
+			Err(VfsError::SchemeError(SchemeError::UrlAccessError(_)))
+		));
+	}
+}
@@ -0,0 +1,272 @@
+use crate::node::poll_io_err;
+use crate::scheme::{BorrowedReadDirStream, NodeGetOptions, NodeMetadata};
+use crate::{Node, PinnedNode, Scheme, SchemeError, Vfs};
+use futures_lite::{AsyncRead, AsyncSeek, AsyncWrite};
+use std::borrow::Cow;
+use std::io::SeekFrom;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use url::Url;
+
+/// Serves read-only nodes whose bytes are a pure function of their offset, see `byte_at`, so the
+/// expected data after any read or seek is known without storing anything.  Every url of the scheme
+/// is the same sequence of the configured length, which makes it an oracle for testing seeks.
+pub struct SequenceScheme {
+	len: usize,
+}
+
+impl SequenceScheme {
+	pub fn new(len: usize) -> Self {
+		Self { len }
+	}
+
+	/// The byte at `offset` of every node of this scheme, 251 is prime so the pattern does not
+	/// line up with any power of two buffer size.
+	pub fn byte_at(offset: usize) -> u8 {
+		(offset % 251) as u8
+	}
+}
+
+#[async_trait::async_trait]
+impl Scheme for SequenceScheme {
+	async fn get_node<'a>(
+		&self,
+		_vfs: &Vfs,
+		url: &'a Url,
+		options: &NodeGetOptions,
+	) -> Result<PinnedNode, SchemeError<'a>> {
+		if !options.get_read() || options.get_write() {
+			return Err(SchemeError::UrlAccessError(Cow::Borrowed(url)));
+		}
+		Ok(Box::pin(SequenceNode {
+			len: self.len,
+			cursor: 0,
+		}))
+	}
+
+	async fn remove_node<'a>(
+		&self,
+		_vfs: &Vfs,
+		url: &'a Url,
+		_force: bool,
+	) -> Result<(), SchemeError<'a>> {
+		Err(SchemeError::UrlAccessError(Cow::Borrowed(url)))
+	}
+
+	async fn metadata<'a>(
+		&self,
+		_vfs: &Vfs,
+		_url: &'a Url,
+	) -> Result<NodeMetadata, SchemeError<'a>> {
+		Ok(NodeMetadata {
+			is_node: true,
+			len: Some((self.len, Some(self.len))),
+			modified: None,
+			..Default::default()
+		})
+	}
+
+	async fn read_dir<'s, 'a>(
+		&'s self,
+		_vfs: &'s Vfs,
+		_url: &'a Url,
+	) -> Result<BorrowedReadDirStream<'s>, SchemeError<'a>> {
+		Err(SchemeError::Unsupported("read_dir"))
+	}
+}
+
+pub struct SequenceNode {
+	len: usize,
+	cursor: usize,
+}
+
+#[async_trait::async_trait]
+impl Node for SequenceNode {
+	fn is_reader(&self) -> bool {
+		true
+	}
+
+	fn is_writer(&self) -> bool {
+		false
+	}
+
+	fn is_seeker(&self) -> bool {
+		true
+	}
+}
+
+impl AsyncRead for SequenceNode {
+	fn poll_read(
+		mut self: Pin<&mut Self>,
+		_cx: &mut Context<'_>,
+		buf: &mut [u8],
+	) -> Poll<std::io::Result<usize>> {
+		if self.cursor >= self.len {
+			return Poll::Ready(Ok(0));
+		}
+
+		let amt = std::cmp::min(self.len - self.cursor, buf.len());
+		for (offset, byte) in (self.cursor..).zip(&mut buf[..amt]) {
+			*byte = SequenceScheme::byte_at(offset);
+		}
+		self.cursor += amt;
+
+		Poll::Ready(Ok(amt))
+	}
+}
+
+impl AsyncWrite for SequenceNode {
+	fn poll_write(
+		self: Pin<&mut Self>,
+		_cx: &mut Context<'_>,
+		_buf: &[u8],
+	) -> Poll<std::io::Result<usize>> {
+		poll_io_err()
+	}
+
+	fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+		poll_io_err()
+	}
+
+	fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+		poll_io_err()
+	}
+}
+
+impl AsyncSeek for SequenceNode {
+	/// Clamps to the start and end of the sequence, the same as the other in-memory nodes.
+	fn poll_seek(
+		mut self: Pin<&mut Self>,
+		_cx: &mut Context<'_>,
+		pos: SeekFrom,
+	) -> Poll<std::io::Result<u64>> {
+		let target = match pos {
+			SeekFrom::Start(pos) => pos.min(self.len as u64) as i128,
+			SeekFrom::End(offset) => self.len as i128 + offset as i128,
+			SeekFrom::Current(offset) => self.cursor as i128 + offset as i128,
+		};
+		self.cursor = target.clamp(0, self.len as i128) as usize;
+		Poll::Ready(Ok(self.cursor as u64))
+	}
+}
+
+#[cfg(test)]
+#[cfg(feature = "backend_tokio")]
+mod async_tokio_tests {
+	use crate::scheme::NodeGetOptions;
+	use crate::{PinnedNode, SequenceScheme, Vfs};
+	use futures_lite::io::SeekFrom;
+	use futures_lite::{AsyncReadExt, AsyncSeekExt};
+
+	const LEN: usize = 10_000;
+
+	/// A tiny deterministic generator so the "random" offsets are the same every run.
+	struct Lcg(u64);
+
+	impl Lcg {
+		fn next(&mut self, bound: usize) -> usize {
+			self.0 = self
+				.0
+				.wrapping_mul(6364136223846793005)
+				.wrapping_add(1442695040888963407);
+			((self.0 >> 33) as usize) % bound
+		}
+
+		fn seek(&mut self) -> SeekFrom {
+			let offset = self.next(LEN * 2) as i64 - (LEN / 2) as i64;
+			match self.next(3) {
+				0 => SeekFrom::Start(offset.unsigned_abs()),
+				1 => SeekFrom::End(offset - LEN as i64),
+				_ => SeekFrom::Current(offset - (LEN / 2) as i64),
+			}
+		}
+	}
+
+	async fn sequence_node() -> PinnedNode {
+		let vfs = Vfs::empty();
+		vfs.add_scheme("seq", SequenceScheme::new(LEN)).unwrap();
+		assert_eq!(
+			vfs.metadata_at("seq:/anything").await.unwrap().len,
+			Some((LEN, Some(LEN)))
+		);
+		vfs.get_node_at("seq:/anything", &NodeGetOptions::new().read(true))
+			.await
+			.unwrap()
+	}
+
+	#[tokio::test]
+	async fn sequence_read() {
+		let mut data = Vec::new();
+		sequence_node().await.read_to_end(&mut data).await.unwrap();
+		assert_eq!(data.len(), LEN);
+		assert!(data
+			.iter()
+			.enumerate()
+			.all(|(i, &b)| b == SequenceScheme::byte_at(i)));
+	}
+
+	#[tokio::test]
+	async fn sequence_seeking() {
+		let mut node = sequence_node().await;
+		let mut rng = Lcg(2463);
+		let mut expected_cursor: i64 = 0;
+		for _ in 0..1000 {
+			let seek = rng.seek();
+			let target = match seek {
+				SeekFrom::Start(pos) => pos as i64,
+				SeekFrom::End(offset) => LEN as i64 + offset,
+				SeekFrom::Current(offset) => expected_cursor + offset,
+			};
+			expected_cursor = target.clamp(0, LEN as i64);
+			assert_eq!(node.seek(seek).await.unwrap(), expected_cursor as u64);
+			let mut byte = [0u8];
+			let read = node.read(&mut byte).await.unwrap();
+			if (expected_cursor as usize) < LEN {
+				assert_eq!(read, 1);
+				assert_eq!(byte[0], SequenceScheme::byte_at(expected_cursor as usize));
+				expected_cursor += 1;
+			} else {
+				assert_eq!(read, 0, "reading at the end returns nothing");
+			}
+		}
+	}
+
+	#[cfg(feature = "in_memory")]
+	#[tokio::test]
+	async fn memory_node_matches_sequence() {
+		use crate::MemoryScheme;
+		use futures_lite::AsyncWriteExt;
+
+		let vfs = Vfs::empty();
+		vfs.add_scheme("mem", MemoryScheme::default()).unwrap();
+		let mut memory = vfs
+			.get_node_at(
+				"mem:/sequence",
+				&NodeGetOptions::new().read(true).create_new(true),
+			)
+			.await
+			.unwrap();
+		let mut data = Vec::new();
+		sequence_node().await.read_to_end(&mut data).await.unwrap();
+		memory.write_all(&data).await.unwrap();
+		memory.seek(SeekFrom::Start(0)).await.unwrap();
+
+		let mut sequence = sequence_node().await;
+		let mut rng = Lcg(42);
+		for _ in 0..1000 {
+			let seek = rng.seek();
+			assert_eq!(
+				memory.seek(seek).await.unwrap(),
+				sequence.seek(seek).await.unwrap(),
+				"{:?}",
+				seek
+			);
+			let (mut l, mut r) = ([0u8; 7], [0u8; 7]);
+			assert_eq!(
+				memory.read(&mut l).await.unwrap(),
+				sequence.read(&mut r).await.unwrap()
+			);
+			assert_eq!(l, r);
+		}
+	}
+}
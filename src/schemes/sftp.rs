@@ -0,0 +1,551 @@
+use crate::node::IsAllowed;
+use crate::scheme::{BorrowedReadDirStream, NodeEntry, NodeGetOptions, NodeKind, NodeMetadata};
+use crate::{Node, PinnedNode, Scheme, SchemeError, Vfs};
+use futures_lite::{ready, AsyncRead, AsyncSeek, AsyncWrite, StreamExt};
+use openssh::{KnownHosts, Session, SessionBuilder};
+use openssh_sftp_client::error::SftpErrorKind;
+use openssh_sftp_client::file::TokioCompatFile;
+use openssh_sftp_client::fs::Fs;
+use openssh_sftp_client::metadata::MetaData;
+use openssh_sftp_client::{Error, Sftp, SftpOptions};
+use percent_encoding::percent_decode_str;
+use std::borrow::Cow;
+use std::io::{IoSlice, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use url::Url;
+
+/// How many SSH connections a `SftpScheme` opens at most unless set otherwise, see
+/// `SftpScheme::set_max_connections`.
+pub const DEFAULT_SFTP_MAX_CONNECTIONS: usize = 4;
+
+/// Serves the files below `root_path` of a remote host over SFTP, connecting through the system
+/// `ssh` so its configuration, keys and agent all apply.  Connections are opened as they are
+/// needed, an idle one is reused before another is opened, and once `max_connections` are open
+/// requests are spread over them, which is fine as SFTP pipelines requests.  A connection that
+/// fails, other than the server refusing a request, is dropped so the next request opens a new
+/// one.  The requests need a tokio runtime.
+pub struct SftpScheme {
+	destination: String,
+	root_path: PathBuf,
+	session_builder: SessionBuilder,
+	max_connections: usize,
+	connections: Mutex<Vec<Arc<Sftp>>>,
+	next_connection: AtomicUsize,
+}
+
+impl SftpScheme {
+	/// Connects to `destination`, such as `user@host`, which must be a known host already.
+	pub fn new(destination: impl Into<String>, root_path: impl Into<PathBuf>) -> Self {
+		let mut session_builder = SessionBuilder::default();
+		session_builder.known_hosts_check(KnownHosts::Strict);
+		Self::with_session_builder(destination, root_path, session_builder)
+	}
+
+	/// Connects to `destination` with `session_builder`, such as to set a user, port, or key.
+	pub fn with_session_builder(
+		destination: impl Into<String>,
+		root_path: impl Into<PathBuf>,
+		session_builder: SessionBuilder,
+	) -> Self {
+		Self {
+			destination: destination.into(),
+			root_path: root_path.into(),
+			session_builder,
+			max_connections: DEFAULT_SFTP_MAX_CONNECTIONS,
+			connections: Mutex::new(Vec::new()),
+			next_connection: AtomicUsize::new(0),
+		}
+	}
+
+	pub fn destination(&self) -> &str {
+		&self.destination
+	}
+
+	pub fn root_path(&self) -> &Path {
+		&self.root_path
+	}
+
+	/// At least one connection is always allowed.  Defaults to `DEFAULT_SFTP_MAX_CONNECTIONS`.
+	pub fn set_max_connections(&mut self, max_connections: usize) -> &mut Self {
+		self.max_connections = max_connections.max(1);
+		self
+	}
+
+	pub fn max_connections(&self) -> usize {
+		self.max_connections
+	}
+
+	/// How many connections are open right now.
+	pub fn open_connections(&self) -> usize {
+		self.connections.lock().expect("poisoned lock").len()
+	}
+
+	/// Segments are percent-decoded into file names, one that decodes to something that is not a
+	/// single file name, such as `..` or one holding a `/`, could escape the root so is refused.
+	pub fn remote_path_from_url<'a>(&self, url: &'a Url) -> Result<PathBuf, SchemeError<'a>> {
+		let segments = url
+			.path_segments()
+			.ok_or(SchemeError::NodeDoesNotExist(Cow::Borrowed(url.path())))?;
+		let mut path = self.root_path.clone();
+		for segment in segments.filter(|segment| !segment.is_empty()) {
+			let name = percent_decode_str(segment).decode_utf8_lossy();
+			if name == "." || name == ".." || name.contains('/') {
+				return Err(SchemeError::UrlAccessError(Cow::Borrowed(url)));
+			}
+			path.push(&*name);
+		}
+		Ok(path)
+	}
+
+	/// An idle connection if there is one, else a new one if there is room for it, else the next
+	/// busy one in turn.  A connection is busy while anything but the pool holds it.
+	async fn connection(&self) -> Result<Arc<Sftp>, SchemeError<'static>> {
+		{
+			let connections = self.connections.lock().expect("poisoned lock");
+			if let Some(idle) = connections
+				.iter()
+				.find(|connection| Arc::strong_count(connection) == 1)
+			{
+				return Ok(idle.clone());
+			}
+			if connections.len() >= self.max_connections {
+				let index = self.next_connection.fetch_add(1, Ordering::Relaxed);
+				return Ok(connections[index % connections.len()].clone());
+			}
+		}
+		let session: Session = self
+			.session_builder
+			.connect(&self.destination)
+			.await
+			.map_err(|error| {
+				SchemeError::GenericError(Some("SSH connection failed"), Some(Box::new(error)))
+			})?;
+		let sftp = Sftp::from_session(session, SftpOptions::default())
+			.await
+			.map_err(|error| {
+				SchemeError::GenericError(Some("SFTP session failed"), Some(Box::new(error)))
+			})?;
+		let sftp = Arc::new(sftp);
+		let mut connections = self.connections.lock().expect("poisoned lock");
+		if connections.len() < self.max_connections {
+			connections.push(sftp.clone());
+		}
+		Ok(sftp)
+	}
+
+	/// Turns `error` of a request over `connection` into a `SchemeError`, dropping `connection`
+	/// from the pool unless the server merely refused the request.
+	fn failed<'a>(&self, connection: &Arc<Sftp>, url: &'a Url, error: Error) -> SchemeError<'a> {
+		match error {
+			Error::SftpError(SftpErrorKind::NoSuchFile, _message) => {
+				SchemeError::NodeDoesNotExist(Cow::Borrowed(url.path()))
+			}
+			Error::SftpError(SftpErrorKind::PermDenied, _message) => {
+				SchemeError::UrlAccessError(Cow::Borrowed(url))
+			}
+			error @ Error::SftpError(..) => {
+				SchemeError::GenericError(Some("SFTP request failed"), Some(Box::new(error)))
+			}
+			error => {
+				self.connections
+					.lock()
+					.expect("poisoned lock")
+					.retain(|pooled| !Arc::ptr_eq(pooled, connection));
+				match error {
+					Error::IOError(error) => {
+						SchemeError::IOErrorAt(Cow::Borrowed(url.path()), error)
+					}
+					error => SchemeError::GenericError(
+						Some("SFTP connection failed"),
+						Some(Box::new(error)),
+					),
+				}
+			}
+		}
+	}
+
+	async fn open_path<'a>(
+		&self,
+		connection: Arc<Sftp>,
+		url: &'a Url,
+		path: &Path,
+		options: &NodeGetOptions,
+	) -> Result<PinnedNode, SchemeError<'a>> {
+		if options.get_create() && options.get_create_parents() {
+			if let Some(parent) = path.parent() {
+				create_dir_all(&mut connection.fs(), parent)
+					.await
+					.map_err(|error| self.failed(&connection, url, error))?;
+			}
+		}
+		let file = connection
+			.options()
+			.read(options.get_read())
+			.write(options.get_write())
+			.append(options.get_append())
+			.truncate(options.get_truncate())
+			.create(options.get_create())
+			.create_new(options.get_create_new())
+			.open(path)
+			.await
+			.map_err(|error| match error {
+				Error::SftpError(SftpErrorKind::Failure, _message) if options.get_create_new() => {
+					SchemeError::NodeAlreadyExists(Cow::Borrowed(url.path()))
+				}
+				error => self.failed(&connection, url, error),
+			})?;
+		Ok(Box::pin(SftpNode {
+			file: Box::pin(TokioCompatFile::new(file)),
+			_connection: connection,
+			seek: None,
+			read: options.get_read(),
+			write: options.get_write(),
+		}))
+	}
+}
+
+/// Creates `path` and every missing directory above it, SFTP only creates one at a time.
+async fn create_dir_all(fs: &mut Fs, path: &Path) -> Result<(), Error> {
+	let mut missing = Vec::new();
+	for ancestor in path.ancestors() {
+		match fs.metadata(ancestor).await {
+			Ok(_metadata) => break,
+			Err(Error::SftpError(SftpErrorKind::NoSuchFile, _message)) => missing.push(ancestor),
+			Err(error) => return Err(error),
+		}
+	}
+	for dir in missing.into_iter().rev() {
+		fs.create_dir(dir).await?;
+	}
+	Ok(())
+}
+
+/// Removes the directory at `path` with everything in it.
+fn remove_dir_all<'f>(
+	fs: &'f mut Fs,
+	path: PathBuf,
+) -> Pin<Box<dyn std::future::Future<Output = Result<(), Error>> + Send + 'f>> {
+	Box::pin(async move {
+		let mut entries = Box::pin(fs.open_dir(&path).await?.read_dir());
+		let mut children = Vec::new();
+		while let Some(entry) = entries.next().await {
+			let entry = entry?;
+			let name = entry.filename();
+			if name != Path::new(".") && name != Path::new("..") {
+				let is_dir = entry
+					.file_type()
+					.is_some_and(|file_type| file_type.is_dir());
+				children.push((path.join(name), is_dir));
+			}
+		}
+		for (child, is_dir) in children {
+			if is_dir {
+				remove_dir_all(fs, child).await?;
+			} else {
+				fs.remove_file(&child).await?;
+			}
+		}
+		fs.remove_dir(&path).await
+	})
+}
+
+/// The metadata of a remote entry, with `linked` if `metadata` is of the target of a symlink at
+/// the path rather than of the path itself.
+fn node_metadata(metadata: &MetaData, linked: bool) -> NodeMetadata {
+	let file_type = metadata.file_type();
+	let is_file = file_type.is_some_and(|file_type| file_type.is_file());
+	let kind = file_type.map(|file_type| {
+		if linked {
+			NodeKind::Symlink
+		} else if file_type.is_file() {
+			NodeKind::File
+		} else if file_type.is_dir() {
+			NodeKind::Directory
+		} else {
+			NodeKind::Other
+		}
+	});
+	let len = metadata.len().map(|len| (len as usize, Some(len as usize)));
+	NodeMetadata {
+		is_node: is_file,
+		len,
+		modified: metadata
+			.modified()
+			.map(|modified| modified.as_system_time()),
+		kind,
+		created: None,
+		accessed: metadata
+			.accessed()
+			.map(|accessed| accessed.as_system_time()),
+		read_only: metadata
+			.permissions()
+			.map(|permissions| permissions.readonly()),
+	}
+}
+
+#[async_trait::async_trait]
+impl Scheme for SftpScheme {
+	async fn get_node<'a>(
+		&self,
+		_vfs: &Vfs,
+		url: &'a Url,
+		options: &NodeGetOptions,
+	) -> Result<PinnedNode, SchemeError<'a>> {
+		let path = self.remote_path_from_url(url)?;
+		let connection = self.connection().await?;
+		if let Ok(metadata) = connection.fs().metadata(&path).await {
+			if metadata
+				.file_type()
+				.is_some_and(|file_type| file_type.is_dir())
+			{
+				return Err(SchemeError::IsADirectory(Cow::Borrowed(url.path())));
+			}
+		}
+		self.open_path(connection, url, &path, options).await
+	}
+
+	async fn remove_node<'a>(
+		&self,
+		_vfs: &Vfs,
+		url: &'a Url,
+		force: bool,
+	) -> Result<(), SchemeError<'a>> {
+		let path = self.remote_path_from_url(url)?;
+		let connection = self.connection().await?;
+		let mut fs = connection.fs();
+		let result = match fs.symlink_metadata(&path).await {
+			Ok(metadata)
+				if metadata
+					.file_type()
+					.is_some_and(|file_type| file_type.is_dir()) =>
+			{
+				if force {
+					remove_dir_all(&mut fs, path).await
+				} else {
+					fs.remove_dir(&path).await
+				}
+			}
+			Ok(_metadata) => fs.remove_file(&path).await,
+			// Like the filesystem, removing what is not there is not an error
+			Err(Error::SftpError(SftpErrorKind::NoSuchFile, _message)) => Ok(()),
+			Err(error) => Err(error),
+		};
+		result.map_err(|error| self.failed(&connection, url, error))
+	}
+
+	async fn metadata<'a>(
+		&self,
+		_vfs: &Vfs,
+		url: &'a Url,
+	) -> Result<NodeMetadata, SchemeError<'a>> {
+		let path = self.remote_path_from_url(url)?;
+		let connection = self.connection().await?;
+		let mut fs = connection.fs();
+		// Only a symlink needs a second lookup to follow it
+		let metadata = match fs.symlink_metadata(&path).await {
+			Ok(metadata)
+				if metadata
+					.file_type()
+					.is_some_and(|file_type| file_type.is_symlink()) =>
+			{
+				fs.metadata(&path)
+					.await
+					.map(|metadata| node_metadata(&metadata, true))
+			}
+			result => result.map(|metadata| node_metadata(&metadata, false)),
+		};
+		metadata.map_err(|error| self.failed(&connection, url, error))
+	}
+
+	async fn read_dir<'s, 'a>(
+		&'s self,
+		_vfs: &'s Vfs,
+		url: &'a Url,
+	) -> Result<BorrowedReadDirStream<'s>, SchemeError<'a>> {
+		let path = self.remote_path_from_url(url)?;
+		let connection = self.connection().await?;
+		let mut fs = connection.fs();
+		match fs.metadata(&path).await {
+			Ok(metadata)
+				if !metadata
+					.file_type()
+					.is_some_and(|file_type| file_type.is_dir()) =>
+			{
+				return Err(SchemeError::NotADirectory(Cow::Borrowed(url.path())));
+			}
+			Ok(_metadata) => (),
+			Err(error) => return Err(self.failed(&connection, url, error)),
+		}
+		let entries = fs
+			.open_dir(&path)
+			.await
+			.map_err(|error| self.failed(&connection, url, error))?
+			.read_dir();
+		let dir = url.clone();
+		Ok(Box::pin(entries.filter_map(move |entry| {
+			// Skip entries that fail to be read, like the filesystem does
+			let entry = entry.ok()?;
+			// A name that is not valid UTF-8 cannot be put in a url
+			let name = entry.filename().to_str()?;
+			if name == "." || name == ".." {
+				return None;
+			}
+			let node_entry = NodeEntry::child(&dir, [name])?;
+			// The entry has the metadata of a symlink itself, that is left to `metadata` to follow
+			Some(match entry.file_type() {
+				Some(file_type) if !file_type.is_symlink() => {
+					node_entry.with_metadata(node_metadata(&entry.metadata(), false))
+				}
+				_ => node_entry,
+			})
+		})))
+	}
+
+	async fn create_dir<'a>(
+		&self,
+		_vfs: &Vfs,
+		url: &'a Url,
+		parents: bool,
+	) -> Result<(), SchemeError<'a>> {
+		let path = self.remote_path_from_url(url)?;
+		let connection = self.connection().await?;
+		let mut fs = connection.fs();
+		if parents {
+			create_dir_all(&mut fs, &path).await
+		} else {
+			fs.create_dir(&path).await
+		}
+		.map_err(|error| self.failed(&connection, url, error))
+	}
+}
+
+pub struct SftpNode {
+	file: Pin<Box<TokioCompatFile>>,
+	/// The connection `file` is open over, kept so the pool sees it as busy.
+	_connection: Arc<Sftp>,
+	seek: Option<SeekFrom>,
+	read: bool,
+	write: bool,
+}
+
+#[async_trait::async_trait]
+impl Node for SftpNode {
+	fn is_reader(&self) -> bool {
+		self.read
+	}
+
+	fn is_writer(&self) -> bool {
+		self.write
+	}
+
+	fn is_seeker(&self) -> bool {
+		self.read || self.write
+	}
+}
+
+impl AsyncRead for SftpNode {
+	fn poll_read(
+		mut self: Pin<&mut Self>,
+		cx: &mut Context<'_>,
+		buf: &mut [u8],
+	) -> Poll<std::io::Result<usize>> {
+		self.read.into_poll_io_then(|| {
+			let mut buf = tokio::io::ReadBuf::new(buf);
+			ready!(tokio::io::AsyncRead::poll_read(
+				self.file.as_mut(),
+				cx,
+				&mut buf
+			))?;
+			Poll::Ready(Ok(buf.filled().len()))
+		})
+	}
+}
+
+impl AsyncWrite for SftpNode {
+	fn poll_write(
+		mut self: Pin<&mut Self>,
+		cx: &mut Context<'_>,
+		buf: &[u8],
+	) -> Poll<std::io::Result<usize>> {
+		self.write
+			.into_poll_io_then(|| tokio::io::AsyncWrite::poll_write(self.file.as_mut(), cx, buf))
+	}
+
+	fn poll_write_vectored(
+		mut self: Pin<&mut Self>,
+		cx: &mut Context<'_>,
+		bufs: &[IoSlice<'_>],
+	) -> Poll<std::io::Result<usize>> {
+		self.write.into_poll_io_then(|| {
+			tokio::io::AsyncWrite::poll_write_vectored(self.file.as_mut(), cx, bufs)
+		})
+	}
+
+	fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+		self.write
+			.into_poll_io_then(|| tokio::io::AsyncWrite::poll_flush(self.file.as_mut(), cx))
+	}
+
+	fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+		tokio::io::AsyncWrite::poll_shutdown(self.file.as_mut(), cx)
+	}
+}
+
+impl AsyncSeek for SftpNode {
+	fn poll_seek(
+		mut self: Pin<&mut Self>,
+		cx: &mut Context<'_>,
+		pos: SeekFrom,
+	) -> Poll<std::io::Result<u64>> {
+		(self.read || self.write).into_poll_io_then(|| {
+			if self.seek != Some(pos) {
+				tokio::io::AsyncSeek::start_seek(self.file.as_mut(), pos)?;
+				self.seek = Some(pos);
+			}
+			let res = ready!(tokio::io::AsyncSeek::poll_complete(self.file.as_mut(), cx));
+			self.seek = None;
+			Poll::Ready(res)
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::{SchemeError, SftpScheme};
+	use std::path::Path;
+	use url::Url;
+
+	#[test]
+	fn remote_paths() {
+		let mut scheme = SftpScheme::new("user@host", "/srv/data");
+		let path = |uri: &str| {
+			scheme
+				.remote_path_from_url(&Url::parse(uri).unwrap())
+				.map_err(SchemeError::into_owned)
+		};
+		assert_eq!(path("sftp:/").unwrap(), Path::new("/srv/data"));
+		assert_eq!(
+			path("sftp:/dir/a%20node").unwrap(),
+			Path::new("/srv/data/dir/a node")
+		);
+		assert_eq!(
+			path("sftp:/dir/%2E%2E/../escape").unwrap(),
+			Path::new("/srv/data/escape"),
+			"dot segments are resolved by the url itself"
+		);
+		assert!(matches!(
+			path("sftp:/dir%2Fescape"),
+			Err(SchemeError::UrlAccessError(_))
+		));
+		assert_eq!(
+			scheme.open_connections(),
+			0,
+			"connections are opened lazily"
+		);
+		assert_eq!(scheme.set_max_connections(0).max_connections(), 1);
+	}
+}
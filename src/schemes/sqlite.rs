@@ -0,0 +1,495 @@
+use crate::scheme::{BorrowedReadDirStream, NodeEntry, NodeGetOptions, NodeMetadata};
+use crate::{Node, PinnedNode, Scheme, SchemeError, Vfs};
+use futures_lite::{AsyncRead, AsyncSeek, AsyncWrite};
+use rusqlite::{params, Connection, OptionalExtension};
+use std::borrow::Cow;
+use std::collections::BTreeSet;
+use std::io::SeekFrom;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, SystemTime};
+use url::Url;
+
+fn sql_error(error: rusqlite::Error) -> SchemeError<'static> {
+	("sqlite error", Box::new(error) as Box<_>).into()
+}
+
+/// Milliseconds since the epoch, as the `modified` column holds them.
+fn now_millis() -> i64 {
+	SystemTime::now()
+		.duration_since(SystemTime::UNIX_EPOCH)
+		.map_or(0, |since| since.as_millis() as i64)
+}
+
+/// Stores nodes as blobs in a SQLite database keyed by their url path, directories are implied by
+/// the paths of the nodes in them, so a whole tree is a single file.  Nodes are read into memory
+/// when opened and written back when flushed or closed, each write-back, creation and removal is
+/// its own transaction, and `write_batch` writes several nodes in one, so readers never see a
+/// partial write.
+pub struct SqliteScheme {
+	db: Arc<Mutex<Connection>>,
+}
+
+impl SqliteScheme {
+	/// Uses an already open connection, the nodes are stored in their own `vfs_nodes` table.
+	pub fn new(db: Connection) -> Result<Self, SchemeError<'static>> {
+		db.execute_batch(
+			"CREATE TABLE IF NOT EXISTS vfs_nodes (
+				path TEXT PRIMARY KEY NOT NULL,
+				data BLOB NOT NULL,
+				modified INTEGER NOT NULL
+			) WITHOUT ROWID",
+		)
+		.map_err(sql_error)?;
+		Ok(Self {
+			db: Arc::new(Mutex::new(db)),
+		})
+	}
+
+	/// Opens, or creates, the database file at `path`.
+	pub fn open(path: impl AsRef<Path>) -> Result<Self, SchemeError<'static>> {
+		Self::new(Connection::open(path).map_err(sql_error)?)
+	}
+
+	/// A database that only lives in memory, mostly useful for tests.
+	pub fn in_memory() -> Result<Self, SchemeError<'static>> {
+		Self::new(Connection::open_in_memory().map_err(sql_error)?)
+	}
+
+	pub fn db(&self) -> &Arc<Mutex<Connection>> {
+		&self.db
+	}
+
+	/// Writes every node of `nodes`, by url path such as `/saves/slot1`, in one transaction, so
+	/// either all of them are written or none are.
+	pub fn write_batch<'n>(
+		&self,
+		nodes: impl IntoIterator<Item = (&'n str, &'n [u8])>,
+	) -> Result<(), SchemeError<'static>> {
+		let mut db = self.db.lock().expect("poisoned lock");
+		let txn = db.transaction().map_err(sql_error)?;
+		let modified = now_millis();
+		for (path, data) in nodes {
+			write_back(&txn, path, data, modified)?;
+		}
+		txn.commit().map_err(sql_error)
+	}
+
+	fn get(&self, path: &str) -> Result<Option<(Vec<u8>, i64)>, SchemeError<'static>> {
+		self.db
+			.lock()
+			.expect("poisoned lock")
+			.query_row(
+				"SELECT data, modified FROM vfs_nodes WHERE path = ?1",
+				[path],
+				|row| Ok((row.get(0)?, row.get(1)?)),
+			)
+			.optional()
+			.map_err(sql_error)
+	}
+
+	/// The paths of every node under the directory `prefix`, which ends in `/`.
+	fn paths_under(&self, prefix: &str) -> Result<Vec<String>, SchemeError<'static>> {
+		let db = self.db.lock().expect("poisoned lock");
+		let mut statement = db
+			.prepare_cached(
+				"SELECT path FROM vfs_nodes WHERE path >= ?1 AND path < ?2 ORDER BY path",
+			)
+			.map_err(sql_error)?;
+		let paths = statement
+			.query_map([prefix, &prefix_end(prefix)], |row| row.get(0))
+			.map_err(sql_error)?
+			.collect::<Result<_, _>>()
+			.map_err(sql_error)?;
+		Ok(paths)
+	}
+}
+
+fn dir_prefix(path: &str) -> String {
+	if path.ends_with('/') {
+		path.to_owned()
+	} else {
+		format!("{}/", path)
+	}
+}
+
+/// Every path starting with the directory `prefix` sorts before this, `prefix` with its last `/`
+/// bumped to `0`, so the paths under it are a range of the primary key.
+fn prefix_end(prefix: &str) -> String {
+	format!("{}0", &prefix[..prefix.len() - 1])
+}
+
+fn write_back(
+	db: &Connection,
+	path: &str,
+	data: &[u8],
+	modified: i64,
+) -> Result<(), SchemeError<'static>> {
+	db.execute(
+		"INSERT OR REPLACE INTO vfs_nodes (path, data, modified) VALUES (?1, ?2, ?3)",
+		params![path, data, modified],
+	)
+	.map_err(sql_error)?;
+	Ok(())
+}
+
+#[async_trait::async_trait]
+impl Scheme for SqliteScheme {
+	async fn get_node<'a>(
+		&self,
+		_vfs: &Vfs,
+		url: &'a Url,
+		options: &NodeGetOptions,
+	) -> Result<PinnedNode, SchemeError<'a>> {
+		let path = url.path();
+		let data = match self.get(path)? {
+			Some(_data) if options.get_create_new() => {
+				return Err(SchemeError::NodeAlreadyExists(Cow::Borrowed(url.path())));
+			}
+			Some(_data) if options.get_truncate() => {
+				let db = self.db.lock().expect("poisoned lock");
+				write_back(&db, path, &[], now_millis())?;
+				Vec::new()
+			}
+			Some((data, _modified)) => data,
+			None if !self.paths_under(&dir_prefix(path))?.is_empty() => {
+				return Err(SchemeError::IsADirectory(Cow::Borrowed(url.path())));
+			}
+			None if options.get_create() => {
+				let db = self.db.lock().expect("poisoned lock");
+				write_back(&db, path, &[], now_millis())?;
+				Vec::new()
+			}
+			None => return Err(SchemeError::NodeDoesNotExist(Cow::Borrowed(url.path()))),
+		};
+		let cursor = if options.get_append() { data.len() } else { 0 };
+		Ok(Box::pin(SqliteNode {
+			db: self.db.clone(),
+			path: path.to_owned(),
+			data,
+			cursor,
+			read: options.get_read(),
+			write: options.get_write(),
+			dirty: false,
+		}))
+	}
+
+	async fn remove_node<'a>(
+		&self,
+		_vfs: &Vfs,
+		url: &'a Url,
+		force: bool,
+	) -> Result<(), SchemeError<'a>> {
+		let path = url.path();
+		let mut db = self.db.lock().expect("poisoned lock");
+		let txn = db.transaction().map_err(sql_error)?;
+		let mut removed = txn
+			.execute("DELETE FROM vfs_nodes WHERE path = ?1", [path])
+			.map_err(sql_error)?;
+		// Forcing also removes every node under it as a directory
+		if force {
+			let prefix = dir_prefix(path);
+			removed += txn
+				.execute(
+					"DELETE FROM vfs_nodes WHERE path >= ?1 AND path < ?2",
+					[&prefix, &prefix_end(&prefix)],
+				)
+				.map_err(sql_error)?;
+		}
+		txn.commit().map_err(sql_error)?;
+		if removed > 0 {
+			Ok(())
+		} else {
+			Err(SchemeError::NodeDoesNotExist(Cow::Borrowed(url.path())))
+		}
+	}
+
+	async fn metadata<'a>(
+		&self,
+		_vfs: &Vfs,
+		url: &'a Url,
+	) -> Result<NodeMetadata, SchemeError<'a>> {
+		if let Some((data, modified)) = self.get(url.path())? {
+			Ok(NodeMetadata {
+				is_node: true,
+				len: Some((data.len(), Some(data.len()))),
+				modified: Some(
+					SystemTime::UNIX_EPOCH + Duration::from_millis(modified.max(0) as u64),
+				),
+				..Default::default()
+			})
+		} else if url.path() == "/" || !self.paths_under(&dir_prefix(url.path()))?.is_empty() {
+			Ok(NodeMetadata {
+				is_node: false,
+				len: None,
+				modified: None,
+				..Default::default()
+			})
+		} else {
+			Err(SchemeError::NodeDoesNotExist(Cow::Borrowed(url.path())))
+		}
+	}
+
+	async fn read_dir<'s, 'a>(
+		&'s self,
+		_vfs: &'s Vfs,
+		url: &'a Url,
+	) -> Result<BorrowedReadDirStream<'s>, SchemeError<'a>> {
+		if !url.path().ends_with('/') && self.get(url.path())?.is_some() {
+			return Err(SchemeError::NotADirectory(Cow::Borrowed(url.path())));
+		}
+		let prefix = dir_prefix(url.path());
+		// Only the immediate children, a deeper path lists the directory it is in
+		let children: BTreeSet<String> = self
+			.paths_under(&prefix)?
+			.into_iter()
+			.map(|path| match path[prefix.len()..].find('/') {
+				Some(pos) => path[..prefix.len() + pos].to_owned(),
+				None => path,
+			})
+			.collect();
+		let entries: Vec<NodeEntry> = children
+			.into_iter()
+			.map(|path| {
+				let mut url = url.clone();
+				url.set_path(&path);
+				NodeEntry::new(url)
+			})
+			.collect();
+		Ok(Box::pin(futures_lite::stream::iter(entries)))
+	}
+}
+
+pub struct SqliteNode {
+	db: Arc<Mutex<Connection>>,
+	path: String,
+	data: Vec<u8>,
+	cursor: usize,
+	read: bool,
+	write: bool,
+	/// Whether `data` has changes that have not been written back yet.
+	dirty: bool,
+}
+
+impl SqliteNode {
+	fn write_back(&mut self) -> std::io::Result<()> {
+		if self.dirty {
+			let db = self.db.lock().expect("poisoned lock");
+			write_back(&db, &self.path, &self.data, now_millis()).map_err(std::io::Error::other)?;
+			self.dirty = false;
+		}
+		Ok(())
+	}
+}
+
+impl Drop for SqliteNode {
+	fn drop(&mut self) {
+		// Best effort, close the node to find out whether the write-back failed
+		let _ = self.write_back();
+	}
+}
+
+#[async_trait::async_trait]
+impl Node for SqliteNode {
+	fn is_reader(&self) -> bool {
+		self.read
+	}
+
+	fn is_writer(&self) -> bool {
+		self.write
+	}
+
+	fn is_seeker(&self) -> bool {
+		self.read || self.write
+	}
+
+	fn is_at_end(&self) -> Option<bool> {
+		Some(self.cursor >= self.data.len())
+	}
+}
+
+impl AsyncRead for SqliteNode {
+	fn poll_read(
+		mut self: Pin<&mut Self>,
+		_cx: &mut Context<'_>,
+		buf: &mut [u8],
+	) -> Poll<std::io::Result<usize>> {
+		if !self.read {
+			return Poll::Ready(Err(std::io::Error::from_raw_os_error(13)));
+		}
+		if self.cursor >= self.data.len() {
+			return Poll::Ready(Ok(0));
+		}
+
+		let amt = std::cmp::min(self.data.len() - self.cursor, buf.len());
+		buf[..amt].copy_from_slice(&self.data[self.cursor..(self.cursor + amt)]);
+		self.cursor += amt;
+
+		Poll::Ready(Ok(amt))
+	}
+}
+
+impl AsyncWrite for SqliteNode {
+	fn poll_write(
+		mut self: Pin<&mut Self>,
+		_cx: &mut Context<'_>,
+		buf: &[u8],
+	) -> Poll<std::io::Result<usize>> {
+		if !self.write {
+			return Poll::Ready(Err(std::io::Error::from_raw_os_error(13)));
+		}
+		let this = &mut *self;
+		let end = this.cursor + buf.len();
+		if end > this.data.len() {
+			this.data.resize(end, 0);
+		}
+		this.data[this.cursor..end].copy_from_slice(buf);
+		this.cursor = end;
+		this.dirty = true;
+		Poll::Ready(Ok(buf.len()))
+	}
+
+	fn poll_flush(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+		if !self.write {
+			return Poll::Ready(Err(std::io::Error::from_raw_os_error(13)));
+		}
+		Poll::Ready(self.write_back())
+	}
+
+	fn poll_close(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+		if !self.write {
+			return Poll::Ready(Err(std::io::Error::from_raw_os_error(13)));
+		}
+		Poll::Ready(self.write_back())
+	}
+}
+
+impl AsyncSeek for SqliteNode {
+	fn poll_seek(
+		mut self: Pin<&mut Self>,
+		_cx: &mut Context<'_>,
+		pos: SeekFrom,
+	) -> Poll<std::io::Result<u64>> {
+		if !self.read && !self.write {
+			return Poll::Ready(Err(std::io::Error::from_raw_os_error(13)));
+		}
+		let len = self.data.len();
+		self.cursor = match pos {
+			SeekFrom::Start(pos) => std::cmp::min(pos, len as u64) as usize,
+			SeekFrom::End(end_pos) if end_pos > 0 => len,
+			SeekFrom::End(end_pos) => len.saturating_sub((-end_pos) as usize),
+			SeekFrom::Current(offset) => {
+				(self.cursor as i64 + offset).clamp(0, len as i64) as usize
+			}
+		};
+		Poll::Ready(Ok(self.cursor as u64))
+	}
+}
+
+#[cfg(test)]
+#[cfg(feature = "backend_tokio")]
+mod async_tokio_tests {
+	use crate::scheme::NodeGetOptions;
+	use crate::{SchemeError, SqliteScheme, Vfs, VfsError};
+	use futures_lite::{AsyncReadExt, AsyncWriteExt, StreamExt};
+
+	async fn create(vfs: &Vfs, uri: &str, content: &str) {
+		let mut node = vfs
+			.get_node_at(uri, &NodeGetOptions::new().create_new(true))
+			.await
+			.unwrap();
+		node.write_all(content.as_bytes()).await.unwrap();
+		node.close().await.unwrap();
+	}
+
+	async fn listing(vfs: &Vfs, uri: &str) -> Vec<String> {
+		vfs.read_dir_at(uri)
+			.await
+			.unwrap()
+			.map(|entry| entry.url.path().to_owned())
+			.collect()
+			.await
+	}
+
+	#[tokio::test]
+	async fn sqlite_create_list_remove() {
+		let vfs = Vfs::empty();
+		vfs.add_scheme("db", SqliteScheme::in_memory().unwrap())
+			.unwrap();
+		create(&vfs, "db:/config.toml", "config").await;
+		create(&vfs, "db:/assets/a.png", "a").await;
+		create(&vfs, "db:/assets/deep/b.png", "bb").await;
+		create(&vfs, "db:/assets0", "not under assets").await;
+
+		let mut buffer = String::new();
+		vfs.get_node_at("db:/config.toml", &NodeGetOptions::new().read(true))
+			.await
+			.unwrap()
+			.read_to_string(&mut buffer)
+			.await
+			.unwrap();
+		assert_eq!(&buffer, "config");
+		let metadata = vfs.metadata_at("db:/assets/deep/b.png").await.unwrap();
+		assert_eq!(metadata.len, Some((2, Some(2))));
+		assert!(metadata.modified.is_some());
+		assert!(!vfs.metadata_at("db:/assets").await.unwrap().is_node);
+		assert_eq!(
+			listing(&vfs, "db:/").await,
+			["/assets", "/assets0", "/config.toml"]
+		);
+		assert_eq!(
+			listing(&vfs, "db:/assets").await,
+			["/assets/a.png", "/assets/deep"]
+		);
+		assert!(matches!(
+			vfs.get_node_at("db:/config.toml", &NodeGetOptions::new().create_new(true))
+				.await,
+			Err(VfsError::SchemeError(SchemeError::NodeAlreadyExists(_)))
+		));
+
+		vfs.remove_node_at("db:/config.toml", false).await.unwrap();
+		assert!(vfs.metadata_at("db:/config.toml").await.is_err());
+		vfs.remove_node_at("db:/assets", true).await.unwrap();
+		assert_eq!(listing(&vfs, "db:/").await, ["/assets0"]);
+		assert!(vfs.remove_node_at("db:/assets", true).await.is_err());
+	}
+
+	#[tokio::test]
+	async fn sqlite_write_batch() {
+		let vfs = Vfs::empty();
+		vfs.add_scheme("db", SqliteScheme::in_memory().unwrap())
+			.unwrap();
+		let sqlite = vfs.get_scheme_as::<SqliteScheme>("db").unwrap();
+		sqlite
+			.write_batch([("/save/world", &b"world"[..]), ("/save/player", b"player")])
+			.unwrap();
+		assert_eq!(
+			listing(&vfs, "db:/save").await,
+			["/save/player", "/save/world"]
+		);
+		assert_eq!(
+			vfs.read_to_vec_at("db:/save/world").await.unwrap(),
+			b"world"
+		);
+
+		// A failing statement rolls the whole batch back
+		sqlite
+			.db()
+			.lock()
+			.unwrap()
+			.execute_batch(
+				"CREATE TRIGGER refuse BEFORE INSERT ON vfs_nodes WHEN NEW.path = '/save/bad'
+				BEGIN SELECT RAISE(ABORT, 'refused'); END",
+			)
+			.unwrap();
+		assert!(sqlite
+			.write_batch([("/save/world", &b"changed"[..]), ("/save/bad", b"bad")])
+			.is_err());
+		assert_eq!(
+			vfs.read_to_vec_at("db:/save/world").await.unwrap(),
+			b"world"
+		);
+	}
+}
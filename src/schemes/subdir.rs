@@ -0,0 +1,376 @@
+#![allow(clippy::try_err)]
+
+use crate::scheme::{
+	BorrowedReadDirStream, NodeEntry, NodeGetOptions, NodeMetadata, WatchEvent, WatchStream,
+};
+use crate::{PinnedNode, Scheme, SchemeError, Vfs};
+use futures_lite::StreamExt;
+use percent_encoding::percent_decode_str;
+use std::borrow::Cow;
+use std::time::SystemTime;
+use url::Url;
+
+/// Exposes only the subtree of another scheme below a root path, so `sub:/a.png` of a
+/// `SubdirScheme::new(inner, "/assets/textures")` is `/assets/textures/a.png` of the inner scheme,
+/// a lighter tool than a `SymLinkScheme` for a single prefix.  Urls handed back, such as by
+/// `read_dir` and `watch`, and the paths in errors are translated back to be relative to the root.
+/// Urls cannot escape the root, `..` segments are already resolved when a url is parsed and a
+/// segment that would only become a `..` or gain a `/` once percent-decoded is refused with
+/// `SchemeError::UrlAccessError`.
+pub struct SubdirScheme {
+	scheme: Box<dyn Scheme>,
+	/// The root path without a trailing `/`, empty for the root of the inner scheme.
+	root: String,
+}
+
+impl SubdirScheme {
+	/// The `root` is an absolute path of the inner scheme, such as `/assets/textures`.
+	pub fn new(scheme: impl Scheme, root: &str) -> Result<Self, SchemeError<'static>> {
+		Self::new_boxed(Box::new(scheme), root)
+	}
+
+	pub fn new_boxed(scheme: Box<dyn Scheme>, root: &str) -> Result<Self, SchemeError<'static>> {
+		if !root.starts_with('/') {
+			Err("subdir root must be an absolute path")?;
+		}
+		let parsed = Url::parse(&format!("x:{}", root))?;
+		if parsed.path() != root && parsed.path() != format!("{}/", root.trim_end_matches('/')) {
+			Err("subdir root must be a normalized path")?;
+		}
+		if parsed.query().is_some() || parsed.fragment().is_some() {
+			Err("subdir root must only be a path")?;
+		}
+		Ok(Self {
+			scheme,
+			root: parsed.path().trim_end_matches('/').to_owned(),
+		})
+	}
+
+	pub fn inner(&self) -> &dyn Scheme {
+		&*self.scheme
+	}
+
+	pub fn into_inner(self) -> Box<dyn Scheme> {
+		self.scheme
+	}
+
+	/// The root path in the inner scheme, without a trailing `/`.
+	pub fn root(&self) -> &str {
+		&self.root
+	}
+
+	/// The url of the inner scheme that `url` names.
+	pub fn inner_url<'a>(&self, url: &'a Url) -> Result<Url, SchemeError<'a>> {
+		let escapes = url.path().split('/').any(|segment| {
+			let decoded = percent_decode_str(segment).decode_utf8_lossy();
+			decoded == "." || decoded == ".." || decoded.contains(['/', '\\'])
+		});
+		if escapes {
+			return Err(SchemeError::UrlAccessError(Cow::Borrowed(url)));
+		}
+		let mut inner = url.clone();
+		inner.set_path(&format!("{}{}", self.root, url.path()));
+		Ok(inner)
+	}
+
+	/// The url of this scheme that names `inner`, a url of the inner scheme, `None` if it is not
+	/// below the root.
+	pub fn outer_url(&self, inner: &Url) -> Option<Url> {
+		let path = self.outer_path(inner.path())?;
+		let mut outer = inner.clone();
+		outer.set_path(&path);
+		Some(outer)
+	}
+
+	fn outer_path(&self, path: &str) -> Option<String> {
+		outer_path(&self.root, path).map(str::to_owned)
+	}
+
+	fn outer<T>(&self, result: Result<T, SchemeError<'_>>) -> Result<T, SchemeError<'static>> {
+		result.map_err(|error| self.outer_error(error))
+	}
+
+	/// Translates the paths an error of the inner scheme names back to this scheme.
+	fn outer_error(&self, error: SchemeError<'_>) -> SchemeError<'static> {
+		let path = |name: Cow<'_, str>| -> Cow<'static, str> {
+			Cow::Owned(self.outer_path(&name).unwrap_or_else(|| name.into_owned()))
+		};
+		match error {
+			SchemeError::NodeDoesNotExist(name) => SchemeError::NodeDoesNotExist(path(name)),
+			SchemeError::NodeAlreadyExists(name) => SchemeError::NodeAlreadyExists(path(name)),
+			SchemeError::IsADirectory(name) => SchemeError::IsADirectory(path(name)),
+			SchemeError::NotADirectory(name) => SchemeError::NotADirectory(path(name)),
+			SchemeError::IOErrorAt(name, source) => SchemeError::IOErrorAt(path(name), source),
+			SchemeError::QuotaExceeded(name) => SchemeError::QuotaExceeded(path(name)),
+			SchemeError::UrlAccessError(url) => {
+				let url = self.outer_url(&url).unwrap_or_else(|| url.into_owned());
+				SchemeError::UrlAccessError(Cow::Owned(url))
+			}
+			error => error.into_owned(),
+		}
+	}
+}
+
+/// The path below `root` that `path` names, `None` if it is not below it.
+fn outer_path<'p>(root: &str, path: &'p str) -> Option<&'p str> {
+	match path.strip_prefix(root)? {
+		"" => Some("/"),
+		rest if rest.starts_with('/') => Some(rest),
+		_ => None,
+	}
+}
+
+#[async_trait::async_trait]
+impl Scheme for SubdirScheme {
+	async fn get_node<'a>(
+		&self,
+		vfs: &Vfs,
+		url: &'a Url,
+		options: &NodeGetOptions,
+	) -> Result<PinnedNode, SchemeError<'a>> {
+		let inner = self.inner_url(url)?;
+		self.outer(self.scheme.get_node(vfs, &inner, options).await)
+	}
+
+	async fn remove_node<'a>(
+		&self,
+		vfs: &Vfs,
+		url: &'a Url,
+		force: bool,
+	) -> Result<(), SchemeError<'a>> {
+		let inner = self.inner_url(url)?;
+		self.outer(self.scheme.remove_node(vfs, &inner, force).await)
+	}
+
+	async fn metadata<'a>(&self, vfs: &Vfs, url: &'a Url) -> Result<NodeMetadata, SchemeError<'a>> {
+		let inner = self.inner_url(url)?;
+		self.outer(self.scheme.metadata(vfs, &inner).await)
+	}
+
+	async fn read_dir<'s, 'a>(
+		&'s self,
+		vfs: &'s Vfs,
+		url: &'a Url,
+	) -> Result<BorrowedReadDirStream<'s>, SchemeError<'a>> {
+		let inner = self.inner_url(url)?;
+		let entries = self.outer(self.scheme.read_dir(vfs, &inner).await)?;
+		Ok(Box::pin(entries.filter_map(move |entry| {
+			self.outer_url(&entry.url).map(|url| NodeEntry {
+				url,
+				metadata: entry.metadata,
+			})
+		})))
+	}
+
+	async fn read_small_file<'a>(
+		&self,
+		vfs: &Vfs,
+		url: &'a Url,
+		max_len: usize,
+	) -> Result<Option<Vec<u8>>, SchemeError<'a>> {
+		let inner = self.inner_url(url)?;
+		self.outer(self.scheme.read_small_file(vfs, &inner, max_len).await)
+	}
+
+	#[cfg(feature = "bytes")]
+	async fn read_bytes<'a>(
+		&self,
+		vfs: &Vfs,
+		url: &'a Url,
+	) -> Result<Option<bytes::Bytes>, SchemeError<'a>> {
+		let inner = self.inner_url(url)?;
+		self.outer(self.scheme.read_bytes(vfs, &inner).await)
+	}
+
+	async fn stat_and_open<'a>(
+		&self,
+		vfs: &Vfs,
+		url: &'a Url,
+		options: &NodeGetOptions,
+	) -> Result<(NodeMetadata, PinnedNode), SchemeError<'a>> {
+		let inner = self.inner_url(url)?;
+		self.outer(self.scheme.stat_and_open(vfs, &inner, options).await)
+	}
+
+	async fn get_node_with_metadata<'a>(
+		&self,
+		vfs: &Vfs,
+		url: &'a Url,
+		options: &NodeGetOptions,
+		metadata: &NodeMetadata,
+	) -> Result<PinnedNode, SchemeError<'a>> {
+		let inner = self.inner_url(url)?;
+		self.outer(
+			self.scheme
+				.get_node_with_metadata(vfs, &inner, options, metadata)
+				.await,
+		)
+	}
+
+	async fn canonicalize<'a>(&self, vfs: &Vfs, url: &'a Url) -> Result<Url, SchemeError<'a>> {
+		let inner = self.inner_url(url)?;
+		let canonical = self.outer(self.scheme.canonicalize(vfs, &inner).await)?;
+		// Such as a symlink of the inner scheme pointing outside of the root
+		self.outer_url(&canonical)
+			.ok_or(SchemeError::UrlAccessError(Cow::Borrowed(url)))
+	}
+
+	async fn set_modified<'a>(
+		&self,
+		vfs: &Vfs,
+		url: &'a Url,
+		modified: SystemTime,
+	) -> Result<(), SchemeError<'a>> {
+		let inner = self.inner_url(url)?;
+		self.outer(self.scheme.set_modified(vfs, &inner, modified).await)
+	}
+
+	async fn create_dir<'a>(
+		&self,
+		vfs: &Vfs,
+		url: &'a Url,
+		parents: bool,
+	) -> Result<(), SchemeError<'a>> {
+		let inner = self.inner_url(url)?;
+		self.outer(self.scheme.create_dir(vfs, &inner, parents).await)
+	}
+
+	async fn copy_node<'a>(
+		&self,
+		vfs: &Vfs,
+		from: &'a Url,
+		to: &'a Url,
+	) -> Result<(), SchemeError<'a>> {
+		let (from, to) = (self.inner_url(from)?, self.inner_url(to)?);
+		self.outer(self.scheme.copy_node(vfs, &from, &to).await)
+	}
+
+	async fn rename_node<'a>(
+		&self,
+		vfs: &Vfs,
+		from: &'a Url,
+		to: &'a Url,
+	) -> Result<(), SchemeError<'a>> {
+		let (from, to) = (self.inner_url(from)?, self.inner_url(to)?);
+		self.outer(self.scheme.rename_node(vfs, &from, &to).await)
+	}
+
+	async fn get_node_split<'a>(
+		&self,
+		vfs: &Vfs,
+		url: &'a Url,
+		options: &NodeGetOptions,
+	) -> Result<(PinnedNode, PinnedNode), SchemeError<'a>> {
+		let inner = self.inner_url(url)?;
+		self.outer(self.scheme.get_node_split(vfs, &inner, options).await)
+	}
+
+	async fn watch<'a>(&self, vfs: &Vfs, url: &'a Url) -> Result<WatchStream, SchemeError<'a>> {
+		let inner = self.inner_url(url)?;
+		let events = self.outer(self.scheme.watch(vfs, &inner).await)?;
+		let root = self.root.clone();
+		Ok(Box::pin(events.filter_map(move |event| {
+			let mut url = event.url.clone();
+			url.set_path(outer_path(&root, event.url.path())?);
+			Some(WatchEvent {
+				url,
+				kind: event.kind,
+			})
+		})))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::{FnScheme, SubdirScheme};
+	use url::Url;
+
+	#[test]
+	fn subdir_urls() {
+		let subdir = SubdirScheme::new(FnScheme::new(), "/assets/textures/").unwrap();
+		assert_eq!(subdir.root(), "/assets/textures");
+		let inner = |url: &str| {
+			subdir
+				.inner_url(&Url::parse(url).unwrap())
+				.map(|url| url.to_string())
+				.ok()
+		};
+		assert_eq!(
+			inner("sub:/a.png").as_deref(),
+			Some("sub:/assets/textures/a.png")
+		);
+		assert_eq!(inner("sub:/").as_deref(), Some("sub:/assets/textures/"));
+		assert_eq!(
+			inner("sub:/../../secret").as_deref(),
+			Some("sub:/assets/textures/secret")
+		);
+		assert_eq!(inner("sub:/..%2F..%2Fsecret"), None);
+		assert_eq!(inner("sub:/dir/%2E%2E%5Csecret"), None);
+		assert_eq!(
+			subdir
+				.outer_url(&Url::parse("sub:/assets/textures/dir/a.png").unwrap())
+				.map(|url| url.to_string())
+				.as_deref(),
+			Some("sub:/dir/a.png")
+		);
+		assert_eq!(
+			subdir.outer_url(&Url::parse("sub:/assets/texturesque").unwrap()),
+			None
+		);
+		assert!(SubdirScheme::new(FnScheme::new(), "assets").is_err());
+		assert!(SubdirScheme::new(FnScheme::new(), "/assets/../etc").is_err());
+		assert_eq!(SubdirScheme::new(FnScheme::new(), "/").unwrap().root(), "");
+	}
+}
+
+#[cfg(test)]
+#[cfg(feature = "backend_tokio")]
+#[cfg(feature = "in_memory")]
+mod async_tokio_tests {
+	use crate::scheme::NodeGetOptions;
+	use crate::{MemoryScheme, SchemeError, SubdirScheme, Vfs, VfsError};
+	use futures_lite::{AsyncWriteExt, StreamExt};
+	use url::Url;
+
+	#[tokio::test]
+	async fn subdir_memory() {
+		let vfs = Vfs::empty();
+		vfs.add_scheme(
+			"sub",
+			SubdirScheme::new(MemoryScheme::default(), "/assets/textures").unwrap(),
+		)
+		.unwrap();
+		let subdir = vfs.get_scheme_as::<SubdirScheme>("sub").unwrap();
+		for (path, data) in [("/assets/textures/a.png", "a"), ("/assets/secret", "s")] {
+			let mut node = subdir
+				.inner()
+				.get_node(
+					&vfs,
+					&Url::parse(&format!("sub:{}", path)).unwrap(),
+					&NodeGetOptions::new().create_new(true),
+				)
+				.await
+				.unwrap();
+			node.write_all(data.as_bytes()).await.unwrap();
+			node.close().await.unwrap();
+		}
+
+		assert_eq!(vfs.read_to_vec_at("sub:/a.png").await.unwrap(), b"a");
+		let listed: Vec<String> = vfs
+			.read_dir_at("sub:/")
+			.await
+			.unwrap()
+			.map(|entry| entry.url.to_string())
+			.collect()
+			.await;
+		assert_eq!(listed, ["sub:/a.png"]);
+		assert!(matches!(
+			vfs.read_to_vec_at("sub:/../secret").await,
+			Err(VfsError::SchemeError(SchemeError::NodeDoesNotExist(path))) if path == "/secret"
+		));
+		assert!(matches!(
+			vfs.read_to_vec_at("sub:/..%2Fsecret").await,
+			Err(VfsError::SchemeError(SchemeError::UrlAccessError(_)))
+		));
+	}
+}
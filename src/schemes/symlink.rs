@@ -1,9 +1,12 @@
 #![allow(clippy::try_err)]
 
-use crate::scheme::{NodeGetOptions, NodeMetadata, ReadDirStream};
+use crate::scheme::{BorrowedReadDirStream, NodeEntry, NodeGetOptions, NodeKind, NodeMetadata};
 use crate::{PinnedNode, Scheme, SchemeError, Vfs};
+use percent_encoding::percent_decode_str;
 use std::borrow::Cow;
 use std::collections::HashMap;
+use std::sync::RwLock;
+use std::time::SystemTime;
 use url::Url;
 
 const MAX_SYMLINK_PATH_SEGMENTS: usize = 16;
@@ -20,11 +23,74 @@ impl SymLinkTreeNode {
 	pub fn get_base_url(&self) -> Option<&Url> {
 		self.base_url.as_ref()
 	}
+
+	/// Adds a link to `to` at the path of `from`, which was checked by `validate_from_url_path`.
+	fn link(&mut self, from: &Url, to: Url) -> Result<(), SchemeError<'static>> {
+		let node = if let Some(path_segments) = from.path_segments() {
+			let mut depth = 0;
+			let mut node = self;
+			for segment in path_segments {
+				depth += 1;
+				if depth >= MAX_SYMLINK_PATH_SEGMENTS {
+					Err(MAX_SYMLINK_PATH_SEGMENTS_ERR)?;
+				}
+				node = node.children.entry(segment.to_owned()).or_default();
+			}
+			node
+		} else if from.path().is_empty() {
+			// Set the root node
+			self
+		} else {
+			Err("relative symlink is not allowed")?
+		};
+		if node.base_url.is_some() {
+			Err("url already set at link, remove it first")?;
+		}
+		node.base_url = Some(to);
+		Ok(())
+	}
+
+	/// Takes out the link at the path of `from`, which was checked by `validate_from_url_path`,
+	/// failing with `NodeDoesNotExist` if there is none.
+	fn unlink_path(&mut self, from: &Url) -> Result<Url, SchemeError<'static>> {
+		let removed = match from.path_segments() {
+			Some(path_segments) => self.unlink(path_segments),
+			None if from.path().is_empty() => self.base_url.take(),
+			None => Err("relative symlink is not allowed")?,
+		};
+		removed.ok_or_else(|| SchemeError::NodeDoesNotExist(Cow::Owned(from.path().to_owned())))
+	}
+
+	/// Takes out the link at `segments` below this node, dropping the nodes left holding nothing.
+	fn unlink<'s>(&mut self, mut segments: impl Iterator<Item = &'s str>) -> Option<Url> {
+		match segments.next() {
+			None => self.base_url.take(),
+			Some(segment) => {
+				let child = self.children.get_mut(segment)?;
+				let removed = child.unlink(segments);
+				if child.base_url.is_none() && child.children.is_empty() {
+					self.children.remove(segment);
+				}
+				removed
+			}
+		}
+	}
+
+	fn collect_links<'s>(&'s self, path: &str, links: &mut Vec<(String, &'s Url)>) {
+		if let Some(base_url) = &self.base_url {
+			links.push((path.to_owned(), base_url));
+		}
+		for (segment, child) in &self.children {
+			child.collect_links(&format!("{}/{}", path, segment), links);
+		}
+	}
 }
 
+/// Redirects urls to the links they are at or below.  Links can be changed while the scheme is
+/// registered, through `Vfs::get_scheme_as`, and the change applies to every later request.
 #[derive(Default)]
 pub struct SymLinkScheme {
-	base: SymLinkTreeNode,
+	base: RwLock<SymLinkTreeNode>,
 }
 
 impl SymLinkScheme {
@@ -49,37 +115,57 @@ impl SymLinkScheme {
 		}
 	}
 
-	pub fn link(&mut self, from: &str, to: Url) -> Result<(), SchemeError<'static>> {
+	pub fn link(&self, from: &str, to: Url) -> Result<(), SchemeError<'static>> {
 		let from = Self::validate_from_url_path(from)?;
-		if let Some(path_segments) = from.path_segments() {
-			let mut depth = 0;
-			let mut node = &mut self.base;
-			for segment in path_segments {
-				depth += 1;
-				if depth >= MAX_SYMLINK_PATH_SEGMENTS {
-					Err(MAX_SYMLINK_PATH_SEGMENTS_ERR)?;
-				}
-				node = node
-					.children
-					.entry(segment.to_owned())
-					.or_insert_with(Default::default);
-			}
-			if node.base_url.is_some() {
-				Err("url already set at link, remove it first")?;
-			} else {
-				node.base_url = Some(to);
-			}
-		} else if from.path().is_empty() {
-			// Set the root node
-			if self.base.base_url.is_some() {
-				Err("url already set at link, remove it first")?;
-			} else {
-				self.base.base_url = Some(to)
-			}
-		} else {
-			Err("relative symlink is not allowed")?;
+		self.base.write().expect("poisoned lock").link(&from, to)
+	}
+
+	/// Removes the link at `from`, returning where it pointed, or `NodeDoesNotExist` if there was
+	/// no link there.
+	pub fn unlink(&self, from: &str) -> Result<Url, SchemeError<'static>> {
+		let from = Self::validate_from_url_path(from)?;
+		self.base.write().expect("poisoned lock").unlink_path(&from)
+	}
+
+	/// Points the link at `from` to `to`, adding it if there was none, returning where it pointed
+	/// before.
+	pub fn relink(&self, from: &str, to: Url) -> Result<Option<Url>, SchemeError<'static>> {
+		let from = Self::validate_from_url_path(from)?;
+		let mut base = self.base.write().expect("poisoned lock");
+		let previous = match base.unlink_path(&from) {
+			Ok(previous) => Some(previous),
+			Err(SchemeError::NodeDoesNotExist(_path)) => None,
+			Err(error) => return Err(error),
+		};
+		base.link(&from, to)?;
+		Ok(previous)
+	}
+
+	/// Every link as the `from` path it was linked at, `""` for the root, and where it points,
+	/// sorted by path.
+	pub fn links(&self) -> impl Iterator<Item = (String, Url)> {
+		let base = self.base.read().expect("poisoned lock");
+		let mut links = Vec::new();
+		base.collect_links("", &mut links);
+		links.sort_by(|(a, _), (b, _)| a.cmp(b));
+		let links: Vec<(String, Url)> = links
+			.into_iter()
+			.map(|(from, to)| (from, to.clone()))
+			.collect();
+		links.into_iter()
+	}
+
+	/// The sorted names below the node of the link tree at exactly the path of `url`, whether or
+	/// not it is a link, `None` if there is no such node.
+	fn tree_children(&self, url: &Url) -> Option<Vec<String>> {
+		let base = self.base.read().expect("poisoned lock");
+		let mut node = &*base;
+		for segment in url.path_segments()?.filter(|segment| !segment.is_empty()) {
+			node = node.children.get(segment)?;
 		}
-		Ok(())
+		let mut names: Vec<String> = node.children.keys().cloned().collect();
+		names.sort_unstable();
+		Some(names)
 	}
 
 	fn merge_urls(base_url: &Url, url: &Url, url_path: &str) -> Result<Url, SchemeError<'static>> {
@@ -139,8 +225,9 @@ impl SymLinkScheme {
 	}
 
 	pub fn get_symlink_dest<'a>(&self, url: &'a Url) -> Result<Url, SchemeError<'a>> {
+		let base = self.base.read().expect("poisoned lock");
 		if let Some(path_segments) = url.path_segments() {
-			let mut cur_node = &self.base;
+			let mut cur_node = &*base;
 			let mut cur_path = [""; MAX_SYMLINK_PATH_SEGMENTS];
 			let mut valid_node = if cur_node.base_url.is_some() {
 				Some(cur_node)
@@ -175,11 +262,11 @@ impl SymLinkScheme {
 					.trim_start_matches('/');
 				Self::merge_urls(base_url, url, url_path)
 			} else {
-				return Err(SchemeError::NodeDoesNotExist(Cow::Borrowed(url.as_str())));
+				Err(SchemeError::NodeDoesNotExist(Cow::Borrowed(url.as_str())))
 			}
 		} else {
 			// Data paths are only supported on base
-			if let Some(base_url) = &self.base.base_url {
+			if let Some(base_url) = &base.base_url {
 				Self::merge_urls(base_url, url, url.path())
 			} else {
 				Err(SchemeError::NodeDoesNotExist(Cow::Borrowed(url.as_str())))
@@ -197,11 +284,16 @@ impl SymLinkSchemeBuilder {
 		self.scheme
 	}
 
-	pub fn link(mut self, from: &str, to: Url) -> Self {
-		self.scheme
-			.link(from, to)
-			.expect("SymLinkSchemeBuilder links must have unique `from` paths");
-		self
+	/// See `SymLinkScheme::link`, fails the same way, such as for a `from` already linked.
+	pub fn link(self, from: &str, to: Url) -> Result<Self, SchemeError<'static>> {
+		self.scheme.link(from, to)?;
+		Ok(self)
+	}
+
+	/// Like `link` but replaces a link already at `from` rather than failing.
+	pub fn relink(self, from: &str, to: Url) -> Result<Self, SchemeError<'static>> {
+		self.scheme.relink(from, to)?;
+		Ok(self)
 	}
 }
 
@@ -231,20 +323,94 @@ impl Scheme for SymLinkScheme {
 		Ok(fut.await?)
 	}
 
+	/// Paths above links that no link covers are directories holding them.
 	async fn metadata<'a>(&self, vfs: &Vfs, url: &'a Url) -> Result<NodeMetadata, SchemeError<'a>> {
-		let url = self.get_symlink_dest(url)?;
+		let url = match self.get_symlink_dest(url) {
+			Ok(url) => url,
+			Err(error) => {
+				return match self.tree_children(url) {
+					Some(_names) => Ok(NodeMetadata {
+						is_node: false,
+						kind: Some(NodeKind::Directory),
+						read_only: Some(true),
+						..Default::default()
+					}),
+					None => Err(error),
+				}
+			}
+		};
 		let fut = vfs.metadata(&url);
 		// Split the `await` from the `fut` so `url` can drop or else lifetime annoyance
 		Ok(fut.await?)
 	}
 
-	async fn read_dir<'a>(
+	async fn read_dir<'s, 'a>(
+		&'s self,
+		vfs: &'s Vfs,
+		url: &'a Url,
+	) -> Result<BorrowedReadDirStream<'s>, SchemeError<'a>> {
+		// Paths above links that no link covers list the link points below them
+		let url = match self.get_symlink_dest(url) {
+			Ok(url) => url,
+			Err(error) => {
+				let names = match self.tree_children(url) {
+					Some(names) => names,
+					None => return Err(error),
+				};
+				let entries: Vec<NodeEntry> = names
+					.into_iter()
+					.filter_map(|name| {
+						let name = percent_decode_str(&name).decode_utf8_lossy();
+						NodeEntry::child(url, [name])
+					})
+					.collect();
+				return Ok(Box::pin(futures_lite::stream::iter(entries)));
+			}
+		};
+		let fut = vfs.read_dir(&url);
+		// Split the `await` from the `fut` so `url` can drop or else lifetime annoyance
+		Ok(fut.await?)
+	}
+
+	async fn stat_and_open<'a>(
 		&self,
 		vfs: &Vfs,
 		url: &'a Url,
-	) -> Result<ReadDirStream, SchemeError<'a>> {
+		options: &NodeGetOptions,
+	) -> Result<(NodeMetadata, PinnedNode), SchemeError<'a>> {
 		let url = self.get_symlink_dest(url)?;
-		let fut = vfs.read_dir(&url);
+		let fut = vfs.stat_and_open(&url, options);
+		// Split the `await` from the `fut` so `url` can drop or else lifetime annoyance
+		Ok(fut.await?)
+	}
+
+	async fn canonicalize<'a>(&self, vfs: &Vfs, url: &'a Url) -> Result<Url, SchemeError<'a>> {
+		let url = self.get_symlink_dest(url)?;
+		let fut = vfs.canonicalize(&url);
+		// Split the `await` from the `fut` so `url` can drop or else lifetime annoyance
+		Ok(fut.await?)
+	}
+
+	async fn set_modified<'a>(
+		&self,
+		vfs: &Vfs,
+		url: &'a Url,
+		modified: SystemTime,
+	) -> Result<(), SchemeError<'a>> {
+		let url = self.get_symlink_dest(url)?;
+		let fut = vfs.set_modified(&url, modified);
+		// Split the `await` from the `fut` so `url` can drop or else lifetime annoyance
+		Ok(fut.await?)
+	}
+
+	async fn get_node_split<'a>(
+		&self,
+		vfs: &Vfs,
+		url: &'a Url,
+		options: &NodeGetOptions,
+	) -> Result<(PinnedNode, PinnedNode), SchemeError<'a>> {
+		let url = self.get_symlink_dest(url)?;
+		let fut = vfs.get_node_split(&url, options);
 		// Split the `await` from the `fut` so `url` can drop or else lifetime annoyance
 		Ok(fut.await?)
 	}
@@ -252,7 +418,7 @@ impl Scheme for SymLinkScheme {
 
 #[cfg(test)]
 mod tests {
-	use crate::SymLinkScheme;
+	use crate::{SchemeError, SymLinkScheme, Vfs};
 	use url::Url;
 
 	fn u(s: &str) -> Url {
@@ -288,13 +454,97 @@ mod tests {
 			.expect("deep child path must be accepted");
 		let _ = url;
 	}
+
+	#[test]
+	fn unlink_and_relink() {
+		let scheme = SymLinkScheme::builder()
+			.link("", u("data:"))
+			.unwrap()
+			.link("/deep/child", u("fs:/child"))
+			.unwrap()
+			.link("/other", u("fs:/other"))
+			.unwrap()
+			.relink("/other", u("fs:/replaced"))
+			.unwrap()
+			.build();
+		let links = |scheme: &SymLinkScheme| {
+			scheme
+				.links()
+				.map(|(from, to)| (from, to.to_string()))
+				.collect::<Vec<_>>()
+		};
+		assert_eq!(
+			links(&scheme),
+			[
+				("".to_owned(), "data:".to_owned()),
+				("/deep/child".to_owned(), "fs:/child".to_owned()),
+				("/other".to_owned(), "fs:/replaced".to_owned()),
+			]
+		);
+
+		assert_eq!(scheme.unlink("/deep/child").unwrap(), u("fs:/child"));
+		assert!(matches!(
+			scheme.unlink("/deep/child"),
+			Err(SchemeError::NodeDoesNotExist(path)) if path == "/deep/child"
+		));
+		assert!(matches!(
+			scheme.unlink("/deep"),
+			Err(SchemeError::NodeDoesNotExist(_))
+		));
+		scheme
+			.relink("rel", u("fs:/rel"))
+			.expect_err("relative path is not allowed");
+		scheme
+			.relink("/trailing/", u("fs:/trailing"))
+			.expect_err("trailing slash is not allowed");
+		assert_eq!(scheme.unlink("").unwrap(), u("data:"));
+		assert_eq!(
+			scheme.relink("/other", u("fs:/again")).unwrap(),
+			Some(u("fs:/replaced"))
+		);
+		assert_eq!(scheme.relink("/new", u("fs:/new")).unwrap(), None);
+		assert_eq!(
+			links(&scheme),
+			[
+				("/new".to_owned(), "fs:/new".to_owned()),
+				("/other".to_owned(), "fs:/again".to_owned()),
+			]
+		);
+
+		SymLinkScheme::builder()
+			.link("/twice", u("fs:/first"))
+			.unwrap()
+			.link("/twice", u("fs:/second"))
+			.err()
+			.expect("a `from` can only be linked once");
+	}
+
+	#[test]
+	fn relink_shared() {
+		let vfs = Vfs::empty();
+		vfs.add_scheme(
+			"sl",
+			SymLinkScheme::builder()
+				.link("/assets", u("fs:/old/"))
+				.unwrap()
+				.build(),
+		)
+		.unwrap();
+		let shared = vfs.clone();
+		let scheme = shared.get_scheme_as::<SymLinkScheme>("sl").unwrap();
+		scheme.relink("/assets", u("fs:/new/")).unwrap();
+		assert_eq!(
+			scheme.get_symlink_dest(&u("sl:/assets/font")).unwrap(),
+			u("fs:/new/font")
+		);
+	}
 }
 
 #[cfg(test)]
 #[cfg(feature = "backend_tokio")]
 mod async_tokio_tests {
 	use crate::scheme::NodeGetOptions;
-	use crate::{SymLinkScheme, TokioFileSystemScheme, Vfs};
+	use crate::{SchemeError, SymLinkScheme, TokioFileSystemScheme, Vfs, VfsError};
 	use futures_lite::AsyncReadExt;
 	use url::Url;
 
@@ -314,15 +564,20 @@ mod async_tokio_tests {
 
 	#[tokio::test]
 	async fn node_get() {
-		let mut vfs = Vfs::default();
+		let vfs = Vfs::default();
 		vfs.add_scheme(
 			"sl",
 			SymLinkScheme::builder()
 				.link("", u("data:"))
+				.unwrap()
 				.link("/data", u("data:"))
+				.unwrap()
 				.link("/fs", u("fs:/"))
+				.unwrap()
 				.link("/fst", u("fs:/target/"))
+				.unwrap()
 				.link("/fsc.toml", u("fs:/Cargo.toml"))
+				.unwrap()
 				.build(),
 		)
 		.unwrap();
@@ -345,5 +600,44 @@ mod async_tokio_tests {
 				.unwrap(),
 			"[package]"
 		);
+		assert!(
+			matches!(
+				vfs.get_node_at("sl:/fs/src", &NodeGetOptions::new().read(true))
+					.await,
+				Err(VfsError::SchemeError(SchemeError::IsADirectory(_)))
+			),
+			"directory errors pass through the symlink"
+		);
+	}
+
+	#[tokio::test]
+	async fn read_dir_link_points() {
+		use futures_lite::StreamExt;
+		let vfs = Vfs::default();
+		vfs.add_scheme(
+			"sl",
+			SymLinkScheme::builder()
+				.link("/assets/fonts", u("data:"))
+				.unwrap()
+				.link("/config", u("data:"))
+				.unwrap()
+				.build(),
+		)
+		.unwrap();
+		let listed = |uri: &'static str| {
+			let vfs = &vfs;
+			async move {
+				vfs.read_dir_at(uri)
+					.await
+					.unwrap()
+					.map(|entry| entry.url.to_string())
+					.collect::<Vec<_>>()
+					.await
+			}
+		};
+		assert_eq!(listed("sl:/").await, ["sl:/assets", "sl:/config"]);
+		assert_eq!(listed("sl:/assets").await, ["sl:/assets/fonts"]);
+		assert!(!vfs.metadata_at("sl:/assets").await.unwrap().is_node);
+		assert!(vfs.metadata_at("sl:/missing").await.is_err());
 	}
 }
@@ -0,0 +1,197 @@
+use crate::io_util;
+use crate::{AssetContainerScheme, ContainerBackend, SchemeError, Vfs, VfsError};
+use futures_lite::AsyncRead;
+use std::collections::HashMap;
+use std::io::Cursor;
+use url::Url;
+
+/// A tar archive as a `ContainerBackend`.  Tar entries are stored uncompressed, so the archive is
+/// buffered in memory and indexed once, then opening an entry is just copying its bytes out.  A
+/// `.tar.gz` or `.tar.zst` archive, recognized by its leading magic bytes, is decompressed into
+/// memory once up front, which needs the `archive_tar_gzip` or `archive_tar_zstd` feature.
+pub struct TarContainer {
+	data: Vec<u8>,
+	/// Offset and length of each file entry within `data`.
+	files: HashMap<String, (usize, usize)>,
+	dirs: Vec<String>,
+}
+
+/// Serves the files of a tar archive as read-only nodes.
+pub type TarArchiveScheme = AssetContainerScheme<TarContainer>;
+
+fn tar_error(error: std::io::Error) -> SchemeError<'static> {
+	("tar archive error", Box::new(error) as Box<_>).into()
+}
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+/// The tar archive within `data`, decompressed if it is compressed.
+fn decompress(data: Vec<u8>) -> Result<Vec<u8>, SchemeError<'static>> {
+	if data.starts_with(GZIP_MAGIC) {
+		#[cfg(feature = "archive_tar_gzip")]
+		{
+			use std::io::Read;
+			let mut decoded = Vec::new();
+			flate2::read::MultiGzDecoder::new(&data[..])
+				.read_to_end(&mut decoded)
+				.map_err(tar_error)?;
+			return Ok(decoded);
+		}
+		#[cfg(not(feature = "archive_tar_gzip"))]
+		return Err("gzip compressed tar archives need the archive_tar_gzip feature".into());
+	}
+	if data.starts_with(ZSTD_MAGIC) {
+		#[cfg(feature = "archive_tar_zstd")]
+		return zstd::stream::decode_all(&data[..]).map_err(tar_error);
+		#[cfg(not(feature = "archive_tar_zstd"))]
+		return Err("zstd compressed tar archives need the archive_tar_zstd feature".into());
+	}
+	Ok(data)
+}
+
+impl TarContainer {
+	pub fn from_bytes(data: Vec<u8>) -> Result<Self, SchemeError<'static>> {
+		let data = decompress(data)?;
+		let mut files = HashMap::new();
+		let mut dirs = Vec::new();
+		let mut archive = tar::Archive::new(Cursor::new(&data[..]));
+		for entry in archive.entries().map_err(tar_error)? {
+			let entry = entry.map_err(tar_error)?;
+			let path = entry.path().map_err(tar_error)?;
+			let name = path.to_string_lossy();
+			let name = name.trim_start_matches("./").trim_start_matches('/');
+			if name.is_empty() {
+				continue;
+			}
+			let entry_type = entry.header().entry_type();
+			if entry_type.is_dir() {
+				dirs.push(format!("{}/", name.trim_end_matches('/')));
+			} else if entry_type.is_file() {
+				let offset = entry.raw_file_position() as usize;
+				let len = entry.size() as usize;
+				files.insert(name.to_owned(), (offset, len));
+			}
+		}
+		Ok(Self { data, files, dirs })
+	}
+}
+
+impl ContainerBackend for TarContainer {
+	fn list(&self) -> Vec<String> {
+		self.files.keys().chain(self.dirs.iter()).cloned().collect()
+	}
+
+	fn len(&self, name: &str) -> Option<usize> {
+		self.files.get(name).map(|&(_offset, len)| len)
+	}
+
+	fn open_entry(&self, name: &str) -> Result<Vec<u8>, SchemeError<'static>> {
+		let &(offset, len) = self.files.get(name).ok_or("no such tar archive entry")?;
+		Ok(self.data[offset..offset + len].to_vec())
+	}
+}
+
+impl TarArchiveScheme {
+	pub fn from_bytes(data: Vec<u8>) -> Result<Self, SchemeError<'static>> {
+		Ok(Self::new(TarContainer::from_bytes(data)?))
+	}
+
+	/// Reads the archive to the end out of any async reader, such as an already opened node.
+	pub async fn from_reader(
+		mut reader: impl AsyncRead + Unpin,
+	) -> Result<Self, SchemeError<'static>> {
+		let mut data = Vec::new();
+		io_util::read_to_end(&mut reader, &mut data).await?;
+		Self::from_bytes(data)
+	}
+
+	/// Reads the archive out of the node at `url`, which can be in any scheme of `vfs`.
+	#[allow(clippy::needless_lifetimes)] // Clippy is wrong here, it is necessary
+	pub async fn from_node<'a>(vfs: &Vfs, url: &'a Url) -> Result<Self, VfsError<'a>> {
+		let data = vfs.read_to_vec(url).await?;
+		Ok(Self::from_bytes(data)?)
+	}
+}
+
+#[cfg(test)]
+#[cfg(feature = "backend_tokio")]
+mod async_tokio_tests {
+	use crate::scheme::NodeGetOptions;
+	use crate::{TarArchiveScheme, Vfs};
+	use futures_lite::{AsyncReadExt, StreamExt};
+
+	fn build_tar() -> Vec<u8> {
+		let mut tar = tar::Builder::new(Vec::new());
+		let mut header = tar::Header::new_gnu();
+		header.set_entry_type(tar::EntryType::Directory);
+		header.set_size(0);
+		header.set_mode(0o755);
+		tar.append_data(&mut header, "./empty/", &[][..]).unwrap();
+		for (path, content) in [
+			("./readme.txt", &b"tarred readme"[..]),
+			("assets/a.txt", b"a"),
+		] {
+			let mut header = tar::Header::new_gnu();
+			header.set_size(content.len() as u64);
+			header.set_mode(0o644);
+			tar.append_data(&mut header, path, content).unwrap();
+		}
+		tar.into_inner().unwrap()
+	}
+
+	#[tokio::test]
+	async fn tar_archive() {
+		let vfs = Vfs::empty();
+		vfs.add_scheme("tar", TarArchiveScheme::from_bytes(build_tar()).unwrap())
+			.unwrap();
+		let mut buffer = String::new();
+		vfs.get_node_at("tar:/readme.txt", &NodeGetOptions::new().read(true))
+			.await
+			.unwrap()
+			.read_to_string(&mut buffer)
+			.await
+			.unwrap();
+		assert_eq!(&buffer, "tarred readme");
+		assert_eq!(
+			vfs.metadata_at("tar:/assets/a.txt").await.unwrap().len,
+			Some((1, Some(1)))
+		);
+		let listed: Vec<_> = vfs
+			.read_dir_at("tar:/")
+			.await
+			.unwrap()
+			.map(|entry| entry.url.path().to_owned())
+			.collect()
+			.await;
+		assert_eq!(listed, ["/assets", "/empty", "/readme.txt"]);
+	}
+
+	#[cfg(feature = "archive_tar_gzip")]
+	#[tokio::test]
+	async fn tar_gzip() {
+		use std::io::Write;
+		let mut encoder = flate2::write::GzEncoder::new(Vec::new(), Default::default());
+		encoder.write_all(&build_tar()).unwrap();
+		let compressed = encoder.finish().unwrap();
+		let vfs = Vfs::empty();
+		vfs.add_scheme("tar", TarArchiveScheme::from_bytes(compressed).unwrap())
+			.unwrap();
+		assert_eq!(vfs.read_to_vec_at("tar:/assets/a.txt").await.unwrap(), b"a");
+	}
+
+	#[cfg(feature = "archive_tar_zstd")]
+	#[tokio::test]
+	async fn tar_zstd() {
+		let compressed = zstd::stream::encode_all(&build_tar()[..], 0).unwrap();
+		let vfs = Vfs::empty();
+		vfs.add_scheme("tar", TarArchiveScheme::from_bytes(compressed).unwrap())
+			.unwrap();
+		assert_eq!(vfs.read_to_vec_at("tar:/assets/a.txt").await.unwrap(), b"a");
+		// Truncated streams fail instead of serving a partial archive
+		assert!(TarArchiveScheme::from_bytes(
+			zstd::stream::encode_all(&b"x"[..], 0).unwrap()[..6].to_vec()
+		)
+		.is_err());
+	}
+}
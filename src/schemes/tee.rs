@@ -0,0 +1,290 @@
+use crate::scheme::{BorrowedReadDirStream, NodeGetOptions, NodeMetadata};
+use crate::{Node, PinnedNode, Scheme, SchemeError, Vfs};
+use futures_lite::{ready, AsyncRead, AsyncSeek, AsyncWrite};
+use std::io::SeekFrom;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use url::Url;
+
+/// What a `TeeScheme` node does when mirroring to the tee fails.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TeeFailure {
+	/// Stop mirroring and carry on with the primary node as if there were no tee.
+	Ignore,
+	/// Fail the open, read or write that could not be mirrored.
+	Propagate,
+}
+
+/// Wraps a scheme and mirrors every byte read from or written to its nodes onto the end of the
+/// node at a tee url, which can be in any scheme of the `Vfs`, such as to capture what a pipeline
+/// moves while debugging it.  The tee is opened, and created if need be, in append mode along with
+/// each node.  Everything other than opening nodes passes through to the wrapped scheme.
+pub struct TeeScheme {
+	scheme: Box<dyn Scheme>,
+	tee: Url,
+	on_failure: TeeFailure,
+}
+
+impl TeeScheme {
+	pub fn new(scheme: impl Scheme, tee: Url) -> Self {
+		Self::new_boxed(Box::new(scheme), tee)
+	}
+
+	pub fn new_boxed(scheme: Box<dyn Scheme>, tee: Url) -> Self {
+		Self {
+			scheme,
+			tee,
+			on_failure: TeeFailure::Ignore,
+		}
+	}
+
+	/// What to do when mirroring fails, `TeeFailure::Ignore` by default.
+	pub fn on_failure(self, on_failure: TeeFailure) -> Self {
+		Self { on_failure, ..self }
+	}
+
+	pub fn inner(&self) -> &dyn Scheme {
+		&*self.scheme
+	}
+
+	pub fn tee(&self) -> &Url {
+		&self.tee
+	}
+}
+
+#[async_trait::async_trait]
+impl Scheme for TeeScheme {
+	async fn get_node<'a>(
+		&self,
+		vfs: &Vfs,
+		url: &'a Url,
+		options: &NodeGetOptions,
+	) -> Result<PinnedNode, SchemeError<'a>> {
+		let node = self.scheme.get_node(vfs, url, options).await?;
+		let tee = match vfs
+			.get_node(&self.tee, &NodeGetOptions::new().create(true).append(true))
+			.await
+		{
+			Ok(tee) => Some(tee),
+			Err(_error) if self.on_failure == TeeFailure::Ignore => None,
+			Err(error) => return Err(error.into()),
+		};
+		Ok(Box::pin(TeeNode {
+			node,
+			tee,
+			pending: Vec::new(),
+			on_failure: self.on_failure,
+		}))
+	}
+
+	async fn remove_node<'a>(
+		&self,
+		vfs: &Vfs,
+		url: &'a Url,
+		force: bool,
+	) -> Result<(), SchemeError<'a>> {
+		self.scheme.remove_node(vfs, url, force).await
+	}
+
+	async fn metadata<'a>(&self, vfs: &Vfs, url: &'a Url) -> Result<NodeMetadata, SchemeError<'a>> {
+		self.scheme.metadata(vfs, url).await
+	}
+
+	async fn read_dir<'s, 'a>(
+		&'s self,
+		vfs: &'s Vfs,
+		url: &'a Url,
+	) -> Result<BorrowedReadDirStream<'s>, SchemeError<'a>> {
+		self.scheme.read_dir(vfs, url).await
+	}
+}
+
+pub struct TeeNode {
+	node: PinnedNode,
+	/// `None` once mirroring failed and the failure is ignored.
+	tee: Option<PinnedNode>,
+	/// Bytes that have not been written to the tee yet.
+	pending: Vec<u8>,
+	on_failure: TeeFailure,
+}
+
+impl TeeNode {
+	/// Writes as much of `pending` to the tee as it takes without blocking.
+	fn poll_mirror(&mut self, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+		while !self.pending.is_empty() {
+			let tee = match &mut self.tee {
+				Some(tee) => tee,
+				None => {
+					self.pending.clear();
+					break;
+				}
+			};
+			let error = match ready!(tee.as_mut().poll_write(cx, &self.pending)) {
+				Ok(0) => std::io::ErrorKind::WriteZero.into(),
+				Ok(amt) => {
+					self.pending.drain(..amt);
+					continue;
+				}
+				Err(error) => error,
+			};
+			if self.on_failure == TeeFailure::Propagate {
+				return Poll::Ready(Err(error));
+			}
+			self.tee = None;
+		}
+		Poll::Ready(Ok(()))
+	}
+}
+
+#[async_trait::async_trait]
+impl Node for TeeNode {
+	fn is_reader(&self) -> bool {
+		self.node.is_reader()
+	}
+
+	fn is_writer(&self) -> bool {
+		self.node.is_writer()
+	}
+
+	fn is_seeker(&self) -> bool {
+		self.node.is_seeker()
+	}
+}
+
+impl AsyncRead for TeeNode {
+	fn poll_read(
+		mut self: Pin<&mut Self>,
+		cx: &mut Context<'_>,
+		buf: &mut [u8],
+	) -> Poll<std::io::Result<usize>> {
+		let this = &mut *self;
+		// Catch the tee up first so it never falls further behind than one read
+		ready!(this.poll_mirror(cx))?;
+		let amt = ready!(this.node.as_mut().poll_read(cx, buf))?;
+		if this.tee.is_some() {
+			this.pending.extend_from_slice(&buf[..amt]);
+		}
+		match this.poll_mirror(cx) {
+			Poll::Ready(Err(error)) => Poll::Ready(Err(error)),
+			// A tee that is not ready yet is caught up on the next call
+			_ => Poll::Ready(Ok(amt)),
+		}
+	}
+}
+
+impl AsyncWrite for TeeNode {
+	fn poll_write(
+		mut self: Pin<&mut Self>,
+		cx: &mut Context<'_>,
+		buf: &[u8],
+	) -> Poll<std::io::Result<usize>> {
+		let this = &mut *self;
+		ready!(this.poll_mirror(cx))?;
+		let amt = ready!(this.node.as_mut().poll_write(cx, buf))?;
+		if this.tee.is_some() {
+			this.pending.extend_from_slice(&buf[..amt]);
+		}
+		match this.poll_mirror(cx) {
+			Poll::Ready(Err(error)) => Poll::Ready(Err(error)),
+			_ => Poll::Ready(Ok(amt)),
+		}
+	}
+
+	fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+		let this = &mut *self;
+		ready!(this.poll_mirror(cx))?;
+		if let Some(tee) = &mut this.tee {
+			if let Err(error) = ready!(tee.as_mut().poll_flush(cx)) {
+				if this.on_failure == TeeFailure::Propagate {
+					return Poll::Ready(Err(error));
+				}
+				this.tee = None;
+			}
+		}
+		this.node.as_mut().poll_flush(cx)
+	}
+
+	fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+		let this = &mut *self;
+		ready!(this.poll_mirror(cx))?;
+		if let Some(tee) = &mut this.tee {
+			if let Err(error) = ready!(tee.as_mut().poll_close(cx)) {
+				if this.on_failure == TeeFailure::Propagate {
+					return Poll::Ready(Err(error));
+				}
+				this.tee = None;
+			}
+		}
+		this.node.as_mut().poll_close(cx)
+	}
+}
+
+impl AsyncSeek for TeeNode {
+	fn poll_seek(
+		mut self: Pin<&mut Self>,
+		cx: &mut Context<'_>,
+		pos: SeekFrom,
+	) -> Poll<std::io::Result<u64>> {
+		self.node.as_mut().poll_seek(cx, pos)
+	}
+}
+
+#[cfg(test)]
+#[cfg(all(feature = "backend_tokio", feature = "in_memory"))]
+mod async_tokio_tests {
+	use crate::scheme::NodeGetOptions;
+	use crate::{DataLoaderScheme, MemoryScheme, TeeFailure, TeeScheme, Vfs};
+	use futures_lite::{AsyncReadExt, AsyncWriteExt};
+	use url::Url;
+
+	fn tee_vfs(tee: &str, on_failure: TeeFailure) -> Vfs {
+		let vfs = Vfs::empty();
+		vfs.add_scheme("mem", MemoryScheme::default()).unwrap();
+		vfs.add_scheme(
+			"data",
+			TeeScheme::new(DataLoaderScheme::default(), Url::parse(tee).unwrap())
+				.on_failure(on_failure),
+		)
+		.unwrap();
+		vfs.add_scheme(
+			"tmem",
+			TeeScheme::new(MemoryScheme::default(), Url::parse(tee).unwrap())
+				.on_failure(on_failure),
+		)
+		.unwrap();
+		vfs
+	}
+
+	async fn read(vfs: &Vfs, uri: &str) -> std::io::Result<String> {
+		let mut node = vfs
+			.get_node_at(uri, &NodeGetOptions::new().read(true))
+			.await
+			.map_err(|error| std::io::Error::other(error.to_string()))?;
+		let mut buffer = String::new();
+		node.read_to_string(&mut buffer).await?;
+		Ok(buffer)
+	}
+
+	#[tokio::test]
+	async fn tee_mirrors_reads_and_writes() {
+		let vfs = tee_vfs("mem:/mirror", TeeFailure::Propagate);
+		assert_eq!(read(&vfs, "data:first").await.unwrap(), "first");
+		assert_eq!(read(&vfs, "data:second").await.unwrap(), "second");
+		let mut node = vfs
+			.get_node_at("tmem:/written", &NodeGetOptions::new().create_new(true))
+			.await
+			.unwrap();
+		node.write_all(b"third").await.unwrap();
+		node.close().await.unwrap();
+		assert_eq!(read(&vfs, "mem:/mirror").await.unwrap(), "firstsecondthird");
+	}
+
+	#[tokio::test]
+	async fn tee_failure() {
+		// The tee is in a scheme that does not exist
+		let vfs = tee_vfs("nowhere:/mirror", TeeFailure::Ignore);
+		assert_eq!(read(&vfs, "data:content").await.unwrap(), "content");
+		let vfs = tee_vfs("nowhere:/mirror", TeeFailure::Propagate);
+		assert!(read(&vfs, "data:content").await.is_err());
+	}
+}
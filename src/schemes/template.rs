@@ -0,0 +1,394 @@
+#![allow(clippy::try_err)]
+
+use crate::node::poll_io_err;
+use crate::scheme::{BorrowedReadDirStream, NodeEntry, NodeGetOptions, NodeMetadata};
+use crate::{Node, PinnedNode, Scheme, SchemeError, Vfs};
+use futures_lite::{AsyncRead, AsyncSeek, AsyncWrite};
+use std::borrow::Cow;
+use std::io::SeekFrom;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use url::Url;
+
+type Generator =
+	Box<dyn Fn(&TemplateCaptures) -> Result<Vec<u8>, SchemeError<'static>> + Send + Sync>;
+
+enum PatternSegment {
+	Literal(String),
+	Capture(String),
+}
+
+struct Template {
+	segments: Vec<PatternSegment>,
+	generator: Generator,
+}
+
+impl Template {
+	fn captures(&self, path: &str) -> Option<TemplateCaptures> {
+		let parts: Vec<&str> = path.strip_prefix('/')?.split('/').collect();
+		if parts.len() != self.segments.len() {
+			return None;
+		}
+		let mut captures = Vec::new();
+		for (segment, part) in self.segments.iter().zip(parts) {
+			match segment {
+				PatternSegment::Literal(literal) if literal == part => (),
+				PatternSegment::Capture(name) if !part.is_empty() => {
+					let value = percent_encoding::percent_decode_str(part).decode_utf8_lossy();
+					captures.push((name.clone(), value.into_owned()));
+				}
+				_ => return None,
+			}
+		}
+		Some(TemplateCaptures(captures))
+	}
+}
+
+/// The values of the `{name}` segments of a template pattern that matched a url path.
+#[derive(Debug, Clone)]
+pub struct TemplateCaptures(Vec<(String, String)>);
+
+impl TemplateCaptures {
+	pub fn get(&self, name: &str) -> Option<&str> {
+		self.0
+			.iter()
+			.find(|(n, _)| n == name)
+			.map(|(_, v)| v.as_str())
+	}
+
+	pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+		self.0.iter().map(|(n, v)| (n.as_str(), v.as_str()))
+	}
+}
+
+/// Generates read-only nodes from url paths matched against registered patterns, such as
+/// `/report/{year}/{name}`, where each `{name}` segment captures one path segment.  Patterns are
+/// tried in the order they were registered.
+///
+/// The space of matching urls is usually infinite so `read_dir` only lists the paths explicitly
+/// registered with `list`, and is unsupported if there are none.
+#[derive(Default)]
+pub struct TemplateScheme {
+	templates: Vec<Template>,
+	listed: Vec<String>,
+}
+
+impl TemplateScheme {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	pub fn register<F>(&mut self, pattern: &str, generator: F) -> Result<(), SchemeError<'static>>
+	where
+		F: Fn(&TemplateCaptures) -> Result<Vec<u8>, SchemeError<'static>> + Send + Sync + 'static,
+	{
+		let pattern = match pattern.strip_prefix('/') {
+			Some(pattern) => pattern,
+			None => Err("template pattern must start with `/`")?,
+		};
+		let mut segments = Vec::new();
+		for segment in pattern.split('/') {
+			if let Some(name) = segment.strip_prefix('{').and_then(|s| s.strip_suffix('}')) {
+				if name.is_empty() {
+					Err("template pattern has an unnamed capture")?;
+				}
+				segments.push(PatternSegment::Capture(name.to_owned()));
+			} else if segment.contains(['{', '}']) {
+				Err("template pattern captures must be an entire path segment")?;
+			} else {
+				segments.push(PatternSegment::Literal(segment.to_owned()));
+			}
+		}
+		self.templates.push(Template {
+			segments,
+			generator: Box::new(generator),
+		});
+		Ok(())
+	}
+
+	/// Adds a path for `read_dir` to list, it must match a registered pattern.
+	pub fn list(&mut self, path: &str) -> Result<(), SchemeError<'static>> {
+		if self.find(path).is_none() {
+			Err("listed path does not match any template pattern")?;
+		}
+		self.listed.push(path.to_owned());
+		Ok(())
+	}
+
+	fn find(&self, path: &str) -> Option<(&Template, TemplateCaptures)> {
+		self.templates
+			.iter()
+			.find_map(|template| template.captures(path).map(|c| (template, c)))
+	}
+}
+
+#[async_trait::async_trait]
+impl Scheme for TemplateScheme {
+	async fn get_node<'a>(
+		&self,
+		_vfs: &Vfs,
+		url: &'a Url,
+		options: &NodeGetOptions,
+	) -> Result<PinnedNode, SchemeError<'a>> {
+		if !options.get_read() || options.get_write() {
+			return Err(SchemeError::UrlAccessError(Cow::Borrowed(url)));
+		}
+		if let Some((template, captures)) = self.find(url.path()) {
+			let data = (template.generator)(&captures)?;
+			Ok(Box::pin(TemplateNode { data, cursor: 0 }))
+		} else {
+			Err(SchemeError::NodeDoesNotExist(Cow::Borrowed(url.path())))
+		}
+	}
+
+	async fn remove_node<'a>(
+		&self,
+		_vfs: &Vfs,
+		url: &'a Url,
+		_force: bool,
+	) -> Result<(), SchemeError<'a>> {
+		Err(SchemeError::UrlAccessError(Cow::Borrowed(url)))
+	}
+
+	async fn metadata<'a>(
+		&self,
+		_vfs: &Vfs,
+		url: &'a Url,
+	) -> Result<NodeMetadata, SchemeError<'a>> {
+		if self.find(url.path()).is_some() {
+			// The length is unknown without running the generator
+			Ok(NodeMetadata {
+				is_node: true,
+				len: None,
+				modified: None,
+				..Default::default()
+			})
+		} else {
+			Err(SchemeError::NodeDoesNotExist(Cow::Borrowed(url.path())))
+		}
+	}
+
+	async fn read_dir<'s, 'a>(
+		&'s self,
+		_vfs: &'s Vfs,
+		url: &'a Url,
+	) -> Result<BorrowedReadDirStream<'s>, SchemeError<'a>> {
+		if self.listed.is_empty() {
+			return Err(SchemeError::Unsupported("read_dir"));
+		}
+		let path = url.path();
+		let path = if path.ends_with('/') {
+			Cow::Borrowed(path)
+		} else if self.listed.iter().any(|listed| listed == path) {
+			return Err(SchemeError::NotADirectory(Cow::Borrowed(path)));
+		} else {
+			Cow::Owned(format!("{}/", path))
+		};
+		let entries: Vec<NodeEntry> = self
+			.listed
+			.iter()
+			.filter(|listed| listed.starts_with(&*path))
+			.map(|listed| {
+				let mut url = url.clone();
+				url.set_path(listed);
+				NodeEntry::new(url)
+			})
+			.collect();
+		Ok(Box::pin(futures_lite::stream::iter(entries)))
+	}
+}
+
+pub struct TemplateNode {
+	data: Vec<u8>,
+	cursor: usize,
+}
+
+#[async_trait::async_trait]
+impl Node for TemplateNode {
+	fn is_reader(&self) -> bool {
+		true
+	}
+
+	fn is_writer(&self) -> bool {
+		false
+	}
+
+	fn is_seeker(&self) -> bool {
+		true
+	}
+
+	fn is_at_end(&self) -> Option<bool> {
+		Some(self.cursor >= self.data.len())
+	}
+}
+
+impl AsyncRead for TemplateNode {
+	fn poll_read(
+		mut self: Pin<&mut Self>,
+		_cx: &mut Context<'_>,
+		buf: &mut [u8],
+	) -> Poll<std::io::Result<usize>> {
+		if self.cursor >= self.data.len() {
+			return Poll::Ready(Ok(0));
+		}
+
+		let amt = std::cmp::min(self.data.len() - self.cursor, buf.len());
+		buf[..amt].copy_from_slice(&self.data[self.cursor..(self.cursor + amt)]);
+		self.cursor += amt;
+
+		Poll::Ready(Ok(amt))
+	}
+}
+
+impl AsyncWrite for TemplateNode {
+	fn poll_write(
+		self: Pin<&mut Self>,
+		_cx: &mut Context<'_>,
+		_buf: &[u8],
+	) -> Poll<std::io::Result<usize>> {
+		poll_io_err()
+	}
+
+	fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+		poll_io_err()
+	}
+
+	fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+		poll_io_err()
+	}
+}
+
+impl AsyncSeek for TemplateNode {
+	fn poll_seek(
+		mut self: Pin<&mut Self>,
+		_cx: &mut Context<'_>,
+		pos: SeekFrom,
+	) -> Poll<std::io::Result<u64>> {
+		match pos {
+			SeekFrom::Start(pos) => {
+				if pos > self.data.len() as u64 {
+					self.cursor = self.data.len();
+				} else {
+					self.cursor = pos as usize;
+				}
+			}
+			SeekFrom::End(end_pos) => {
+				if end_pos > 0 {
+					self.cursor = self.data.len();
+				} else if (-end_pos) as usize > self.data.len() {
+					self.cursor = 0;
+				} else {
+					self.cursor = self.data.len() - ((-end_pos) as usize);
+				}
+			}
+			SeekFrom::Current(offset) => {
+				let new_cur = self.cursor as i64 + offset;
+				if new_cur < 0 {
+					self.cursor = 0;
+				} else if new_cur as usize > self.data.len() {
+					self.cursor = self.data.len();
+				} else {
+					self.cursor = new_cur as usize;
+				}
+			}
+		};
+		Poll::Ready(Ok(self.cursor as u64))
+	}
+}
+
+#[cfg(test)]
+#[cfg(feature = "backend_tokio")]
+mod async_tokio_tests {
+	use crate::scheme::NodeGetOptions;
+	use crate::{SchemeError, TemplateScheme, Vfs, VfsError};
+	use futures_lite::{AsyncReadExt, StreamExt};
+
+	fn report_scheme() -> TemplateScheme {
+		let mut scheme = TemplateScheme::new();
+		scheme
+			.register("/report/{year}/{name}", |captures| {
+				Ok(format!(
+					"{} for {}",
+					captures.get("name").unwrap(),
+					captures.get("year").unwrap()
+				)
+				.into_bytes())
+			})
+			.unwrap();
+		scheme
+			.register("/static/index.html", |_| Ok(b"<html></html>".to_vec()))
+			.unwrap();
+		scheme
+	}
+
+	#[tokio::test]
+	async fn template_captures() {
+		let vfs = Vfs::empty();
+		vfs.add_scheme("gen", report_scheme()).unwrap();
+		let mut buffer = String::new();
+		vfs.get_node_at(
+			"gen:/report/2024/summary.json",
+			&NodeGetOptions::new().read(true),
+		)
+		.await
+		.unwrap()
+		.read_to_string(&mut buffer)
+		.await
+		.unwrap();
+		assert_eq!(&buffer, "summary.json for 2024");
+		buffer.clear();
+		vfs.get_node_at("gen:/report/2024/a%20b", &NodeGetOptions::new().read(true))
+			.await
+			.unwrap()
+			.read_to_string(&mut buffer)
+			.await
+			.unwrap();
+		assert_eq!(&buffer, "a b for 2024", "captures are percent-decoded");
+		assert!(
+			vfs.metadata_at("gen:/static/index.html")
+				.await
+				.unwrap()
+				.is_node
+		);
+		assert!(matches!(
+			vfs.get_node_at("gen:/report/2024", &NodeGetOptions::new().read(true))
+				.await,
+			Err(VfsError::SchemeError(SchemeError::NodeDoesNotExist(_)))
+		));
+		assert!(vfs
+			.get_node_at("gen:/static/index.html", &NodeGetOptions::new().write(true))
+			.await
+			.is_err());
+	}
+
+	#[tokio::test]
+	async fn template_listing() {
+		let vfs = Vfs::empty();
+		vfs.add_scheme("gen", report_scheme()).unwrap();
+		assert!(matches!(
+			vfs.read_dir_at("gen:/report/").await,
+			Err(VfsError::SchemeError(SchemeError::Unsupported(_)))
+		));
+
+		let mut scheme = report_scheme();
+		assert!(scheme.list("/report/2024").is_err());
+		scheme.list("/report/2023/summary.json").unwrap();
+		scheme.list("/report/2024/summary.json").unwrap();
+		scheme.list("/static/index.html").unwrap();
+		let vfs = Vfs::empty();
+		vfs.add_scheme("gen", scheme).unwrap();
+		assert_eq!(vfs.read_dir_at("gen:/").await.unwrap().count().await, 3);
+		assert_eq!(
+			vfs.read_dir_at("gen:/report/").await.unwrap().count().await,
+			2
+		);
+	}
+
+	#[test]
+	fn invalid_patterns() {
+		let mut scheme = TemplateScheme::new();
+		assert!(scheme.register("report/{year}", |_| Ok(vec![])).is_err());
+		assert!(scheme.register("/report/{}", |_| Ok(vec![])).is_err());
+		assert!(scheme.register("/report/v{year}", |_| Ok(vec![])).is_err());
+	}
+}
@@ -0,0 +1,290 @@
+use crate::clock::Instant;
+use crate::scheme::{BorrowedReadDirStream, NodeGetOptions, NodeMetadata, WatchStream};
+use crate::{PinnedNode, Scheme, SchemeError, Vfs};
+use std::time::{Duration, SystemTime};
+use url::Url;
+
+type TraceHook = Box<dyn Fn(&TraceEvent<'_>) + Send + Sync>;
+
+/// An operation of a `TracedScheme` that is traced.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TracedOperation {
+	GetNode,
+	RemoveNode,
+	Metadata,
+	ReadDir,
+}
+
+impl TracedOperation {
+	pub fn name(self) -> &'static str {
+		match self {
+			TracedOperation::GetNode => "get_node",
+			TracedOperation::RemoveNode => "remove_node",
+			TracedOperation::Metadata => "metadata",
+			TracedOperation::ReadDir => "read_dir",
+		}
+	}
+}
+
+/// One traced call, handed to the `TracedScheme::on_trace` hook as soon as the call returns.
+#[derive(Debug)]
+pub struct TraceEvent<'e> {
+	/// The name the `TracedScheme` was given, to tell the layers of a deep stack apart.
+	pub name: &'e str,
+	pub operation: TracedOperation,
+	pub url: &'e Url,
+	/// The options of a `get_node`.
+	pub options: Option<&'e NodeGetOptions>,
+	/// How long the wrapped scheme took, for `read_dir` until the listing was returned rather
+	/// than until it was read through.
+	pub elapsed: Duration,
+	/// The error the wrapped scheme returned, `None` if it succeeded.
+	pub error: Option<&'e SchemeError<'e>>,
+}
+
+/// Wraps a scheme and traces every `get_node`, `remove_node`, `metadata` and `read_dir` call made
+/// to it, with the url, the options, how long it took and whether it failed, such as to find out
+/// which layer of an overlay actually served a node.  Events go to the `on_trace` hook, and with
+/// the `tracing` feature are also emitted as `tracing` debug events.  Everything else passes
+/// through untraced, except `read_small_file` and `read_bytes`, which are not forwarded so reads
+/// always show up as a `get_node`.
+pub struct TracedScheme {
+	scheme: Box<dyn Scheme>,
+	name: String,
+	on_trace: Option<TraceHook>,
+}
+
+impl TracedScheme {
+	pub fn new(scheme: impl Scheme, name: impl Into<String>) -> Self {
+		Self::new_boxed(Box::new(scheme), name)
+	}
+
+	pub fn new_boxed(scheme: Box<dyn Scheme>, name: impl Into<String>) -> Self {
+		Self {
+			scheme,
+			name: name.into(),
+			on_trace: None,
+		}
+	}
+
+	/// Called with every traced call, on the task that made it.
+	pub fn on_trace<F>(self, hook: F) -> Self
+	where
+		F: Fn(&TraceEvent<'_>) + Send + Sync + 'static,
+	{
+		Self {
+			on_trace: Some(Box::new(hook)),
+			..self
+		}
+	}
+
+	pub fn inner(&self) -> &dyn Scheme {
+		&*self.scheme
+	}
+
+	pub fn into_inner(self) -> Box<dyn Scheme> {
+		self.scheme
+	}
+
+	pub fn name(&self) -> &str {
+		&self.name
+	}
+
+	fn trace<T>(
+		&self,
+		operation: TracedOperation,
+		url: &Url,
+		options: Option<&NodeGetOptions>,
+		started: Instant,
+		result: &Result<T, SchemeError<'_>>,
+	) {
+		let event = TraceEvent {
+			name: &self.name,
+			operation,
+			url,
+			options,
+			elapsed: started.elapsed(),
+			error: result.as_ref().err(),
+		};
+		#[cfg(feature = "tracing")]
+		match event.error {
+			None => tracing::debug!(
+				scheme = event.name,
+				operation = event.operation.name(),
+				url = %event.url,
+				options = ?event.options,
+				elapsed = ?event.elapsed,
+				"vfs call",
+			),
+			Some(error) => tracing::debug!(
+				scheme = event.name,
+				operation = event.operation.name(),
+				url = %event.url,
+				options = ?event.options,
+				elapsed = ?event.elapsed,
+				%error,
+				"vfs call failed",
+			),
+		}
+		if let Some(hook) = &self.on_trace {
+			hook(&event);
+		}
+	}
+}
+
+#[async_trait::async_trait]
+impl Scheme for TracedScheme {
+	async fn get_node<'a>(
+		&self,
+		vfs: &Vfs,
+		url: &'a Url,
+		options: &NodeGetOptions,
+	) -> Result<PinnedNode, SchemeError<'a>> {
+		let started = Instant::now();
+		let result = self.scheme.get_node(vfs, url, options).await;
+		self.trace(
+			TracedOperation::GetNode,
+			url,
+			Some(options),
+			started,
+			&result,
+		);
+		result
+	}
+
+	async fn remove_node<'a>(
+		&self,
+		vfs: &Vfs,
+		url: &'a Url,
+		force: bool,
+	) -> Result<(), SchemeError<'a>> {
+		let started = Instant::now();
+		let result = self.scheme.remove_node(vfs, url, force).await;
+		self.trace(TracedOperation::RemoveNode, url, None, started, &result);
+		result
+	}
+
+	async fn metadata<'a>(&self, vfs: &Vfs, url: &'a Url) -> Result<NodeMetadata, SchemeError<'a>> {
+		let started = Instant::now();
+		let result = self.scheme.metadata(vfs, url).await;
+		self.trace(TracedOperation::Metadata, url, None, started, &result);
+		result
+	}
+
+	async fn read_dir<'s, 'a>(
+		&'s self,
+		vfs: &'s Vfs,
+		url: &'a Url,
+	) -> Result<BorrowedReadDirStream<'s>, SchemeError<'a>> {
+		let started = Instant::now();
+		let result = self.scheme.read_dir(vfs, url).await;
+		self.trace(TracedOperation::ReadDir, url, None, started, &result);
+		result
+	}
+
+	async fn canonicalize<'a>(&self, vfs: &Vfs, url: &'a Url) -> Result<Url, SchemeError<'a>> {
+		self.scheme.canonicalize(vfs, url).await
+	}
+
+	async fn set_modified<'a>(
+		&self,
+		vfs: &Vfs,
+		url: &'a Url,
+		modified: SystemTime,
+	) -> Result<(), SchemeError<'a>> {
+		self.scheme.set_modified(vfs, url, modified).await
+	}
+
+	async fn create_dir<'a>(
+		&self,
+		vfs: &Vfs,
+		url: &'a Url,
+		parents: bool,
+	) -> Result<(), SchemeError<'a>> {
+		self.scheme.create_dir(vfs, url, parents).await
+	}
+
+	async fn copy_node<'a>(
+		&self,
+		vfs: &Vfs,
+		from: &'a Url,
+		to: &'a Url,
+	) -> Result<(), SchemeError<'a>> {
+		self.scheme.copy_node(vfs, from, to).await
+	}
+
+	async fn rename_node<'a>(
+		&self,
+		vfs: &Vfs,
+		from: &'a Url,
+		to: &'a Url,
+	) -> Result<(), SchemeError<'a>> {
+		self.scheme.rename_node(vfs, from, to).await
+	}
+
+	async fn get_node_split<'a>(
+		&self,
+		vfs: &Vfs,
+		url: &'a Url,
+		options: &NodeGetOptions,
+	) -> Result<(PinnedNode, PinnedNode), SchemeError<'a>> {
+		self.scheme.get_node_split(vfs, url, options).await
+	}
+
+	async fn watch<'a>(&self, vfs: &Vfs, url: &'a Url) -> Result<WatchStream, SchemeError<'a>> {
+		self.scheme.watch(vfs, url).await
+	}
+}
+
+#[cfg(test)]
+#[cfg(feature = "backend_tokio")]
+#[cfg(feature = "in_memory")]
+mod async_tokio_tests {
+	use crate::scheme::NodeGetOptions;
+	use crate::{MemoryScheme, TracedScheme, Vfs};
+	use futures_lite::AsyncWriteExt;
+	use std::sync::{Arc, Mutex};
+
+	#[tokio::test]
+	async fn traced_calls() {
+		let traced = Arc::new(Mutex::new(Vec::new()));
+		let hook = traced.clone();
+		let vfs = Vfs::empty();
+		vfs.add_scheme(
+			"mem",
+			TracedScheme::new(MemoryScheme::default(), "layer").on_trace(move |event| {
+				hook.lock().unwrap().push(format!(
+					"{} {} {} {} {}",
+					event.name,
+					event.operation.name(),
+					event.url,
+					event.options.is_some_and(NodeGetOptions::get_write),
+					event.error.map_or("ok".to_owned(), ToString::to_string),
+				))
+			}),
+		)
+		.unwrap();
+
+		let mut node = vfs
+			.get_node_at("mem:/node", &NodeGetOptions::new().create_new(true))
+			.await
+			.unwrap();
+		node.write_all(b"traced").await.unwrap();
+		node.close().await.unwrap();
+		assert_eq!(vfs.read_to_vec_at("mem:/node").await.unwrap(), b"traced");
+		let _ = vfs.metadata_at("mem:/missing").await;
+		let _entries = vfs.read_dir_at("mem:/").await.unwrap();
+		vfs.remove_node_at("mem:/node", false).await.unwrap();
+
+		assert_eq!(
+			*traced.lock().unwrap(),
+			[
+				"layer get_node mem:/node true ok",
+				"layer get_node mem:/node false ok",
+				"layer metadata mem:/missing false node not found: /missing",
+				"layer read_dir mem:/ false ok",
+				"layer remove_node mem:/node false ok",
+			]
+		);
+	}
+}
@@ -0,0 +1,1075 @@
+use crate::node::{seek_position, IsAllowed};
+use crate::scheme::{BorrowedReadDirStream, NodeEntry, NodeGetOptions, NodeKind, NodeMetadata};
+use crate::{Node, PinnedNode, Scheme, SchemeError, Vfs};
+use futures_lite::{AsyncRead, AsyncSeek, AsyncWrite, Future};
+use md5::{Digest, Md5};
+use percent_encoding::percent_decode_str;
+use reqwest::header::{AUTHORIZATION, CONTENT_TYPE, WWW_AUTHENTICATE};
+use reqwest::{Method, RequestBuilder, Response, StatusCode};
+use std::borrow::Cow;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::io::SeekFrom;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use url::{Position, Url};
+
+const DAV: &str = "DAV:";
+
+const PROPFIND_BODY: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<d:propfind xmlns:d="DAV:"><d:prop><d:resourcetype/><d:getcontentlength/><d:getlastmodified/></d:prop></d:propfind>"#;
+
+/// Serves the files below a WebDAV `endpoint`, such as
+/// `https://cloud.example.com/remote.php/dav/files/alice/` of Nextcloud or ownCloud, so
+/// `dav:/dir/node` is `dir/node` below it.  `metadata` and `read_dir` are PROPFIND requests.  A
+/// node holds its whole content in memory, fetched when it is opened and stored by a PUT when a
+/// node opened for writing is flushed or closed.  Basic and digest (MD5) authentication can be set
+/// up at construction, the digest challenge of the server is kept so only the first request is
+/// answered with a 401.  The requests need a tokio runtime.
+#[derive(Clone)]
+pub struct WebDavScheme {
+	client: reqwest::Client,
+	endpoint: Url,
+	auth: Arc<Auth>,
+}
+
+enum Auth {
+	None,
+	Basic { user: String, password: String },
+	Digest(DigestAuth),
+}
+
+struct DigestAuth {
+	user: String,
+	password: String,
+	challenge: Mutex<Option<DigestChallenge>>,
+}
+
+struct DigestChallenge {
+	realm: String,
+	nonce: String,
+	opaque: Option<String>,
+	qop: bool,
+	count: u32,
+}
+
+struct PropEntry {
+	/// The decoded path of the entry on the server.
+	path: String,
+	metadata: NodeMetadata,
+}
+
+impl WebDavScheme {
+	pub fn new(endpoint: Url) -> Self {
+		Self::with_client(endpoint, reqwest::Client::new())
+	}
+
+	/// Sends every request through `client`, such as to set a user agent, timeouts, or proxies.
+	pub fn with_client(endpoint: Url, client: reqwest::Client) -> Self {
+		Self {
+			client,
+			endpoint,
+			auth: Arc::new(Auth::None),
+		}
+	}
+
+	pub fn with_basic_auth(mut self, user: impl Into<String>, password: impl Into<String>) -> Self {
+		self.auth = Arc::new(Auth::Basic {
+			user: user.into(),
+			password: password.into(),
+		});
+		self
+	}
+
+	pub fn with_digest_auth(
+		mut self,
+		user: impl Into<String>,
+		password: impl Into<String>,
+	) -> Self {
+		self.auth = Arc::new(Auth::Digest(DigestAuth {
+			user: user.into(),
+			password: password.into(),
+			challenge: Mutex::new(None),
+		}));
+		self
+	}
+
+	pub fn client(&self) -> &reqwest::Client {
+		&self.client
+	}
+
+	pub fn endpoint(&self) -> &Url {
+		&self.endpoint
+	}
+
+	/// Segments are percent-decoded into file names, one that decodes to something that is not a
+	/// single file name, such as `..` or one holding a `/`, could escape the endpoint so is refused.
+	pub fn remote_url<'a>(&self, url: &'a Url) -> Result<Url, SchemeError<'a>> {
+		let segments = url
+			.path_segments()
+			.ok_or(SchemeError::NodeDoesNotExist(Cow::Borrowed(url.path())))?;
+		let mut names = Vec::new();
+		for segment in segments.filter(|segment| !segment.is_empty()) {
+			let name = percent_decode_str(segment).decode_utf8_lossy();
+			if name == "." || name == ".." || name.contains('/') {
+				return Err(SchemeError::UrlAccessError(Cow::Borrowed(url)));
+			}
+			names.push(name);
+		}
+		let mut remote = self.endpoint.clone();
+		if !names.is_empty() {
+			remote
+				.path_segments_mut()
+				.map_err(|()| SchemeError::UrlAccessError(Cow::Borrowed(url)))?
+				.pop_if_empty()
+				.extend(&names);
+		}
+		Ok(remote)
+	}
+
+	fn request(&self, method: &Method, target: &Url) -> RequestBuilder {
+		let request = self.client.request(method.clone(), target.clone());
+		match &*self.auth {
+			Auth::None => request,
+			Auth::Basic { user, password } => request.basic_auth(user, Some(password)),
+			Auth::Digest(digest) => match digest.authorization(method, target) {
+				Some(authorization) => request.header(AUTHORIZATION, authorization),
+				None => request,
+			},
+		}
+	}
+
+	/// Sends a request built by `build`, sending it again if the server asked for a digest.
+	async fn send(
+		&self,
+		method: Method,
+		target: &Url,
+		build: impl Fn(RequestBuilder) -> RequestBuilder,
+	) -> reqwest::Result<Response> {
+		let response = build(self.request(&method, target)).send().await?;
+		if response.status() == StatusCode::UNAUTHORIZED {
+			if let Auth::Digest(digest) = &*self.auth {
+				if digest.challenged(&response) {
+					return build(self.request(&method, target)).send().await;
+				}
+			}
+		}
+		Ok(response)
+	}
+
+	async fn propfind<'a>(
+		&self,
+		url: &'a Url,
+		target: &Url,
+		depth: &'static str,
+	) -> Result<Vec<PropEntry>, SchemeError<'a>> {
+		let method = Method::from_bytes(b"PROPFIND").expect("valid method");
+		let response = self
+			.send(method, target, |request| {
+				request
+					.header("Depth", depth)
+					.header(CONTENT_TYPE, "application/xml; charset=utf-8")
+					.body(PROPFIND_BODY)
+			})
+			.await
+			.map_err(request_failed)?;
+		let body = checked(response, url)?
+			.text()
+			.await
+			.map_err(request_failed)?;
+		parse_multistatus(&body, target).map_err(|error| {
+			SchemeError::GenericError(
+				Some("invalid PROPFIND response of a WebDAV server"),
+				Some(Box::new(error)),
+			)
+		})
+	}
+
+	/// The metadata of what is at `target`, `None` if there is nothing.
+	async fn existing<'a>(
+		&self,
+		url: &'a Url,
+		target: &Url,
+	) -> Result<Option<NodeMetadata>, SchemeError<'a>> {
+		match self.propfind(url, target, "0").await {
+			Ok(entries) => Ok(entries.into_iter().next().map(|entry| entry.metadata)),
+			Err(SchemeError::NodeDoesNotExist(_path)) => Ok(None),
+			Err(error) => Err(error),
+		}
+	}
+
+	async fn get<'a>(&self, url: &'a Url, target: &Url) -> Result<Vec<u8>, SchemeError<'a>> {
+		let response = self
+			.send(Method::GET, target, |request| request)
+			.await
+			.map_err(request_failed)?;
+		let content = checked(response, url)?
+			.bytes()
+			.await
+			.map_err(request_failed)?;
+		Ok(content.to_vec())
+	}
+
+	async fn put<'a>(
+		&self,
+		url: &'a Url,
+		target: &Url,
+		content: &[u8],
+	) -> Result<(), SchemeError<'a>> {
+		let response = self
+			.send(Method::PUT, target, |request| {
+				request.body(content.to_vec())
+			})
+			.await
+			.map_err(request_failed)?;
+		checked(response, url)?;
+		Ok(())
+	}
+
+	/// Creates the collection at `target`, `false` if there already is something.
+	async fn mkcol<'a>(&self, url: &'a Url, target: &Url) -> Result<bool, SchemeError<'a>> {
+		let method = Method::from_bytes(b"MKCOL").expect("valid method");
+		let response = self
+			.send(method, target, |request| request)
+			.await
+			.map_err(request_failed)?;
+		match response.status() {
+			StatusCode::METHOD_NOT_ALLOWED => Ok(false),
+			// The parent collection is missing
+			StatusCode::CONFLICT => Err(SchemeError::NodeDoesNotExist(Cow::Borrowed(url.path()))),
+			_ => checked(response, url).map(|_response| true),
+		}
+	}
+
+	/// Creates every missing collection down to the parent of `url`, and `url` itself with `all`.
+	async fn mkcol_all<'a>(&self, url: &'a Url, all: bool) -> Result<(), SchemeError<'a>> {
+		let segments: Vec<&str> = url
+			.path_segments()
+			.into_iter()
+			.flatten()
+			.filter(|segment| !segment.is_empty())
+			.collect();
+		let count = if all {
+			segments.len()
+		} else {
+			segments.len().saturating_sub(1)
+		};
+		let mut dir = url.clone();
+		for end in 1..=count {
+			dir.set_path(&format!("/{}", segments[..end].join("/")));
+			let target = self.remote_url(&dir).map_err(SchemeError::into_owned)?;
+			self.mkcol(url, &target).await?;
+		}
+		Ok(())
+	}
+}
+
+fn request_failed(error: reqwest::Error) -> SchemeError<'static> {
+	SchemeError::GenericError(Some("WebDAV request failed"), Some(Box::new(error)))
+}
+
+fn io_error(error: reqwest::Error) -> std::io::Error {
+	std::io::Error::other(error)
+}
+
+fn checked(response: Response, url: &Url) -> Result<Response, SchemeError<'_>> {
+	match response.status() {
+		StatusCode::NOT_FOUND | StatusCode::GONE => {
+			Err(SchemeError::NodeDoesNotExist(Cow::Borrowed(url.path())))
+		}
+		StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+			Err(SchemeError::UrlAccessError(Cow::Borrowed(url)))
+		}
+		status if status.is_success() => Ok(response),
+		_ => Err(response
+			.error_for_status()
+			.err()
+			.map_or_else(|| "unexpected WebDAV status".into(), request_failed)),
+	}
+}
+
+fn md5_hex(data: &str) -> String {
+	format!("{:x}", Md5::digest(data.as_bytes()))
+}
+
+fn quoted(value: &str) -> String {
+	format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// The `key=value` parameters of an authentication challenge, values unquoted, keys lowercase.
+fn auth_params(params: &str) -> Vec<(String, String)> {
+	let mut result = Vec::new();
+	let mut rest = params.trim();
+	while let Some((key, after)) = rest.split_once('=') {
+		let key = key.trim().to_ascii_lowercase();
+		let after = after.trim_start();
+		let (value, remaining) = match after.strip_prefix('"') {
+			Some(quoted) => {
+				let mut value = String::new();
+				let mut end = quoted.len();
+				let mut chars = quoted.char_indices();
+				while let Some((index, c)) = chars.next() {
+					match c {
+						'\\' => value.extend(chars.next().map(|(_index, c)| c)),
+						'"' => {
+							end = index + 1;
+							break;
+						}
+						c => value.push(c),
+					}
+				}
+				(value, &quoted[end..])
+			}
+			None => {
+				let (value, remaining) = after.split_once(',').unwrap_or((after, ""));
+				(value.trim().to_owned(), remaining)
+			}
+		};
+		result.push((key, value));
+		rest = remaining.trim_start().trim_start_matches(',').trim_start();
+	}
+	result
+}
+
+impl DigestAuth {
+	/// Takes up the digest challenge of `response`, `false` if it has none that is supported.
+	fn challenged(&self, response: &Response) -> bool {
+		let challenge = response
+			.headers()
+			.get_all(WWW_AUTHENTICATE)
+			.iter()
+			.filter_map(|value| value.to_str().ok())
+			.find_map(|value| {
+				let (kind, params) = value.trim().split_once(' ')?;
+				if !kind.eq_ignore_ascii_case("digest") {
+					return None;
+				}
+				let params = auth_params(params);
+				let param = |key: &str| {
+					params
+						.iter()
+						.find(|(name, _value)| name == key)
+						.map(|(_name, value)| value.clone())
+				};
+				if param("algorithm")
+					.is_some_and(|algorithm| !algorithm.eq_ignore_ascii_case("md5"))
+				{
+					return None;
+				}
+				Some(DigestChallenge {
+					realm: param("realm")?,
+					nonce: param("nonce")?,
+					opaque: param("opaque"),
+					qop: param("qop")
+						.is_some_and(|qop| qop.split(',').any(|qop| qop.trim() == "auth")),
+					count: 0,
+				})
+			});
+		let found = challenge.is_some();
+		*self.challenge.lock().expect("poisoned lock") = challenge;
+		found
+	}
+
+	/// The `Authorization` header of a request, once a challenge was taken up.
+	fn authorization(&self, method: &Method, target: &Url) -> Option<String> {
+		let mut challenge = self.challenge.lock().expect("poisoned lock");
+		let challenge = challenge.as_mut()?;
+		let uri = &target[Position::BeforePath..Position::AfterQuery];
+		let ha1 = md5_hex(&format!(
+			"{}:{}:{}",
+			self.user, challenge.realm, self.password
+		));
+		let ha2 = md5_hex(&format!("{}:{}", method, uri));
+		let mut header = format!(
+			"Digest username={}, realm={}, nonce={}, uri={}, algorithm=MD5",
+			quoted(&self.user),
+			quoted(&challenge.realm),
+			quoted(&challenge.nonce),
+			quoted(uri)
+		);
+		if challenge.qop {
+			challenge.count += 1;
+			let count = format!("{:08x}", challenge.count);
+			let mut hasher = RandomState::new().build_hasher();
+			hasher.write_u32(challenge.count);
+			let cnonce = format!("{:016x}", hasher.finish());
+			let response = md5_hex(&format!(
+				"{}:{}:{}:{}:auth:{}",
+				ha1, challenge.nonce, count, cnonce, ha2
+			));
+			header += &format!(
+				", response=\"{}\", qop=auth, nc={}, cnonce=\"{}\"",
+				response, count, cnonce
+			);
+		} else {
+			let response = md5_hex(&format!("{}:{}:{}", ha1, challenge.nonce, ha2));
+			header += &format!(", response=\"{}\"", response);
+		}
+		if let Some(opaque) = &challenge.opaque {
+			header += &format!(", opaque={}", quoted(opaque));
+		}
+		Some(header)
+	}
+}
+
+/// The text of the first `DAV:` element named `name` within `node`.
+fn text<'d>(node: roxmltree::Node<'d, '_>, name: &str) -> Option<&'d str> {
+	node.descendants()
+		.find(|child| child.has_tag_name((DAV, name)))
+		.and_then(|child| child.text())
+		.map(str::trim)
+}
+
+/// The entries of a `207 Multi-Status` PROPFIND response, the hrefs resolved against `target`.
+fn parse_multistatus(body: &str, target: &Url) -> Result<Vec<PropEntry>, roxmltree::Error> {
+	let document = roxmltree::Document::parse(body)?;
+	Ok(document
+		.descendants()
+		.filter(|node| node.has_tag_name((DAV, "response")))
+		.filter_map(|response| {
+			let href = target.join(text(response, "href")?).ok()?;
+			let path = percent_decode_str(href.path())
+				.decode_utf8_lossy()
+				.into_owned();
+			let is_dir = response
+				.descendants()
+				.any(|node| node.has_tag_name((DAV, "collection")));
+			let len = text(response, "getcontentlength").and_then(|len| len.parse::<usize>().ok());
+			let modified = text(response, "getlastmodified")
+				.and_then(|modified| httpdate::parse_http_date(modified).ok());
+			Some(PropEntry {
+				path,
+				metadata: NodeMetadata {
+					is_node: !is_dir,
+					len: len.filter(|_len| !is_dir).map(|len| (len, Some(len))),
+					modified,
+					kind: Some(if is_dir {
+						NodeKind::Directory
+					} else {
+						NodeKind::File
+					}),
+					..Default::default()
+				},
+			})
+		})
+		.collect())
+}
+
+#[async_trait::async_trait]
+impl Scheme for WebDavScheme {
+	async fn get_node<'a>(
+		&self,
+		_vfs: &Vfs,
+		url: &'a Url,
+		options: &NodeGetOptions,
+	) -> Result<PinnedNode, SchemeError<'a>> {
+		let target = self.remote_url(url)?;
+		let creates = options.get_create() || options.get_create_new();
+		let content = if !options.get_write() && !creates {
+			self.get(url, &target).await?
+		} else {
+			match self.existing(url, &target).await? {
+				Some(metadata) if !metadata.is_node => {
+					return Err(SchemeError::IsADirectory(Cow::Borrowed(url.path())))
+				}
+				Some(_metadata) if options.get_create_new() => {
+					return Err(SchemeError::NodeAlreadyExists(Cow::Borrowed(url.path())))
+				}
+				Some(_metadata) if options.get_write() && options.get_truncate() => {
+					self.put(url, &target, &[]).await?;
+					Vec::new()
+				}
+				Some(_metadata) => self.get(url, &target).await?,
+				None if creates => {
+					if options.get_create_parents() {
+						self.mkcol_all(url, false).await?;
+					}
+					self.put(url, &target, &[]).await?;
+					Vec::new()
+				}
+				None => return Err(SchemeError::NodeDoesNotExist(Cow::Borrowed(url.path()))),
+			}
+		};
+		let cursor = if options.get_append() {
+			content.len()
+		} else {
+			0
+		};
+		Ok(Box::pin(WebDavNode {
+			scheme: self.clone(),
+			target,
+			content,
+			cursor,
+			read: options.get_read(),
+			write: options.get_write(),
+			append: options.get_append(),
+			dirty: false,
+			storing: Mutex::new(None),
+		}))
+	}
+
+	async fn remove_node<'a>(
+		&self,
+		_vfs: &Vfs,
+		url: &'a Url,
+		force: bool,
+	) -> Result<(), SchemeError<'a>> {
+		let target = self.remote_url(url)?;
+		if !force {
+			match self.propfind(url, &target, "1").await {
+				Ok(entries) if entries.len() > 1 => return Err("directory is not empty".into()),
+				Ok(_entries) => {}
+				// Like the filesystem, removing what is not there is not an error
+				Err(SchemeError::NodeDoesNotExist(_path)) => return Ok(()),
+				Err(error) => return Err(error),
+			}
+		}
+		let response = self
+			.send(Method::DELETE, &target, |request| request)
+			.await
+			.map_err(request_failed)?;
+		match checked(response, url) {
+			Ok(_) | Err(SchemeError::NodeDoesNotExist(_)) => Ok(()),
+			Err(error) => Err(error),
+		}
+	}
+
+	async fn metadata<'a>(
+		&self,
+		_vfs: &Vfs,
+		url: &'a Url,
+	) -> Result<NodeMetadata, SchemeError<'a>> {
+		let target = self.remote_url(url)?;
+		self.existing(url, &target)
+			.await?
+			.ok_or(SchemeError::NodeDoesNotExist(Cow::Borrowed(url.path())))
+	}
+
+	async fn read_dir<'s, 'a>(
+		&'s self,
+		_vfs: &'s Vfs,
+		url: &'a Url,
+	) -> Result<BorrowedReadDirStream<'s>, SchemeError<'a>> {
+		let target = self.remote_url(url)?;
+		let own_path = percent_decode_str(target.path())
+			.decode_utf8_lossy()
+			.trim_end_matches('/')
+			.to_owned();
+		let entries = self.propfind(url, &target, "1").await?;
+		let dir = url.clone();
+		Ok(Box::pin(futures_lite::stream::iter(
+			entries.into_iter().filter_map(move |entry| {
+				let path = entry.path.trim_end_matches('/');
+				if path == own_path {
+					return None;
+				}
+				let name = path.rsplit('/').next()?;
+				Some(NodeEntry::child(&dir, [name])?.with_metadata(entry.metadata))
+			}),
+		)))
+	}
+
+	async fn create_dir<'a>(
+		&self,
+		_vfs: &Vfs,
+		url: &'a Url,
+		parents: bool,
+	) -> Result<(), SchemeError<'a>> {
+		if parents {
+			return self.mkcol_all(url, true).await;
+		}
+		let target = self.remote_url(url)?;
+		if self.mkcol(url, &target).await? {
+			Ok(())
+		} else {
+			Err(SchemeError::NodeAlreadyExists(Cow::Borrowed(url.path())))
+		}
+	}
+}
+
+type StoreFuture = Pin<Box<dyn Future<Output = std::io::Result<()>> + Send>>;
+
+pub struct WebDavNode {
+	scheme: WebDavScheme,
+	target: Url,
+	content: Vec<u8>,
+	cursor: usize,
+	read: bool,
+	write: bool,
+	append: bool,
+	/// Whether `content` was written to since it was last stored.
+	dirty: bool,
+	/// Only ever used through `Mutex::get_mut`, it is only there as the request is not `Sync`.
+	storing: Mutex<Option<StoreFuture>>,
+}
+
+impl WebDavNode {
+	/// Stores `content` if it was written to, once done the node is up to date on the server.
+	fn poll_store(&mut self, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+		let storing = self.storing.get_mut().expect("poisoned lock");
+		if storing.is_none() {
+			if !self.dirty {
+				return Poll::Ready(Ok(()));
+			}
+			let scheme = self.scheme.clone();
+			let target = self.target.clone();
+			let content = self.content.clone();
+			self.dirty = false;
+			*storing = Some(Box::pin(async move {
+				let response = scheme
+					.send(Method::PUT, &target, |request| {
+						request.body(content.clone())
+					})
+					.await
+					.map_err(io_error)?;
+				response.error_for_status().map(drop).map_err(io_error)
+			}));
+		}
+		let result = futures_lite::ready!(storing.as_mut().expect("just set").as_mut().poll(cx));
+		*storing = None;
+		if result.is_err() {
+			self.dirty = true;
+		}
+		Poll::Ready(result)
+	}
+}
+
+#[async_trait::async_trait]
+impl Node for WebDavNode {
+	fn is_reader(&self) -> bool {
+		self.read
+	}
+
+	fn is_writer(&self) -> bool {
+		self.write
+	}
+
+	fn is_seeker(&self) -> bool {
+		true
+	}
+
+	fn known_len(&self) -> Option<u64> {
+		Some(self.content.len() as u64)
+	}
+}
+
+impl AsyncRead for WebDavNode {
+	fn poll_read(
+		self: Pin<&mut Self>,
+		_cx: &mut Context<'_>,
+		buf: &mut [u8],
+	) -> Poll<std::io::Result<usize>> {
+		let this = self.get_mut();
+		this.read.into_poll_io_then(|| {
+			let remaining = this.content.get(this.cursor..).unwrap_or(&[]);
+			let amt = remaining.len().min(buf.len());
+			buf[..amt].copy_from_slice(&remaining[..amt]);
+			this.cursor += amt;
+			Poll::Ready(Ok(amt))
+		})
+	}
+}
+
+impl AsyncWrite for WebDavNode {
+	fn poll_write(
+		self: Pin<&mut Self>,
+		_cx: &mut Context<'_>,
+		buf: &[u8],
+	) -> Poll<std::io::Result<usize>> {
+		let this = self.get_mut();
+		this.write.into_poll_io_then(|| {
+			if this.append {
+				this.cursor = this.content.len();
+			}
+			if this.content.len() < this.cursor {
+				this.content.resize(this.cursor, 0);
+			}
+			let overwritten = (this.content.len() - this.cursor).min(buf.len());
+			this.content[this.cursor..this.cursor + overwritten]
+				.copy_from_slice(&buf[..overwritten]);
+			this.content.extend_from_slice(&buf[overwritten..]);
+			this.cursor += buf.len();
+			this.dirty = true;
+			Poll::Ready(Ok(buf.len()))
+		})
+	}
+
+	fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+		let this = self.get_mut();
+		this.write.into_poll_io_then(|| this.poll_store(cx))
+	}
+
+	fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+		self.get_mut().poll_store(cx)
+	}
+}
+
+impl AsyncSeek for WebDavNode {
+	fn poll_seek(
+		self: Pin<&mut Self>,
+		_cx: &mut Context<'_>,
+		pos: SeekFrom,
+	) -> Poll<std::io::Result<u64>> {
+		let this = self.get_mut();
+		let position = seek_position(pos, this.cursor as u64, Some(this.content.len() as u64))?;
+		this.cursor = position as usize;
+		Poll::Ready(Ok(position))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{auth_params, parse_multistatus};
+	use crate::scheme::NodeKind;
+	use crate::{SchemeError, WebDavScheme};
+	use std::time::{Duration, SystemTime};
+	use url::Url;
+
+	#[test]
+	fn challenges() {
+		assert_eq!(
+			auth_params(r#"realm="a \"b\", c", nonce=xyz ,qop="auth,auth-int""#),
+			[
+				("realm".to_owned(), r#"a "b", c"#.to_owned()),
+				("nonce".to_owned(), "xyz".to_owned()),
+				("qop".to_owned(), "auth,auth-int".to_owned()),
+			]
+		);
+	}
+
+	#[test]
+	fn multistatus() {
+		let target = Url::parse("https://host/dav/dir/").unwrap();
+		let entries = parse_multistatus(
+			r#"<?xml version="1.0"?>
+			<multistatus xmlns="DAV:">
+				<response>
+					<href>/dav/dir/</href>
+					<propstat><prop><resourcetype><collection/></resourcetype></prop></propstat>
+				</response>
+				<response>
+					<href>https://host/dav/dir/a%20node</href>
+					<propstat>
+						<prop>
+							<resourcetype/>
+							<getcontentlength>12</getcontentlength>
+							<getlastmodified>Thu, 01 Jan 1970 00:00:01 GMT</getlastmodified>
+						</prop>
+					</propstat>
+				</response>
+			</multistatus>"#,
+			&target,
+		)
+		.unwrap();
+		assert_eq!(entries.len(), 2);
+		assert_eq!(entries[0].path, "/dav/dir/");
+		assert_eq!(entries[0].metadata.kind, Some(NodeKind::Directory));
+		assert_eq!(entries[1].path, "/dav/dir/a node");
+		assert_eq!(entries[1].metadata.len, Some((12, Some(12))));
+		assert_eq!(
+			entries[1].metadata.modified,
+			Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1))
+		);
+		assert!(parse_multistatus("<multistatus", &target).is_err());
+	}
+
+	#[test]
+	fn remote_urls() {
+		let scheme =
+			WebDavScheme::new(Url::parse("https://host/remote.php/dav/files/alice/").unwrap());
+		let remote = |uri: &str| {
+			scheme
+				.remote_url(&Url::parse(uri).unwrap())
+				.map(String::from)
+				.map_err(SchemeError::into_owned)
+		};
+		assert_eq!(
+			remote("dav:/").unwrap(),
+			"https://host/remote.php/dav/files/alice/"
+		);
+		assert_eq!(
+			remote("dav:/dir/a%20node").unwrap(),
+			"https://host/remote.php/dav/files/alice/dir/a%20node"
+		);
+		assert_eq!(
+			remote("dav:/dir/%2E%2E/../escape").unwrap(),
+			"https://host/remote.php/dav/files/alice/escape",
+			"dot segments are resolved by the url itself"
+		);
+		assert!(matches!(
+			remote("dav:/dir%2Fescape"),
+			Err(SchemeError::UrlAccessError(_))
+		));
+	}
+}
+
+#[cfg(test)]
+#[cfg(feature = "backend_tokio")]
+mod async_tokio_tests {
+	use super::{auth_params, md5_hex};
+	use crate::scheme::NodeGetOptions;
+	use crate::{SchemeError, Vfs, VfsError, WebDavScheme};
+	use futures_lite::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt, StreamExt};
+	use std::collections::{BTreeMap, BTreeSet};
+	use std::io::SeekFrom;
+	use std::sync::{Arc, Mutex};
+	use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _};
+	use tokio::net::{TcpListener, TcpStream};
+	use url::Url;
+
+	#[derive(Default)]
+	struct Files {
+		files: BTreeMap<String, Vec<u8>>,
+		dirs: BTreeSet<String>,
+	}
+
+	/// Serves a WebDAV server below `/dav/` holding `file`, with digest authentication of `user`
+	/// and `password`, one request per connection.  Returns the url of the endpoint.
+	async fn serve() -> Url {
+		let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+		let address = listener.local_addr().unwrap();
+		let files = Arc::new(Mutex::new(Files::default()));
+		{
+			let mut files = files.lock().unwrap();
+			files
+				.files
+				.insert("/dav/file".to_owned(), b"content".to_vec());
+			files.dirs.insert("/dav".to_owned());
+		}
+		tokio::spawn(async move {
+			while let Ok((stream, _address)) = listener.accept().await {
+				tokio::spawn(respond(stream, files.clone()));
+			}
+		});
+		Url::parse(&format!("http://{}/dav/", address)).unwrap()
+	}
+
+	fn authorized(method: &str, authorization: Option<&str>) -> bool {
+		let params = match authorization.and_then(|value| value.strip_prefix("Digest ")) {
+			Some(params) => auth_params(params),
+			None => return false,
+		};
+		let param = |key: &str| {
+			params
+				.iter()
+				.find(|(name, _value)| name == key)
+				.map_or("", |(_name, value)| value.as_str())
+		};
+		let ha1 = md5_hex("user:test:password");
+		let ha2 = md5_hex(&format!("{}:{}", method, param("uri")));
+		let expected = md5_hex(&format!(
+			"{}:nonce:{}:{}:auth:{}",
+			ha1,
+			param("nc"),
+			param("cnonce"),
+			ha2
+		));
+		param("username") == "user" && param("opaque") == "opaque" && param("response") == expected
+	}
+
+	fn propfind_entry(path: &str, files: &Files) -> String {
+		let prop = match files.files.get(path) {
+			Some(content) => format!(
+				"<d:resourcetype/><d:getcontentlength>{}</d:getcontentlength>",
+				content.len()
+			),
+			None => "<d:resourcetype><d:collection/></d:resourcetype>".to_owned(),
+		};
+		format!(
+			"<d:response><d:href>{}</d:href><d:propstat><d:prop>{}</d:prop>\
+			 <d:status>HTTP/1.1 200 OK</d:status></d:propstat></d:response>",
+			path, prop
+		)
+	}
+
+	async fn respond(mut stream: TcpStream, files: Arc<Mutex<Files>>) {
+		let mut request = Vec::new();
+		let mut buffer = [0; 1024];
+		let head_end = loop {
+			if let Some(end) = request.windows(4).position(|window| window == b"\r\n\r\n") {
+				break end + 4;
+			}
+			match stream.read(&mut buffer).await {
+				Ok(0) | Err(_) => return,
+				Ok(amt) => request.extend_from_slice(&buffer[..amt]),
+			}
+		};
+		let head = String::from_utf8_lossy(&request[..head_end]).into_owned();
+		let header = |name: &str| {
+			head.lines().find_map(|line| {
+				let (key, value) = line.split_once(':')?;
+				key.eq_ignore_ascii_case(name).then(|| value.trim())
+			})
+		};
+		let len = header("content-length").map_or(0, |len| len.parse().unwrap());
+		while request.len() < head_end + len {
+			match stream.read(&mut buffer).await {
+				Ok(0) | Err(_) => return,
+				Ok(amt) => request.extend_from_slice(&buffer[..amt]),
+			}
+		}
+		let body = request[head_end..].to_vec();
+		let mut request_line = head.split(' ');
+		let method = request_line.next().unwrap();
+		let path = request_line.next().unwrap().trim_end_matches('/');
+
+		let (status, response) = {
+			let mut files = files.lock().unwrap();
+			let exists = files.files.contains_key(path) || files.dirs.contains(path);
+			if !authorized(method, header("authorization")) {
+				("401 Unauthorized", String::new())
+			} else {
+				match method {
+					"GET" => match files.files.get(path) {
+						Some(content) => ("200 OK", String::from_utf8_lossy(content).into_owned()),
+						None => ("404 Not Found", String::new()),
+					},
+					"PUT" => {
+						files.files.insert(path.to_owned(), body);
+						("201 Created", String::new())
+					}
+					"DELETE" if exists => {
+						let prefix = format!("{}/", path);
+						files
+							.files
+							.retain(|name, _content| name != path && !name.starts_with(&prefix));
+						files
+							.dirs
+							.retain(|name| name != path && !name.starts_with(&prefix));
+						("204 No Content", String::new())
+					}
+					"MKCOL" if exists => ("405 Method Not Allowed", String::new()),
+					"MKCOL" => {
+						files.dirs.insert(path.to_owned());
+						("201 Created", String::new())
+					}
+					"PROPFIND" if exists => {
+						let mut listing = propfind_entry(path, &files);
+						if header("depth") == Some("1") {
+							let prefix = format!("{}/", path);
+							let children = files.files.keys().chain(&files.dirs).filter(|name| {
+								name.strip_prefix(&prefix)
+									.is_some_and(|name| !name.contains('/'))
+							});
+							for child in children {
+								listing += &propfind_entry(child, &files);
+							}
+						}
+						(
+							"207 Multi-Status",
+							format!(
+								"<d:multistatus xmlns:d=\"DAV:\">{}</d:multistatus>",
+								listing
+							),
+						)
+					}
+					_ => ("404 Not Found", String::new()),
+				}
+			}
+		};
+		let head = format!(
+			"HTTP/1.1 {}\r\nWWW-Authenticate: Basic realm=\"test\"\r\n\
+			 WWW-Authenticate: Digest realm=\"test\", nonce=\"nonce\", opaque=\"opaque\", \
+			 qop=\"auth\", algorithm=MD5\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+			status,
+			response.len()
+		);
+		let _ = stream.write_all(head.as_bytes()).await;
+		let _ = stream.write_all(response.as_bytes()).await;
+	}
+
+	#[tokio::test]
+	async fn webdav_server() {
+		let endpoint = serve().await;
+		let vfs = Vfs::empty();
+		vfs.add_scheme(
+			"dav",
+			WebDavScheme::new(endpoint.clone()).with_digest_auth("user", "password"),
+		)
+		.unwrap();
+
+		assert_eq!(vfs.read_to_vec_at("dav:/file").await.unwrap(), b"content");
+		let metadata = vfs.metadata_at("dav:/file").await.unwrap();
+		assert_eq!(metadata.len, Some((7, Some(7))));
+		assert!(!vfs.metadata_at("dav:/").await.unwrap().is_node);
+		assert!(matches!(
+			vfs.metadata_at("dav:/none").await,
+			Err(VfsError::SchemeError(SchemeError::NodeDoesNotExist(_)))
+		));
+
+		let mut node = vfs
+			.get_node_at(
+				"dav:/dir/node",
+				&NodeGetOptions::new()
+					.write(true)
+					.create_new(true)
+					.create_parents(true),
+			)
+			.await
+			.unwrap();
+		node.write_all(b"new node").await.unwrap();
+		node.seek(SeekFrom::Start(4)).await.unwrap();
+		node.write_all(b"file").await.unwrap();
+		node.close().await.unwrap();
+		assert_eq!(
+			vfs.read_to_vec_at("dav:/dir/node").await.unwrap(),
+			b"new file"
+		);
+		assert!(matches!(
+			vfs.get_node_at("dav:/dir/node", &NodeGetOptions::new().create_new(true))
+				.await,
+			Err(VfsError::SchemeError(SchemeError::NodeAlreadyExists(_)))
+		));
+
+		let mut node = vfs
+			.get_node_at(
+				"dav:/file",
+				&NodeGetOptions::new().read(true).append(true).write(true),
+			)
+			.await
+			.unwrap();
+		node.write_all(b" appended").await.unwrap();
+		node.seek(SeekFrom::Start(0)).await.unwrap();
+		let mut content = String::new();
+		node.read_to_string(&mut content).await.unwrap();
+		assert_eq!(content, "content appended");
+		node.close().await.unwrap();
+
+		let mut listed: Vec<_> = vfs
+			.read_dir_at("dav:/")
+			.await
+			.unwrap()
+			.map(|entry| (entry.url.to_string(), entry.metadata.map(|m| m.is_node)))
+			.collect()
+			.await;
+		listed.sort();
+		assert_eq!(
+			listed,
+			[
+				("dav:/dir".to_owned(), Some(false)),
+				("dav:/file".to_owned(), Some(true))
+			]
+		);
+
+		assert!(matches!(
+			vfs.remove_node_at("dav:/dir", false).await,
+			Err(VfsError::SchemeError(SchemeError::GenericError(..)))
+		));
+		vfs.remove_node_at("dav:/dir", true).await.unwrap();
+		assert!(matches!(
+			vfs.metadata_at("dav:/dir").await,
+			Err(VfsError::SchemeError(SchemeError::NodeDoesNotExist(_)))
+		));
+
+		vfs.add_scheme(
+			"wrong",
+			WebDavScheme::new(endpoint).with_digest_auth("user", "wrong"),
+		)
+		.unwrap();
+		assert!(matches!(
+			vfs.metadata_at("wrong:/file").await,
+			Err(VfsError::SchemeError(SchemeError::UrlAccessError(_)))
+		));
+	}
+}
@@ -0,0 +1,227 @@
+use crate::io_util;
+use crate::{AssetContainerScheme, ContainerBackend, SchemeError, Vfs, VfsError};
+use futures_lite::AsyncRead;
+use std::collections::HashMap;
+use std::io::{Cursor, Read};
+use std::path::Path;
+use std::sync::Mutex;
+use url::Url;
+use zip::ZipArchive;
+
+struct ZipEntryInfo {
+	index: usize,
+	len: usize,
+}
+
+/// A zip archive as a `ContainerBackend`.  The whole archive is buffered in memory and its index
+/// read up front, so it can come from anywhere, including a node of another scheme via
+/// `ZipArchiveScheme::from_node`, which allows nesting archives.  Entries are decompressed when
+/// opened.
+pub struct ZipContainer {
+	archive: Mutex<ZipArchive<Cursor<Vec<u8>>>>,
+	entries: HashMap<String, ZipEntryInfo>,
+}
+
+/// Serves the files of a zip archive as read-only nodes.
+pub type ZipArchiveScheme = AssetContainerScheme<ZipContainer>;
+
+fn zip_error(error: zip::result::ZipError) -> SchemeError<'static> {
+	("zip archive error", Box::new(error) as Box<_>).into()
+}
+
+impl ZipContainer {
+	pub fn from_bytes(data: Vec<u8>) -> Result<Self, SchemeError<'static>> {
+		let mut archive = ZipArchive::new(Cursor::new(data)).map_err(zip_error)?;
+		let mut entries = HashMap::with_capacity(archive.len());
+		for index in 0..archive.len() {
+			// Raw access only reads the header, nothing is decompressed
+			let file = archive.by_index_raw(index).map_err(zip_error)?;
+			let name = file.name().trim_start_matches('/').to_owned();
+			let len = file.size() as usize;
+			entries.insert(name, ZipEntryInfo { index, len });
+		}
+		Ok(Self {
+			archive: Mutex::new(archive),
+			entries,
+		})
+	}
+}
+
+impl ContainerBackend for ZipContainer {
+	fn list(&self) -> Vec<String> {
+		self.entries.keys().cloned().collect()
+	}
+
+	fn len(&self, name: &str) -> Option<usize> {
+		self.entries.get(name).map(|info| info.len)
+	}
+
+	fn open_entry(&self, name: &str) -> Result<Vec<u8>, SchemeError<'static>> {
+		let info = self.entries.get(name).ok_or("no such zip archive entry")?;
+		let mut archive = self.archive.lock().expect("poisoned lock");
+		let mut file = archive.by_index(info.index).map_err(zip_error)?;
+		let mut data = Vec::with_capacity(info.len);
+		file.read_to_end(&mut data)?;
+		Ok(data)
+	}
+}
+
+impl ZipArchiveScheme {
+	pub fn from_bytes(data: Vec<u8>) -> Result<Self, SchemeError<'static>> {
+		Ok(Self::new(ZipContainer::from_bytes(data)?))
+	}
+
+	/// Reads the archive out of a file on the local filesystem, blocking while it does, so it is
+	/// meant for setting up a vfs before it is used, such as to mount the asset packs of a game.
+	pub fn from_path(path: impl AsRef<Path>) -> Result<Self, SchemeError<'static>> {
+		Self::from_bytes(std::fs::read(path)?)
+	}
+
+	/// Reads the archive to the end out of any async reader, such as an already opened node.
+	pub async fn from_reader(
+		mut reader: impl AsyncRead + Unpin,
+	) -> Result<Self, SchemeError<'static>> {
+		let mut data = Vec::new();
+		io_util::read_to_end(&mut reader, &mut data).await?;
+		Self::from_bytes(data)
+	}
+
+	/// Reads the archive out of the node at `url`, which can be in any scheme of `vfs`.
+	#[allow(clippy::needless_lifetimes)] // Clippy is wrong here, it is necessary
+	pub async fn from_node<'a>(vfs: &Vfs, url: &'a Url) -> Result<Self, VfsError<'a>> {
+		let data = vfs.read_to_vec(url).await?;
+		Ok(Self::from_bytes(data)?)
+	}
+}
+
+#[cfg(test)]
+#[cfg(all(feature = "backend_tokio", feature = "in_memory"))]
+mod async_tokio_tests {
+	use crate::scheme::NodeGetOptions;
+	use crate::{MemoryScheme, SchemeError, Vfs, VfsError, ZipArchiveScheme};
+	use futures_lite::{AsyncReadExt, AsyncWriteExt, StreamExt};
+	use std::io::Write;
+	use url::Url;
+
+	fn build_zip() -> Vec<u8> {
+		let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+		let options = zip::write::SimpleFileOptions::default();
+		zip.start_file("readme.txt", options).unwrap();
+		zip.write_all(b"zipped readme").unwrap();
+		zip.add_directory("empty/", options).unwrap();
+		// No explicit entry for `assets/` or `assets/textures/`
+		zip.start_file("assets/textures/grass.txt", options)
+			.unwrap();
+		zip.write_all(b"green").unwrap();
+		zip.start_file("assets/config.txt", options).unwrap();
+		zip.write_all(b"config").unwrap();
+		zip.finish().unwrap().into_inner()
+	}
+
+	async fn read(vfs: &Vfs, uri: &str) -> String {
+		let mut buffer = String::new();
+		vfs.get_node_at(uri, &NodeGetOptions::new().read(true))
+			.await
+			.unwrap()
+			.read_to_string(&mut buffer)
+			.await
+			.unwrap();
+		buffer
+	}
+
+	async fn list(vfs: &Vfs, uri: &str) -> Vec<String> {
+		vfs.read_dir_at(uri)
+			.await
+			.unwrap()
+			.map(|e| e.url.path().to_owned())
+			.collect()
+			.await
+	}
+
+	#[tokio::test]
+	async fn zip_from_memory_node() {
+		let vfs = Vfs::empty();
+		vfs.add_scheme("mem", MemoryScheme::default()).unwrap();
+		let mut node = vfs
+			.get_node_at(
+				"mem:/packs/assets.zip",
+				&NodeGetOptions::new().create_new(true),
+			)
+			.await
+			.unwrap();
+		node.write_all(&build_zip()).await.unwrap();
+		drop(node);
+
+		let zip = ZipArchiveScheme::from_node(&vfs, &Url::parse("mem:/packs/assets.zip").unwrap())
+			.await
+			.unwrap();
+		vfs.add_scheme("zip", zip).unwrap();
+		assert_eq!(read(&vfs, "zip:/readme.txt").await, "zipped readme");
+		assert_eq!(read(&vfs, "zip:/assets/textures/grass.txt").await, "green");
+		assert_eq!(
+			vfs.metadata_at("zip:/assets/config.txt").await.unwrap().len,
+			Some((6, Some(6)))
+		);
+		assert!(!vfs.metadata_at("zip:/assets").await.unwrap().is_node);
+		assert!(matches!(
+			vfs.get_node_at("zip:/assets/textures", &NodeGetOptions::new().read(true))
+				.await,
+			Err(VfsError::SchemeError(SchemeError::IsADirectory(_)))
+		));
+		assert!(vfs.metadata_at("zip:/missing.txt").await.is_err());
+
+		assert_eq!(
+			list(&vfs, "zip:/").await,
+			["/assets", "/empty", "/readme.txt"]
+		);
+		assert_eq!(
+			list(&vfs, "zip:/assets/").await,
+			["/assets/config.txt", "/assets/textures"]
+		);
+		assert!(list(&vfs, "zip:/empty").await.is_empty());
+
+		let node = vfs
+			.get_node_at("mem:/packs/assets.zip", &NodeGetOptions::new().read(true))
+			.await
+			.unwrap();
+		let zip = ZipArchiveScheme::from_reader(node).await.unwrap();
+		vfs.add_scheme("zip2", zip).unwrap();
+		assert_eq!(read(&vfs, "zip2:/readme.txt").await, "zipped readme");
+	}
+
+	#[tokio::test]
+	async fn zip_from_path() {
+		let path = std::env::current_dir()
+			.unwrap()
+			.join("target/test_zip_from_path.zip");
+		std::fs::write(&path, build_zip()).unwrap();
+		let zip = ZipArchiveScheme::from_path(&path);
+		std::fs::remove_file(&path).unwrap();
+		let vfs = Vfs::empty();
+		vfs.add_scheme("zip", zip.unwrap()).unwrap();
+		assert_eq!(read(&vfs, "zip:/assets/config.txt").await, "config");
+		assert!(ZipArchiveScheme::from_path(&path).is_err());
+	}
+
+	#[tokio::test]
+	async fn zip_invalid() {
+		let vfs = Vfs::empty();
+		vfs.add_scheme("mem", MemoryScheme::default()).unwrap();
+		vfs.get_node_at("mem:/bad.zip", &NodeGetOptions::new().create_new(true))
+			.await
+			.unwrap()
+			.write_all(b"not a zip")
+			.await
+			.unwrap();
+		assert!(
+			ZipArchiveScheme::from_node(&vfs, &Url::parse("mem:/bad.zip").unwrap())
+				.await
+				.is_err()
+		);
+		assert!(
+			ZipArchiveScheme::from_node(&vfs, &Url::parse("mem:/missing.zip").unwrap())
+				.await
+				.is_err()
+		);
+	}
+}
@@ -0,0 +1,90 @@
+use crate::SchemeError;
+
+/// Decodes text by its byte order mark, UTF-8 or UTF-16 in either endianness, falling back to
+/// UTF-8 when there is none.  The byte order mark itself is not included in the result.
+pub(crate) fn decode_text(data: &[u8]) -> Result<String, SchemeError<'static>> {
+	match data {
+		[0xEF, 0xBB, 0xBF, rest @ ..] => decode_utf8(rest),
+		[0xFF, 0xFE, rest @ ..] => decode_utf16(rest, u16::from_le_bytes),
+		[0xFE, 0xFF, rest @ ..] => decode_utf16(rest, u16::from_be_bytes),
+		_ => decode_utf8(data),
+	}
+}
+
+fn decode_utf8(data: &[u8]) -> Result<String, SchemeError<'static>> {
+	String::from_utf8(data.to_vec())
+		.map_err(|e| ("invalid UTF-8 text", Box::new(e) as Box<_>).into())
+}
+
+fn decode_utf16(data: &[u8], to_u16: fn([u8; 2]) -> u16) -> Result<String, SchemeError<'static>> {
+	if !data.len().is_multiple_of(2) {
+		Err("invalid UTF-16 text, odd number of bytes")?;
+	}
+	let units: Vec<u16> = data
+		.chunks_exact(2)
+		.map(|pair| to_u16([pair[0], pair[1]]))
+		.collect();
+	String::from_utf16(&units).map_err(|e| ("invalid UTF-16 text", Box::new(e) as Box<_>).into())
+}
+
+#[cfg(test)]
+#[cfg(all(feature = "backend_tokio", feature = "in_memory"))]
+mod async_tokio_tests {
+	use crate::scheme::NodeGetOptions;
+	use crate::{MemoryScheme, Vfs};
+	use futures_lite::AsyncWriteExt;
+
+	async fn vfs_with(files: &[(&str, &[u8])]) -> Vfs {
+		let vfs = Vfs::empty();
+		vfs.add_scheme("mem", MemoryScheme::default()).unwrap();
+		for (path, data) in files {
+			let mut node = vfs
+				.get_node_at(path, &NodeGetOptions::new().create_new(true))
+				.await
+				.unwrap();
+			node.write_all(data).await.unwrap();
+		}
+		vfs
+	}
+
+	#[tokio::test]
+	async fn read_text() {
+		let utf16le: Vec<u8> = vec![0xFF, 0xFE]
+			.into_iter()
+			.chain("héllo".encode_utf16().flat_map(u16::to_le_bytes))
+			.collect();
+		let utf16be: Vec<u8> = vec![0xFE, 0xFF]
+			.into_iter()
+			.chain("héllo".encode_utf16().flat_map(u16::to_be_bytes))
+			.collect();
+		let vfs = vfs_with(&[
+			("mem:/plain.txt", "héllo".as_bytes()),
+			("mem:/bom.txt", b"\xEF\xBB\xBFh\xC3\xA9llo"),
+			("mem:/utf16le.txt", &utf16le),
+			("mem:/utf16be.txt", &utf16be),
+		])
+		.await;
+		for path in [
+			"mem:/plain.txt",
+			"mem:/bom.txt",
+			"mem:/utf16le.txt",
+			"mem:/utf16be.txt",
+		] {
+			assert_eq!(vfs.read_text_at(path).await.unwrap(), "héllo", "{}", path);
+		}
+	}
+
+	#[tokio::test]
+	async fn read_text_invalid() {
+		let vfs = vfs_with(&[
+			("mem:/bad_utf8.txt", b"\xC3\x28"),
+			("mem:/odd_utf16.txt", b"\xFF\xFEh\x00e"),
+			("mem:/bad_utf16.txt", b"\xFF\xFE\x00\xD8"),
+		])
+		.await;
+		assert!(vfs.read_text_at("mem:/bad_utf8.txt").await.is_err());
+		assert!(vfs.read_text_at("mem:/odd_utf16.txt").await.is_err());
+		assert!(vfs.read_text_at("mem:/bad_utf16.txt").await.is_err());
+		assert!(vfs.read_text_at("mem:/missing.txt").await.is_err());
+	}
+}
@@ -0,0 +1,609 @@
+use crate::access::VfsOp;
+use crate::io_util;
+use crate::scheme::NodeGetOptions;
+use crate::walk::{dir_url, walk_dir, WalkOptions};
+use crate::{PinnedNode, SchemeError, Vfs, VfsError};
+use futures_lite::{AsyncWriteExt, StreamExt};
+use std::borrow::Cow;
+use url::Url;
+
+/// How many suffixed names `ConflictPolicy::Rename` tries before giving up, so a destination that
+/// always reports something already there cannot keep a transfer trying forever.
+pub const MAX_RENAME_ATTEMPTS: usize = 1000;
+
+const RENAME_ATTEMPTS_EXHAUSTED: &str = "every renamed destination already exists";
+
+/// A piece of node metadata that a move tries to carry over to the destination.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MetadataField {
+	/// When the node was last modified, see `Scheme::set_modified`.
+	Modified,
+}
+
+/// What a copy or move does when something already exists at its destination.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConflictPolicy {
+	/// Fail with the error of the destination scheme, such as `SchemeError::NodeAlreadyExists`.
+	Fail,
+	/// Replace what is at the destination.
+	Overwrite,
+	/// Leave the destination, and for a move the source, as they are.
+	Skip,
+	/// Write to the first of `name-1.ext`, `name-2.ext` and so on that does not exist yet, failing
+	/// once `MAX_RENAME_ATTEMPTS` all exist.
+	Rename,
+}
+
+/// What a copy or move did at its destination.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum ConflictOutcome {
+	/// Nothing was at the destination.
+	#[default]
+	Created,
+	/// What was at the destination was replaced.
+	Overwritten,
+	/// Something was at the destination so nothing was done.
+	Skipped,
+	/// Something was at the destination so the node was written to this url instead.
+	Renamed(Url),
+}
+
+/// What `Vfs::copy_node` and `Vfs::move_node` did, the content itself is always transferred unless
+/// the outcome is `ConflictOutcome::Skipped`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TransferReport {
+	pub outcome: ConflictOutcome,
+	/// Metadata the source had but the destination scheme does not support setting.
+	pub dropped: Vec<MetadataField>,
+}
+
+impl TransferReport {
+	pub fn is_complete(&self) -> bool {
+		self.dropped.is_empty()
+	}
+}
+
+/// The transfer of one node of a `Vfs::copy_dir_all`.
+#[derive(Debug)]
+pub struct NodeTransfer {
+	pub from: Url,
+	pub to: Url,
+	pub result: Result<TransferReport, VfsError<'static>>,
+}
+
+/// What `Vfs::copy_dir_all` did with each node it found, in the order it found them.
+#[derive(Debug, Default)]
+pub struct DirTransferReport {
+	pub nodes: Vec<NodeTransfer>,
+}
+
+impl DirTransferReport {
+	/// Whether every node was transferred, skipped ones included, without an error.
+	pub fn is_ok(&self) -> bool {
+		self.nodes.iter().all(|node| node.result.is_ok())
+	}
+}
+
+fn already_exists(error: &VfsError) -> bool {
+	match error {
+		VfsError::SchemeError(SchemeError::NodeAlreadyExists(_)) => true,
+		VfsError::SchemeError(error) => error
+			.io_error()
+			.is_some_and(|error| error.kind() == std::io::ErrorKind::AlreadyExists),
+		_ => false,
+	}
+}
+
+/// `url` with `-n` appended to the name of its last segment, before any extension.
+fn with_suffix(url: &Url, n: usize) -> Url {
+	let path = url.path();
+	let name_start = path.rfind('/').map_or(0, |pos| pos + 1);
+	// A leading `.` is part of the name rather than the start of an extension
+	let split = match path[name_start..].rfind('.') {
+		Some(pos) if pos > 0 => name_start + pos,
+		_ => path.len(),
+	};
+	let mut url = url.clone();
+	url.set_path(&format!("{}-{}{}", &path[..split], n, &path[split..]));
+	url
+}
+
+/// Opens the destination of a transfer for writing as `policy` says, `None` if it is skipped.
+async fn open_destination<'a>(
+	vfs: &Vfs,
+	to: &'a Url,
+	policy: ConflictPolicy,
+) -> Result<Option<(PinnedNode, ConflictOutcome)>, VfsError<'a>> {
+	let create_new = NodeGetOptions::new().create_new(true);
+	match policy {
+		ConflictPolicy::Fail => Ok(Some((
+			vfs.get_node(to, &create_new).await?,
+			ConflictOutcome::Created,
+		))),
+		ConflictPolicy::Overwrite => {
+			let outcome = if vfs.metadata(to).await.is_ok() {
+				ConflictOutcome::Overwritten
+			} else {
+				ConflictOutcome::Created
+			};
+			let options = NodeGetOptions::new().create(true).truncate(true);
+			Ok(Some((vfs.get_node(to, &options).await?, outcome)))
+		}
+		ConflictPolicy::Skip => match vfs.get_node(to, &create_new).await {
+			Ok(node) => Ok(Some((node, ConflictOutcome::Created))),
+			Err(error) if already_exists(&error) => Ok(None),
+			Err(error) => Err(error),
+		},
+		ConflictPolicy::Rename => {
+			match vfs.get_node(to, &create_new).await {
+				Ok(node) => return Ok(Some((node, ConflictOutcome::Created))),
+				Err(error) if already_exists(&error) => (),
+				Err(error) => return Err(error),
+			}
+			for n in 1..=MAX_RENAME_ATTEMPTS {
+				let renamed = with_suffix(to, n);
+				match vfs.get_node(&renamed, &create_new).await {
+					Ok(node) => return Ok(Some((node, ConflictOutcome::Renamed(renamed)))),
+					Err(error) if already_exists(&error) => (),
+					Err(error) => return Err(error.into_owned()),
+				}
+			}
+			Err(SchemeError::from(RENAME_ATTEMPTS_EXHAUSTED).into())
+		}
+	}
+}
+
+/// Copies or moves a node through the scheme itself when both urls are of the same one, `None` if
+/// it has to be streamed instead.  Only taken when the policy needs no more than replacing the
+/// destination, as schemes always replace it.
+async fn same_scheme_transfer<'a>(
+	vfs: &Vfs,
+	from: &'a Url,
+	to: &'a Url,
+	policy: ConflictPolicy,
+	moving: bool,
+) -> Result<Option<TransferReport>, VfsError<'a>> {
+	if from.scheme() != to.scheme() {
+		return Ok(None);
+	}
+	let metadata = vfs.metadata(from).await?;
+	if !metadata.is_node {
+		return Ok(None);
+	}
+	let outcome = match (vfs.metadata(to).await, policy) {
+		(Err(_), _) => ConflictOutcome::Created,
+		(Ok(_), ConflictPolicy::Overwrite) => ConflictOutcome::Overwritten,
+		(Ok(_), _) => return Ok(None),
+	};
+	vfs.check_access(VfsOp::Read, from)?;
+	vfs.check_access(VfsOp::Write, to)?;
+	if moving {
+		vfs.check_access(VfsOp::Remove, from)?;
+	}
+	let scheme = vfs.scheme_for_url(from)?;
+	let result = if moving {
+		scheme.rename_node(vfs, from, to).await
+	} else {
+		scheme.copy_node(vfs, from, to).await
+	};
+	let mut report = TransferReport {
+		outcome,
+		dropped: Vec::new(),
+	};
+	match result {
+		Ok(()) => (),
+		Err(SchemeError::Unsupported(_)) => return Ok(None),
+		Err(error) => return Err(error.into()),
+	}
+	// A scheme renaming by copying may not be able to keep the modified time
+	if moving && metadata.modified.is_some() {
+		let moved = vfs.metadata(to).await?;
+		if moved.modified != metadata.modified {
+			report.dropped.push(MetadataField::Modified);
+		}
+	}
+	Ok(Some(report))
+}
+
+async fn transfer_node<'a>(
+	vfs: &Vfs,
+	from: &'a Url,
+	to: &'a Url,
+	policy: ConflictPolicy,
+	keep_metadata: bool,
+) -> Result<TransferReport, VfsError<'a>> {
+	let metadata = vfs.metadata(from).await?;
+	if !metadata.is_node {
+		return Err(SchemeError::IsADirectory(Cow::Borrowed(from.path())).into());
+	}
+	let mut reader = vfs
+		.get_node(from, &NodeGetOptions::new().read(true))
+		.await?;
+	let (mut writer, outcome) = match open_destination(vfs, to, policy).await? {
+		Some(destination) => destination,
+		None => {
+			return Ok(TransferReport {
+				outcome: ConflictOutcome::Skipped,
+				dropped: Vec::new(),
+			})
+		}
+	};
+	io_util::copy(&mut reader, &mut writer).await?;
+	// Everything has to be written out before the metadata is set or a late write would bump it
+	writer.close().await?;
+	drop(writer);
+	drop(reader);
+
+	let mut report = TransferReport {
+		outcome,
+		dropped: Vec::new(),
+	};
+	if let (true, Some(modified)) = (keep_metadata, metadata.modified) {
+		let written = match &report.outcome {
+			ConflictOutcome::Renamed(renamed) => renamed,
+			_ => to,
+		};
+		match vfs.set_modified(written, modified).await {
+			Ok(()) => (),
+			Err(VfsError::SchemeError(SchemeError::Unsupported(_))) => {
+				report.dropped.push(MetadataField::Modified)
+			}
+			Err(error) => return Err(error.into_owned()),
+		}
+	}
+	Ok(report)
+}
+
+pub(crate) async fn copy_node<'a>(
+	vfs: &Vfs,
+	from: &'a Url,
+	to: &'a Url,
+	policy: ConflictPolicy,
+) -> Result<TransferReport, VfsError<'a>> {
+	if let Some(report) = same_scheme_transfer(vfs, from, to, policy, false).await? {
+		return Ok(report);
+	}
+	transfer_node(vfs, from, to, policy, false).await
+}
+
+pub(crate) async fn rename_node<'a>(
+	vfs: &Vfs,
+	from: &'a Url,
+	to: &'a Url,
+) -> Result<(), VfsError<'a>> {
+	if from.scheme() != to.scheme() {
+		move_node(vfs, from, to, ConflictPolicy::Overwrite).await?;
+		return Ok(());
+	}
+	vfs.check_access(VfsOp::Read, from)?;
+	vfs.check_access(VfsOp::Write, to)?;
+	vfs.check_access(VfsOp::Remove, from)?;
+	Ok(vfs.scheme_for_url(from)?.rename_node(vfs, from, to).await?)
+}
+
+pub(crate) async fn move_node<'a>(
+	vfs: &Vfs,
+	from: &'a Url,
+	to: &'a Url,
+	policy: ConflictPolicy,
+) -> Result<TransferReport, VfsError<'a>> {
+	if let Some(report) = same_scheme_transfer(vfs, from, to, policy, true).await? {
+		return Ok(report);
+	}
+	let report = transfer_node(vfs, from, to, policy, true).await?;
+	if report.outcome != ConflictOutcome::Skipped {
+		vfs.remove_node(from, false).await?;
+	}
+	Ok(report)
+}
+
+pub(crate) async fn copy_dir_all<'a>(
+	vfs: &Vfs,
+	from: &'a Url,
+	to: &'a Url,
+	policy: ConflictPolicy,
+) -> Result<DirTransferReport, VfsError<'a>> {
+	let from = dir_url(from);
+	let to = dir_url(to);
+	let mut walk = walk_dir(vfs, &from, WalkOptions::new())
+		.await
+		.map_err(VfsError::into_owned)?;
+	let mut report = DirTransferReport::default();
+	while let Some(entry) = walk.next().await {
+		let node = entry?.url;
+		// Nodes found through a symlink keep the url of the link so they are still under `from`
+		let relative = match node.path().strip_prefix(from.path()) {
+			Some(relative) => relative,
+			None => continue,
+		};
+		let destination = to.join(relative).map_err(VfsError::from)?;
+		let result = copy_node(vfs, &node, &destination, policy)
+			.await
+			.map_err(VfsError::into_owned);
+		report.nodes.push(NodeTransfer {
+			from: node,
+			to: destination,
+			result,
+		});
+	}
+	Ok(report)
+}
+
+#[cfg(test)]
+#[cfg(all(feature = "backend_tokio", feature = "in_memory"))]
+mod async_tokio_tests {
+	use super::{ConflictOutcome, ConflictPolicy, MetadataField, TransferReport};
+	use crate::scheme::NodeGetOptions;
+	use crate::{MemoryScheme, OverlayScheme, SchemeError, Vfs, VfsError};
+	use futures_lite::AsyncWriteExt;
+	use std::time::{Duration, SystemTime};
+	use url::Url;
+
+	async fn write(vfs: &Vfs, uri: &str, content: &str) {
+		let mut node = vfs
+			.get_node_at(uri, &NodeGetOptions::new().create(true).truncate(true))
+			.await
+			.unwrap();
+		node.write_all(content.as_bytes()).await.unwrap();
+	}
+
+	async fn conflicting_vfs() -> Vfs {
+		let vfs = Vfs::empty();
+		vfs.add_scheme("mem", MemoryScheme::default()).unwrap();
+		write(&vfs, "mem:/from.txt", "new").await;
+		write(&vfs, "mem:/to.txt", "old").await;
+		vfs
+	}
+
+	#[tokio::test]
+	async fn move_between_memory_schemes() {
+		let vfs = Vfs::empty();
+		vfs.add_scheme("from", MemoryScheme::default()).unwrap();
+		vfs.add_scheme("to", MemoryScheme::default()).unwrap();
+		write(&vfs, "from:/node", "moved").await;
+		let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+		vfs.set_modified_at("from:/node", modified).await.unwrap();
+
+		let report = vfs
+			.move_node_at("from:/node", "to:/node", ConflictPolicy::Fail)
+			.await
+			.unwrap();
+		assert!(report.is_complete());
+		assert_eq!(report.outcome, ConflictOutcome::Created);
+		assert!(vfs.metadata_at("from:/node").await.is_err());
+		assert_eq!(vfs.read_to_vec_at("to:/node").await.unwrap(), b"moved");
+		assert_eq!(
+			vfs.metadata_at("to:/node").await.unwrap().modified,
+			Some(modified)
+		);
+	}
+
+	#[tokio::test]
+	async fn move_within_scheme_renames() {
+		let vfs = conflicting_vfs().await;
+		let mut open = vfs
+			.get_node_at(
+				"mem:/from.txt",
+				&NodeGetOptions::new().write(true).append(true),
+			)
+			.await
+			.unwrap();
+		let report = vfs
+			.move_node_at("mem:/from.txt", "mem:/moved.txt", ConflictPolicy::Fail)
+			.await
+			.unwrap();
+		assert_eq!(report.outcome, ConflictOutcome::Created);
+		// Renamed rather than streamed, so the node still open on it writes to the moved one
+		open.write_all(b"er").await.unwrap();
+		assert_eq!(
+			vfs.read_to_vec_at("mem:/moved.txt").await.unwrap(),
+			b"newer"
+		);
+		assert!(vfs.metadata_at("mem:/from.txt").await.is_err());
+
+		let report = vfs
+			.copy_node_at("mem:/moved.txt", "mem:/to.txt", ConflictPolicy::Overwrite)
+			.await
+			.unwrap();
+		assert_eq!(report.outcome, ConflictOutcome::Overwritten);
+		assert_eq!(vfs.read_to_vec_at("mem:/to.txt").await.unwrap(), b"newer");
+		assert_eq!(
+			vfs.read_to_vec_at("mem:/moved.txt").await.unwrap(),
+			b"newer"
+		);
+	}
+
+	#[tokio::test]
+	async fn rename_node() {
+		let vfs = conflicting_vfs().await;
+		// Overlays have no rename of their own so they copy and remove
+		vfs.add_scheme(
+			"overlay",
+			OverlayScheme::builder_read_write(MemoryScheme::default()).build(),
+		)
+		.unwrap();
+		vfs.rename_node_at("mem:/from.txt", "mem:/to.txt")
+			.await
+			.unwrap();
+		assert_eq!(vfs.read_to_vec_at("mem:/to.txt").await.unwrap(), b"new");
+		assert!(vfs.metadata_at("mem:/from.txt").await.is_err());
+
+		vfs.rename_node_at("mem:/to.txt", "overlay:/node")
+			.await
+			.unwrap();
+		vfs.rename_node_at("overlay:/node", "overlay:/renamed")
+			.await
+			.unwrap();
+		assert_eq!(
+			vfs.read_to_vec_at("overlay:/renamed").await.unwrap(),
+			b"new"
+		);
+		assert!(vfs.metadata_at("overlay:/node").await.is_err());
+		assert!(vfs.metadata_at("mem:/to.txt").await.is_err());
+	}
+
+	#[tokio::test]
+	async fn move_reports_dropped_metadata() {
+		let vfs = Vfs::empty();
+		vfs.add_scheme("mem", MemoryScheme::default()).unwrap();
+		// Overlays do not pass `set_modified` on to their layers
+		vfs.add_scheme(
+			"overlay",
+			OverlayScheme::builder_read_write(MemoryScheme::default()).build(),
+		)
+		.unwrap();
+		write(&vfs, "mem:/node", "moved").await;
+
+		let report = vfs
+			.move_node_at("mem:/node", "overlay:/node", ConflictPolicy::Fail)
+			.await
+			.unwrap();
+		assert_eq!(
+			report,
+			TransferReport {
+				outcome: ConflictOutcome::Created,
+				dropped: vec![MetadataField::Modified]
+			}
+		);
+		assert_eq!(vfs.read_to_vec_at("overlay:/node").await.unwrap(), b"moved");
+		assert!(vfs.metadata_at("mem:/node").await.is_err());
+	}
+
+	#[tokio::test]
+	async fn conflict_fail() {
+		let vfs = conflicting_vfs().await;
+		assert!(matches!(
+			vfs.copy_node_at("mem:/from.txt", "mem:/to.txt", ConflictPolicy::Fail)
+				.await,
+			Err(VfsError::SchemeError(SchemeError::NodeAlreadyExists(_)))
+		));
+		assert!(vfs
+			.move_node_at("mem:/from.txt", "mem:/to.txt", ConflictPolicy::Fail)
+			.await
+			.is_err());
+		assert_eq!(vfs.read_to_vec_at("mem:/to.txt").await.unwrap(), b"old");
+		assert_eq!(vfs.read_to_vec_at("mem:/from.txt").await.unwrap(), b"new");
+	}
+
+	#[tokio::test]
+	async fn conflict_overwrite() {
+		let vfs = conflicting_vfs().await;
+		let report = vfs
+			.move_node_at("mem:/from.txt", "mem:/to.txt", ConflictPolicy::Overwrite)
+			.await
+			.unwrap();
+		assert_eq!(report.outcome, ConflictOutcome::Overwritten);
+		assert_eq!(vfs.read_to_vec_at("mem:/to.txt").await.unwrap(), b"new");
+		assert!(vfs.metadata_at("mem:/from.txt").await.is_err());
+	}
+
+	#[tokio::test]
+	async fn conflict_skip() {
+		let vfs = conflicting_vfs().await;
+		let report = vfs
+			.move_node_at("mem:/from.txt", "mem:/to.txt", ConflictPolicy::Skip)
+			.await
+			.unwrap();
+		assert_eq!(report.outcome, ConflictOutcome::Skipped);
+		assert_eq!(vfs.read_to_vec_at("mem:/to.txt").await.unwrap(), b"old");
+		assert_eq!(vfs.read_to_vec_at("mem:/from.txt").await.unwrap(), b"new");
+	}
+
+	#[tokio::test]
+	async fn conflict_rename() {
+		let vfs = conflicting_vfs().await;
+		write(&vfs, "mem:/to-1.txt", "older").await;
+		let report = vfs
+			.copy_node_at("mem:/from.txt", "mem:/to.txt", ConflictPolicy::Rename)
+			.await
+			.unwrap();
+		assert_eq!(
+			report.outcome,
+			ConflictOutcome::Renamed(Url::parse("mem:/to-2.txt").unwrap())
+		);
+		assert_eq!(vfs.read_to_vec_at("mem:/to.txt").await.unwrap(), b"old");
+		assert_eq!(vfs.read_to_vec_at("mem:/to-1.txt").await.unwrap(), b"older");
+		assert_eq!(vfs.read_to_vec_at("mem:/to-2.txt").await.unwrap(), b"new");
+		assert_eq!(vfs.read_to_vec_at("mem:/from.txt").await.unwrap(), b"new");
+	}
+
+	#[tokio::test]
+	async fn conflict_rename_gives_up() {
+		use super::MAX_RENAME_ATTEMPTS;
+		use crate::FnScheme;
+		use std::borrow::Cow;
+		use std::sync::atomic::{AtomicUsize, Ordering};
+		use std::sync::Arc;
+
+		let attempts = Arc::new(AtomicUsize::new(0));
+		let vfs = conflicting_vfs().await;
+		vfs.add_scheme(
+			"full",
+			FnScheme::new().on_get_node({
+				let attempts = attempts.clone();
+				move |_vfs, url, _options| {
+					attempts.fetch_add(1, Ordering::SeqCst);
+					Box::pin(async move {
+						Err(SchemeError::NodeAlreadyExists(Cow::Owned(
+							url.path().to_owned(),
+						)))
+					})
+				}
+			}),
+		)
+		.unwrap();
+		assert!(matches!(
+			vfs.copy_node_at("mem:/from.txt", "full:/to.txt", ConflictPolicy::Rename)
+				.await,
+			Err(VfsError::SchemeError(SchemeError::GenericError(..)))
+		));
+		assert_eq!(attempts.load(Ordering::SeqCst), MAX_RENAME_ATTEMPTS + 1);
+	}
+
+	#[tokio::test]
+	async fn copy_dir_all() {
+		let vfs = conflicting_vfs().await;
+		write(&vfs, "mem:/src/a.txt", "a").await;
+		write(&vfs, "mem:/src/sub/b.txt", "b").await;
+		write(&vfs, "mem:/dst/a.txt", "old a").await;
+
+		let report = vfs
+			.copy_dir_all_at("mem:/src", "mem:/dst", ConflictPolicy::Skip)
+			.await
+			.unwrap();
+		assert!(report.is_ok());
+		let mut outcomes: Vec<_> = report
+			.nodes
+			.iter()
+			.map(|node| {
+				(
+					node.to.path().to_owned(),
+					node.result.as_ref().unwrap().outcome.clone(),
+				)
+			})
+			.collect();
+		outcomes.sort_by(|a, b| a.0.cmp(&b.0));
+		assert_eq!(
+			outcomes,
+			[
+				("/dst/a.txt".to_owned(), ConflictOutcome::Skipped),
+				("/dst/sub/b.txt".to_owned(), ConflictOutcome::Created),
+			]
+		);
+		assert_eq!(
+			vfs.read_to_vec_at("mem:/dst/a.txt").await.unwrap(),
+			b"old a"
+		);
+		assert_eq!(
+			vfs.read_to_vec_at("mem:/dst/sub/b.txt").await.unwrap(),
+			b"b"
+		);
+
+		let report = vfs
+			.copy_dir_all_at("mem:/src", "mem:/dst", ConflictPolicy::Fail)
+			.await
+			.unwrap();
+		assert!(!report.is_ok());
+		assert!(report.nodes.iter().all(|node| node.result.is_err()));
+	}
+}
@@ -0,0 +1,631 @@
+use crate::scheme::{BorrowedReadDirStream, NodeEntry, NodeMetadata};
+use crate::{SchemeError, Vfs, VfsError};
+use futures_lite::{Stream, StreamExt};
+use percent_encoding::percent_decode_str;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::pin::Pin;
+use url::Url;
+
+/// The stream of nodes `Vfs::walk_dir` finds, it borrows the `Vfs` it walks.
+pub type WalkStream<'s> =
+	Pin<Box<dyn Stream<Item = Result<NodeEntry, VfsError<'static>>> + Send + 's>>;
+
+/// What a recursive walk does when it reaches a directory it has already visited, such as through
+/// a symlink back into one of its ancestors.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LoopBehavior {
+	/// Do not descend into the directory again, the walk carries on with everything else.
+	Skip,
+	/// Yield `VfsError::DirectoryLoop` for the directory, the walk carries on after it.
+	Error,
+}
+
+#[derive(Clone, Debug)]
+pub struct WalkOptions {
+	on_loop: LoopBehavior,
+	max_depth: Option<usize>,
+	include_dirs: bool,
+}
+
+impl Default for WalkOptions {
+	fn default() -> Self {
+		Self {
+			on_loop: LoopBehavior::Skip,
+			max_depth: None,
+			include_dirs: false,
+		}
+	}
+}
+
+impl WalkOptions {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	pub fn get_on_loop(&self) -> LoopBehavior {
+		self.on_loop
+	}
+
+	pub fn on_loop(self, on_loop: LoopBehavior) -> Self {
+		Self { on_loop, ..self }
+	}
+
+	pub fn get_max_depth(&self) -> Option<usize> {
+		self.max_depth
+	}
+
+	/// How many levels of subdirectories to descend into, `0` only lists the walked directory
+	/// itself.  Unlimited by default.
+	pub fn max_depth(self, max_depth: Option<usize>) -> Self {
+		Self { max_depth, ..self }
+	}
+
+	pub fn get_include_dirs(&self) -> bool {
+		self.include_dirs
+	}
+
+	/// Also yield each directory found, before anything in it, rather than only the nodes.
+	pub fn include_dirs(self, include_dirs: bool) -> Self {
+		Self {
+			include_dirs,
+			..self
+		}
+	}
+}
+
+type EntryFilter = Box<dyn Fn(&str) -> bool + Send + Sync>;
+
+/// Limits for `Vfs::load_dir_to_map`, by default every node is loaded however large.
+#[derive(Default)]
+pub struct LoadDirOptions {
+	max_total_len: Option<usize>,
+	filter: Option<EntryFilter>,
+}
+
+impl LoadDirOptions {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	pub fn get_max_total_len(&self) -> Option<usize> {
+		self.max_total_len
+	}
+
+	/// Fail instead of loading more than `max_total_len` bytes in total.  Nodes whose metadata
+	/// already says they would go over are not read at all.
+	pub fn max_total_len(self, max_total_len: usize) -> Self {
+		Self {
+			max_total_len: Some(max_total_len),
+			..self
+		}
+	}
+
+	/// Only load the nodes whose path relative to the directory, such as `shaders/water.glsl`,
+	/// passes `filter`.  Filtered out nodes do not count towards `max_total_len`.
+	pub fn filter<F>(self, filter: F) -> Self
+	where
+		F: Fn(&str) -> bool + Send + Sync + 'static,
+	{
+		Self {
+			filter: Some(Box::new(filter)),
+			..self
+		}
+	}
+}
+
+const LOAD_DIR_TOO_LARGE: &str = "directory contents exceed the maximum total length";
+
+/// A directory url with a trailing `/`, so entry names join onto it instead of replacing its last
+/// segment.
+pub(crate) fn dir_url(url: &Url) -> Url {
+	let mut url = url.clone();
+	if !url.path().ends_with('/') {
+		url.set_path(&format!("{}/", url.path()));
+	}
+	url
+}
+
+struct Walk<'s> {
+	vfs: &'s Vfs,
+	options: WalkOptions,
+	/// The directory being listed and its depth below the walked one.
+	current: Option<(BorrowedReadDirStream<'s>, usize)>,
+	pending: Vec<(Url, usize)>,
+	/// Canonical urls of every directory already listed or queued to be.
+	visited: HashSet<Url>,
+}
+
+impl<'s> Walk<'s> {
+	async fn next(mut self) -> Option<(Result<NodeEntry, VfsError<'static>>, Self)> {
+		loop {
+			if let Some((stream, depth)) = &mut self.current {
+				let depth = *depth + 1;
+				if let Some(entry) = stream.next().await {
+					// Only entries the scheme listed without their metadata are looked up
+					let entry = match entry.metadata {
+						Some(_) => entry,
+						None => match self.vfs.metadata(&entry.url).await {
+							Ok(metadata) => entry.with_metadata(metadata),
+							Err(_error) => continue,
+						},
+					};
+					if entry
+						.metadata
+						.as_ref()
+						.is_some_and(|metadata| metadata.is_node)
+					{
+						return Some((Ok(entry), self));
+					}
+					let include = self.options.include_dirs;
+					if self.options.max_depth.is_some_and(|max| depth > max) {
+						if include {
+							return Some((Ok(entry), self));
+						}
+						continue;
+					}
+					let dir = dir_url(&entry.url);
+					let canonical = match self.vfs.canonicalize(&dir).await {
+						Ok(canonical) => dir_url(&canonical),
+						Err(_error) => continue,
+					};
+					if !self.visited.insert(canonical) {
+						match self.options.on_loop {
+							LoopBehavior::Skip if include => return Some((Ok(entry), self)),
+							LoopBehavior::Skip => continue,
+							LoopBehavior::Error => {
+								return Some((Err(VfsError::DirectoryLoop(entry.url)), self))
+							}
+						}
+					}
+					self.pending.push((dir, depth));
+					if include {
+						return Some((Ok(entry), self));
+					}
+					continue;
+				}
+				self.current = None;
+			}
+			let (dir, depth) = self.pending.pop()?;
+			// A directory that cannot be listed is skipped like an entry without metadata
+			self.current = self
+				.vfs
+				.read_dir(&dir)
+				.await
+				.ok()
+				.map(|stream| (stream, depth));
+		}
+	}
+}
+
+pub(crate) async fn walk_dir<'s, 'a>(
+	vfs: &'s Vfs,
+	url: &'a Url,
+	options: WalkOptions,
+) -> Result<WalkStream<'s>, VfsError<'a>> {
+	let dir = dir_url(url);
+	let canonical = dir_url(&vfs.canonicalize(&dir).await.map_err(VfsError::into_owned)?);
+	let stream = vfs.read_dir(&dir).await.map_err(VfsError::into_owned)?;
+	let walk = Walk {
+		vfs,
+		options,
+		current: Some((stream, 0)),
+		pending: Vec::new(),
+		visited: vec![canonical].into_iter().collect(),
+	};
+	// `unfold` panics when polled after it ended
+	Ok(Box::pin(
+		futures_lite::stream::unfold(walk, Walk::next).fuse(),
+	))
+}
+
+pub(crate) async fn load_dir_to_map<'a>(
+	vfs: &Vfs,
+	url: &'a Url,
+	options: &LoadDirOptions,
+) -> Result<HashMap<String, Vec<u8>>, VfsError<'a>> {
+	let dir = dir_url(url);
+	let mut walk = walk_dir(vfs, &dir, WalkOptions::new())
+		.await
+		.map_err(VfsError::into_owned)?;
+	let mut loaded = HashMap::new();
+	let mut total_len = 0;
+	while let Some(entry) = walk.next().await {
+		let NodeEntry { url, metadata } = entry?;
+		// Nodes found through a symlink keep the url of the link so they are still under `dir`
+		let relative = match url.path().strip_prefix(dir.path()) {
+			Some(relative) => relative,
+			None => continue,
+		};
+		// Keyed by the names as they are rather than as they are encoded in the url, a name that
+		// is not valid UTF-8 once decoded stays encoded
+		let relative = percent_decode_str(relative)
+			.decode_utf8()
+			.unwrap_or(Cow::Borrowed(relative));
+		if let Some(filter) = &options.filter {
+			if !filter(&relative) {
+				continue;
+			}
+		}
+		if let Some(max_total_len) = options.max_total_len {
+			let metadata = match metadata {
+				Some(metadata) => metadata,
+				None => vfs.metadata(&url).await.map_err(VfsError::into_owned)?,
+			};
+			if let Some((min_len, _max_len)) = metadata.len {
+				if total_len + min_len > max_total_len {
+					return Err(SchemeError::from(LOAD_DIR_TOO_LARGE).into());
+				}
+			}
+		}
+		let data = vfs.read_to_vec(&url).await.map_err(VfsError::into_owned)?;
+		total_len += data.len();
+		if options.max_total_len.is_some_and(|max| total_len > max) {
+			return Err(SchemeError::from(LOAD_DIR_TOO_LARGE).into());
+		}
+		loaded.insert(relative.into_owned(), data);
+	}
+	Ok(loaded)
+}
+
+pub(crate) async fn disk_usage<'a>(
+	vfs: &Vfs,
+	url: &'a Url,
+	on_loop: LoopBehavior,
+) -> Result<u64, VfsError<'a>> {
+	let metadata = vfs.metadata(url).await?;
+	if metadata.is_node {
+		return Ok(known_len(&metadata));
+	}
+	let mut walk = walk_dir(vfs, url, WalkOptions::new().on_loop(on_loop)).await?;
+	let mut total = 0;
+	while let Some(entry) = walk.next().await {
+		total += entry?.metadata.as_ref().map_or(0, known_len);
+	}
+	Ok(total)
+}
+
+fn known_len(metadata: &NodeMetadata) -> u64 {
+	metadata.len.map_or(0, |(min_len, _max_len)| min_len as u64)
+}
+
+pub(crate) async fn remove_node_dry_run<'a>(
+	vfs: &Vfs,
+	url: &'a Url,
+	on_loop: LoopBehavior,
+) -> Result<Vec<Url>, VfsError<'a>> {
+	let mut removed = Vec::new();
+	if !vfs.metadata(url).await?.is_node {
+		let options = WalkOptions::new().on_loop(on_loop).include_dirs(true);
+		let mut walk = walk_dir(vfs, url, options).await?;
+		while let Some(entry) = walk.next().await {
+			removed.push(entry?.url);
+		}
+		// A directory is walked before what is in it, and removed after
+		removed.reverse();
+	}
+	removed.push(url.clone());
+	Ok(removed)
+}
+
+#[cfg(test)]
+#[cfg(feature = "backend_tokio")]
+mod async_tokio_tests {
+	use crate::scheme::{NodeEntry, NodeMetadata, ReadDirStream};
+	use crate::walk::{LoopBehavior, WalkOptions};
+	use crate::{FnScheme, SymLinkScheme, Vfs, VfsError};
+	use futures_lite::StreamExt;
+	use url::Url;
+
+	fn u(s: &str) -> Url {
+		Url::parse(s).unwrap()
+	}
+
+	/// `tree:/` holds `file` and `sub/`, which holds `file` and `back`, a symlink to `tree:/`.  The
+	/// files are 4 bytes long.
+	fn looping_vfs() -> Vfs {
+		let vfs = Vfs::empty();
+		vfs.add_scheme(
+			"tree",
+			FnScheme::new()
+				.on_metadata(|_vfs, url| {
+					let is_node = url.path().ends_with("file");
+					Box::pin(async move {
+						Ok(NodeMetadata {
+							is_node,
+							len: is_node.then_some((4, Some(4))),
+							modified: None,
+							..Default::default()
+						})
+					})
+				})
+				.on_read_dir(|_vfs, url| {
+					let entries = match url.path() {
+						"/" => vec![u("tree:/file"), u("tree:/sub")],
+						"/sub/" => vec![u("tree:/sub/file"), u("link:/back")],
+						_ => vec![],
+					};
+					Box::pin(async move {
+						let stream: ReadDirStream = Box::pin(futures_lite::stream::iter(
+							entries.into_iter().map(NodeEntry::new),
+						));
+						Ok(stream)
+					})
+				}),
+		)
+		.unwrap();
+		vfs.add_scheme(
+			"link",
+			SymLinkScheme::builder()
+				.link("/back", u("tree:/"))
+				.unwrap()
+				.build(),
+		)
+		.unwrap();
+		vfs
+	}
+
+	#[tokio::test]
+	async fn walk_dir_loop_skip() {
+		let vfs = looping_vfs();
+		assert_eq!(
+			vfs.canonicalize_at("link:/back/").await.unwrap(),
+			u("tree:/")
+		);
+		// Bounded so a regression fails instead of hanging
+		let mut found: Vec<_> = vfs
+			.walk_dir_at("tree:/", WalkOptions::new())
+			.await
+			.unwrap()
+			.take(100)
+			.map(|entry| entry.unwrap().url.path().to_owned())
+			.collect()
+			.await;
+		found.sort();
+		assert_eq!(found, ["/file", "/sub/file"]);
+
+		let mut walk = vfs.walk_dir_at("tree:/", WalkOptions::new()).await.unwrap();
+		while walk.next().await.is_some() {}
+		assert!(walk.next().await.is_none(), "fused");
+	}
+
+	#[tokio::test]
+	async fn walk_dir_loop_error() {
+		let vfs = looping_vfs();
+		let results: Vec<_> = vfs
+			.walk_dir_at("tree:/", WalkOptions::new().on_loop(LoopBehavior::Error))
+			.await
+			.unwrap()
+			.take(100)
+			.collect()
+			.await;
+		assert_eq!(results.len(), 3);
+		assert!(results.iter().any(
+			|result| matches!(result, Err(VfsError::DirectoryLoop(url)) if url.as_str() == "link:/back")
+		));
+	}
+
+	#[tokio::test]
+	async fn copy_dir_all_loop() {
+		use crate::transfer::ConflictPolicy;
+
+		let vfs = looping_vfs();
+		// `tree:` cannot open nodes so every copy fails, it is the walk that has to end
+		let report = vfs
+			.copy_dir_all_at("tree:/", "tree:/copy/", ConflictPolicy::Overwrite)
+			.await
+			.unwrap();
+		let mut copied: Vec<_> = report
+			.nodes
+			.iter()
+			.map(|node| node.from.to_string())
+			.collect();
+		copied.sort();
+		assert_eq!(copied, ["tree:/file", "tree:/sub/file"]);
+	}
+
+	#[tokio::test]
+	async fn disk_usage_loop() {
+		let vfs = looping_vfs();
+		assert_eq!(
+			vfs.disk_usage_at("tree:/", LoopBehavior::Skip)
+				.await
+				.unwrap(),
+			8
+		);
+		assert_eq!(
+			vfs.disk_usage_at("tree:/sub/file", LoopBehavior::Skip)
+				.await
+				.unwrap(),
+			4
+		);
+		assert!(matches!(
+			vfs.disk_usage_at("tree:/", LoopBehavior::Error).await,
+			Err(VfsError::DirectoryLoop(url)) if url.as_str() == "link:/back"
+		));
+	}
+
+	#[tokio::test]
+	async fn remove_node_dry_run_loop() {
+		let vfs = looping_vfs();
+		let removed: Vec<_> = vfs
+			.remove_node_dry_run_at("tree:/", LoopBehavior::Skip)
+			.await
+			.unwrap()
+			.iter()
+			.map(Url::to_string)
+			.collect();
+		assert_eq!(
+			removed,
+			[
+				"link:/back",
+				"tree:/sub/file",
+				"tree:/sub",
+				"tree:/file",
+				"tree:/"
+			]
+		);
+		assert_eq!(
+			vfs.remove_node_dry_run_at("tree:/file", LoopBehavior::Skip)
+				.await
+				.unwrap(),
+			[u("tree:/file")]
+		);
+		assert!(matches!(
+			vfs.remove_node_dry_run_at("tree:/", LoopBehavior::Error)
+				.await,
+			Err(VfsError::DirectoryLoop(_))
+		));
+	}
+
+	#[tokio::test]
+	async fn walk_dir_depth_and_dirs() {
+		let vfs = looping_vfs();
+		let walked = |options: WalkOptions| {
+			let vfs = &vfs;
+			async move {
+				let mut found: Vec<_> = vfs
+					.walk_dir_at("tree:/", options)
+					.await
+					.unwrap()
+					.take(100)
+					.map(|entry| entry.unwrap().url.to_string())
+					.collect()
+					.await;
+				found.sort();
+				found
+			}
+		};
+		assert_eq!(
+			walked(WalkOptions::new().max_depth(Some(0))).await,
+			["tree:/file"]
+		);
+		assert_eq!(
+			walked(WalkOptions::new().max_depth(Some(0)).include_dirs(true)).await,
+			["tree:/file", "tree:/sub"]
+		);
+		// The link back is still yielded as a directory, it just is not descended into again
+		assert_eq!(
+			walked(WalkOptions::new().include_dirs(true)).await,
+			["link:/back", "tree:/file", "tree:/sub", "tree:/sub/file"]
+		);
+	}
+
+	#[tokio::test]
+	async fn walk_dir_uses_listed_metadata() {
+		use std::sync::atomic::{AtomicUsize, Ordering};
+		use std::sync::Arc;
+
+		let lookups = Arc::new(AtomicUsize::new(0));
+		let vfs = Vfs::empty();
+		vfs.add_scheme(
+			"tree",
+			FnScheme::new()
+				.on_metadata({
+					let lookups = lookups.clone();
+					move |_vfs, url| {
+						lookups.fetch_add(1, Ordering::SeqCst);
+						let is_node = url.path().ends_with("file");
+						Box::pin(async move {
+							Ok(NodeMetadata {
+								is_node,
+								..Default::default()
+							})
+						})
+					}
+				})
+				.on_read_dir(|_vfs, url| {
+					let entries = match url.path() {
+						"/" => vec![(u("tree:/file"), true), (u("tree:/sub"), false)],
+						"/sub/" => vec![(u("tree:/sub/file"), true)],
+						_ => vec![],
+					};
+					Box::pin(async move {
+						let stream: ReadDirStream = Box::pin(futures_lite::stream::iter(
+							entries.into_iter().map(|(url, is_node)| {
+								NodeEntry::new(url).with_metadata(NodeMetadata {
+									is_node,
+									len: Some((4, Some(4))),
+									..Default::default()
+								})
+							}),
+						));
+						Ok(stream)
+					})
+				}),
+		)
+		.unwrap();
+
+		let mut found: Vec<_> = vfs
+			.walk_dir_at("tree:/", WalkOptions::new())
+			.await
+			.unwrap()
+			.take(100)
+			.map(|entry| {
+				let entry = entry.unwrap();
+				(entry.url.path().to_owned(), entry.metadata.unwrap().len)
+			})
+			.collect()
+			.await;
+		found.sort();
+		assert_eq!(
+			found,
+			[
+				("/file".to_owned(), Some((4, Some(4)))),
+				("/sub/file".to_owned(), Some((4, Some(4))))
+			]
+		);
+		assert_eq!(lookups.load(Ordering::SeqCst), 0);
+	}
+
+	#[cfg(feature = "in_memory")]
+	#[tokio::test]
+	async fn load_dir_to_map() {
+		use crate::scheme::NodeGetOptions;
+		use crate::walk::LoadDirOptions;
+		use crate::{MemoryScheme, SchemeError};
+		use futures_lite::AsyncWriteExt;
+
+		let vfs = Vfs::empty();
+		vfs.add_scheme("mem", MemoryScheme::default()).unwrap();
+		for (path, content) in [
+			("mem:/shaders/water.glsl", "water"),
+			("mem:/shaders/lava/lava.glsl", "lava"),
+			("mem:/shaders/readme.txt", "notes"),
+			("mem:/shaders/sky%20box.glsl", "sky"),
+			("mem:/other.glsl", "other"),
+		] {
+			let mut node = vfs
+				.get_node_at(path, &NodeGetOptions::new().create_new(true))
+				.await
+				.unwrap();
+			node.write_all(content.as_bytes()).await.unwrap();
+		}
+
+		let loaded = vfs
+			.load_dir_to_map_at(
+				"mem:/shaders",
+				&LoadDirOptions::new().filter(|path| path.ends_with(".glsl")),
+			)
+			.await
+			.unwrap();
+		let mut paths: Vec<_> = loaded.keys().map(String::as_str).collect();
+		paths.sort_unstable();
+		assert_eq!(paths, ["lava/lava.glsl", "sky box.glsl", "water.glsl"]);
+		assert_eq!(loaded["lava/lava.glsl"], b"lava");
+		assert_eq!(loaded["sky box.glsl"], b"sky");
+
+		assert_eq!(
+			vfs.load_dir_to_map_at("mem:/shaders", &LoadDirOptions::new().max_total_len(17))
+				.await
+				.unwrap()
+				.len(),
+			4
+		);
+		assert!(matches!(
+			vfs.load_dir_to_map_at("mem:/shaders", &LoadDirOptions::new().max_total_len(16))
+				.await,
+			Err(VfsError::SchemeError(SchemeError::GenericError(..)))
+		));
+	}
+}